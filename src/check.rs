@@ -0,0 +1,183 @@
+//! `--check`: validate the configuration without starting the network
+//!
+//! Parses the same arguments as a normal start, then checks everything that
+//! would otherwise only fail once the TUI is up: multiaddresses, the relay
+//! URL, the stored identity, and that the data and download directories are
+//! writable. Prints one line per check and exits with the configuration
+//! error code if any failed, so deployment scripts can run it before
+//! starting the service.
+
+use std::path::Path;
+
+use crate::Args;
+use crate::paths::Paths;
+
+/// Outcome of `--check`
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// Settings that checked out, as shown to the user
+    pub ok: Vec<String>,
+    /// What is wrong, one line each
+    pub problems: Vec<String>,
+}
+
+impl CheckReport {
+    fn pass(&mut self, line: String) {
+        self.ok.push(line);
+    }
+
+    fn fail(&mut self, line: String) {
+        self.problems.push(line);
+    }
+
+    /// Whether every check passed
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Print the summary to stdout/stderr
+    pub fn print(&self) {
+        for line in &self.ok {
+            println!("ok    {}", line);
+        }
+        for line in &self.problems {
+            eprintln!("error {}", line);
+        }
+        if self.is_ok() {
+            println!("Configuration OK");
+        } else {
+            eprintln!("{} problem(s) found", self.problems.len());
+        }
+    }
+}
+
+/// Validate `args` without touching the network
+pub fn check_config(args: &Args) -> CheckReport {
+    let mut report = CheckReport::default();
+    let paths = Paths::new(args.data_dir.clone()).with_profile(args.profile.clone());
+
+    // Combining these with --lan-only is already a parse error
+    for addr in &args.bootstrap {
+        match crate::network::parse_dial_addr(addr) {
+            Ok(addr) => report.pass(format!("bootstrap address {}", addr)),
+            Err(e) => report.fail(format!("bootstrap: {}", e)),
+        }
+    }
+    if let Some(addr) = &args.p2p_relay {
+        match crate::network::parse_dial_addr(addr) {
+            Ok(addr) if crate::network::nat::target_peer(&addr).is_some() => {
+                report.pass(format!("relay address {}", addr))
+            }
+            Ok(addr) => report.fail(format!("p2p-relay: {} must end with /p2p/<relay peer id>", addr)),
+            Err(e) => report.fail(format!("p2p-relay: {}", e)),
+        }
+    }
+
+    if let Some(url) = &args.relay_url {
+        if url.starts_with("wss://") || url.starts_with("ws://") {
+            report.pass(format!("relay URL {}", url));
+        } else {
+            report.fail(format!("relay URL '{}' must start with ws:// or wss://", url));
+        }
+    }
+
+    if args.web {
+        match std::net::TcpListener::bind(("0.0.0.0", args.web_port)) {
+            Ok(_) => report.pass(format!("web port {} is free", args.web_port)),
+            Err(e) => report.fail(format!("web port {}: {}", args.web_port, e)),
+        }
+    }
+
+    if let Some(password) = &args.lan_password {
+        match crate::encryption::derive_password_key(password, crate::network::PROTOCOL_VERSION) {
+            Ok(_) => report.pass("LAN password".to_string()),
+            Err(e) => report.fail(format!("LAN password: {}", e)),
+        }
+    }
+
+    if let Some(profile) = paths.profile() {
+        report.pass(format!("profile {} in {}", profile, paths.data_dir().display()));
+    }
+    let identity = args.identity.clone().unwrap_or_else(|| paths.identity_path());
+    if crate::crypto::is_encrypted_key_file(&identity) {
+        report.pass(format!("identity {} (passphrase-protected)", identity.display()));
+    } else if identity.exists() {
+        match crate::crypto::CryptoManager::load_from(&identity) {
+            Ok(crypto) => report.pass(format!("identity {} ({})", identity.display(), crypto.peer_id())),
+            Err(e) => report.fail(format!("identity {}: {}", identity.display(), e)),
+        }
+    } else {
+        report.pass(format!("identity {} not created yet", identity.display()));
+    }
+
+    for (what, dir) in [("data directory", paths.data_dir().to_path_buf()), ("download directory", paths.received_dir())] {
+        match check_writable(&dir) {
+            Ok(true) => report.pass(format!("{} {} is writable", what, dir.display())),
+            Ok(false) => report.pass(format!("{} {} can be created", what, dir.display())),
+            Err(e) => report.fail(format!("{} {}: {}", what, dir.display(), e)),
+        }
+    }
+
+    report
+}
+
+/// Write (then remove) a scratch file in `dir`, or in its nearest existing
+/// ancestor when `dir` doesn't exist yet, so nothing is created. Returns
+/// whether `dir` itself exists.
+fn check_writable(dir: &Path) -> std::io::Result<bool> {
+    let existing = dir
+        .ancestors()
+        .find(|a| a.exists())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no existing parent directory"))?;
+    if !existing.is_dir() {
+        return Err(std::io::Error::other(format!("{} is not a directory", existing.display())));
+    }
+    let probe = existing.join(".openwire-check");
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(probe)?;
+    Ok(existing == dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn args(extra: &[&str]) -> Args {
+        let dir = std::env::temp_dir().join(format!("openwire-check-{}", std::process::id()));
+        let mut argv = vec!["openwire", "--check", "--data-dir", dir.to_str().unwrap()];
+        argv.extend_from_slice(extra);
+        Args::try_parse_from(argv).unwrap()
+    }
+
+    #[test]
+    fn test_bad_bootstrap_address_fails_check() {
+        let report = check_config(&args(&["--bootstrap", "/ip4/10.0.0.1/udp/4001"]));
+        assert!(!report.is_ok());
+        assert!(
+            report.problems.iter().any(|p| p.starts_with("bootstrap:") && p.contains("/udp/4001")),
+            "{:?}",
+            report.problems
+        );
+
+        let good = args(&["--bootstrap", "/ip4/10.0.0.1/tcp/4001"]);
+        let report = check_config(&good);
+        assert!(report.is_ok(), "{:?}", report.problems);
+        let _ = std::fs::remove_dir_all(good.data_dir.unwrap());
+    }
+
+    #[test]
+    fn test_check_writable_creates_nothing() {
+        let dir = std::env::temp_dir().join(format!("openwire-check-writable-{}", std::process::id()));
+        let missing = dir.join("a/b");
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(!check_writable(&missing).unwrap());
+        assert!(!dir.join("a").exists());
+        assert!(check_writable(&dir).unwrap());
+
+        // A file where a directory would go can't be fixed by creating it
+        std::fs::write(dir.join("file"), b"").unwrap();
+        assert!(check_writable(&dir.join("file/sub")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
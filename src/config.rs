@@ -0,0 +1,425 @@
+//! Settings from `config.toml` and `OPENWIRE_*` environment variables
+//!
+//! Every command-line option can also be set in the config file (in the
+//! config directory, or `--data-dir`) as `web_port = 3001`, or in the
+//! environment as `OPENWIRE_WEB_PORT=3001`. The layers are turned into
+//! arguments placed ahead of the real command line, so clap sees one
+//! argument list and the last occurrence wins: command line over
+//! environment over file over the built-in default.
+//!
+//! The file is TOML with top-level keys only: strings, numbers,
+//! `true`/`false` and arrays of those. A switch like `lobby` can be turned
+//! on from a layer but not back off by a later one.
+//!
+//! `/reload` (or SIGHUP) reads the layers again. Settings in `RELOADABLE`
+//! take effect right away; changes to the rest are reported as needing a
+//! restart.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::sync::{Arc, RwLock};
+
+use clap::error::ErrorKind;
+use clap::{ArgAction, CommandFactory, FromArgMatches};
+
+use crate::Args;
+use crate::paths::Paths;
+
+/// Prefix of the environment variables read as settings
+const ENV_PREFIX: &str = "OPENWIRE_";
+
+/// Settings never printed by `--print-config`
+const SECRETS: &[&str] = &["lan_password", "room_passphrase", "web_token"];
+
+/// Options that are actions rather than settings
+const NOT_SETTINGS: &[&str] = &["help", "version", "check", "print_config", "import"];
+
+/// Settings printed as arrays, even with a single value
+const LISTS: &[&str] = &["dial_allowlist", "bootstrap"];
+
+/// Settings a running instance picks up on reload
+const RELOADABLE: &[&str] = &["nick", "trust_dial", "dial_allowlist", "accept_only_verified"];
+
+/// Where a setting's value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    Env,
+    CommandLine,
+}
+
+impl Source {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::File => "config file",
+            Self::Env => "environment",
+            Self::CommandLine => "command line",
+        }
+    }
+}
+
+/// One effective setting
+#[derive(Debug, Clone)]
+struct Setting {
+    key: String,
+    values: Vec<String>,
+    source: Source,
+}
+
+/// Arguments after merging every layer
+pub struct Merged {
+    pub args: Args,
+    settings: Vec<Setting>,
+    klipy_key_set: bool,
+}
+
+/// Merge the config file, environment and command line (`argv` includes
+/// the program name). Errors are clap errors so `--help` and bad values
+/// exit the usual way.
+pub fn load(argv: Vec<OsString>, env: &dyn Fn(&str) -> Option<String>) -> Result<Merged, clap::Error> {
+    // The file's location can only come from the command line or environment
+    let cli = Args::command().try_get_matches_from(&argv)?;
+    let data_dir = cli
+        .get_one::<std::path::PathBuf>("data_dir")
+        .cloned()
+        .or_else(|| env(&env_name("data_dir")).map(Into::into));
+    let path = Paths::new(data_dir).config_path();
+    let file = match std::fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(invalid(format!("Can't read {}: {}", path.display(), e))),
+    };
+    merge(argv, env, file.as_deref())
+}
+
+/// `load` with the config file's contents given
+pub fn merge(
+    argv: Vec<OsString>,
+    env: &dyn Fn(&str) -> Option<String>,
+    file: Option<&str>,
+) -> Result<Merged, clap::Error> {
+    let command = Args::command();
+    let mut layered: Vec<OsString> = argv.first().cloned().into_iter().collect();
+    let mut sources: HashMap<String, Source> = HashMap::new();
+
+    if let Some(text) = file {
+        for (key, value) in parse_file(text)? {
+            layered.extend(to_args(&command, &key, &value)?);
+            sources.insert(key, Source::File);
+        }
+    }
+    for arg in settings(&command) {
+        let key = arg.get_id().to_string();
+        if let Some(value) = env(&env_name(&key)) {
+            layered.extend(to_args(&command, &key, &value)?);
+            sources.insert(key, Source::Env);
+        }
+    }
+    let cli = command.clone().try_get_matches_from(&argv)?;
+    for arg in settings(&command) {
+        let key = arg.get_id().to_string();
+        if cli.value_source(&key) == Some(clap::parser::ValueSource::CommandLine) {
+            sources.insert(key, Source::CommandLine);
+        }
+    }
+    layered.extend(argv.into_iter().skip(1));
+
+    let matches = command.args_override_self(true).try_get_matches_from(layered)?;
+    let args = Args::from_arg_matches(&matches)?;
+    let settings = settings(&Args::command())
+        .map(|arg| {
+            let key = arg.get_id().to_string();
+            let values = matches
+                .get_raw(&key)
+                .map(|raw| raw.map(|v| v.to_string_lossy().into_owned()).collect())
+                .unwrap_or_default();
+            let source = sources.get(&key).copied().unwrap_or(Source::Default);
+            Setting { key, values, source }
+        })
+        .collect();
+    Ok(Merged {
+        args,
+        settings,
+        klipy_key_set: env("KLIPY_KEY").is_some_and(|k| !k.is_empty()),
+    })
+}
+
+/// The configuration in effect, shared with whoever can reload it
+pub type SharedConfig = Arc<RwLock<Config>>;
+
+/// The merged settings plus the command line they came from, so they can
+/// be read again
+pub struct Config {
+    argv: Vec<OsString>,
+    merged: Merged,
+}
+
+/// Settings whose value changed on reload
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Reload {
+    /// Now in effect
+    pub applied: Vec<String>,
+    /// Only take effect after a restart
+    pub needs_restart: Vec<String>,
+}
+
+impl Config {
+    pub fn new(argv: Vec<OsString>, merged: Merged) -> Self {
+        Self { argv, merged }
+    }
+
+    pub fn args(&self) -> &Args {
+        &self.merged.args
+    }
+
+    /// Read the config file and environment again
+    pub fn reload(&mut self) -> Result<Reload, clap::Error> {
+        let merged = load(self.argv.clone(), &|name| std::env::var(name).ok())?;
+        Ok(self.replace(merged))
+    }
+
+    /// `reload` with the environment and config file's contents given
+    #[cfg(test)]
+    pub fn reload_from(
+        &mut self,
+        env: &dyn Fn(&str) -> Option<String>,
+        file: Option<&str>,
+    ) -> Result<Reload, clap::Error> {
+        let merged = merge(self.argv.clone(), env, file)?;
+        Ok(self.replace(merged))
+    }
+
+    fn replace(&mut self, merged: Merged) -> Reload {
+        let mut reload = Reload::default();
+        for (old, new) in self.merged.settings.iter().zip(&merged.settings) {
+            if old.values == new.values {
+                continue;
+            }
+            if RELOADABLE.contains(&new.key.as_str()) {
+                reload.applied.push(new.key.clone());
+            } else {
+                reload.needs_restart.push(new.key.clone());
+            }
+        }
+        self.merged = merged;
+        reload
+    }
+}
+
+impl Merged {
+    /// The effective settings as TOML, each annotated with where it came
+    /// from. Secrets are commented out and redacted, so the output is
+    /// itself a usable config file minus the secrets.
+    pub fn to_toml(&self) -> String {
+        let mut out = String::from("# Effective OpenWire configuration\n");
+        for setting in &self.settings {
+            let source = setting.source.label();
+            let value = match setting.values.as_slice() {
+                [] => {
+                    let _ = writeln!(out, "# {} is not set", setting.key);
+                    continue;
+                }
+                _ if SECRETS.contains(&setting.key.as_str()) => {
+                    let _ = writeln!(out, "# {} = \"<redacted>\"  # {}", setting.key, source);
+                    continue;
+                }
+                [one] if !LISTS.contains(&setting.key.as_str()) => scalar(one),
+                many => format!("[{}]", many.iter().map(|v| quote(v)).collect::<Vec<_>>().join(", ")),
+            };
+            let _ = writeln!(out, "{} = {}  # {}", setting.key, value, source);
+        }
+        if self.klipy_key_set {
+            out.push_str("# KLIPY_KEY is set in the environment (redacted)\n");
+        }
+        out
+    }
+}
+
+/// Command options that are settings
+fn settings(command: &clap::Command) -> impl Iterator<Item = &clap::Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| arg.get_long().is_some() && !NOT_SETTINGS.contains(&arg.get_id().as_str()))
+}
+
+/// `OPENWIRE_WEB_PORT` for `web_port`
+fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase())
+}
+
+/// Arguments that set `key` to `value`
+fn to_args(command: &clap::Command, key: &str, value: &str) -> Result<Vec<OsString>, clap::Error> {
+    let key = key.replace('-', "_");
+    let arg = settings(command)
+        .find(|arg| arg.get_id() == key.as_str())
+        .ok_or_else(|| invalid(format!("Unknown setting `{}`", key)))?;
+    let long = format!("--{}", arg.get_long().unwrap_or_default());
+    if matches!(arg.get_action(), ArgAction::SetTrue) {
+        return match value {
+            "true" => Ok(vec![long.into()]),
+            "false" => Ok(Vec::new()),
+            _ => Err(invalid(format!("`{}` must be true or false", key))),
+        };
+    }
+    Ok(vec![format!("{}={}", long, value).into()])
+}
+
+/// `key = value` pairs of a config file, values as they'd be typed on
+/// the command line; arrays become comma-separated lists
+fn parse_file(text: &str) -> Result<Vec<(String, String)>, clap::Error> {
+    let table: toml::Table = toml::from_str(text).map_err(|e| {
+        let line = e.span().map_or(0, |span| text[..span.start].lines().count().max(1));
+        invalid(format!("config.toml line {}: {}", line, e.message()))
+    })?;
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                toml::Value::Array(items) => items
+                    .into_iter()
+                    .map(|item| to_arg(&key, item))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(","),
+                value => to_arg(&key, value)?,
+            };
+            Ok((key.replace('-', "_"), value))
+        })
+        .collect()
+}
+
+/// A scalar config value as an argument
+fn to_arg(key: &str, value: toml::Value) -> Result<String, clap::Error> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(n) => Ok(n.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(invalid(format!("`{}` must be a string, number, boolean or array of them", key))),
+    }
+}
+
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Numbers and booleans bare, everything else quoted
+fn scalar(value: &str) -> String {
+    if value == "true" || value == "false" || value.parse::<i64>().is_ok() {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+fn invalid(message: String) -> clap::Error {
+    let mut command = Args::command();
+    command.error(ErrorKind::InvalidValue, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        std::iter::once("openwire").chain(args.iter().copied()).map(Into::into).collect()
+    }
+
+    fn line<'a>(toml: &'a str, key: &str) -> &'a str {
+        toml.lines()
+            .find(|l| l.starts_with(&format!("{} = ", key)))
+            .unwrap_or_else(|| panic!("no {} in\n{}", key, toml))
+    }
+
+    #[test]
+    fn test_precedence_shows_in_printed_config() {
+        let file = r#"
+            # shared settings
+            port = 4001
+            web_port = 3001
+            nick = "from file"
+            log_level = "info"
+            lan_password = "hunter2"
+            dial_allowlist = ["/ip4/10.0.0.*", "/ip4/10.0.1.*"]
+            lobby = true
+        "#;
+        let env = |name: &str| match name {
+            "OPENWIRE_WEB_PORT" => Some("3002".to_string()),
+            "OPENWIRE_NICK" => Some("from env".to_string()),
+            "KLIPY_KEY" => Some("k-123".to_string()),
+            _ => None,
+        };
+        let merged = merge(argv(&["--nick", "from cli"]), &env, Some(file)).unwrap();
+
+        assert_eq!(merged.args.nick, "from cli");
+        assert_eq!(merged.args.web_port, 3002);
+        assert_eq!(merged.args.port, 4001);
+        assert_eq!(merged.args.max_rooms, crate::room::DEFAULT_MAX_ROOMS);
+        assert!(merged.args.lobby);
+
+        let toml = merged.to_toml();
+        assert_eq!(line(&toml, "nick"), r#"nick = "from cli"  # command line"#);
+        assert_eq!(line(&toml, "web_port"), "web_port = 3002  # environment");
+        assert_eq!(line(&toml, "port"), "port = 4001  # config file");
+        assert!(line(&toml, "max_rooms").ends_with("# default"));
+        assert_eq!(
+            line(&toml, "dial_allowlist"),
+            r#"dial_allowlist = ["/ip4/10.0.0.*", "/ip4/10.0.1.*"]  # config file"#
+        );
+        assert!(toml.contains("# lan_password = \"<redacted>\"  # config file"));
+        assert!(toml.contains("# bootstrap is not set"));
+        assert!(toml.contains("KLIPY_KEY is set"));
+        assert!(!toml.contains("hunter2") && !toml.contains("k-123"));
+        assert!(!toml.contains("print_config"));
+
+        // The printed config reads back as the same settings
+        let again = merge(argv(&[]), &|_| None, Some(&toml)).unwrap();
+        assert_eq!(again.args.nick, "from cli");
+        assert_eq!(again.args.web_port, 3002);
+    }
+
+    #[test]
+    fn test_reload_sorts_changes_by_whether_they_apply_live() {
+        let none = |_: &str| None;
+        let merged = merge(argv(&["--web-port", "3005"]), &none, Some("nick = \"ann\"\nport = 4001")).unwrap();
+        let mut config = Config::new(argv(&["--web-port", "3005"]), merged);
+
+        let reload = config.reload_from(&none, Some("nick = \"ann\"\nport = 4001")).unwrap();
+        assert_eq!(reload, Reload::default());
+
+        let file = "nick = \"bea\"\nport = 4002\ntrust_dial = true\nweb_port = 3006";
+        let reload = config.reload_from(&none, Some(file)).unwrap();
+        assert_eq!(reload.applied, ["nick", "trust_dial"]);
+        assert_eq!(reload.needs_restart, ["port"]);
+        assert_eq!(config.args().nick, "bea");
+        // The command line still wins
+        assert_eq!(config.args().web_port, 3005);
+
+        // A bad file leaves the settings as they were
+        assert!(config.reload_from(&none, Some("port = lots")).is_err());
+        assert_eq!(config.args().port, 4002);
+    }
+
+    #[test]
+    fn test_bad_config_is_reported() {
+        let none = |_: &str| None;
+        assert!(merge(argv(&[]), &none, Some("colour = \"blue\"")).is_err());
+        assert!(merge(argv(&[]), &none, Some("lobby = yes")).is_err());
+        assert!(merge(argv(&[]), &none, Some("just words")).is_err());
+        assert!(merge(argv(&[]), &none, Some("port = 99999")).is_err());
+        assert!(merge(argv(&[]), &none, Some("[network]\nport = 4001")).is_err());
+        let Err(err) = merge(argv(&[]), &none, Some("port = 4001\nnick = \"open")) else {
+            panic!("unterminated string accepted");
+        };
+        assert!(err.to_string().contains("config.toml line 2"), "{}", err);
+
+        // Quoting and comments follow TOML
+        let merged = merge(argv(&[]), &none, Some("nick = 'a # b'  # comment\nmax-rooms = 7")).unwrap();
+        assert_eq!(merged.args.nick, "a # b");
+        assert_eq!(merged.args.max_rooms, 7);
+        let bad_env = |name: &str| (name == "OPENWIRE_MAX_ROOMS").then(|| "lots".to_string());
+        assert!(merge(argv(&[]), &bad_env, None).is_err());
+    }
+}
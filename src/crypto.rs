@@ -12,7 +12,7 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use zeroize::ZeroizeOnDrop;
 
-use crate::encryption::SessionManager;
+use crate::encryption::{RATCHET_PREFIX, RatchetEpochs, RatchetMessage, RatchetSession, SessionManager};
 
 /// Represents a peer's cryptographic identity.
 ///
@@ -103,6 +103,183 @@ impl Identity {
     pub fn signing_key_bytes(&self) -> [u8; 32] {
         self.signing_key.to_bytes()
     }
+
+    /// Write the signing seed to `path`, encrypted with `passphrase`
+    pub fn save_encrypted(&self, path: &std::path::Path, passphrase: &str) -> Result<()> {
+        let seed = zeroize::Zeroizing::new(self.to_bytes());
+        seal_key_file(path, &*seed, passphrase, &KeyFileParams::default())
+    }
+
+    /// Read a seed written by [`save_encrypted`](Self::save_encrypted)
+    pub fn load_encrypted(path: &std::path::Path, passphrase: &str) -> Result<Self> {
+        let seed = open_key_file(path, passphrase)?;
+        let seed: [u8; 32] = seed
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Identity file holds {} bytes, not a signing key", seed.len()))?;
+        Self::from_bytes(seed)
+    }
+}
+
+/// Maximum allowed clock skew for signed timestamps (seconds)
+pub const MAX_TIMESTAMP_SKEW: u64 = 60;
+
+/// Starts a passphrase-protected key file
+const KEY_FILE_MAGIC: &[u8] = b"OWKEY";
+/// Layout after the magic: version, Argon2id memory (KiB), iterations and
+/// lanes as little-endian u32s, salt length and salt, then the sealed keys
+/// as an `EncryptedMessage`. Everything before the keys is authenticated.
+const KEY_FILE_VERSION: u8 = 1;
+const KEY_FILE_SALT_SIZE: usize = 16;
+/// Starts a backup from [`CryptoManager::export_bundle`]. Same layout as a
+/// key file; the sealed data is a JSON [`Bundle`].
+const BUNDLE_MAGIC: &[u8] = b"OWBAK";
+
+/// Everything a backup restores
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Bundle {
+    /// [`CryptoManager::to_key_bytes`]
+    #[serde(with = "hex::serde")]
+    keys: Vec<u8>,
+    /// Joined rooms with their group keys
+    rooms: Vec<crate::room::Room>,
+}
+
+impl Drop for Bundle {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.keys);
+    }
+}
+
+/// Argon2id cost for a key file. Stored in the header, so files written
+/// with older settings still open after the defaults change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyFileParams {
+    memory_kib: u32,
+    iterations: u32,
+    lanes: u32,
+}
+
+impl Default for KeyFileParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            lanes: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KeyFileParams {
+    fn derive_key(&self, passphrase: &str, salt: &[u8]) -> Result<zeroize::Zeroizing<[u8; 32]>> {
+        let params = argon2::Params::new(self.memory_kib, self.iterations, self.lanes, Some(32))
+            .map_err(|e| anyhow::anyhow!("Bad key file parameters: {}", e))?;
+        let argon = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+        let mut key = zeroize::Zeroizing::new([0u8; 32]);
+        argon
+            .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+        Ok(key)
+    }
+}
+
+/// Whether `path` holds keys protected with a passphrase
+pub fn is_encrypted_key_file(path: &std::path::Path) -> bool {
+    use std::io::Read;
+    let mut magic = [0u8; KEY_FILE_MAGIC.len()];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| magic == KEY_FILE_MAGIC)
+}
+
+fn seal_key_file(path: &std::path::Path, secret: &[u8], passphrase: &str, params: &KeyFileParams) -> Result<()> {
+    write_private(path, &seal_keys(KEY_FILE_MAGIC, secret, passphrase, params)?)
+}
+
+fn open_key_file(path: &std::path::Path, passphrase: &str) -> Result<zeroize::Zeroizing<Vec<u8>>> {
+    open_keys(KEY_FILE_MAGIC, &std::fs::read(path)?, passphrase, "identity file")
+}
+
+/// Encrypt `secret` under `passphrase` in the key file layout, starting with `magic`
+fn seal_keys(magic: &[u8], secret: &[u8], passphrase: &str, params: &KeyFileParams) -> Result<Vec<u8>> {
+    if passphrase.is_empty() {
+        return Err(anyhow::anyhow!("The passphrase can't be empty"));
+    }
+    let mut salt = [0u8; KEY_FILE_SALT_SIZE];
+    rand::Rng::fill_bytes(&mut rand::rng(), &mut salt);
+    let mut header = magic.to_vec();
+    header.push(KEY_FILE_VERSION);
+    for value in [params.memory_kib, params.iterations, params.lanes] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    header.push(salt.len() as u8);
+    header.extend_from_slice(&salt);
+
+    let key = params.derive_key(passphrase, &salt)?;
+    let sealed = crate::encryption::encrypt_with_key(secret, &key, Some(&header))?;
+    let mut data = header;
+    data.extend_from_slice(&sealed.to_bytes()?);
+    Ok(data)
+}
+
+/// Decrypt what [`seal_keys`] wrote; `what` names the data in errors
+fn open_keys(magic: &[u8], data: &[u8], passphrase: &str, what: &str) -> Result<zeroize::Zeroizing<Vec<u8>>> {
+    let truncated = || anyhow::anyhow!("The {} is truncated", what);
+    let rest = data
+        .strip_prefix(magic)
+        .ok_or_else(|| anyhow::anyhow!("Not a passphrase-protected {}", what))?;
+    let (&version, rest) = rest.split_first().ok_or_else(truncated)?;
+    if version != KEY_FILE_VERSION {
+        return Err(anyhow::anyhow!("Unsupported {} version {}", what, version));
+    }
+    let mut fields = [0u32; 3];
+    for (i, field) in fields.iter_mut().enumerate() {
+        let bytes = rest.get(i * 4..i * 4 + 4).ok_or_else(truncated)?;
+        *field = u32::from_le_bytes(bytes.try_into()?);
+    }
+    let [memory_kib, iterations, lanes] = fields;
+    let params = KeyFileParams { memory_kib, iterations, lanes };
+    let rest = &rest[12..];
+    let (&salt_len, rest) = rest.split_first().ok_or_else(truncated)?;
+    let salt = rest.get(..salt_len as usize).ok_or_else(truncated)?;
+    let header_len = data.len() - rest.len() + salt.len();
+    let (header, sealed) = data.split_at(header_len);
+
+    let corrupted = || anyhow::anyhow!("The {} is corrupted", what);
+    let sealed = crate::encryption::EncryptedMessage::from_bytes(sealed).map_err(|_| corrupted())?;
+    if sealed.aad.as_deref() != Some(header) {
+        return Err(corrupted());
+    }
+    let key = params.derive_key(passphrase, salt)?;
+    crate::encryption::decrypt_with_key(&sealed, &key)
+        .map(zeroize::Zeroizing::new)
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase, or the {} is corrupted", what))
+}
+
+/// Write `data` to `path`, readable only by the owner on Unix. It goes to a
+/// temporary file that is renamed over `path`, so a crash never leaves a
+/// half-written file.
+pub fn write_private(path: &std::path::Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = std::path::PathBuf::from(tmp);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options.open(&tmp).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| std::fs::rename(&tmp, path)) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
 }
 
 /// A signed message with authentication
@@ -114,24 +291,79 @@ pub struct SignedMessage {
     pub signature: Vec<u8>,
     /// The sender's public key
     pub sender_public_key: Vec<u8>,
-    /// Timestamp for replay protection
+    /// Timestamp for replay protection, covered by the signature
     pub timestamp: u64,
+    /// Position in the sender's stream of broadcasts on a topic, covered by
+    /// the signature; 0 when the message isn't part of one
+    #[serde(default)]
+    pub seq: u64,
 }
 
 impl SignedMessage {
     /// Create a new signed message
     pub fn new(identity: &Identity, content: Vec<u8>) -> Result<Self> {
-        let signature = identity.sign(&content)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        Self::new_at(identity, content, timestamp)
+    }
+
+    /// Create a signed message with the given Unix timestamp (seconds)
+    pub fn new_at(identity: &Identity, content: Vec<u8>, timestamp: u64) -> Result<Self> {
+        Self::new_sequenced_at(identity, content, timestamp, 0)
+    }
+
+    /// Create a signed message that is number `seq` in its sender's stream
+    pub fn new_sequenced(identity: &Identity, content: Vec<u8>, seq: u64) -> Result<Self> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        Self::new_sequenced_at(identity, content, timestamp, seq)
+    }
+
+    /// [`new_sequenced`](Self::new_sequenced) with the given Unix timestamp
+    pub fn new_sequenced_at(identity: &Identity, content: Vec<u8>, timestamp: u64, seq: u64) -> Result<Self> {
+        let signature = identity.sign(&Self::signed_bytes(&content, timestamp, seq))?;
         Ok(Self {
             content,
             signature: signature.to_bytes().to_vec(),
             sender_public_key: identity.public_key().to_vec(),
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)?
-                .as_secs(),
+            timestamp,
+            seq,
         })
     }
 
+    /// What the signature covers: the content, then the timestamp and any
+    /// sequence number (little-endian), so none can be changed in transit.
+    /// Older peers signed the content alone and can't verify these, hence
+    /// the `PROTOCOL_VERSION` bump that came with the timestamp.
+    fn signed_bytes(content: &[u8], timestamp: u64, seq: u64) -> Vec<u8> {
+        let mut data = Vec::with_capacity(content.len() + 16);
+        data.extend_from_slice(content);
+        data.extend_from_slice(&timestamp.to_le_bytes());
+        if seq != 0 {
+            data.extend_from_slice(&seq.to_le_bytes());
+        }
+        data
+    }
+
+    /// [`verify`](Self::verify), and reject a timestamp more than
+    /// `max_skew_secs` from our clock, like `KeyExchangeMessage::verify`
+    pub fn verify_fresh(&self, max_skew_secs: u64) -> Result<()> {
+        self.verify()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let skew = now.abs_diff(self.timestamp);
+        if skew > max_skew_secs {
+            return Err(anyhow::anyhow!(
+                "Message timestamp too old or too far in the future ({}s skew)",
+                skew
+            ));
+        }
+        Ok(())
+    }
+
     /// Verify the message signature
     ///
     /// Reconstructs the sender's public key and verifies the Ed25519 signature.
@@ -159,7 +391,7 @@ impl SignedMessage {
         let signature = Signature::from_bytes(&signature_bytes);
 
         verifying_key
-            .verify_strict(&self.content, &signature)
+            .verify_strict(&Self::signed_bytes(&self.content, self.timestamp, self.seq), &signature)
             .map_err(|e| anyhow::anyhow!("Signature verification failed: {}", e))
     }
 
@@ -183,6 +415,55 @@ pub fn verify_with_key(message: &[u8], signature: &Signature, public_key: &[u8;
         .map_err(|e| anyhow::anyhow!("Signature verification failed: {}", e))
 }
 
+/// The libp2p `PeerId` of the node whose identity is this Ed25519 signing
+/// key. Our libp2p keypair is the signing keypair, so this is how a signed
+/// payload is tied back to the peer that sent it.
+pub fn peer_id_from_signing_key(public_key: &[u8; 32]) -> Result<libp2p::PeerId> {
+    let key = libp2p::identity::ed25519::PublicKey::try_from_bytes(public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+    Ok(libp2p::identity::PublicKey::from(key).to_peer_id())
+}
+
+/// A node's public keys as one blob: signing key, then encryption key
+fn key_blob(signing_public_key: &[u8; 32], encryption_public_key: &[u8; 32]) -> [u8; 64] {
+    let mut blob = [0u8; 64];
+    blob[..32].copy_from_slice(signing_public_key);
+    blob[32..].copy_from_slice(encryption_public_key);
+    blob
+}
+
+/// Show a digest as 60 decimal digits in groups of five, as Signal does:
+/// each 5-byte chunk becomes ten digits
+fn render_digits(digest: &[u8]) -> String {
+    digest
+        .chunks_exact(5)
+        .take(6)
+        .flat_map(|chunk| {
+            let n = chunk.iter().fold(0u64, |n, &b| (n << 8) | b as u64) % 10_000_000_000;
+            let digits = format!("{:010}", n);
+            [digits[..5].to_string(), digits[5..].to_string()]
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fingerprint of one node's public keys, for reading aloud
+pub fn fingerprint_of(signing_public_key: &[u8; 32], encryption_public_key: &[u8; 32]) -> String {
+    use sha2::Digest;
+    render_digits(&sha2::Sha256::digest(key_blob(signing_public_key, encryption_public_key)))
+}
+
+/// Safety number for two nodes' key blobs. They are sorted before hashing,
+/// so both sides compute the same number.
+fn safety_number_of(a: [u8; 64], b: [u8; 64]) -> String {
+    use sha2::Digest;
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(first);
+    hasher.update(second);
+    render_digits(&hasher.finalize())
+}
+
 /// A peer's public information stored locally
 #[derive(Debug, Clone)]
 pub struct PeerInfo {
@@ -194,6 +475,115 @@ pub struct PeerInfo {
     pub first_seen: u64,
     /// Last activity timestamp
     pub last_seen: u64,
+    /// Confirmed out of band with `/verify <peer> confirm`; cleared if the keys change
+    pub verified: bool,
+}
+
+/// A peer remembered across restarts
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Contact {
+    /// Name we gave the peer with `/contact rename`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    /// The peer's signing public key (Ed25519), hex
+    #[serde(with = "hex::serde")]
+    pub signing_public_key: [u8; 32],
+    /// The peer's encryption public key (X25519), hex
+    #[serde(with = "hex::serde")]
+    pub encryption_public_key: [u8; 32],
+    /// Last key exchange, Unix seconds
+    pub last_seen: u64,
+}
+
+/// A peer offered keys that differ from the ones in its contact entry.
+/// They are held back until accepted with
+/// [`CryptoManager::trust_new_keys`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Keys of {peer_id} changed; accept them with /trust")]
+pub struct KeyChanged {
+    pub peer_id: String,
+    /// Fingerprint of the keys we knew
+    pub old_key: String,
+    /// Fingerprint of the keys now offered
+    pub new_key: String,
+}
+
+/// Address book of every peer we exchanged keys with, keyed by peer ID and
+/// saved as JSON after each change. It is also the trust-on-first-use
+/// record: a peer's first keys are accepted, later different ones are not.
+#[derive(Debug, Default)]
+pub struct Contacts {
+    /// Where the book is saved; `None` keeps it in memory only
+    path: Option<std::path::PathBuf>,
+    entries: std::collections::BTreeMap<String, Contact>,
+}
+
+impl Contacts {
+    /// Load the book at `path`, starting empty if the file doesn't exist
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        let entries = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            entries,
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => write_private(path, &serde_json::to_vec_pretty(&self.entries)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Note a key exchange with `peer_id`, keeping any nickname we gave it
+    pub fn record(&mut self, peer_id: &str, signing_public_key: [u8; 32], encryption_public_key: [u8; 32], now: u64) -> Result<()> {
+        let nickname = self.entries.get(peer_id).and_then(|c| c.nickname.clone());
+        self.entries.insert(
+            peer_id.to_string(),
+            Contact {
+                nickname,
+                signing_public_key,
+                encryption_public_key,
+                last_seen: now,
+            },
+        );
+        self.save()
+    }
+
+    /// Name a known peer; an empty name clears it. Returns false if we
+    /// never exchanged keys with the peer.
+    pub fn rename(&mut self, peer_id: &str, nickname: &str) -> Result<bool> {
+        let Some(contact) = self.entries.get_mut(peer_id) else {
+            return Ok(false);
+        };
+        contact.nickname = (!nickname.is_empty()).then(|| nickname.to_string());
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn get(&self, peer_id: &str) -> Option<&Contact> {
+        self.entries.get(peer_id)
+    }
+
+    /// All contacts, ordered by peer ID
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Contact)> {
+        self.entries.iter()
+    }
+}
+
+/// A peer's (signing, encryption) public keys
+type PeerKeys = ([u8; 32], [u8; 32]);
+
+/// Ratchet state with a peer that advertised support
+struct PeerRatchet {
+    /// The run of the peer's client the session belongs to
+    epoch: u64,
+    /// `None` until either side starts a session
+    session: Option<RatchetSession>,
 }
 
 /// Manages cryptographic state including E2E encryption.
@@ -206,6 +596,17 @@ pub struct CryptoManager {
     session_manager: SessionManager,
     /// Known peers and their public keys
     pub known_peers: Arc<RwLock<HashMap<String, PeerInfo>>>,
+    /// Every peer we ever exchanged keys with, kept across restarts
+    pub contacts: Arc<RwLock<Contacts>>,
+    /// Changed keys waiting for `/trust` (peer_id -> signing, encryption)
+    changed_keys: Arc<RwLock<HashMap<String, PeerKeys>>>,
+    /// Compression and padding for direct messages
+    options: crate::encryption::EncryptionOptions,
+    /// Set with `--ratchet`: random per run, so peers can tell when our
+    /// sessions were lost
+    ratchet_epoch: Option<u64>,
+    /// Double ratchet sessions by peer ID, for peers that take them
+    ratchets: Arc<RwLock<HashMap<String, PeerRatchet>>>,
 }
 
 impl CryptoManager {
@@ -217,6 +618,11 @@ impl CryptoManager {
             identity,
             session_manager,
             known_peers: Arc::new(RwLock::new(HashMap::new())),
+            contacts: Arc::default(),
+            changed_keys: Arc::default(),
+            options: Default::default(),
+            ratchet_epoch: None,
+            ratchets: Arc::default(),
         })
     }
 
@@ -227,9 +633,181 @@ impl CryptoManager {
             identity: Arc::new(identity),
             session_manager,
             known_peers: Arc::new(RwLock::new(HashMap::new())),
+            contacts: Arc::default(),
+            changed_keys: Arc::default(),
+            options: Default::default(),
+            ratchet_epoch: None,
+            ratchets: Arc::default(),
+        })
+    }
+
+    /// Create from an existing identity and X25519 encryption secret, so
+    /// both the peer ID and the key peers encrypt to stay the same
+    pub fn from_identity_and_encryption(identity: Identity, enc_secret: [u8; 32]) -> Result<Self> {
+        Ok(Self {
+            identity: Arc::new(identity),
+            session_manager: SessionManager::from_secret_bytes(enc_secret),
+            known_peers: Arc::new(RwLock::new(HashMap::new())),
+            contacts: Arc::default(),
+            changed_keys: Arc::default(),
+            options: Default::default(),
+            ratchet_epoch: None,
+            ratchets: Arc::default(),
         })
     }
 
+    /// Serialize both secret keys for storage: signing key || X25519 secret
+    ///
+    /// # Security
+    /// The returned bytes contain both private keys. Handle with care.
+    pub fn to_key_bytes(&self) -> zeroize::Zeroizing<[u8; 64]> {
+        let mut bytes = zeroize::Zeroizing::new([0u8; 64]);
+        bytes[..32].copy_from_slice(&self.identity.to_bytes());
+        bytes[32..].copy_from_slice(&self.session_manager.secret_bytes());
+        bytes
+    }
+
+    /// Restore from [`to_key_bytes`](Self::to_key_bytes) output. A bare
+    /// 32-byte signing key is accepted too and gets a fresh X25519 key.
+    pub fn from_key_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut signing = [0u8; 32];
+        let manager = match bytes.len() {
+            32 => {
+                signing.copy_from_slice(bytes);
+                Self::from_identity(Identity::from_bytes(signing)?)
+            }
+            64 => {
+                signing.copy_from_slice(&bytes[..32]);
+                let mut enc_secret = [0u8; 32];
+                enc_secret.copy_from_slice(&bytes[32..]);
+                let manager = Self::from_identity_and_encryption(Identity::from_bytes(signing)?, enc_secret);
+                zeroize::Zeroize::zeroize(&mut enc_secret);
+                manager
+            }
+            n => Err(anyhow::anyhow!("Invalid key data: expected 32 or 64 bytes, got {}", n)),
+        };
+        zeroize::Zeroize::zeroize(&mut signing);
+        manager
+    }
+
+    /// Write [`to_key_bytes`](Self::to_key_bytes) to `path`, readable only
+    /// by the owner on Unix. The keys go to a temporary file that is renamed
+    /// over `path`, so a crash never leaves a half-written identity.
+    pub fn save_to(&self, path: &std::path::Path) -> Result<()> {
+        write_private(path, &*self.to_key_bytes())
+    }
+
+    /// Like [`save_to`](Self::save_to), with the keys encrypted under
+    /// `passphrase` (Argon2id and ChaCha20-Poly1305)
+    pub fn save_encrypted(&self, path: &std::path::Path, passphrase: &str) -> Result<()> {
+        seal_key_file(path, &*self.to_key_bytes(), passphrase, &KeyFileParams::default())
+    }
+
+    /// Load keys written by [`save_encrypted`](Self::save_encrypted) or
+    /// [`Identity::save_encrypted`]
+    pub fn load_encrypted(path: &std::path::Path, passphrase: &str) -> Result<Self> {
+        Self::from_key_bytes(&open_key_file(path, passphrase)?)
+    }
+
+    /// Back up both secret keys and `rooms` (with their group keys) into
+    /// one blob encrypted under `passphrase`, for [`import_bundle`](Self::import_bundle)
+    pub fn export_bundle(&self, rooms: Vec<crate::room::Room>, passphrase: &str) -> Result<Vec<u8>> {
+        let bundle = Bundle {
+            keys: self.to_key_bytes().to_vec(),
+            rooms,
+        };
+        let json = zeroize::Zeroizing::new(serde_json::to_vec(&bundle)?);
+        seal_keys(BUNDLE_MAGIC, &json, passphrase, &KeyFileParams::default())
+    }
+
+    /// Restore the keys and rooms of an [`export_bundle`](Self::export_bundle) backup
+    pub fn import_bundle(data: &[u8], passphrase: &str) -> Result<(Self, Vec<crate::room::Room>)> {
+        let json = open_keys(BUNDLE_MAGIC, data, passphrase, "backup")?;
+        let mut bundle: Bundle = serde_json::from_slice(&json)
+            .map_err(|e| anyhow::anyhow!("The backup is corrupted: {}", e))?;
+        let manager = Self::from_key_bytes(&bundle.keys)?;
+        Ok((manager, std::mem::take(&mut bundle.rooms)))
+    }
+
+    /// Load an identity written by [`save_to`](Self::save_to)
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
+        let bytes = zeroize::Zeroizing::new(std::fs::read(path)?);
+        Self::from_key_bytes(&bytes)
+    }
+
+    /// Load the identity at `path`, or create and save a new one there, so
+    /// the peer ID survives restarts. A file holding only a signing key is
+    /// rewritten with the encryption key too.
+    pub fn load_or_create(path: &std::path::Path) -> Result<Self> {
+        if is_encrypted_key_file(path) {
+            return Err(anyhow::anyhow!(
+                "The identity is passphrase-protected; start with --encrypted-identity"
+            ));
+        }
+        match std::fs::read(path).map(zeroize::Zeroizing::new) {
+            Ok(bytes) => {
+                let manager = Self::from_key_bytes(&bytes)?;
+                if bytes.len() == 32 {
+                    manager.save_to(path)?;
+                }
+                Ok(manager)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let manager = Self::new()?;
+                manager.save_to(path)?;
+                Ok(manager)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Choose the AEAD for messages we encrypt (`--cipher`)
+    pub fn set_cipher(&mut self, suite: crate::encryption::CipherSuite) {
+        self.session_manager.set_cipher(suite);
+    }
+
+    /// Compress direct messages before encrypting them (`--compress`)
+    pub fn set_compression(&mut self, compress: bool) {
+        self.options.compress = compress;
+    }
+
+    /// Pad direct messages to power-of-two sizes before encrypting them (`--pad`)
+    pub fn set_padding(&mut self, pad: bool) {
+        self.options.pad = pad;
+    }
+
+    /// Use a double ratchet for direct messages to peers that support it (`--ratchet`)
+    pub fn set_ratchet(&mut self, enabled: bool) {
+        self.ratchet_epoch = enabled.then(|| {
+            use rand::TryRng;
+            let mut epoch = [0u8; 8];
+            rand::rng()
+                .try_fill_bytes(&mut epoch)
+                .expect("Failed to generate ratchet epoch");
+            u64::from_le_bytes(epoch)
+        });
+    }
+
+    /// What our key exchange advertises: `None` without `--ratchet`
+    pub fn ratchet_epoch(&self) -> Option<u64> {
+        self.ratchet_epoch
+    }
+
+    /// Record the ratchet epoch a peer's key exchange advertised. A new
+    /// epoch means the peer restarted, so any session with it starts over.
+    pub async fn set_peer_ratchet(&self, peer_id: &str, epoch: Option<u64>) {
+        let mut ratchets = self.ratchets.write().await;
+        match epoch.filter(|_| self.ratchet_epoch.is_some()) {
+            Some(epoch) if ratchets.get(peer_id).is_some_and(|r| r.epoch == epoch) => {}
+            Some(epoch) => {
+                ratchets.insert(peer_id.to_string(), PeerRatchet { epoch, session: None });
+            }
+            None => {
+                ratchets.remove(peer_id);
+            }
+        }
+    }
+
     /// Get a reference to the identity
     pub fn identity(&self) -> &Identity {
         &self.identity
@@ -245,6 +823,14 @@ impl CryptoManager {
         self.session_manager.public_key_bytes()
     }
 
+    /// Get the X25519 secret that room invites are decrypted with
+    ///
+    /// # Security
+    /// The caller is responsible for securely handling the returned bytes.
+    pub fn encryption_secret_bytes(&self) -> [u8; 32] {
+        self.session_manager.secret_bytes()
+    }
+
     /// Get signing public key bytes
     pub fn signing_public_key(&self) -> [u8; 32] {
         self.identity.public_key_bytes()
@@ -266,30 +852,130 @@ impl CryptoManager {
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs();
 
-        let peer_info = PeerInfo {
-            signing_public_key,
-            encryption_public_key,
-            first_seen: now,
-            last_seen: now,
-        };
+        // Trust on first use: keys that differ from the recorded ones wait
+        // for the user
+        if let Some(contact) = self.contacts.read().await.get(&peer_id)
+            && (contact.signing_public_key, contact.encryption_public_key)
+                != (signing_public_key, encryption_public_key)
+        {
+            self.changed_keys
+                .write()
+                .await
+                .insert(peer_id.clone(), (signing_public_key, encryption_public_key));
+            return Err(KeyChanged {
+                old_key: fingerprint_of(&contact.signing_public_key, &contact.encryption_public_key),
+                new_key: fingerprint_of(&signing_public_key, &encryption_public_key),
+                peer_id,
+            }
+            .into());
+        }
 
         // Establish E2E session with this peer
         self.session_manager
             .establish_session(&encryption_public_key)?;
 
-        // Store peer info
+        // Store peer info, keeping the verification only if the keys are unchanged
         let mut peers = self.known_peers.write().await;
-        peers.insert(peer_id, peer_info);
+        let previous = peers.get(&peer_id);
+        if let Some(old) = previous.filter(|p| p.encryption_public_key != encryption_public_key) {
+            self.session_manager.forget_session(&old.encryption_public_key);
+        }
+        let peer_info = PeerInfo {
+            signing_public_key,
+            encryption_public_key,
+            first_seen: previous.map_or(now, |p| p.first_seen),
+            last_seen: now,
+            verified: previous.is_some_and(|p| {
+                p.verified
+                    && p.signing_public_key == signing_public_key
+                    && p.encryption_public_key == encryption_public_key
+            }),
+        };
+        peers.insert(peer_id.clone(), peer_info);
+        drop(peers);
 
+        if let Err(e) = self
+            .contacts
+            .write()
+            .await
+            .record(&peer_id, signing_public_key, encryption_public_key, now)
+        {
+            tracing::warn!("Couldn't save contact {}: {}", peer_id, e);
+        }
         Ok(())
     }
 
+    /// Accept the changed keys a peer offered last. Returns false if none
+    /// are waiting.
+    pub async fn trust_new_keys(&self, peer_id: &str) -> Result<bool> {
+        let Some((signing, encryption)) = self.changed_keys.write().await.remove(peer_id) else {
+            return Ok(false);
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        self.contacts.write().await.record(peer_id, signing, encryption, now)?;
+        self.register_peer(peer_id.to_string(), signing, encryption).await?;
+        if let Some(ratchet) = self.ratchets.write().await.get_mut(peer_id) {
+            ratchet.session = None;
+        }
+        Ok(true)
+    }
+
+    /// Keep contacts in `contacts` (loaded from disk) from now on
+    pub fn set_contacts(&mut self, contacts: Contacts) {
+        self.contacts = Arc::new(RwLock::new(contacts));
+    }
+
     /// Get a peer's info
     pub async fn get_peer(&self, peer_id: &str) -> Option<PeerInfo> {
         let peers = self.known_peers.read().await;
         peers.get(peer_id).cloned()
     }
 
+    /// Drop a peer's keys. Nothing can be encrypted to it until its next
+    /// key exchange registers it again. Returns false if it wasn't known.
+    pub async fn forget_peer(&self, peer_id: &str) -> bool {
+        self.ratchets.write().await.remove(peer_id);
+        let Some(peer) = self.known_peers.write().await.remove(peer_id) else {
+            return false;
+        };
+        self.session_manager.forget_session(&peer.encryption_public_key);
+        true
+    }
+
+    /// Mark a known peer as verified. Returns false if we hold no keys for it
+    pub async fn set_verified(&self, peer_id: &str) -> bool {
+        match self.known_peers.write().await.get_mut(peer_id) {
+            Some(peer) => {
+                peer.verified = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the peer has been verified with its current keys
+    pub async fn is_verified(&self, peer_id: &str) -> bool {
+        self.known_peers.read().await.get(peer_id).is_some_and(|p| p.verified)
+    }
+
+    /// Fingerprint of our signing and encryption public keys
+    pub fn fingerprint(&self) -> String {
+        fingerprint_of(&self.signing_public_key(), &self.encryption_public_key())
+    }
+
+    /// The number to compare with a peer out of band: both sides get the
+    /// same one if neither set of keys was swapped in transit. `None` until
+    /// the peer's keys arrive.
+    pub async fn safety_number(&self, peer_id: &str) -> Option<String> {
+        let peer = self.get_peer(peer_id).await?;
+        Some(safety_number_of(
+            key_blob(&self.signing_public_key(), &self.encryption_public_key()),
+            key_blob(&peer.signing_public_key, &peer.encryption_public_key),
+        ))
+    }
+
     /// Sign a message
     pub fn sign(&self, message: &[u8]) -> Result<Signature> {
         self.identity.sign(message)
@@ -312,7 +998,7 @@ impl CryptoManager {
         plaintext: &[u8],
     ) -> Result<crate::encryption::EncryptedMessage> {
         self.session_manager
-            .encrypt_for_peer(peer_encryption_key, plaintext, None)
+            .encrypt_for_peer(peer_encryption_key, plaintext, None, self.options)
     }
 
     /// Decrypt a message from a peer
@@ -340,11 +1026,64 @@ impl CryptoManager {
         let signed = SignedMessage::new(&self.identity, plaintext.to_vec())?;
 
         // Encrypt the signed message
-        let encrypted = self.encrypt_for_peer(&peer.encryption_public_key, &signed.to_bytes()?)?;
+        let signed = signed.to_bytes()?;
+        if let Some(message) = self.ratchet_encrypt(peer_id, &peer, &signed).await? {
+            return message.to_bytes();
+        }
+        let encrypted = self.encrypt_for_peer(&peer.encryption_public_key, &signed)?;
 
         encrypted.to_bytes()
     }
 
+    /// Seal with the peer's ratchet session, starting one if it's ours to
+    /// start. `None` means the peer gets the plain scheme: it doesn't take
+    /// ratchet messages, or it starts the session and hasn't yet.
+    async fn ratchet_encrypt(
+        &self,
+        peer_id: &str,
+        peer: &PeerInfo,
+        plaintext: &[u8],
+    ) -> Result<Option<RatchetMessage>> {
+        let mut ratchets = self.ratchets.write().await;
+        let (Some(ours), Some(ratchet)) = (self.ratchet_epoch, ratchets.get_mut(peer_id)) else {
+            return Ok(None);
+        };
+        if ratchet.session.is_none() {
+            let epochs = RatchetEpochs { ours, theirs: ratchet.epoch };
+            ratchet.session = self.session_manager.start_ratchet(&peer.encryption_public_key, epochs)?;
+        }
+        match ratchet.session.as_mut() {
+            Some(session) if session.can_send() => session.encrypt(plaintext, self.options).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Open a ratchet message, joining the session it belongs to if the
+    /// peer just started it
+    async fn ratchet_decrypt(&self, peer_id: &str, peer: &PeerInfo, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(ours) = self.ratchet_epoch else {
+            return Err(anyhow::anyhow!("Got a ratchet message without --ratchet"));
+        };
+        let message = RatchetMessage::from_bytes(data)?;
+        let mut ratchets = self.ratchets.write().await;
+        let ratchet = ratchets
+            .get_mut(peer_id)
+            .ok_or_else(|| anyhow::anyhow!("Got a ratchet message from a peer that didn't advertise one"))?;
+        // Sessions only end when either side restarts, which clears them
+        // through the key exchange; any other session id is a replay
+        if let Some(session) = ratchet.session.as_mut() {
+            return session.decrypt(&message);
+        }
+
+        let epochs = RatchetEpochs { ours, theirs: ratchet.epoch };
+        let mut session = self
+            .session_manager
+            .join_ratchet(&peer.encryption_public_key, message.header.session, epochs)?;
+        let plaintext = session.decrypt(&message)?;
+        ratchet.session = Some(session);
+        Ok(plaintext)
+    }
+
     /// Decrypt and verify a message from a peer
     pub async fn decrypt_and_verify_message(
         &self,
@@ -356,11 +1095,15 @@ impl CryptoManager {
             .await
             .ok_or_else(|| anyhow::anyhow!("Peer not found: {}", peer_id))?;
 
-        let encrypted = crate::encryption::EncryptedMessage::from_bytes(encrypted_bytes)?;
-        let decrypted = self.decrypt_from_peer(&encrypted, &peer.encryption_public_key)?;
+        let decrypted = if encrypted_bytes.starts_with(RATCHET_PREFIX) {
+            self.ratchet_decrypt(peer_id, &peer, encrypted_bytes).await?
+        } else {
+            let encrypted = crate::encryption::EncryptedMessage::from_bytes(encrypted_bytes)?;
+            self.decrypt_from_peer(&encrypted, &peer.encryption_public_key)?
+        };
 
         let signed = SignedMessage::from_bytes(&decrypted)?;
-        signed.verify()?;
+        signed.verify_fresh(MAX_TIMESTAMP_SKEW)?;
 
         // Verify the sender's public key matches the registered peer
         if signed.sender_public_key != peer.signing_public_key.to_vec() {
@@ -377,6 +1120,188 @@ impl CryptoManager {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_peer_id_from_signing_key_matches_keypair_bridge() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let public = signing_key.verifying_key().to_bytes();
+
+        // The seed || public bridge used to build the libp2p keypair
+        let mut keypair_bytes = [0u8; 64];
+        keypair_bytes[..32].copy_from_slice(&signing_key.to_bytes());
+        keypair_bytes[32..].copy_from_slice(&public);
+        let keypair = libp2p::identity::ed25519::Keypair::try_from_bytes(&mut keypair_bytes).unwrap();
+        let bridged = libp2p::PeerId::from(libp2p::identity::Keypair::from(keypair).public());
+
+        assert_eq!(peer_id_from_signing_key(&public).unwrap(), bridged);
+        assert_ne!(
+            peer_id_from_signing_key(&SigningKey::from_bytes(&[8u8; 32]).verifying_key().to_bytes()).unwrap(),
+            bridged
+        );
+    }
+
+    #[test]
+    fn test_reloaded_crypto_manager_keeps_encryption_key() {
+        let original = CryptoManager::new().unwrap();
+        let stored = original.to_key_bytes();
+
+        let reloaded = CryptoManager::from_key_bytes(&stored[..]).unwrap();
+        assert_eq!(reloaded.peer_id(), original.peer_id());
+        assert_eq!(reloaded.encryption_public_key(), original.encryption_public_key());
+
+        // A signing key alone keeps the peer ID but not the encryption key
+        let legacy = CryptoManager::from_key_bytes(&stored[..32]).unwrap();
+        assert_eq!(legacy.peer_id(), original.peer_id());
+        assert_ne!(legacy.encryption_public_key(), original.encryption_public_key());
+
+        assert!(CryptoManager::from_key_bytes(&stored[..40]).is_err());
+    }
+
+    #[test]
+    fn test_identity_persists_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("openwire-identity-{}", std::process::id()));
+        let path = dir.join("keys").join("identity.key");
+
+        let first = CryptoManager::load_or_create(&path).unwrap();
+        let second = CryptoManager::load_or_create(&path).unwrap();
+        assert_eq!(second.peer_id(), first.peer_id());
+        assert_eq!(second.encryption_public_key(), first.encryption_public_key());
+        assert!(!dir.join("keys").join("identity.key.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // A signing-key-only file is upgraded so the encryption key sticks
+        std::fs::write(&path, first.signing_key_bytes()).unwrap();
+        let upgraded = CryptoManager::load_or_create(&path).unwrap();
+        assert_eq!(upgraded.peer_id(), first.peer_id());
+        assert_eq!(std::fs::read(&path).unwrap().len(), 64);
+        let again = CryptoManager::load_or_create(&path).unwrap();
+        assert_eq!(again.encryption_public_key(), upgraded.encryption_public_key());
+
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(CryptoManager::load_or_create(&path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_identity_needs_its_passphrase() {
+        let dir = std::env::temp_dir().join(format!("openwire-identity-enc-{}", std::process::id()));
+        let path = dir.join("identity.key");
+        // Cheap parameters keep the test fast; they're read back from the header
+        let params = KeyFileParams { memory_kib: 64, iterations: 1, lanes: 1 };
+        let original = CryptoManager::new().unwrap();
+        seal_key_file(&path, &*original.to_key_bytes(), "correct horse", &params).unwrap();
+
+        assert!(is_encrypted_key_file(&path));
+        assert!(!std::fs::read(&path).unwrap().windows(32).any(|w| w == original.signing_key_bytes()));
+        let loaded = CryptoManager::load_encrypted(&path, "correct horse").unwrap();
+        assert_eq!(loaded.peer_id(), original.peer_id());
+        assert_eq!(loaded.encryption_public_key(), original.encryption_public_key());
+
+        let wrong = CryptoManager::load_encrypted(&path, "battery staple").err().unwrap();
+        assert!(wrong.to_string().contains("Wrong passphrase"), "{}", wrong);
+        let plain = CryptoManager::load_or_create(&path).err().unwrap();
+        assert!(plain.to_string().contains("--encrypted-identity"), "{}", plain);
+
+        // The header is authenticated: weakening the stored cost breaks it
+        let mut data = std::fs::read(&path).unwrap();
+        data[KEY_FILE_MAGIC.len() + 1] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+        assert!(CryptoManager::load_encrypted(&path, "correct horse").is_err());
+
+        // A bare signing seed round-trips through Identity
+        seal_key_file(&path, &original.signing_key_bytes(), "pw", &params).unwrap();
+        let identity = Identity::load_encrypted(&path, "pw").unwrap();
+        assert_eq!(identity.peer_id(), original.peer_id());
+        assert!(seal_key_file(&path, b"x", "", &params).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_restores_keys_and_rooms() {
+        let original = CryptoManager::new().unwrap();
+        let room = crate::room::Room::new("Backed up".into()).unwrap();
+        let bundle = original.export_bundle(vec![room.clone()], "correct horse").unwrap();
+        assert!(bundle.starts_with(BUNDLE_MAGIC));
+        assert!(!bundle.windows(32).any(|w| w == room.group_key.as_bytes()));
+
+        let (restored, rooms) = CryptoManager::import_bundle(&bundle, "correct horse").unwrap();
+        assert_eq!(restored.peer_id(), original.peer_id());
+        assert_eq!(restored.encryption_public_key(), original.encryption_public_key());
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].id, room.id);
+        assert_eq!(rooms[0].group_key.as_bytes(), room.group_key.as_bytes());
+
+        let wrong = CryptoManager::import_bundle(&bundle, "battery staple").err().unwrap();
+        assert!(wrong.to_string().contains("Wrong passphrase"), "{}", wrong);
+        // A key file isn't a backup
+        let key_file = seal_keys(KEY_FILE_MAGIC, &*original.to_key_bytes(), "pw", &KeyFileParams::default()).unwrap();
+        assert!(CryptoManager::import_bundle(&key_file, "pw").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_safety_number_matches_on_both_sides() {
+        let alice = CryptoManager::new().unwrap();
+        let bob = CryptoManager::new().unwrap();
+        let mallory = CryptoManager::new().unwrap();
+        assert_eq!(alice.safety_number("bob").await, None);
+
+        for (manager, name, other) in [(&alice, "bob", &bob), (&bob, "alice", &alice)] {
+            manager
+                .register_peer(name.to_string(), other.signing_public_key(), other.encryption_public_key())
+                .await
+                .unwrap();
+        }
+        let number = alice.safety_number("bob").await.unwrap();
+        assert_eq!(bob.safety_number("alice").await.unwrap(), number);
+        let groups: Vec<&str> = number.split(' ').collect();
+        assert_eq!(groups.len(), 12);
+        assert!(groups.iter().all(|g| g.len() == 5 && g.bytes().all(|b| b.is_ascii_digit())));
+
+        // Keys swapped by a man in the middle give a different number
+        let _ = alice
+            .register_peer("bob".to_string(), mallory.signing_public_key(), mallory.encryption_public_key())
+            .await;
+        assert!(alice.trust_new_keys("bob").await.unwrap());
+        assert_ne!(alice.safety_number("bob").await.unwrap(), number);
+        assert_ne!(alice.fingerprint(), bob.fingerprint());
+        assert_eq!(alice.fingerprint().len(), 71);
+    }
+
+    #[tokio::test]
+    async fn test_contacts_keep_names_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("openwire-contacts-{}", std::process::id()));
+        let path = dir.join("contacts.json");
+        let peer = CryptoManager::new().unwrap();
+
+        let mut manager = CryptoManager::new().unwrap();
+        manager.set_contacts(Contacts::load(&path).unwrap());
+        manager
+            .register_peer("peer-a".into(), peer.signing_public_key(), peer.encryption_public_key())
+            .await
+            .unwrap();
+        assert!(!manager.contacts.write().await.rename("peer-b", "Bob").unwrap());
+        assert!(manager.contacts.write().await.rename("peer-a", "Alice").unwrap());
+
+        // A later key exchange updates the keys but keeps the name
+        let mut restarted = CryptoManager::new().unwrap();
+        restarted.set_contacts(Contacts::load(&path).unwrap());
+        let rotated = CryptoManager::new().unwrap();
+        let _ = restarted
+            .register_peer("peer-a".into(), peer.signing_public_key(), rotated.encryption_public_key())
+            .await;
+        assert!(restarted.trust_new_keys("peer-a").await.unwrap());
+        let reloaded = Contacts::load(&path).unwrap();
+        let contact = reloaded.get("peer-a").unwrap();
+        assert_eq!(contact.nickname.as_deref(), Some("Alice"));
+        assert_eq!(contact.signing_public_key, peer.signing_public_key());
+        assert_eq!(contact.encryption_public_key, rotated.encryption_public_key());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[test]
     fn test_identity_generation() {
         let identity = Identity::generate().unwrap();
@@ -422,6 +1347,44 @@ mod tests {
         let mut tampered = signed.clone();
         tampered.content[0] ^= 0xFF;
         assert!(tampered.verify().is_err());
+
+        // So should a refreshed timestamp on a recorded message
+        let mut refreshed = signed.clone();
+        refreshed.timestamp += 600;
+        assert!(refreshed.verify().is_err());
+
+        // And a renumbered one, in either direction
+        let sequenced = SignedMessage::new_sequenced(&identity, b"numbered".to_vec(), 7).unwrap();
+        assert!(sequenced.verify().is_ok());
+        let mut renumbered = sequenced.clone();
+        renumbered.seq = 8;
+        assert!(renumbered.verify().is_err());
+        let mut unnumbered = sequenced;
+        unnumbered.seq = 0;
+        assert!(unnumbered.verify().is_err());
+        let mut numbered = signed;
+        numbered.seq = 1;
+        assert!(numbered.verify().is_err());
+    }
+
+    #[test]
+    fn test_stale_signed_message_is_rejected() {
+        let identity = Identity::generate().unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let fresh = SignedMessage::new(&identity, b"hi".to_vec()).unwrap();
+        assert!(fresh.verify_fresh(60).is_ok());
+
+        for timestamp in [now - 3600, now + 3600] {
+            let msg = SignedMessage::new_at(&identity, b"hi".to_vec(), timestamp).unwrap();
+            // Validly signed, just not fresh
+            assert!(msg.verify().is_ok());
+            let err = msg.verify_fresh(60).unwrap_err();
+            assert!(err.to_string().contains("too old"), "{}", err);
+        }
     }
 
     #[test]
@@ -473,6 +1436,137 @@ mod tests {
             .unwrap();
 
         assert_eq!(plaintext.to_vec(), decrypted);
+
+        // A stale signed message is refused even when it decrypts
+        let old = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - MAX_TIMESTAMP_SKEW
+            - 30;
+        let stale = SignedMessage::new_at(&alice.identity, plaintext.to_vec(), old).unwrap();
+        let stale = alice
+            .encrypt_for_peer(&bob.encryption_public_key(), &stale.to_bytes().unwrap())
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        assert!(bob.decrypt_and_verify_message(&stale, &alice.peer_id()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ratchet_direct_messages() {
+        let (mut a, mut b) = (CryptoManager::new().unwrap(), CryptoManager::new().unwrap());
+        a.set_ratchet(true);
+        b.set_ratchet(true);
+        let (initiator, mut responder) =
+            if a.encryption_public_key() < b.encryption_public_key() { (a, b) } else { (b, a) };
+        for (us, them) in [(&initiator, &responder), (&responder, &initiator)] {
+            us.register_peer(them.peer_id(), them.signing_public_key(), them.encryption_public_key())
+                .await
+                .unwrap();
+            us.set_peer_ratchet(&them.peer_id(), them.ratchet_epoch()).await;
+        }
+        let send = async |from: &CryptoManager, to: &CryptoManager, text: &[u8]| {
+            let bytes = from.create_encrypted_signed_message(text, &to.peer_id()).await.unwrap();
+            assert_eq!(to.decrypt_and_verify_message(&bytes, &from.peer_id()).await.unwrap(), text);
+            bytes.starts_with(RATCHET_PREFIX)
+        };
+
+        // The responder falls back until the initiator's first message arrives
+        assert!(!send(&responder, &initiator, b"early").await);
+        let first = initiator.create_encrypted_signed_message(b"first", &responder.peer_id()).await.unwrap();
+        assert!(first.starts_with(RATCHET_PREFIX));
+        assert_eq!(responder.decrypt_and_verify_message(&first, &initiator.peer_id()).await.unwrap(), b"first");
+        assert!(send(&responder, &initiator, b"reply").await);
+        assert!(send(&initiator, &responder, b"again").await);
+
+        // A repeated key exchange keeps the session
+        responder.set_peer_ratchet(&initiator.peer_id(), initiator.ratchet_epoch()).await;
+        assert!(send(&initiator, &responder, b"same session").await);
+
+        // A restart starts a new session, which the old one's first message
+        // can't replace or be replayed into
+        responder.set_ratchet(true);
+        responder.ratchets.write().await.clear();
+        initiator.set_peer_ratchet(&responder.peer_id(), responder.ratchet_epoch()).await;
+        responder.set_peer_ratchet(&initiator.peer_id(), initiator.ratchet_epoch()).await;
+        assert!(responder.decrypt_and_verify_message(&first, &initiator.peer_id()).await.is_err());
+        assert!(send(&initiator, &responder, b"new session").await);
+        assert!(responder.decrypt_and_verify_message(&first, &initiator.peer_id()).await.is_err());
+        assert!(send(&responder, &initiator, b"still ratcheted").await);
+        assert!(send(&initiator, &responder, b"live session kept").await);
+
+        // Peers that don't advertise it get the regular scheme
+        initiator.set_peer_ratchet(&responder.peer_id(), None).await;
+        assert!(!send(&initiator, &responder, b"plain").await);
+    }
+
+    #[tokio::test]
+    async fn test_forgotten_peer_needs_new_key_exchange() {
+        let alice = CryptoManager::new().unwrap();
+        let bob = CryptoManager::new().unwrap();
+        let register = || {
+            alice.register_peer(bob.peer_id(), bob.signing_public_key(), bob.encryption_public_key())
+        };
+        register().await.unwrap();
+        assert!(alice.create_encrypted_signed_message(b"hi", &bob.peer_id()).await.is_ok());
+
+        assert!(alice.forget_peer(&bob.peer_id()).await);
+        assert!(!alice.forget_peer(&bob.peer_id()).await);
+        assert!(alice.create_encrypted_signed_message(b"hi", &bob.peer_id()).await.is_err());
+
+        register().await.unwrap();
+        assert!(alice.create_encrypted_signed_message(b"hi", &bob.peer_id()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verification_cleared_when_keys_change() {
+        let alice = CryptoManager::new().unwrap();
+        let bob = CryptoManager::new().unwrap();
+        assert!(!alice.set_verified(&bob.peer_id()).await);
+
+        alice
+            .register_peer(bob.peer_id(), bob.signing_public_key(), bob.encryption_public_key())
+            .await
+            .unwrap();
+        assert!(alice.set_verified(&bob.peer_id()).await);
+        alice
+            .register_peer(bob.peer_id(), bob.signing_public_key(), bob.encryption_public_key())
+            .await
+            .unwrap();
+        assert!(alice.is_verified(&bob.peer_id()).await);
+
+        let other = CryptoManager::new().unwrap();
+        let _ = alice
+            .register_peer(bob.peer_id(), bob.signing_public_key(), other.encryption_public_key())
+            .await;
+        assert!(alice.trust_new_keys(&bob.peer_id()).await.unwrap());
+        assert!(!alice.is_verified(&bob.peer_id()).await);
+    }
+
+    #[tokio::test]
+    async fn test_changed_keys_wait_for_trust() {
+        let alice = CryptoManager::new().unwrap();
+        let bob = CryptoManager::new().unwrap();
+        let imposter = CryptoManager::new().unwrap();
+        alice
+            .register_peer(bob.peer_id(), bob.signing_public_key(), bob.encryption_public_key())
+            .await
+            .unwrap();
+
+        let err = alice
+            .register_peer(bob.peer_id(), imposter.signing_public_key(), imposter.encryption_public_key())
+            .await
+            .unwrap_err();
+        let changed = err.downcast::<KeyChanged>().unwrap();
+        assert_eq!(changed.old_key, bob.fingerprint());
+        assert_eq!(changed.new_key, imposter.fingerprint());
+        // The known keys stay in place
+        assert_eq!(alice.get_peer(&bob.peer_id()).await.unwrap().signing_public_key, bob.signing_public_key());
+
+        assert!(alice.trust_new_keys(&bob.peer_id()).await.unwrap());
+        assert_eq!(alice.get_peer(&bob.peer_id()).await.unwrap().signing_public_key, imposter.signing_public_key());
+        assert!(!alice.trust_new_keys(&bob.peer_id()).await.unwrap());
     }
 
     #[test]
@@ -2,13 +2,15 @@
 //!
 //! Provides application-layer encryption using:
 //! - X25519 for key exchange (Diffie-Hellman)
-//! - ChaCha20-Poly1305 for authenticated encryption (AEAD)
+//! - ChaCha20-Poly1305 (default) or AES-256-GCM for authenticated encryption (AEAD)
 //! - HKDF for key derivation with proper salt
+//! - An optional double ratchet for direct messages ([`RatchetSession`])
 //!
 //! This ensures messages are encrypted end-to-end, not just at the transport layer.
 
 #![allow(dead_code)] // Some functions are for future use or testing
 
+use aes_gcm::Aes256Gcm;
 use anyhow::Result;
 use chacha20poly1305::{
     ChaCha20Poly1305, Nonce,
@@ -17,10 +19,11 @@ use chacha20poly1305::{
 use hkdf::Hkdf;
 use rand::TryRng;
 use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
 use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
 
-/// Nonce size for ChaCha20-Poly1305 (12 bytes)
+/// Nonce size for both AEAD suites (12 bytes)
 pub const NONCE_SIZE: usize = 12;
 
 /// Key size for X25519 and ChaCha20-Poly1305 (32 bytes)
@@ -29,6 +32,179 @@ pub const KEY_SIZE: usize = 32;
 /// Salt size for HKDF (32 bytes)
 pub const SALT_SIZE: usize = 32;
 
+/// Added to the AAD of compressed messages, so the `compressed` flag is
+/// authenticated and can't be flipped in transit
+const COMPRESSED_AAD: &[u8] = b"openwire-deflate-v1";
+
+/// Added to the AAD of padded messages, for the same reason
+const PADDED_AAD: &[u8] = b"openwire-pad-v1";
+
+/// Smallest padded size; shorter messages all look alike
+pub const MIN_PAD_BUCKET: usize = 64;
+
+/// Size of the length header [`pad`] puts in front of the plaintext
+const PAD_HEADER_SIZE: usize = 4;
+
+/// How [`SessionManager::encrypt_for_peer`] prepares the plaintext
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncryptionOptions {
+    /// Deflate the plaintext when that makes it smaller (`--compress`)
+    pub compress: bool,
+    /// Pad the plaintext to a power-of-two size, so the ciphertext only
+    /// reveals which bucket the length falls in (`--pad`)
+    pub pad: bool,
+}
+
+/// Prefix `data` with its length (u32 LE) and fill with zeros up to the
+/// next power of two, at least [`MIN_PAD_BUCKET`]
+pub fn pad(data: &[u8]) -> Result<Vec<u8>> {
+    let len = u32::try_from(data.len()).map_err(|_| anyhow::anyhow!("Message too long to pad"))?;
+    let bucket = (data.len() + PAD_HEADER_SIZE).next_power_of_two().max(MIN_PAD_BUCKET);
+    let mut padded = Vec::with_capacity(bucket);
+    padded.extend_from_slice(&len.to_le_bytes());
+    padded.extend_from_slice(data);
+    padded.resize(bucket, 0);
+    Ok(padded)
+}
+
+/// Undo [`pad`]
+pub fn unpad(mut padded: Vec<u8>) -> Result<Vec<u8>> {
+    let header = padded
+        .get(..PAD_HEADER_SIZE)
+        .ok_or_else(|| anyhow::anyhow!("Padded message is truncated"))?;
+    let len = u32::from_le_bytes(header.try_into()?) as usize;
+    if len > padded.len() - PAD_HEADER_SIZE {
+        return Err(anyhow::anyhow!("Padded message is truncated"));
+    }
+    padded.truncate(PAD_HEADER_SIZE + len);
+    padded.drain(..PAD_HEADER_SIZE);
+    Ok(padded)
+}
+
+/// Largest payload a compressed message may inflate to
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1_048_576;
+
+/// Deflate `data`, or `None` if that doesn't make it smaller
+pub fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).ok()?;
+    let compressed = encoder.finish().ok()?;
+    (compressed.len() < data.len()).then_some(compressed)
+}
+
+/// Inflate what [`compress`] produced, refusing anything that grows past
+/// [`MAX_DECOMPRESSED_SIZE`]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Read;
+    let mut inflated = Vec::new();
+    flate2::read::DeflateDecoder::new(data)
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| anyhow::anyhow!("Decompression failed: {}", e))?;
+    if inflated.len() > MAX_DECOMPRESSED_SIZE {
+        return Err(anyhow::anyhow!("Compressed message inflates past {} bytes", MAX_DECOMPRESSED_SIZE));
+    }
+    Ok(inflated)
+}
+
+/// The AAD a message is sealed with: the caller's, plus [`COMPRESSED_AAD`]
+/// and [`PADDED_AAD`] for the transformations applied to the payload
+pub fn sealed_aad(aad: Option<&[u8]>, compressed: bool, padded: bool) -> Vec<u8> {
+    let mut full = aad.unwrap_or_default().to_vec();
+    if compressed {
+        full.extend_from_slice(COMPRESSED_AAD);
+    }
+    if padded {
+        full.extend_from_slice(PADDED_AAD);
+    }
+    full
+}
+
+/// The AEAD an `EncryptedMessage` is sealed with. The suite is carried in
+/// the message, so peers decrypt whatever they receive regardless of their
+/// own `--cipher`; peers older than this field only read ChaCha20-Poly1305.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CipherSuite {
+    /// ChaCha20-Poly1305: fast everywhere, constant-time without hardware support
+    #[default]
+    ChaCha20Poly1305,
+    /// AES-256-GCM: faster on CPUs with AES-NI / ARMv8 crypto extensions
+    Aes256Gcm,
+}
+
+impl CipherSuite {
+    /// Suite byte stored in `EncryptedMessage::suite`
+    pub fn id(self) -> u8 {
+        match self {
+            Self::ChaCha20Poly1305 => 0,
+            Self::Aes256Gcm => 1,
+        }
+    }
+
+    /// Suite for a suite byte
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Self::ChaCha20Poly1305),
+            1 => Ok(Self::Aes256Gcm),
+            _ => Err(anyhow::anyhow!("Unsupported cipher suite {}", id)),
+        }
+    }
+
+    /// HKDF info for E2E keys. Each suite derives its own key, so a
+    /// message relabelled with another suite can't be opened.
+    fn kdf_info(self) -> &'static [u8] {
+        match self {
+            Self::ChaCha20Poly1305 => b"openwire-e2e-v1",
+            Self::Aes256Gcm => b"openwire-e2e-aes256gcm-v1",
+        }
+    }
+
+    /// Encrypt `payload` under `key`
+    fn seal(self, key: &[u8; KEY_SIZE], nonce: &EncryptionNonce, payload: Payload) -> Result<Vec<u8>> {
+        let nonce = Nonce::from_slice(nonce.as_bytes());
+        match self {
+            Self::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into()).encrypt(nonce, payload),
+            Self::Aes256Gcm => Aes256Gcm::new(key.into()).encrypt(nonce, payload),
+        }
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))
+    }
+
+    /// Decrypt and authenticate `payload` under `key`
+    fn open(self, key: &[u8; KEY_SIZE], nonce: &EncryptionNonce, payload: Payload) -> Result<Vec<u8>> {
+        let nonce = Nonce::from_slice(nonce.as_bytes());
+        match self {
+            Self::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into()).decrypt(nonce, payload),
+            Self::Aes256Gcm => Aes256Gcm::new(key.into()).decrypt(nonce, payload),
+        }
+        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))
+    }
+}
+
+impl std::fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::ChaCha20Poly1305 => "chacha20-poly1305",
+            Self::Aes256Gcm => "aes-256-gcm",
+        })
+    }
+}
+
+impl std::str::FromStr for CipherSuite {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chacha20-poly1305" | "chacha20" | "chacha" => Ok(Self::ChaCha20Poly1305),
+            "aes-256-gcm" | "aes256gcm" | "aes" => Ok(Self::Aes256Gcm),
+            _ => Err(format!(
+                "unknown cipher {:?} (expected chacha20-poly1305 or aes-256-gcm)",
+                s
+            )),
+        }
+    }
+}
+
 /// A nonce used for encryption
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct EncryptionNonce(pub [u8; NONCE_SIZE]);
@@ -49,6 +225,71 @@ impl EncryptionNonce {
     }
 }
 
+/// Messages one [`NonceSequence`] may seal before its key should be
+/// replaced. Well below the 2^64 counter values, and within the AEAD usage
+/// limits for both suites (AES-256-GCM is the tighter one).
+pub const NONCE_REKEY_THRESHOLD: u64 = 1 << 32;
+
+/// Nonces for one key: a random 32-bit prefix followed by a 64-bit
+/// big-endian counter, so a sender never repeats a nonce under a key.
+///
+/// Group keys are shared, so every member has its own sequence. The
+/// counter starts at a random offset below 2^63 rather than at zero: two
+/// members whose prefixes happen to match then only collide if their
+/// counter ranges overlap, instead of on their first messages.
+#[derive(Debug)]
+pub struct NonceSequence {
+    prefix: [u8; 4],
+    start: u64,
+    next: std::sync::atomic::AtomicU64,
+}
+
+impl NonceSequence {
+    /// A sequence with a random prefix and starting point
+    pub fn new() -> Self {
+        let mut rng = rand::rng();
+        let mut prefix = [0u8; 4];
+        rng.try_fill_bytes(&mut prefix)
+            .expect("Failed to generate nonce prefix");
+        let start = rng.try_next_u64().expect("Failed to generate nonce counter") >> 1;
+        Self {
+            prefix,
+            start,
+            next: std::sync::atomic::AtomicU64::new(start),
+        }
+    }
+
+    /// The next nonce. Fails only once the counter would wrap, long after
+    /// [`needs_rekey`](Self::needs_rekey) says to replace the key.
+    pub fn next_nonce(&self) -> Result<EncryptionNonce> {
+        use std::sync::atomic::Ordering;
+        let counter = self
+            .next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_add(1))
+            .map_err(|_| anyhow::anyhow!("Nonce counter exhausted; the key must be replaced"))?;
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[..4].copy_from_slice(&self.prefix);
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        Ok(EncryptionNonce(nonce))
+    }
+
+    /// How many nonces were handed out
+    pub fn used(&self) -> u64 {
+        self.next.load(std::sync::atomic::Ordering::Relaxed) - self.start
+    }
+
+    /// Whether [`NONCE_REKEY_THRESHOLD`] messages were sealed with this sequence
+    pub fn needs_rekey(&self) -> bool {
+        self.used() >= NONCE_REKEY_THRESHOLD
+    }
+}
+
+impl Default for NonceSequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// An encrypted message with all necessary metadata for decryption
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct EncryptedMessage {
@@ -64,6 +305,16 @@ pub struct EncryptedMessage {
     pub timestamp: u64,
     /// Additional authenticated data
     pub aad: Option<Vec<u8>>,
+    /// `CipherSuite::id` of the AEAD; absent (ChaCha20-Poly1305) from older peers
+    #[serde(default)]
+    pub suite: u8,
+    /// The plaintext was deflated before sealing (`--compress`). Left out
+    /// when false, so uncompressed messages look the same to older peers.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed: bool,
+    /// The (possibly compressed) plaintext was padded with [`pad`] (`--pad`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub padded: bool,
 }
 
 impl EncryptedMessage {
@@ -145,6 +396,13 @@ impl EncryptionKeyPair {
 pub struct SessionManager {
     /// Our encryption keypair
     keypair: EncryptionKeyPair,
+    /// AEAD used for messages we encrypt
+    suite: CipherSuite,
+    /// Nonces for messages to each peer, by their public key
+    nonces: std::sync::Mutex<std::collections::HashMap<[u8; KEY_SIZE], NonceSequence>>,
+    /// Static DH results by peer public key, so a message only pays for
+    /// its ephemeral exchange
+    static_secrets: std::sync::Mutex<HashMap<[u8; KEY_SIZE], Zeroizing<[u8; KEY_SIZE]>>>,
 }
 
 impl SessionManager {
@@ -152,18 +410,101 @@ impl SessionManager {
     pub fn new() -> Result<Self> {
         Ok(Self {
             keypair: EncryptionKeyPair::generate()?,
+            suite: CipherSuite::default(),
+            nonces: Default::default(),
+            static_secrets: Default::default(),
         })
     }
 
+    /// Restore a session manager from a stored X25519 secret
+    pub fn from_secret_bytes(bytes: [u8; KEY_SIZE]) -> Self {
+        Self {
+            keypair: EncryptionKeyPair::from_secret_bytes(bytes),
+            suite: CipherSuite::default(),
+            nonces: Default::default(),
+            static_secrets: Default::default(),
+        }
+    }
+
+    /// Choose the AEAD for messages we encrypt. Decryption follows the
+    /// suite each message carries.
+    pub fn set_cipher(&mut self, suite: CipherSuite) {
+        self.suite = suite;
+    }
+
+    /// The AEAD used for messages we encrypt
+    pub fn cipher(&self) -> CipherSuite {
+        self.suite
+    }
+
+
     /// Get our public key bytes to share with peers
     pub fn public_key_bytes(&self) -> [u8; KEY_SIZE] {
         self.keypair.public_key_bytes()
     }
 
-    /// Establish a session with a peer (stores for future use)
-    pub fn establish_session(&self, _peer_public_key: &[u8; KEY_SIZE]) -> Result<String> {
+    /// Our X25519 secret, for persisting alongside the identity
+    ///
+    /// # Security
+    /// The caller is responsible for securely handling the returned bytes.
+    pub fn secret_bytes(&self) -> [u8; KEY_SIZE] {
+        self.keypair.secret_to_bytes()
+    }
+
+    /// Start a [`RatchetSession`] with a peer, or `None` if the peer's key
+    /// makes it the initiator
+    pub fn start_ratchet(
+        &self,
+        peer_public_key: &[u8; KEY_SIZE],
+        epochs: RatchetEpochs,
+    ) -> Result<Option<RatchetSession>> {
+        if !RatchetSession::is_initiator(&self.public_key_bytes(), peer_public_key) {
+            return Ok(None);
+        }
+        RatchetSession::initiate(&self.keypair.secret, peer_public_key, epochs).map(Some)
+    }
+
+    /// Join the ratchet session `id` a peer started, or fail if the peer's
+    /// key makes us the initiator
+    pub fn join_ratchet(
+        &self,
+        peer_public_key: &[u8; KEY_SIZE],
+        id: [u8; KEY_SIZE],
+        epochs: RatchetEpochs,
+    ) -> Result<RatchetSession> {
+        if RatchetSession::is_initiator(&self.public_key_bytes(), peer_public_key) {
+            return Err(anyhow::anyhow!("Peer tried to start a ratchet session we initiate"));
+        }
+        RatchetSession::respond(&self.keypair.secret, peer_public_key, id, epochs)
+    }
+
+    /// Establish a session with a peer: computes the static shared secret
+    /// its messages reuse
+    pub fn establish_session(&self, peer_public_key: &[u8; KEY_SIZE]) -> Result<String> {
+        self.static_shared(peer_public_key);
         // Session ID is the hex of the peer's public key
-        Ok(hex::encode(_peer_public_key))
+        Ok(hex::encode(peer_public_key))
+    }
+
+    /// Drop the cached secret for a peer key that's no longer in use
+    pub fn forget_session(&self, peer_public_key: &[u8; KEY_SIZE]) {
+        self.static_secrets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(peer_public_key);
+    }
+
+    /// DH of our static key with a peer's, computed once per peer key
+    fn static_shared(&self, peer_public_key: &[u8; KEY_SIZE]) -> Zeroizing<[u8; KEY_SIZE]> {
+        self.static_secrets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(*peer_public_key)
+            .or_insert_with(|| {
+                let their_public = EncryptionKeyPair::public_key_from_bytes(peer_public_key);
+                Zeroizing::new(self.keypair.diffie_hellman(&their_public))
+            })
+            .clone()
     }
 
     /// Encrypt a message for a specific peer
@@ -172,6 +513,7 @@ impl SessionManager {
         peer_public_key: &[u8; KEY_SIZE],
         plaintext: &[u8],
         aad: Option<&[u8]>,
+        options: EncryptionOptions,
     ) -> Result<EncryptedMessage> {
         let their_public = EncryptionKeyPair::public_key_from_bytes(peer_public_key);
 
@@ -181,7 +523,7 @@ impl SessionManager {
         let ephemeral_public = PublicKey::from(&ephemeral);
 
         // Perform DH with both static and ephemeral keys
-        let static_shared = self.keypair.diffie_hellman(&their_public);
+        let static_shared = self.static_shared(peer_public_key);
         let ephemeral_shared = *ephemeral.diffie_hellman(&their_public).as_bytes();
 
         // Combine both shared secrets for stronger security
@@ -192,36 +534,37 @@ impl SessionManager {
         rng.try_fill_bytes(&mut salt)
             .map_err(|e| anyhow::anyhow!("Failed to generate salt: {}", e))?;
 
+        // Each message also gets a fresh key from the ephemeral secret and
+        // salt; the per-peer sequence keeps nonces unique regardless
+        let nonce = self
+            .nonces
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(*peer_public_key)
+            .or_default()
+            .next_nonce()?;
+
         // Derive encryption key with proper salt and domain-separated info
         // Note: we use a static info string because the DH shared secret already
         // incorporates both parties' keys. Using one party's key here would cause
         // encrypt/decrypt to derive different keys.
-        let mut key = derive_key_bytes(&combined_secret, &salt, b"openwire-e2e-v1")?;
-
-        // Generate random nonce
-        let nonce = EncryptionNonce::random();
-
-        // Encrypt with ChaCha20-Poly1305
-        let cipher = ChaCha20Poly1305::new_from_slice(&key)
-            .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
-
-        let payload = match aad {
-            Some(aad_data) => Payload {
-                msg: plaintext,
-                aad: aad_data,
-            },
-            None => Payload {
-                msg: plaintext,
-                aad: &[],
-            },
+        let mut key = derive_key_bytes(&combined_secret, &salt, self.suite.kdf_info())?;
+
+        // Compress first: padding afterwards hides the compressed length too
+        let compressed = if options.compress { compress(plaintext) } else { None };
+        let body = compressed.as_deref().unwrap_or(plaintext);
+        let padded = if options.pad { Some(pad(body)?) } else { None };
+        let full_aad = sealed_aad(aad, compressed.is_some(), padded.is_some());
+        let payload = Payload {
+            msg: padded.as_deref().unwrap_or(body),
+            aad: &full_aad,
         };
 
-        let ciphertext = cipher
-            .encrypt(Nonce::from_slice(nonce.as_bytes()), payload)
-            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+        let ciphertext = self.suite.seal(&key, &nonce, payload);
 
         // Zeroize the derived key
         key.zeroize();
+        let ciphertext = ciphertext?;
 
         Ok(EncryptedMessage {
             ciphertext,
@@ -232,6 +575,9 @@ impl SessionManager {
                 .duration_since(std::time::UNIX_EPOCH)?
                 .as_secs(),
             aad: aad.map(|a| a.to_vec()),
+            suite: self.suite.id(),
+            compressed: compressed.is_some(),
+            padded: padded.is_some(),
         })
     }
 
@@ -241,10 +587,10 @@ impl SessionManager {
         encrypted: &EncryptedMessage,
         peer_public_key: &[u8; KEY_SIZE],
     ) -> Result<Vec<u8>> {
-        let their_public = EncryptionKeyPair::public_key_from_bytes(peer_public_key);
+        let suite = CipherSuite::from_id(encrypted.suite)?;
 
         // Compute shared secrets
-        let static_shared = self.keypair.diffie_hellman(&their_public);
+        let static_shared = self.static_shared(peer_public_key);
 
         // If ephemeral key is provided, use it for forward secrecy
         let combined_secret = if let Some(ephemeral_bytes) = &encrypted.ephemeral_public_key {
@@ -270,31 +616,20 @@ impl SessionManager {
         salt.copy_from_slice(&encrypted.salt);
 
         // Derive decryption key with same salt and info
-        let mut key = derive_key_bytes(&combined_secret, &salt, b"openwire-e2e-v1")?;
-
-        // Decrypt with ChaCha20-Poly1305
-        let cipher = ChaCha20Poly1305::new_from_slice(&key)
-            .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
-
-        let payload = match &encrypted.aad {
-            Some(aad_data) => Payload {
-                msg: &encrypted.ciphertext,
-                aad: aad_data.as_slice(),
-            },
-            None => Payload {
-                msg: &encrypted.ciphertext,
-                aad: &[],
-            },
+        let mut key = derive_key_bytes(&combined_secret, &salt, suite.kdf_info())?;
+
+        let full_aad = sealed_aad(encrypted.aad.as_deref(), encrypted.compressed, encrypted.padded);
+        let payload = Payload {
+            msg: &encrypted.ciphertext,
+            aad: &full_aad,
         };
 
-        let plaintext = cipher
-            .decrypt(Nonce::from_slice(encrypted.nonce.as_bytes()), payload)
-            .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
+        let plaintext = suite.open(&key, &encrypted.nonce, payload);
 
         // Zeroize the derived key
         key.zeroize();
 
-        Ok(plaintext)
+        unwrap_payload(plaintext?, encrypted)
     }
 }
 
@@ -315,15 +650,55 @@ fn derive_key_bytes(secret: &[u8], salt: &[u8; SALT_SIZE], info: &[u8]) -> Resul
     Ok(key)
 }
 
+/// Context the LAN password key's salt is derived from, with the network id
+const LAN_KEY_CONTEXT: &[u8] = b"openwire-lan-broadcast-v2";
+/// Argon2id cost of the LAN password key: 19 MiB, 2 passes, 1 lane (the
+/// OWASP minimum). Every peer must derive the same key, so these are fixed
+/// rather than following the argon2 crate's defaults.
+const LAN_KEY_MEMORY_KIB: u32 = 19 * 1024;
+const LAN_KEY_ITERATIONS: u32 = 2;
+const LAN_KEY_LANES: u32 = 1;
+
+/// Derive a shared symmetric key from a password with Argon2id. Everyone
+/// using the same password on the same network id gets the same key. The
+/// salt is SHA-256 of `LAN_KEY_CONTEXT || network_id`: it can't be random,
+/// since peers never exchange one, so it only separates networks.
+pub fn derive_password_key(password: &str, network_id: &str) -> Result<[u8; KEY_SIZE]> {
+    use sha2::Digest;
+    let salt: [u8; SALT_SIZE] = Sha256::new()
+        .chain_update(LAN_KEY_CONTEXT)
+        .chain_update(network_id.as_bytes())
+        .finalize()
+        .into();
+    let params = argon2::Params::new(LAN_KEY_MEMORY_KIB, LAN_KEY_ITERATIONS, LAN_KEY_LANES, Some(KEY_SIZE))
+        .map_err(|e| anyhow::anyhow!("Bad LAN key parameters: {}", e))?;
+    let argon = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+    let mut key = [0u8; KEY_SIZE];
+    argon
+        .hash_password_into(password.as_bytes(), &salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("LAN key derivation failed: {}", e))?;
+    Ok(key)
+}
+
 /// Simple encrypt function for when you already have a shared key
 pub fn encrypt_with_key(
     plaintext: &[u8],
     key: &[u8; KEY_SIZE],
     aad: Option<&[u8]>,
+) -> Result<EncryptedMessage> {
+    encrypt_with_key_using(plaintext, key, aad, CipherSuite::default())
+}
+
+/// [`encrypt_with_key`] with a chosen AEAD. Both suites take a 12-byte
+/// nonce, so the message layout is the same; [`decrypt_with_key`] follows
+/// the suite the message names.
+pub fn encrypt_with_key_using(
+    plaintext: &[u8],
+    key: &[u8; KEY_SIZE],
+    aad: Option<&[u8]>,
+    suite: CipherSuite,
 ) -> Result<EncryptedMessage> {
     let nonce = EncryptionNonce::random();
-    let cipher = ChaCha20Poly1305::new_from_slice(key)
-        .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
 
     let payload = match aad {
         Some(aad_data) => Payload {
@@ -336,9 +711,7 @@ pub fn encrypt_with_key(
         },
     };
 
-    let ciphertext = cipher
-        .encrypt(Nonce::from_slice(nonce.as_bytes()), payload)
-        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+    let ciphertext = suite.seal(key, &nonce, payload)?;
 
     Ok(EncryptedMessage {
         ciphertext,
@@ -349,31 +722,436 @@ pub fn encrypt_with_key(
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs(),
         aad: aad.map(|a| a.to_vec()),
+        suite: suite.id(),
+        compressed: false,
+        padded: false,
     })
 }
 
 /// Simple decrypt function for when you already have a shared key
 pub fn decrypt_with_key(encrypted: &EncryptedMessage, key: &[u8; KEY_SIZE]) -> Result<Vec<u8>> {
-    let cipher = ChaCha20Poly1305::new_from_slice(key)
-        .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
+    let suite = CipherSuite::from_id(encrypted.suite)?;
 
-    let payload = match &encrypted.aad {
-        Some(aad_data) => Payload {
-            msg: &encrypted.ciphertext,
-            aad: aad_data.as_slice(),
-        },
-        None => Payload {
-            msg: &encrypted.ciphertext,
-            aad: &[],
-        },
+    let full_aad = sealed_aad(encrypted.aad.as_deref(), encrypted.compressed, encrypted.padded);
+    let payload = Payload {
+        msg: &encrypted.ciphertext,
+        aad: &full_aad,
     };
 
-    let plaintext = cipher
-        .decrypt(Nonce::from_slice(encrypted.nonce.as_bytes()), payload)
-        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
+    unwrap_payload(suite.open(key, &encrypted.nonce, payload)?, encrypted)
+}
+
+/// Strip the padding and compression `encrypted` says were applied
+fn unwrap_payload(plaintext: Vec<u8>, encrypted: &EncryptedMessage) -> Result<Vec<u8>> {
+    let plaintext = if encrypted.padded { unpad(plaintext)? } else { plaintext };
+    if encrypted.compressed {
+        return decompress(&plaintext);
+    }
     Ok(plaintext)
 }
 
+/// Marks a direct message as a [`RatchetMessage`] rather than an
+/// [`EncryptedMessage`]
+pub const RATCHET_PREFIX: &[u8] = b"RATCHET:";
+
+/// Most message keys a single incoming message may make us skip
+pub const MAX_SKIP: u32 = 1000;
+
+/// Most skipped message keys a session holds for late messages
+const MAX_SKIPPED_KEYS: usize = 2000;
+
+/// HKDF info strings for the ratchet's root, chain and message keys
+const RATCHET_ROOT_INFO: &[u8] = b"openwire-ratchet-root-v1";
+const RATCHET_CHAIN_INFO: &[u8] = b"openwire-ratchet-chain-v1";
+const RATCHET_MESSAGE_INFO: &[u8] = b"openwire-ratchet-message-v1";
+
+/// The ratchet epochs (see `KeyExchangeMessage::ratchet`) of the two
+/// clients a session runs between. A session belongs to one run of each,
+/// so its messages can't be replayed into a later one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RatchetEpochs {
+    pub ours: u64,
+    pub theirs: u64,
+}
+
+/// The clear part of a ratchet message, authenticated as AAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RatchetHeader {
+    /// The session this belongs to: the initiator's first ratchet key
+    #[serde(with = "hex::serde")]
+    pub session: [u8; KEY_SIZE],
+    /// The sender's ratchet epoch
+    pub epoch: u64,
+    /// The ratchet epoch the sender knows for the receiver
+    pub peer_epoch: u64,
+    /// The sender's current ratchet public key
+    #[serde(with = "hex::serde")]
+    pub dh: [u8; KEY_SIZE],
+    /// Length of the sender's previous sending chain
+    pub pn: u32,
+    /// Position of this message in the sender's current chain
+    pub n: u32,
+    /// Whether the payload was deflated
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed: bool,
+    /// Whether the payload was padded
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub padded: bool,
+}
+
+impl RatchetHeader {
+    fn aad(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(2 * KEY_SIZE + 24);
+        header.extend_from_slice(&self.session);
+        header.extend_from_slice(&self.epoch.to_le_bytes());
+        header.extend_from_slice(&self.peer_epoch.to_le_bytes());
+        header.extend_from_slice(&self.dh);
+        header.extend_from_slice(&self.pn.to_le_bytes());
+        header.extend_from_slice(&self.n.to_le_bytes());
+        sealed_aad(Some(&header), self.compressed, self.padded)
+    }
+}
+
+/// A direct message sealed by a [`RatchetSession`]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RatchetMessage {
+    pub header: RatchetHeader,
+    pub ciphertext: Vec<u8>,
+}
+
+impl RatchetMessage {
+    /// Serialize behind [`RATCHET_PREFIX`]
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = RATCHET_PREFIX.to_vec();
+        bytes.extend_from_slice(&serde_json::to_vec(self)?);
+        Ok(bytes)
+    }
+
+    /// Parse what [`RatchetMessage::to_bytes`] produced
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let body = data
+            .strip_prefix(RATCHET_PREFIX)
+            .ok_or_else(|| anyhow::anyhow!("Not a ratchet message"))?;
+        Ok(serde_json::from_slice(body)?)
+    }
+}
+
+/// A double ratchet with one peer. Every message is sealed with its own key
+/// from a hash chain, and every change of speaker mixes a fresh X25519
+/// exchange into the root key: keys already used can't be recovered from
+/// the current state, and a leaked state stops decrypting once the peer
+/// answers with a new ratchet key.
+///
+/// The side with the lower static X25519 key initiates; the other one
+/// can only send after the first message of the session reached it.
+#[derive(Clone)]
+pub struct RatchetSession {
+    id: [u8; KEY_SIZE],
+    epochs: RatchetEpochs,
+    root: [u8; KEY_SIZE],
+    dh_self: StaticSecret,
+    dh_remote: Option<[u8; KEY_SIZE]>,
+    send_chain: Option<[u8; KEY_SIZE]>,
+    recv_chain: Option<[u8; KEY_SIZE]>,
+    send_n: u32,
+    recv_n: u32,
+    prev_n: u32,
+    /// Keys of messages we skipped over, by (ratchet key, position)
+    skipped: HashMap<([u8; KEY_SIZE], u32), [u8; KEY_SIZE]>,
+    skipped_order: VecDeque<([u8; KEY_SIZE], u32)>,
+}
+
+impl RatchetSession {
+    /// Whether the side holding `ours` starts sessions with the holder of `theirs`
+    pub fn is_initiator(ours: &[u8; KEY_SIZE], theirs: &[u8; KEY_SIZE]) -> bool {
+        ours < theirs
+    }
+
+    /// Start a session as the initiator, ready to send
+    pub fn initiate(our_secret: &StaticSecret, their_public: &[u8; KEY_SIZE], epochs: RatchetEpochs) -> Result<Self> {
+        let mut root = ratchet_root(our_secret, their_public)?;
+        let dh_self = StaticSecret::random_from_rng(&mut rand::rng());
+        let id = PublicKey::from(&dh_self).to_bytes();
+        let (next_root, send_chain) = kdf_rk(&root, &dh_self, their_public)?;
+        root.zeroize();
+        Ok(Self {
+            id,
+            epochs,
+            root: next_root,
+            dh_self,
+            dh_remote: Some(*their_public),
+            send_chain: Some(send_chain),
+            recv_chain: None,
+            send_n: 0,
+            recv_n: 0,
+            prev_n: 0,
+            skipped: HashMap::new(),
+            skipped_order: VecDeque::new(),
+        })
+    }
+
+    /// Join the session `id` the initiator started
+    pub fn respond(
+        our_secret: &StaticSecret,
+        their_public: &[u8; KEY_SIZE],
+        id: [u8; KEY_SIZE],
+        epochs: RatchetEpochs,
+    ) -> Result<Self> {
+        Ok(Self {
+            id,
+            epochs,
+            root: ratchet_root(our_secret, their_public)?,
+            dh_self: our_secret.clone(),
+            dh_remote: None,
+            send_chain: None,
+            recv_chain: None,
+            send_n: 0,
+            recv_n: 0,
+            prev_n: 0,
+            skipped: HashMap::new(),
+            skipped_order: VecDeque::new(),
+        })
+    }
+
+    /// The session id carried in every header
+    pub fn id(&self) -> [u8; KEY_SIZE] {
+        self.id
+    }
+
+    /// Whether we hold a sending chain yet
+    pub fn can_send(&self) -> bool {
+        self.send_chain.is_some()
+    }
+
+    /// Seal `plaintext` with the next key of the sending chain
+    pub fn encrypt(&mut self, plaintext: &[u8], options: EncryptionOptions) -> Result<RatchetMessage> {
+        let chain = self
+            .send_chain
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Ratchet session can't send before the peer has"))?;
+        let (mut message_key, next_chain) = kdf_ck(chain)?;
+
+        let compressed = options.compress.then(|| compress(plaintext)).flatten();
+        let payload = compressed.as_deref().unwrap_or(plaintext);
+        let payload = if options.pad { pad(payload)? } else { payload.to_vec() };
+        let header = RatchetHeader {
+            session: self.id,
+            epoch: self.epochs.ours,
+            peer_epoch: self.epochs.theirs,
+            dh: PublicKey::from(&self.dh_self).to_bytes(),
+            pn: self.prev_n,
+            n: self.send_n,
+            compressed: compressed.is_some(),
+            padded: options.pad,
+        };
+        let ciphertext = seal_with_message_key(&message_key, &payload, &header.aad());
+        message_key.zeroize();
+
+        self.send_chain = Some(next_chain);
+        self.send_n += 1;
+        Ok(RatchetMessage { header, ciphertext: ciphertext? })
+    }
+
+    /// Open a message from the peer. The session only changes if it
+    /// decrypts, so forged or replayed messages leave it as it was.
+    pub fn decrypt(&mut self, message: &RatchetMessage) -> Result<Vec<u8>> {
+        let header = &message.header;
+        if header.session != self.id {
+            return Err(anyhow::anyhow!("Message belongs to another ratchet session"));
+        }
+        if (header.epoch, header.peer_epoch) != (self.epochs.theirs, self.epochs.ours) {
+            return Err(anyhow::anyhow!("Ratchet message is from an earlier run of either client"));
+        }
+        let aad = header.aad();
+
+        if let Some(message_key) = self.skipped.get(&(header.dh, header.n)) {
+            let payload = open_with_message_key(message_key, &message.ciphertext, &aad)?;
+            self.skipped.remove(&(header.dh, header.n));
+            self.skipped_order.retain(|k| *k != (header.dh, header.n));
+            return unwrap_ratchet_payload(payload, header);
+        }
+
+        let mut next = self.clone();
+        if next.dh_remote != Some(header.dh) {
+            next.skip_to(header.pn)?;
+            next.dh_step(&header.dh)?;
+        } else if header.n < next.recv_n {
+            return Err(anyhow::anyhow!("Ratchet message was replayed"));
+        }
+        next.skip_to(header.n)?;
+
+        let chain = next
+            .recv_chain
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Ratchet session has no receiving chain"))?;
+        let (mut message_key, next_chain) = kdf_ck(chain)?;
+        let payload = open_with_message_key(&message_key, &message.ciphertext, &aad);
+        message_key.zeroize();
+        let payload = payload?;
+
+        next.recv_chain = Some(next_chain);
+        next.recv_n += 1;
+        *self = next;
+        unwrap_ratchet_payload(payload, header)
+    }
+
+    /// Step the receiving chain up to position `until`, keeping the keys
+    /// passed over for messages still on their way
+    fn skip_to(&mut self, until: u32) -> Result<()> {
+        let (Some(mut chain), Some(dh)) = (self.recv_chain, self.dh_remote) else {
+            return Ok(());
+        };
+        if until.saturating_sub(self.recv_n) > MAX_SKIP {
+            return Err(anyhow::anyhow!("Ratchet message skips more than {} keys", MAX_SKIP));
+        }
+        while self.recv_n < until {
+            let (message_key, next_chain) = kdf_ck(&chain)?;
+            chain.zeroize();
+            chain = next_chain;
+            self.skipped.insert((dh, self.recv_n), message_key);
+            self.skipped_order.push_back((dh, self.recv_n));
+            if self.skipped_order.len() > MAX_SKIPPED_KEYS
+                && let Some(oldest) = self.skipped_order.pop_front()
+                && let Some(mut key) = self.skipped.remove(&oldest)
+            {
+                key.zeroize();
+            }
+            self.recv_n += 1;
+        }
+        self.recv_chain = Some(chain);
+        Ok(())
+    }
+
+    /// The peer has a new ratchet key: derive its sending chain as our
+    /// receiving one, then answer with a fresh key of our own
+    fn dh_step(&mut self, their_dh: &[u8; KEY_SIZE]) -> Result<()> {
+        self.prev_n = self.send_n;
+        self.send_n = 0;
+        self.recv_n = 0;
+        self.dh_remote = Some(*their_dh);
+
+        let (root, recv_chain) = kdf_rk(&self.root, &self.dh_self, their_dh)?;
+        self.dh_self = StaticSecret::random_from_rng(&mut rand::rng());
+        let (root, send_chain) = kdf_rk(&root, &self.dh_self, their_dh)?;
+
+        self.root.zeroize();
+        self.root = root;
+        self.recv_chain = Some(recv_chain);
+        self.send_chain = Some(send_chain);
+        Ok(())
+    }
+}
+
+impl Drop for RatchetSession {
+    fn drop(&mut self) {
+        self.root.zeroize();
+        if let Some(chain) = self.send_chain.as_mut() {
+            chain.zeroize();
+        }
+        if let Some(chain) = self.recv_chain.as_mut() {
+            chain.zeroize();
+        }
+        for key in self.skipped.values_mut() {
+            key.zeroize();
+        }
+    }
+}
+
+impl std::fmt::Debug for RatchetSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RatchetSession")
+            .field("id", &hex::encode(self.id))
+            .field("send_n", &self.send_n)
+            .field("recv_n", &self.recv_n)
+            .field("skipped", &self.skipped.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The root key both sides start from, bound to both static keys
+fn ratchet_root(our_secret: &StaticSecret, their_public: &[u8; KEY_SIZE]) -> Result<[u8; KEY_SIZE]> {
+    let ours = PublicKey::from(our_secret).to_bytes();
+    let (low, high) = if ours < *their_public { (ours, *their_public) } else { (*their_public, ours) };
+    let mut salt = [0u8; 2 * KEY_SIZE];
+    salt[..KEY_SIZE].copy_from_slice(&low);
+    salt[KEY_SIZE..].copy_from_slice(&high);
+
+    let shared = our_secret.diffie_hellman(&PublicKey::from(*their_public));
+    let mut root = [0u8; KEY_SIZE];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(RATCHET_ROOT_INFO, &mut root)
+        .map_err(|e| anyhow::anyhow!("HKDF expansion failed: {}", e))?;
+    Ok(root)
+}
+
+/// Mix a DH output into the root key: (new root, new chain key)
+fn kdf_rk(
+    root: &[u8; KEY_SIZE],
+    ours: &StaticSecret,
+    theirs: &[u8; KEY_SIZE],
+) -> Result<([u8; KEY_SIZE], [u8; KEY_SIZE])> {
+    let shared = ours.diffie_hellman(&PublicKey::from(*theirs));
+    let mut okm = [0u8; 2 * KEY_SIZE];
+    Hkdf::<Sha256>::new(Some(root), shared.as_bytes())
+        .expand(RATCHET_ROOT_INFO, &mut okm)
+        .map_err(|e| anyhow::anyhow!("HKDF expansion failed: {}", e))?;
+    let mut root = [0u8; KEY_SIZE];
+    let mut chain = [0u8; KEY_SIZE];
+    root.copy_from_slice(&okm[..KEY_SIZE]);
+    chain.copy_from_slice(&okm[KEY_SIZE..]);
+    okm.zeroize();
+    Ok((root, chain))
+}
+
+/// Advance a chain key: (message key, next chain key)
+fn kdf_ck(chain: &[u8; KEY_SIZE]) -> Result<([u8; KEY_SIZE], [u8; KEY_SIZE])> {
+    let hkdf = Hkdf::<Sha256>::from_prk(chain).map_err(|e| anyhow::anyhow!("Bad chain key: {}", e))?;
+    let mut message_key = [0u8; KEY_SIZE];
+    let mut next_chain = [0u8; KEY_SIZE];
+    hkdf.expand(RATCHET_MESSAGE_INFO, &mut message_key)
+        .and_then(|_| hkdf.expand(RATCHET_CHAIN_INFO, &mut next_chain))
+        .map_err(|e| anyhow::anyhow!("HKDF expansion failed: {}", e))?;
+    Ok((message_key, next_chain))
+}
+
+/// Expand a message key into the AEAD key and nonce it seals with. Each
+/// message key is used once, so a derived nonce never repeats under it.
+fn message_key_material(message_key: &[u8; KEY_SIZE]) -> Result<([u8; KEY_SIZE], EncryptionNonce)> {
+    let hkdf = Hkdf::<Sha256>::from_prk(message_key).map_err(|e| anyhow::anyhow!("Bad message key: {}", e))?;
+    let mut okm = [0u8; KEY_SIZE + NONCE_SIZE];
+    hkdf.expand(RATCHET_MESSAGE_INFO, &mut okm)
+        .map_err(|e| anyhow::anyhow!("HKDF expansion failed: {}", e))?;
+    let mut key = [0u8; KEY_SIZE];
+    let mut nonce = [0u8; NONCE_SIZE];
+    key.copy_from_slice(&okm[..KEY_SIZE]);
+    nonce.copy_from_slice(&okm[KEY_SIZE..]);
+    okm.zeroize();
+    Ok((key, EncryptionNonce(nonce)))
+}
+
+fn seal_with_message_key(message_key: &[u8; KEY_SIZE], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let (mut key, nonce) = message_key_material(message_key)?;
+    let sealed = CipherSuite::ChaCha20Poly1305.seal(&key, &nonce, Payload { msg: plaintext, aad });
+    key.zeroize();
+    sealed
+}
+
+fn open_with_message_key(message_key: &[u8; KEY_SIZE], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let (mut key, nonce) = message_key_material(message_key)?;
+    let opened = CipherSuite::ChaCha20Poly1305.open(&key, &nonce, Payload { msg: ciphertext, aad });
+    key.zeroize();
+    opened
+}
+
+/// Strip the padding and compression a ratchet header says were applied
+fn unwrap_ratchet_payload(payload: Vec<u8>, header: &RatchetHeader) -> Result<Vec<u8>> {
+    let payload = if header.padded { unpad(payload)? } else { payload };
+    if header.compressed {
+        return decompress(&payload);
+    }
+    Ok(payload)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -394,7 +1172,7 @@ mod tests {
 
         // Alice encrypts for Bob
         let message = b"Hello, Bob! This is a secret message.";
-        let encrypted = alice.encrypt_for_peer(&bob_public, message, None).unwrap();
+        let encrypted = alice.encrypt_for_peer(&bob_public, message, None, EncryptionOptions::default()).unwrap();
 
         // Bob decrypts
         let decrypted = bob.decrypt_from_peer(&encrypted, &alice_public).unwrap();
@@ -413,7 +1191,7 @@ mod tests {
         let message = b"Secret with AAD";
         let aad = b"topic-name";
         let encrypted = alice
-            .encrypt_for_peer(&bob_public, message, Some(aad))
+            .encrypt_for_peer(&bob_public, message, Some(aad), EncryptionOptions::default())
             .unwrap();
 
         let decrypted = bob.decrypt_from_peer(&encrypted, &alice_public).unwrap();
@@ -429,13 +1207,278 @@ mod tests {
             salt: vec![0u8; SALT_SIZE],
             timestamp: 1234567890,
             aad: None,
+            suite: CipherSuite::Aes256Gcm.id(),
+            compressed: false,
+            padded: false,
         };
 
         let bytes = encrypted.to_bytes().unwrap();
+        // Uncompressed messages keep the format older peers know
+        assert!(!String::from_utf8_lossy(&bytes).contains("compressed"));
         let decoded = EncryptedMessage::from_bytes(&bytes).unwrap();
 
         assert_eq!(encrypted.ciphertext, decoded.ciphertext);
         assert_eq!(encrypted.timestamp, decoded.timestamp);
+        assert_eq!(decoded.suite, CipherSuite::Aes256Gcm.id());
+
+        // Messages from peers without the field are ChaCha20-Poly1305
+        let mut legacy: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        legacy.as_object_mut().unwrap().remove("suite");
+        let decoded = EncryptedMessage::from_bytes(&serde_json::to_vec(&legacy).unwrap()).unwrap();
+        assert_eq!(decoded.suite, CipherSuite::ChaCha20Poly1305.id());
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let alice = SessionManager::new().unwrap();
+        let bob = SessionManager::new().unwrap();
+        let compress = EncryptionOptions { compress: true, pad: false };
+
+        let log = "2026-10-15 12:00:00 INFO connection established\n".repeat(200);
+        let sealed = alice.encrypt_for_peer(&bob.public_key_bytes(), log.as_bytes(), Some(b"hdr"), compress).unwrap();
+        assert!(sealed.compressed);
+        assert!(sealed.ciphertext.len() < log.len() / 10);
+        assert_eq!(bob.decrypt_from_peer(&sealed, &alice.public_key_bytes()).unwrap(), log.as_bytes());
+
+        // Flipping the flag breaks authentication instead of yielding garbage
+        let mut flipped = sealed.clone();
+        flipped.compressed = false;
+        assert!(bob.decrypt_from_peer(&flipped, &alice.public_key_bytes()).is_err());
+
+        // Random bytes don't shrink, so they go out as-is
+        let mut noise = vec![0u8; 4096];
+        rand::rng().try_fill_bytes(&mut noise).unwrap();
+        let sealed = alice.encrypt_for_peer(&bob.public_key_bytes(), &noise, None, compress).unwrap();
+        assert!(!sealed.compressed);
+        assert_eq!(bob.decrypt_from_peer(&sealed, &alice.public_key_bytes()).unwrap(), noise);
+
+        // Off by default
+        let sealed = alice.encrypt_for_peer(&bob.public_key_bytes(), log.as_bytes(), None, EncryptionOptions::default()).unwrap();
+        assert!(!sealed.compressed);
+    }
+
+    #[test]
+    fn test_padding_round_trip() {
+        for len in [0, 1, 10, 59, 60, 61, 200, 1020, 1021, 5000] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let padded = pad(&data).unwrap();
+            assert!(padded.len().is_power_of_two() && padded.len() >= MIN_PAD_BUCKET, "{}", len);
+            assert_eq!(unpad(padded).unwrap(), data);
+        }
+        assert_eq!(pad(&[1; 60]).unwrap().len(), 64);
+        assert_eq!(pad(&[1; 61]).unwrap().len(), 128);
+        assert!(unpad(vec![1, 2]).is_err());
+        assert!(unpad(vec![200, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_padded_messages_hide_length() {
+        let alice = SessionManager::new().unwrap();
+        let bob = SessionManager::new().unwrap();
+        let pad = EncryptionOptions { compress: false, pad: true };
+        let seal = |msg: &[u8], options| alice.encrypt_for_peer(&bob.public_key_bytes(), msg, None, options).unwrap();
+
+        let yes = seal(b"yes, at 10", pad);
+        let no = seal(b"no thanks!", pad);
+        assert!(yes.padded);
+        assert_eq!(yes.ciphertext.len(), no.ciphertext.len());
+        assert_eq!(seal(b"ok", pad).ciphertext.len(), yes.ciphertext.len());
+        assert_ne!(
+            seal(b"ok", EncryptionOptions::default()).ciphertext.len(),
+            seal(b"yes, at 10", EncryptionOptions::default()).ciphertext.len()
+        );
+        assert_eq!(bob.decrypt_from_peer(&yes, &alice.public_key_bytes()).unwrap(), b"yes, at 10");
+
+        // Padding and compression together
+        let both = EncryptionOptions { compress: true, pad: true };
+        let log = "INFO connected\n".repeat(500);
+        let sealed = seal(log.as_bytes(), both);
+        assert!(sealed.compressed && sealed.padded);
+        assert_eq!(bob.decrypt_from_peer(&sealed, &alice.public_key_bytes()).unwrap(), log.as_bytes());
+
+        let mut flipped = yes;
+        flipped.padded = false;
+        assert!(bob.decrypt_from_peer(&flipped, &alice.public_key_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_decompression_is_bounded() {
+        let bomb = compress(&vec![0u8; MAX_DECOMPRESSED_SIZE + 1]).unwrap();
+        assert!(decompress(&bomb).is_err());
+        assert!(decompress(b"not deflate").is_err());
+        assert!(compress(b"").is_none());
+    }
+
+    #[test]
+    fn test_nonce_sequence_never_repeats() {
+        let sequence = NonceSequence::new();
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..100_000 {
+            assert!(seen.insert(sequence.next_nonce().unwrap().0));
+        }
+        assert_eq!(sequence.used(), 100_000);
+        assert!(!sequence.needs_rekey());
+
+        // Prefix stays put, the counter counts up
+        let a = sequence.next_nonce().unwrap().0;
+        let b = sequence.next_nonce().unwrap().0;
+        assert_eq!(a[..4], b[..4]);
+        let counter = |n: [u8; NONCE_SIZE]| u64::from_be_bytes(n[4..].try_into().unwrap());
+        assert_eq!(counter(b), counter(a) + 1);
+
+        let nearly_done = NonceSequence {
+            prefix: [0; 4],
+            start: u64::MAX - NONCE_REKEY_THRESHOLD - 1,
+            next: std::sync::atomic::AtomicU64::new(u64::MAX - 1),
+        };
+        assert!(nearly_done.needs_rekey());
+        nearly_done.next_nonce().unwrap();
+        assert!(nearly_done.next_nonce().is_err());
+    }
+
+    #[test]
+    fn test_peer_messages_use_sequential_nonces() {
+        let alice = SessionManager::new().unwrap();
+        let bob = SessionManager::new().unwrap();
+        let first = alice.encrypt_for_peer(&bob.public_key_bytes(), b"one", None, EncryptionOptions::default()).unwrap();
+        let second = alice.encrypt_for_peer(&bob.public_key_bytes(), b"two", None, EncryptionOptions::default()).unwrap();
+        assert_eq!(first.nonce.0[..4], second.nonce.0[..4]);
+        assert_ne!(first.nonce.0, second.nonce.0);
+        assert_eq!(bob.decrypt_from_peer(&second, &alice.public_key_bytes()).unwrap(), b"two");
+    }
+
+    #[test]
+    fn test_round_trip_with_each_cipher() {
+        let mut alice = SessionManager::new().unwrap();
+        let bob = SessionManager::new().unwrap();
+        let message = b"Same message, either suite";
+
+        for suite in [CipherSuite::ChaCha20Poly1305, CipherSuite::Aes256Gcm] {
+            alice.set_cipher(suite);
+            let encrypted = alice
+                .encrypt_for_peer(&bob.public_key_bytes(), message, Some(b"aad"), EncryptionOptions::default())
+                .unwrap();
+            assert_eq!(encrypted.suite, suite.id());
+            let decrypted = bob.decrypt_from_peer(&encrypted, &alice.public_key_bytes()).unwrap();
+            assert_eq!(message.to_vec(), decrypted);
+            assert_eq!(suite.to_string().parse::<CipherSuite>().unwrap(), suite);
+
+            let key = [7u8; KEY_SIZE];
+            let nonce = EncryptionNonce::random();
+            let sealed = suite.seal(&key, &nonce, message.as_ref().into()).unwrap();
+            assert_eq!(suite.open(&key, &nonce, sealed.as_slice().into()).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_cipher_suite_mismatch_is_rejected() {
+        let mut alice = SessionManager::new().unwrap();
+        let bob = SessionManager::new().unwrap();
+        alice.set_cipher(CipherSuite::Aes256Gcm);
+        let encrypted = alice
+            .encrypt_for_peer(&bob.public_key_bytes(), b"relabel me", None, EncryptionOptions::default())
+            .unwrap();
+
+        // Relabelled as the other suite: different key and AEAD, so it fails
+        let mut relabelled = encrypted.clone();
+        relabelled.suite = CipherSuite::ChaCha20Poly1305.id();
+        assert!(bob.decrypt_from_peer(&relabelled, &alice.public_key_bytes()).is_err());
+
+        let mut unknown = encrypted;
+        unknown.suite = 9;
+        let err = bob.decrypt_from_peer(&unknown, &alice.public_key_bytes()).unwrap_err();
+        assert!(err.to_string().contains("Unsupported cipher suite"));
+
+        // Shared-key messages carry the suite too
+        let key = [42u8; KEY_SIZE];
+        let mut sealed = encrypt_with_key(b"lan", &key, None).unwrap();
+        sealed.suite = CipherSuite::Aes256Gcm.id();
+        assert!(decrypt_with_key(&sealed, &key).is_err());
+        assert!("rot13".parse::<CipherSuite>().is_err());
+    }
+
+    #[test]
+    fn test_shared_key_messages_with_each_cipher() {
+        let key = [42u8; KEY_SIZE];
+        let aes = encrypt_with_key_using(b"aes", &key, Some(b"aad"), CipherSuite::Aes256Gcm).unwrap();
+        let chacha = encrypt_with_key_using(b"chacha", &key, Some(b"aad"), CipherSuite::ChaCha20Poly1305).unwrap();
+        assert_eq!(decrypt_with_key(&aes, &key).unwrap(), b"aes");
+        assert_eq!(decrypt_with_key(&chacha, &key).unwrap(), b"chacha");
+
+        // The suite bytes are on the wire and must not change
+        let wire: serde_json::Value = serde_json::from_slice(&aes.to_bytes().unwrap()).unwrap();
+        assert_eq!(wire["suite"], 1);
+        assert_eq!(CipherSuite::ChaCha20Poly1305.id(), 0);
+
+        // Each message only opens with the AEAD it was sealed with
+        for (mut message, other) in [(aes, CipherSuite::ChaCha20Poly1305), (chacha, CipherSuite::Aes256Gcm)] {
+            assert_eq!(message.nonce.0.len(), NONCE_SIZE);
+            message.suite = other.id();
+            assert!(decrypt_with_key(&message, &key).is_err());
+        }
+    }
+
+    #[test]
+    fn test_static_secret_is_cached_per_peer() {
+        let alice = SessionManager::new().unwrap();
+        let bob = SessionManager::new().unwrap();
+        let carol = SessionManager::new().unwrap();
+        let cached = |m: &SessionManager| m.static_secrets.lock().unwrap().len();
+
+        alice.establish_session(&bob.public_key_bytes()).unwrap();
+        assert_eq!(cached(&alice), 1);
+        let expected = alice.keypair.diffie_hellman(&PublicKey::from(bob.public_key_bytes()));
+        assert_eq!(*alice.static_shared(&bob.public_key_bytes()), expected);
+
+        // Messages reuse the entry instead of adding one
+        for _ in 0..3 {
+            let encrypted = alice
+                .encrypt_for_peer(&bob.public_key_bytes(), b"hi", None, EncryptionOptions::default())
+                .unwrap();
+            assert_eq!(bob.decrypt_from_peer(&encrypted, &alice.public_key_bytes()).unwrap(), b"hi");
+        }
+        assert_eq!(cached(&alice), 1);
+        assert_eq!(cached(&bob), 1);
+
+        // Each peer key has its own entry, and forgetting one keeps the rest
+        alice.establish_session(&carol.public_key_bytes()).unwrap();
+        assert_eq!(cached(&alice), 2);
+        alice.forget_session(&bob.public_key_bytes());
+        assert_eq!(cached(&alice), 1);
+        let encrypted = alice
+            .encrypt_for_peer(&carol.public_key_bytes(), b"still", None, EncryptionOptions::default())
+            .unwrap();
+        assert_eq!(carol.decrypt_from_peer(&encrypted, &alice.public_key_bytes()).unwrap(), b"still");
+    }
+
+    /// Caching the static secret skips one of the two X25519 exchanges
+    /// per message, the bulk of the cost of encrypting a short one
+    #[test]
+    fn test_static_secret_cache_speed() {
+        const MESSAGES: usize = 40;
+        let alice = SessionManager::new().unwrap();
+        let bob = SessionManager::new().unwrap().public_key_bytes();
+        let run = |forget: bool| {
+            let start = std::time::Instant::now();
+            for _ in 0..MESSAGES {
+                if forget {
+                    alice.forget_session(&bob);
+                }
+                alice.encrypt_for_peer(&bob, b"benchmark", None, EncryptionOptions::default()).unwrap();
+            }
+            start.elapsed()
+        };
+
+        // Best of several interleaved rounds, so a busy machine doesn't
+        // slow just one side
+        let (mut uncached, mut cached) = (std::time::Duration::MAX, std::time::Duration::MAX);
+        for _ in 0..5 {
+            uncached = uncached.min(run(true));
+            cached = cached.min(run(false));
+        }
+        // Each message still does an ephemeral exchange, so expect a
+        // margin rather than a multiple
+        assert!(cached * 4 < uncached * 3, "{:?} cached vs {:?} uncached", cached, uncached);
     }
 
     #[test]
@@ -447,7 +1490,7 @@ mod tests {
         let bob_public = bob.public_key_bytes();
 
         let message = b"Tamper test";
-        let mut encrypted = alice.encrypt_for_peer(&bob_public, message, None).unwrap();
+        let mut encrypted = alice.encrypt_for_peer(&bob_public, message, None, EncryptionOptions::default()).unwrap();
 
         // Tamper with ciphertext
         if let Some(byte) = encrypted.ciphertext.first_mut() {
@@ -467,4 +1510,113 @@ mod tests {
 
         assert_eq!(message.to_vec(), decrypted);
     }
+
+    #[test]
+    fn test_password_key_is_fixed_argon2id() {
+        // Peers on other builds must derive the same key: pin the output
+        let key = derive_password_key("hunter2", "openwire/1").unwrap();
+        assert_eq!(hex::encode(key), "a973186a1ce770ef864494912788fef8d1e4e846761d0ec271e821dcc71fa613");
+        assert_ne!(key, derive_password_key("hunter2", "openwire/2").unwrap());
+        assert_ne!(key, derive_password_key("hunter3", "openwire/1").unwrap());
+    }
+
+    /// Two fresh sessions, initiator first
+    fn ratchet_pair() -> (RatchetSession, RatchetSession) {
+        let a = StaticSecret::random_from_rng(&mut rand::rng());
+        let b = StaticSecret::random_from_rng(&mut rand::rng());
+        let (a_pub, b_pub) = (PublicKey::from(&a).to_bytes(), PublicKey::from(&b).to_bytes());
+        let (initiator, responder, responder_pub, initiator_pub) =
+            if RatchetSession::is_initiator(&a_pub, &b_pub) { (a, b, b_pub, a_pub) } else { (b, a, a_pub, b_pub) };
+        let epochs = RatchetEpochs { ours: 1, theirs: 2 };
+        let alice = RatchetSession::initiate(&initiator, &responder_pub, epochs).unwrap();
+        let bob = RatchetSession::respond(&responder, &initiator_pub, alice.id(), RatchetEpochs { ours: 2, theirs: 1 })
+            .unwrap();
+        (alice, bob)
+    }
+
+    #[test]
+    fn test_ratchet_conversation() {
+        let (mut alice, mut bob) = ratchet_pair();
+        assert!(!bob.can_send());
+
+        let hello = alice.encrypt(b"hello", EncryptionOptions::default()).unwrap();
+        assert_eq!(bob.decrypt(&hello).unwrap(), b"hello");
+
+        // Each reply moves to a new ratchet key
+        let reply = bob.encrypt(b"hi", EncryptionOptions::default()).unwrap();
+        assert_ne!(reply.header.dh, hello.header.dh);
+        assert_eq!(alice.decrypt(&reply).unwrap(), b"hi");
+        let again = alice.encrypt(b"how are you", EncryptionOptions::default()).unwrap();
+        assert_ne!(again.header.dh, hello.header.dh);
+        assert_eq!(bob.decrypt(&again).unwrap(), b"how are you");
+
+        // Padding and compression survive the trip
+        let options = EncryptionOptions { compress: true, pad: true };
+        let text = "ratchet ".repeat(40);
+        let packed = bob.encrypt(text.as_bytes(), options).unwrap();
+        assert!(packed.header.compressed && packed.header.padded);
+        let parsed = RatchetMessage::from_bytes(&packed.to_bytes().unwrap()).unwrap();
+        assert_eq!(alice.decrypt(&parsed).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn test_ratchet_out_of_order_within_chain() {
+        let (mut alice, mut bob) = ratchet_pair();
+        let messages: Vec<_> = (0..4u8)
+            .map(|i| alice.encrypt(&[i], EncryptionOptions::default()).unwrap())
+            .collect();
+
+        for i in [2, 0, 3, 1] {
+            assert_eq!(bob.decrypt(&messages[i]).unwrap(), [i as u8]);
+        }
+        // Every key is used once
+        assert!(bob.decrypt(&messages[2]).is_err());
+        assert!(bob.decrypt(&messages[3]).is_err());
+    }
+
+    #[test]
+    fn test_ratchet_late_message_from_previous_chain() {
+        let (mut alice, mut bob) = ratchet_pair();
+        let first = alice.encrypt(b"one", EncryptionOptions::default()).unwrap();
+        let late = alice.encrypt(b"two", EncryptionOptions::default()).unwrap();
+        assert_eq!(bob.decrypt(&first).unwrap(), b"one");
+
+        let reply = bob.encrypt(b"ack", EncryptionOptions::default()).unwrap();
+        assert_eq!(alice.decrypt(&reply).unwrap(), b"ack");
+        let next = alice.encrypt(b"three", EncryptionOptions::default()).unwrap();
+        assert_eq!(next.header.pn, 2);
+
+        // Bob moves to the new chain and still reads the one left behind
+        assert_eq!(bob.decrypt(&next).unwrap(), b"three");
+        assert_eq!(bob.decrypt(&late).unwrap(), b"two");
+    }
+
+    #[test]
+    fn test_ratchet_rejects_tampering_without_losing_state() {
+        let (mut alice, mut bob) = ratchet_pair();
+        let message = alice.encrypt(b"intact", EncryptionOptions::default()).unwrap();
+
+        let mut forged = message.clone();
+        forged.ciphertext[0] ^= 0xFF;
+        assert!(bob.decrypt(&forged).is_err());
+        let mut moved = message.clone();
+        moved.header.n = 5;
+        assert!(bob.decrypt(&moved).is_err());
+        let mut earlier_run = message.clone();
+        earlier_run.header.epoch = 3;
+        assert!(bob.decrypt(&earlier_run).is_err());
+
+        assert_eq!(bob.decrypt(&message).unwrap(), b"intact");
+    }
+
+    #[test]
+    fn test_ratchet_limits_skipped_keys() {
+        let (mut alice, mut bob) = ratchet_pair();
+        let mut message = alice.encrypt(b"first", EncryptionOptions::default()).unwrap();
+        assert_eq!(bob.decrypt(&message).unwrap(), b"first");
+        for _ in 0..=MAX_SKIP + 1 {
+            message = alice.encrypt(b"far", EncryptionOptions::default()).unwrap();
+        }
+        assert!(bob.decrypt(&message).is_err());
+    }
 }
@@ -0,0 +1,338 @@
+//! Fuzz-style tests for the wire parsers
+//!
+//! Every `from_bytes` here runs on bytes a remote peer chose. These tests
+//! throw random, truncated, bit-flipped and structurally valid but
+//! wrong-sized inputs at each parser and at the `verify`/decrypt step that
+//! follows it, asserting an error comes back instead of a panic. A fixed
+//! seed keeps failures reproducible; bump `CASES` locally to search longer.
+//!
+//! The crate has no library target, so these live in-tree rather than
+//! under `tests/`.
+
+use rand::{Rng, RngExt, SeedableRng, rngs::StdRng};
+use serde_json::{Value, json};
+
+use crate::crypto::{CryptoManager, Identity, SignedMessage};
+use crate::encryption::{EncryptedMessage, decrypt_with_key};
+use crate::game::{BlackjackAction, GameAction, SlotsAction};
+use crate::media::{ImageFormat, ImageMessage};
+use crate::network::{FileTransferMessage, KeyExchangeMessage};
+use crate::room::{EncryptedRoomMessage, GroupKey, RoomInvite, RoomMessage};
+
+/// Random inputs per generator
+const CASES: usize = 300;
+
+fn rng() -> StdRng {
+    StdRng::seed_from_u64(0x0be1_1e5e)
+}
+
+fn random_bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; rng.random_range(0..=max_len)];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// JSON byte array of a random length, biased towards the sizes the
+/// parsers special-case (32-byte keys, 64-byte signatures) and their neighbours
+fn byte_array(rng: &mut StdRng) -> Value {
+    const LENS: [usize; 9] = [0, 1, 12, 31, 32, 33, 63, 64, 65];
+    let len = if rng.random_bool(0.7) {
+        LENS[rng.random_range(0..LENS.len())]
+    } else {
+        rng.random_range(0..200)
+    };
+    json!(random_bytes_exact(rng, len))
+}
+
+fn random_bytes_exact(rng: &mut StdRng, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Valid encodings cut short at every length and with single bytes flipped
+fn mutations(rng: &mut StdRng, valid: &[u8]) -> Vec<Vec<u8>> {
+    let mut out: Vec<Vec<u8>> = (0..valid.len()).map(|n| valid[..n].to_vec()).collect();
+    for _ in 0..CASES {
+        let mut bytes = valid.to_vec();
+        let i = rng.random_range(0..bytes.len());
+        bytes[i] ^= 1 << rng.random_range(0..8);
+        out.push(bytes);
+    }
+    out
+}
+
+/// Random bytes, mutations of `valid`, and `structured` values encoded as JSON
+fn inputs(rng: &mut StdRng, valid: &[u8], structured: impl Fn(&mut StdRng) -> Value) -> Vec<Vec<u8>> {
+    let mut out = mutations(rng, valid);
+    for _ in 0..CASES {
+        out.push(random_bytes(rng, 256));
+        out.push(serde_json::to_vec(&structured(rng)).unwrap());
+    }
+    out
+}
+
+#[test]
+fn fuzz_signed_message() {
+    let mut rng = rng();
+    let identity = Identity::generate().unwrap();
+    let valid = SignedMessage::new(&identity, b"hello".to_vec()).unwrap();
+    let encoded = valid.to_bytes().unwrap();
+
+    // Round trip
+    let decoded = SignedMessage::from_bytes(&encoded).unwrap();
+    assert_eq!(decoded.content, valid.content);
+    decoded.verify().unwrap();
+
+    let mut parsed = 0;
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({
+            "content": byte_array(rng),
+            "signature": byte_array(rng),
+            "sender_public_key": byte_array(rng),
+            "timestamp": rng.random::<u64>(),
+        })
+    }) {
+        if let Ok(msg) = SignedMessage::from_bytes(&input) {
+            parsed += 1;
+            if input != encoded {
+                let _ = msg.verify();
+            }
+        }
+    }
+    assert!(parsed > CASES / 2, "structured inputs should mostly parse");
+}
+
+#[test]
+fn fuzz_key_exchange_message() {
+    let mut rng = rng();
+    let crypto = CryptoManager::new().unwrap();
+    let valid = KeyExchangeMessage::new(&crypto).unwrap();
+    let encoded = valid.to_bytes().unwrap();
+
+    let decoded = KeyExchangeMessage::from_bytes(&encoded).unwrap();
+    assert_eq!(decoded.encryption_public_key, valid.encryption_public_key);
+    decoded.check().unwrap();
+
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({
+            "signing_public_key": byte_array(rng),
+            "encryption_public_key": byte_array(rng),
+            "timestamp": if rng.random_bool(0.5) { valid.timestamp } else { rng.random() },
+            "signature": byte_array(rng),
+        })
+    }) {
+        if let Ok(msg) = KeyExchangeMessage::from_bytes(&input)
+            && input != encoded
+        {
+            assert!(msg.check().is_err() || msg.signature == valid.signature);
+        }
+    }
+}
+
+#[test]
+fn fuzz_encrypted_message() {
+    let mut rng = rng();
+    let alice = CryptoManager::new().unwrap();
+    let bob = CryptoManager::new().unwrap();
+    let valid = alice
+        .encrypt_for_peer(&bob.encryption_public_key(), b"secret")
+        .unwrap();
+    let encoded = valid.to_bytes().unwrap();
+
+    let decoded = EncryptedMessage::from_bytes(&encoded).unwrap();
+    assert_eq!(
+        bob.decrypt_from_peer(&decoded, &alice.encryption_public_key()).unwrap(),
+        b"secret"
+    );
+
+    let key = [7u8; 32];
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({
+            "ciphertext": byte_array(rng),
+            "nonce": byte_array(rng),
+            "ephemeral_public_key": if rng.random_bool(0.3) { Value::Null } else { byte_array(rng) },
+            "salt": byte_array(rng),
+            "timestamp": rng.random::<u64>(),
+            "aad": if rng.random_bool(0.5) { Value::Null } else { byte_array(rng) },
+        })
+    }) {
+        if let Ok(msg) = EncryptedMessage::from_bytes(&input) {
+            let _ = bob.decrypt_from_peer(&msg, &alice.encryption_public_key());
+            assert!(decrypt_with_key(&msg, &key).is_err());
+        }
+    }
+}
+
+#[test]
+fn fuzz_room_messages_and_invites() {
+    let mut rng = rng();
+    let identity = Identity::generate().unwrap();
+    let group_key = GroupKey::from_bytes([3u8; 32]);
+
+    // Encrypted room message: parse, then decrypt
+    let plain = RoomMessage::new(&identity, "room-1".into(), "nick".into(), b"hi".to_vec()).unwrap();
+    let sealed = group_key.encrypt(&plain.to_bytes().unwrap()).unwrap();
+    let encoded = sealed.to_bytes().unwrap();
+    let opened = group_key
+        .decrypt(&EncryptedRoomMessage::from_bytes(&encoded).unwrap())
+        .unwrap();
+    RoomMessage::from_bytes(&opened).unwrap().verify().unwrap();
+
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({
+            "nonce": byte_array(rng),
+            "ciphertext": byte_array(rng),
+            "timestamp": rng.random::<u64>(),
+        })
+    }) {
+        if let Ok(msg) = EncryptedRoomMessage::from_bytes(&input)
+            && input != encoded
+        {
+            assert!(group_key.decrypt(&msg).is_err() || msg.ciphertext == sealed.ciphertext);
+        }
+    }
+
+    // Decrypted room message: parse, then verify
+    let encoded = plain.to_bytes().unwrap();
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({
+            "room_id": "room-1",
+            "sender_public_key": byte_array(rng),
+            "sender_nick": "nick",
+            "content": byte_array(rng),
+            "timestamp": rng.random::<u64>(),
+            "signature": byte_array(rng),
+        })
+    }) {
+        if let Ok(msg) = RoomMessage::from_bytes(&input)
+            && input != encoded
+        {
+            let _ = msg.verify();
+        }
+    }
+
+    // Invite: parse, verify, then open the wrapped key
+    let invitee = x25519_dalek::StaticSecret::random_from_rng(&mut rand::rng());
+    let invitee_public = x25519_dalek::PublicKey::from(&invitee);
+    let invite = RoomInvite::new(
+        &identity,
+        "room-1".into(),
+        "Room".into(),
+        "peer".into(),
+        &group_key,
+        invitee_public.as_bytes(),
+    )
+    .unwrap();
+    let encoded = invite.to_bytes().unwrap();
+    let decoded = RoomInvite::from_bytes(&encoded).unwrap();
+    decoded.verify().unwrap();
+    assert_eq!(
+        decoded.decrypt_key(invitee.as_bytes()).unwrap().as_bytes(),
+        group_key.as_bytes()
+    );
+
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({
+            "room_id": "room-1",
+            "room_name": "Room",
+            "target_peer_id": "peer",
+            "encrypted_key": byte_array(rng),
+            "inviter_public_key": byte_array(rng),
+            "timestamp": rng.random::<u64>(),
+            "signature": byte_array(rng),
+        })
+    }) {
+        if let Ok(invite) = RoomInvite::from_bytes(&input) {
+            let _ = invite.verify();
+            let _ = invite.decrypt_key(invitee.as_bytes());
+        }
+    }
+}
+
+#[test]
+fn fuzz_file_and_image_messages() {
+    let mut rng = rng();
+    let crypto = CryptoManager::new().unwrap();
+    let data = b"file body".to_vec();
+    let mut valid = FileTransferMessage {
+        filename: "a.txt".into(),
+        size: data.len(),
+        data,
+        sender_public_key: crypto.signing_public_key().to_vec(),
+        signature: Vec::new(),
+        timestamp: 1,
+        caption: None,
+        recipient: None,
+    };
+    valid.signature = crypto.sign(&valid.signed_bytes()).unwrap().to_bytes().to_vec();
+    let encoded = serde_json::to_vec(&valid).unwrap();
+    serde_json::from_slice::<FileTransferMessage>(&encoded)
+        .unwrap()
+        .verify()
+        .unwrap();
+
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({
+            "filename": "a.txt",
+            "size": rng.random::<u32>(),
+            "data": byte_array(rng),
+            "sender_public_key": byte_array(rng),
+            "signature": byte_array(rng),
+            "timestamp": rng.random::<u64>(),
+        })
+    }) {
+        if let Ok(msg) = serde_json::from_slice::<FileTransferMessage>(&input) {
+            let _ = msg.verify();
+        }
+    }
+
+    // Image headers are read straight out of the received bytes
+    let identity = Identity::generate().unwrap();
+    for format in [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif, ImageFormat::WebP] {
+        let mut encoded = Vec::new();
+        for _ in 0..CASES / 10 {
+            let mut data = random_bytes(&mut rng, 64);
+            if format == ImageFormat::Jpeg && data.len() >= 2 && rng.random_bool(0.5) {
+                data[..2].copy_from_slice(&[0xFF, 0xD8]);
+            }
+            let image = ImageMessage::new(&identity, "x".into(), format, data, None).unwrap();
+            encoded = image.to_bytes().unwrap();
+            ImageMessage::from_bytes(&encoded).unwrap().verify().unwrap();
+        }
+        for input in mutations(&mut rng, &encoded) {
+            if let Ok(image) = ImageMessage::from_bytes(&input) {
+                let _ = image.verify();
+            }
+        }
+    }
+}
+
+#[test]
+fn fuzz_game_actions() {
+    let mut rng = rng();
+    let action = GameAction::Move {
+        position: 5,
+        room_id: "room-1".into(),
+        player: "p".into(),
+    };
+    let encoded = action.to_bytes();
+    assert!(matches!(
+        GameAction::from_bytes(&encoded),
+        Some(GameAction::Move { position: 5, .. })
+    ));
+
+    for input in inputs(&mut rng, &encoded, |rng| {
+        json!({ "Move": { "position": rng.random::<u8>(), "room_id": "r", "player": "p" } })
+    }) {
+        let _ = GameAction::from_bytes(&input);
+        // Same bytes behind each game's prefix
+        for prefix in [&b"GAME:"[..], b"BJ:", b"SL:"] {
+            let mut prefixed = prefix.to_vec();
+            prefixed.extend_from_slice(&input);
+            let _ = GameAction::from_bytes(&prefixed);
+            let _ = BlackjackAction::from_bytes(&prefixed);
+            let _ = SlotsAction::from_bytes(&prefixed);
+        }
+    }
+}
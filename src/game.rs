@@ -1869,7 +1869,7 @@ impl SlotsEngine {
 
 // ═══════════════════════════════════════════════════════════════════════════════
 // VIRTUAL WALLET
-// Persists to ~/.openwire/wallet.json
+// Persists to wallet.json in the data directory (see `paths`)
 // Daily refresh of 1000 chips at UTC midnight.
 // ═══════════════════════════════════════════════════════════════════════════════
 
@@ -1885,8 +1885,7 @@ impl Wallet {
     pub const DAILY_CHIPS: u32 = 1000;
 
     fn wallet_path() -> std::path::PathBuf {
-        let home = dirs_next::home_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
-        home.join(".openwire").join("wallet.json")
+        crate::paths::get().wallet_path()
     }
 
     pub fn load() -> Self {
@@ -2010,7 +2009,7 @@ impl CasinoState {
 
 // ═══════════════════════════════════════════════════════════════════════════════
 // TRANSACTION LEDGER
-// Persists to ~/.openwire/history.json
+// Persists to history.json in the data directory (see `paths`)
 // Tracks wins/losses per game session (last 500 entries).
 // ═══════════════════════════════════════════════════════════════════════════════
 
@@ -2031,8 +2030,7 @@ pub struct TransactionLedger {
 
 impl TransactionLedger {
     fn ledger_path() -> std::path::PathBuf {
-        let home = dirs_next::home_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
-        home.join(".openwire").join("history.json")
+        crate::paths::get().ledger_path()
     }
 
     pub fn load() -> Self {
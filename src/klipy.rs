@@ -8,26 +8,77 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 const KLIPY_API_BASE: &str = "https://api.klipy.com";
 
+/// Per-request timeout for Klipy API calls
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long search results are served from the cache
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
 /// Klipy API client
 pub struct KlipyClient {
     app_key: String,
     client: reqwest::Client,
+    /// query → (fetched_at, results)
+    cache: Mutex<HashMap<String, (Instant, Vec<Gif>)>>,
 }
 
 impl KlipyClient {
     /// Create a new Klipy client
     pub fn new(app_key: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
         Self {
             app_key,
-            client: reqwest::Client::new(),
+            client,
+            cache: Mutex::new(HashMap::new()),
         }
     }
 
-    /// Search for GIFs
+    /// Create a client from the `KLIPY_KEY` environment variable, if set
+    pub fn from_env() -> Option<Self> {
+        std::env::var("KLIPY_KEY").ok().map(Self::new)
+    }
+
+    /// Search for GIFs, serving repeated queries from the cache
     pub async fn search(&self, query: &str, limit: u32) -> Result<Vec<Gif>> {
+        if let Some(gifs) = self.cached(query) {
+            return Ok(gifs);
+        }
+        let gifs = self.fetch_search(query, limit).await?;
+        self.store(query, gifs.clone());
+        Ok(gifs)
+    }
+
+    fn cached(&self, query: &str) -> Option<Vec<Gif>> {
+        let cache = self.cache.lock().ok()?;
+        cache
+            .get(query)
+            .filter(|(at, _)| at.elapsed() < CACHE_TTL)
+            .map(|(_, gifs)| gifs.clone())
+    }
+
+    /// Pre-populate the cache for a query (used by tests to avoid the network)
+    #[cfg(test)]
+    pub fn seed_cache(&self, query: &str, gifs: Vec<Gif>) {
+        self.store(query, gifs);
+    }
+
+    fn store(&self, query: &str, gifs: Vec<Gif>) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+            cache.insert(query.to_string(), (Instant::now(), gifs));
+        }
+    }
+
+    async fn fetch_search(&self, query: &str, limit: u32) -> Result<Vec<Gif>> {
         // Klipy API: api/v1/{app_key}/gifs/search?q={query}&per_page={limit}&customer_id=...
         let url = format!("{}/api/v1/{}/gifs/search", KLIPY_API_BASE, self.app_key);
         let per_page = limit.max(8); // Klipy minimum is 8
@@ -275,4 +326,21 @@ mod tests {
             Some("https://example.com/test-preview.gif")
         );
     }
+
+    #[tokio::test]
+    async fn test_search_served_from_cache() {
+        let client = KlipyClient::new("test-key".to_string());
+        let gif = Gif {
+            id: "cached".to_string(),
+            title: None,
+            url: Some("https://example.com/c.gif".to_string()),
+            preview_url: None,
+            media_formats: None,
+        };
+        client.seed_cache("cats", vec![gif]);
+
+        let gifs = client.search("cats", 8).await.unwrap();
+        assert_eq!(gifs.len(), 1);
+        assert_eq!(gifs[0].id, "cached");
+    }
 }
@@ -13,17 +13,26 @@
 //! - Optional web interface
 //! - Image sharing support
 
+mod check;
+mod config;
 mod crypto;
 mod encryption;
 mod game;
 mod klipy;
 mod media;
 mod network;
+mod paths;
 mod relay_bridge;
 mod room;
 mod ui;
 mod web;
 
+#[cfg(test)]
+mod fuzz_parsers;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
 use anyhow::Result;
 use clap::Parser;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -31,7 +40,7 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use crypto::CryptoManager;
 
 /// OpenWire - Decentralized P2P local network messenger with E2E encryption
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// TCP port for P2P listening (0 = random)
@@ -46,12 +55,23 @@ struct Args {
     #[arg(long, default_value = "3000")]
     web_port: u16,
 
-    /// Bootstrap peer multiaddress
-    #[arg(short, long)]
-    bootstrap: Option<String>,
+    /// Bootstrap peer multiaddress; repeat (or comma-separate) for several
+    #[arg(short, long, value_delimiter = ',')]
+    bootstrap: Vec<String>,
+
+    /// Find peers beyond the LAN through a Kademlia DHT, joined via the
+    /// --bootstrap peers (which need a /p2p/<peer id> suffix)
+    #[arg(long)]
+    dht: bool,
+
+    /// libp2p circuit relay (`.../p2p/<relay id>`) to register with, so
+    /// peers behind NAT can reach us; failed dials are retried through it
+    /// and upgraded to direct connections by hole punching when possible
+    #[arg(long)]
+    p2p_relay: Option<String>,
 
-    /// Display nickname
-    #[arg(short = 'n', long, default_value = "Anonymous")]
+    /// Display nickname (control characters are dropped, max 32 chars)
+    #[arg(short = 'n', long, default_value = "Anonymous", value_parser = parse_nick)]
     nick: String,
 
     /// Log level (trace, debug, info, warn, error)
@@ -68,18 +88,298 @@ struct Args {
     /// Default: wss://openwire-relay.openwire.workers.dev
     #[arg(long)]
     relay_url: Option<String>,
+
+    /// Dial any `/connect` address without asking for confirmation
+    #[arg(long)]
+    trust_dial: bool,
+
+    /// Address patterns dialed without confirmation (comma-separated,
+    /// `*` wildcard), e.g. `/ip4/203.0.113.*`. LAN addresses never prompt.
+    #[arg(long, value_delimiter = ',')]
+    dial_allowlist: Vec<String>,
+
+    /// Maximum number of rooms to be in at once
+    #[arg(long, default_value_t = room::DEFAULT_MAX_ROOMS)]
+    max_rooms: usize,
+
+    /// Messages kept per room for /room history; older ones are dropped
+    #[arg(long, default_value_t = ui::ROOM_HISTORY_LIMIT)]
+    room_history: usize,
+
+    /// Serve Prometheus metrics at /metrics on the web port
+    #[arg(long, requires = "web")]
+    metrics: bool,
+
+    /// Token required by the web API's room endpoints (default: random per run)
+    #[arg(long)]
+    web_token: Option<String>,
+
+    /// Directory for all OpenWire data and config (default: XDG dirs, or ~/.openwire if present)
+    #[arg(long)]
+    data_dir: Option<std::path::PathBuf>,
+
+    /// Keep identity, contacts, rooms and history apart under
+    /// profiles/<name> in the config directory
+    #[arg(long, value_parser = paths::parse_profile)]
+    profile: Option<String>,
+
+    /// Keys file for a stable peer ID, created on first start
+    /// (default: identity.key in the data directory)
+    #[arg(long)]
+    identity: Option<std::path::PathBuf>,
+
+    /// Protect the identity file with a passphrase, asked for on startup.
+    /// An existing unprotected file is encrypted in place.
+    #[arg(long)]
+    encrypted_identity: bool,
+
+    /// Restore keys and rooms from an `/export` backup, replacing the
+    /// identity file (the old one is kept as <file>.before-import)
+    #[arg(long, value_name = "FILE")]
+    import: Option<std::path::PathBuf>,
+
+    /// Drop unsigned general-chat messages (`--strict-auth false` shows them marked unverified)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    strict_auth: bool,
+
+    /// Encrypt general chat with a key derived from this shared password;
+    /// peers without it can't read the broadcasts (default: open network)
+    #[arg(long)]
+    lan_password: Option<String>,
+
+    /// Don't use mDNS; peers only come from /connect, --bootstrap or inbound dials
+    #[arg(long)]
+    no_mdns: bool,
+
+    /// Never dial out (/connect and --bootstrap are refused); peers come from mDNS
+    /// or dial in. With --no-mdns as well, only inbound connections are possible.
+    #[arg(long, conflicts_with_all = ["bootstrap", "dht", "p2p_relay"])]
+    lan_only: bool,
+
+    /// Join the public lobby, a shared chat for saying hello before
+    /// exchanging room invites (encrypted when --lan-password is set)
+    #[arg(long)]
+    lobby: bool,
+
+    /// Enter the shared room for this name on startup, as `/room meet` does
+    #[arg(long, requires = "room_passphrase")]
+    room: Option<String>,
+
+    /// Passphrase for --room
+    #[arg(long, requires = "room")]
+    room_passphrase: Option<String>,
+
+    /// IP address of the interface to listen on (e.g. 127.0.0.1 for local testing)
+    #[arg(long, default_value = "0.0.0.0")]
+    listen_addr: std::net::IpAddr,
+
+    /// AEAD for direct messages we encrypt: chacha20-poly1305 or aes-256-gcm
+    /// (`chacha` and `aes` for short). Received messages are decrypted with
+    /// whichever suite they name.
+    #[arg(long, visible_alias = "aead", default_value_t = encryption::CipherSuite::default())]
+    cipher: encryption::CipherSuite,
+
+    /// Deflate room and direct messages before encrypting them when that
+    /// makes them smaller. Peers older than this option can't read them.
+    #[arg(long)]
+    compress: bool,
+
+    /// Pad direct messages to power-of-two sizes (64, 128, 256 … bytes)
+    /// so their length says less. Peers older than this option can't read them.
+    #[arg(long)]
+    pad: bool,
+
+    /// Run direct messages through a double ratchet, so every message gets
+    /// its own key and old keys can't be recovered. Only used with peers
+    /// that run with --ratchet too; others get the regular scheme.
+    #[arg(long)]
+    ratchet: bool,
+
+    /// Hold chat, room and direct messages from peers until they are
+    /// confirmed with /verify <peer> confirm
+    #[arg(long)]
+    accept_only_verified: bool,
+
+    /// Validate the configuration, print a summary and exit (non-zero on problems)
+    #[arg(long)]
+    check: bool,
+
+    /// Print the effective settings (config file, OPENWIRE_* environment
+    /// and command line merged; secrets redacted) as TOML and exit
+    #[arg(long)]
+    print_config: bool,
+}
+
+/// `--nick`, reduced to characters that are safe to draw in the TUI
+fn parse_nick(s: &str) -> Result<String, String> {
+    let nick = ui::format::sanitize_nick(s);
+    if nick.is_empty() {
+        return Err("nickname has no printable characters".to_string());
+    }
+    Ok(nick)
+}
+
+/// Commands issued once the network is up, before the UI starts
+fn startup_commands(args: &Args) -> Vec<network::NetworkCommand> {
+    let mut commands = Vec::new();
+    if let Some(addr) = &args.p2p_relay {
+        commands.push(network::NetworkCommand::UseRelay(addr.clone()));
+    }
+    for addr in &args.bootstrap {
+        commands.push(network::NetworkCommand::Bootstrap(addr.clone()));
+    }
+    if let (Some(name), Some(passphrase)) = (&args.room, &args.room_passphrase) {
+        commands.push(network::NetworkCommand::JoinWellKnownRoom {
+            name: name.clone(),
+            passphrase: passphrase.clone(),
+        });
+    }
+    commands
+}
+
+/// Open the passphrase-protected identity at `path`, asking for the
+/// passphrase on the terminal. A missing or unprotected file gets a new
+/// passphrase, entered twice.
+fn load_encrypted_identity(path: &std::path::Path) -> Result<CryptoManager> {
+    if crypto::is_encrypted_key_file(path) {
+        let passphrase = rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))?;
+        return CryptoManager::load_encrypted(path, &passphrase);
+    }
+    let crypto = if path.exists() {
+        CryptoManager::load_from(path)?
+    } else {
+        CryptoManager::new()?
+    };
+    let passphrase = rpassword::prompt_password(format!("New passphrase for {}: ", path.display()))?;
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(anyhow::anyhow!("The passphrases don't match"));
+    }
+    crypto.save_encrypted(path, &passphrase)?;
+    Ok(crypto)
+}
+
+/// Restore the keys and rooms of the backup at `bundle`, asking for its
+/// passphrase, and make the keys the identity at `path`. With
+/// `encrypted`, the identity file is protected with the same passphrase.
+fn import_bundle(
+    bundle: &std::path::Path,
+    path: &std::path::Path,
+    encrypted: bool,
+) -> Result<(CryptoManager, Vec<room::Room>)> {
+    let data = std::fs::read(bundle)?;
+    let passphrase = rpassword::prompt_password(format!("Passphrase for {}: ", bundle.display()))?;
+    let (crypto, rooms) = CryptoManager::import_bundle(&data, &passphrase)?;
+    if path.exists() {
+        let mut old = path.as_os_str().to_owned();
+        old.push(".before-import");
+        let old = std::path::PathBuf::from(old);
+        if old.exists() {
+            return Err(anyhow::anyhow!("{} is in the way; move it first", old.display()));
+        }
+        std::fs::rename(path, &old)?;
+        eprintln!("Kept the previous identity as {}", old.display());
+    }
+    if encrypted {
+        crypto.save_encrypted(path, &passphrase)?;
+    } else {
+        crypto.save_to(path)?;
+    }
+    eprintln!("Restored {} with {} room(s)", crypto.peer_id(), rooms.len());
+    Ok((crypto, rooms))
+}
+
+/// Why OpenWire stopped. `main` exits with its code so scripts and
+/// supervisors (systemd, docker) can tell the cases apart. Unreadable
+/// identity, contacts or other data files count as `Config`; anything else
+/// that fails before the network starts exits with 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitReason {
+    /// The user quit
+    UserQuit,
+    /// Bad settings in the config file, environment or command line, or
+    /// files they point at that can't be used
+    Config,
+    /// The network couldn't start, failed, or stopped before the user quit
+    NetworkFailed,
+    /// The terminal UI failed
+    UiFailed,
+}
+
+impl ExitReason {
+    fn code(self) -> u8 {
+        match self {
+            Self::UserQuit => 0,
+            Self::Config => 2,
+            Self::NetworkFailed => 3,
+            Self::UiFailed => 4,
+        }
+    }
+
+    /// The reason from how the UI and network task ended. `network_lost`
+    /// is whether the UI saw the network go away; `network` is `None` when
+    /// the task didn't stop in time after the UI exited.
+    fn from_shutdown(ui: &Result<()>, network_lost: bool, network: Option<&Result<()>>) -> Self {
+        if network_lost || matches!(network, Some(Err(_))) {
+            Self::NetworkFailed
+        } else if ui.is_err() {
+            Self::UiFailed
+        } else {
+            Self::UserQuit
+        }
+    }
+}
+
+/// Report a failure to start and exit with `reason`'s code
+fn startup_failed(reason: ExitReason, e: anyhow::Error) -> std::process::ExitCode {
+    tracing::error!("{:#}", e);
+    eprintln!("error: {:#}", e);
+    reason.into()
+}
+
+impl From<ExitReason> for std::process::ExitCode {
+    fn from(reason: ExitReason) -> Self {
+        Self::from(reason.code())
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+async fn main() -> Result<std::process::ExitCode> {
+    let argv: Vec<_> = std::env::args_os().collect();
+    let merged = match config::load(argv.clone(), &|name| std::env::var(name).ok()) {
+        Ok(merged) => merged,
+        // --help and --version arrive as errors too, but aren't failures
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            return Ok(ExitReason::Config.into());
+        }
+    };
+    if merged.args.print_config {
+        print!("{}", merged.to_toml());
+        return Ok(ExitReason::UserQuit.into());
+    }
+    let config = config::Config::new(argv, merged);
+    let args = config.args().clone();
+    if args.check {
+        let report = check::check_config(&args);
+        report.print();
+        let reason = if report.is_ok() { ExitReason::UserQuit } else { ExitReason::Config };
+        return Ok(reason.into());
+    }
 
     // Initialize logging — write to file to avoid polluting the TUI
-    let log_dir = dirs_next::home_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join(".openwire");
-    std::fs::create_dir_all(&log_dir)?;
-    let log_file = std::fs::File::create(log_dir.join("openwire.log"))?;
+    let paths = paths::init(args.data_dir.clone(), args.profile.clone());
+    if let Err(e) = std::fs::create_dir_all(paths.data_dir()) {
+        let e = anyhow::anyhow!("Can't create data directory {}: {}", paths.data_dir().display(), e);
+        return Ok(startup_failed(ExitReason::Config, e));
+    }
+    let log_file = match std::fs::File::create(paths.log_path()) {
+        Ok(file) => file,
+        Err(e) => {
+            let e = anyhow::anyhow!("Can't open log file {}: {}", paths.log_path().display(), e);
+            return Ok(startup_failed(ExitReason::Config, e));
+        }
+    };
 
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(&args.log_level))
@@ -88,48 +388,140 @@ async fn main() -> Result<()> {
 
     tracing::info!("Starting OpenWire with End-to-End Encryption...");
 
-    // Initialize cryptographic manager
-    let crypto = CryptoManager::new()?;
+    // Reuse the stored identity so the peer ID survives restarts
+    let identity_path = args.identity.clone().unwrap_or_else(|| paths.identity_path());
+    let loaded = match &args.import {
+        Some(bundle) => import_bundle(bundle, &identity_path, args.encrypted_identity)
+            .map_err(|e| anyhow::anyhow!("Can't import {}: {}", bundle.display(), e)),
+        None => if args.encrypted_identity {
+            load_encrypted_identity(&identity_path)
+        } else {
+            CryptoManager::load_or_create(&identity_path)
+        }
+        .map(|crypto| (crypto, Vec::new()))
+        .map_err(|e| anyhow::anyhow!("Can't load identity {}: {}", identity_path.display(), e)),
+    };
+    let (mut crypto, imported_rooms) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => return Ok(startup_failed(ExitReason::Config, e)),
+    };
+    crypto.set_cipher(args.cipher);
+    crypto.set_compression(args.compress);
+    crypto.set_padding(args.pad);
+    crypto.set_ratchet(args.ratchet);
+    let contacts_path = paths.contacts_path();
+    match crypto::Contacts::load(&contacts_path) {
+        Ok(contacts) => crypto.set_contacts(contacts),
+        Err(e) => {
+            let e = anyhow::anyhow!("Can't load contacts {}: {}", contacts_path.display(), e);
+            return Ok(startup_failed(ExitReason::Config, e));
+        }
+    }
     let peer_id_display = crypto.peer_id();
     tracing::info!("Peer ID: {}", peer_id_display);
 
     // Initialize network layer
-    let (network, handle) = network::Network::new(crypto, args.port).await?;
+    let options = network::NetworkOptions {
+        mdns: !args.no_mdns,
+        lan_only: args.lan_only,
+        listen_ip: args.listen_addr,
+        dht: args.dht,
+        relay: args.p2p_relay.is_some(),
+    };
+    let (mut network, handle) = match network::Network::with_options(crypto, args.port, options).await {
+        Ok(started) => started,
+        Err(e) => return Ok(startup_failed(ExitReason::NetworkFailed, e.context("Can't start the network"))),
+    };
+    network.set_strict_auth(args.strict_auth);
+    network.set_identity_path(identity_path);
+    let blocklist_path = paths.blocklist_path();
+    match network::blocklist::Blocklist::load(&blocklist_path) {
+        Ok(blocklist) => network.set_blocklist(blocklist),
+        Err(e) => {
+            let e = anyhow::anyhow!("Can't load blocklist {}: {}", blocklist_path.display(), e);
+            return Ok(startup_failed(ExitReason::Config, e));
+        }
+    }
+    let known_peers_path = paths.known_peers_path();
+    match network::addressbook::AddressBook::load(&known_peers_path) {
+        Ok(address_book) => network.set_address_book(address_book),
+        Err(e) => {
+            let e = anyhow::anyhow!("Can't load address book {}: {}", known_peers_path.display(), e);
+            return Ok(startup_failed(ExitReason::Config, e));
+        }
+    }
+    if args.lobby
+        && let Err(e) = network.join_lobby()
+    {
+        return Ok(startup_failed(ExitReason::NetworkFailed, e.context("Can't join the lobby")));
+    }
+    if let Some(password) = &args.lan_password {
+        if let Err(e) = network.set_lan_password(password) {
+            return Ok(startup_failed(ExitReason::Config, e.context("Can't use the LAN password")));
+        }
+        tracing::info!("General chat encrypted with the LAN password");
+    }
     let local_peer_id = network.local_peer_id().to_string();
+    network
+        .room_manager()
+        .write()
+        .await
+        .set_max_rooms(args.max_rooms);
+    network.room_manager().write().await.set_compression(args.compress);
+    // Group keys are saved in the clear, like an unencrypted identity, so
+    // with --encrypted-identity rooms are only kept by /export
+    let rooms_path = (!args.encrypted_identity).then(|| paths.rooms_path());
+    if let Some(rooms_path) = &rooms_path {
+        match room::RoomManager::load_saved(rooms_path) {
+            Ok(saved) => network.restore_rooms(saved).await,
+            Err(e) => {
+                let e = anyhow::anyhow!("Can't load rooms {}: {}", rooms_path.display(), e);
+                return Ok(startup_failed(ExitReason::Config, e));
+            }
+        }
+    }
+    network.restore_rooms(imported_rooms).await;
+    if let Some(rooms_path) = rooms_path {
+        network.room_manager().write().await.set_path(rooms_path);
+    }
+    network.set_nick(&args.nick);
     tracing::info!("Network initialized: {}", local_peer_id);
 
     // Save command sender for shutdown
     let shutdown_sender = handle.command_sender.clone();
 
-    // If bootstrap peer provided, send a connect command
-    if let Some(bootstrap_addr) = &args.bootstrap {
-        handle
-            .command_sender
-            .send(network::NetworkCommand::Connect(bootstrap_addr.clone()))
-            .await?;
+    // Register with the relay, dial the bootstrap peers and enter the --room, if given
+    let mut startup = startup_commands(&args);
+    // Pick up where the last session left off
+    if !args.lan_only {
+        startup.push(network::NetworkCommand::ReconnectAll);
+    }
+    for cmd in startup {
+        if let Err(e) = handle.send_command(cmd).await {
+            return Ok(startup_failed(ExitReason::NetworkFailed, e));
+        }
     }
 
     // Spawn the network event loop
-    let network_task = tokio::spawn(async move {
-        if let Err(e) = network::run_network(network).await {
-            tracing::error!("Network error: {}", e);
-        }
-    });
+    let network_task = tokio::spawn(network::run_network(network));
 
     // Start web interface if --web flag is set
-    if args.web {
+    let web_token = args.web.then(|| args.web_token.clone().unwrap_or_else(web::generate_token));
+    let (web_payloads, _) = tokio::sync::broadcast::channel(64);
+    if let Some(web_token) = web_token.clone() {
         let web_port = args.web_port;
         let web_command_tx = handle.command_sender.clone();
         let web_event_broadcast = handle.event_broadcast.clone();
-        let web_event_tx = handle.event_tx.clone();
-        let web_peer_id = local_peer_id.clone();
+        let web_payloads = web_payloads.clone();
+        let web_metrics = args.metrics.then(|| handle.metrics.clone());
         tokio::spawn(async move {
             if let Err(e) = web::start_web_server(
                 web_port,
-                web_peer_id,
                 web_command_tx,
                 web_event_broadcast,
-                web_event_tx,
+                web_payloads,
+                web_token,
+                web_metrics,
             )
             .await
             {
@@ -146,8 +538,8 @@ async fn main() -> Result<()> {
             .unwrap_or_else(|| "wss://openwire-relay.openwire.workers.dev".to_string());
         let relay_command_tx = handle.command_sender.clone();
         let relay_event_broadcast = handle.event_broadcast.clone();
-        let relay_event_tx = handle.event_tx.clone();
         let relay_nick = args.nick.clone();
+        let relay_lan_protected = args.lan_password.is_some();
         tokio::spawn(async move {
             loop {
                 if let Err(e) = relay_bridge::run_relay_bridge(
@@ -155,7 +547,7 @@ async fn main() -> Result<()> {
                     relay_nick.clone(),
                     relay_command_tx.clone(),
                     relay_event_broadcast.clone(),
-                    relay_event_tx.clone(),
+                    relay_lan_protected,
                 )
                 .await
                 {
@@ -180,19 +572,53 @@ async fn main() -> Result<()> {
     let nick = args.nick.clone();
     let web_port = args.web.then_some(args.web_port);
     let relay_active = args.relay || args.relay_url.is_some();
-    let mut ui = ui::UiApp::new(
+    let mut ui = match ui::UiApp::new(
         nick,
         local_peer_id,
         handle.command_sender,
         handle.event_receiver,
         web_port,
         relay_active,
-    )?;
+        network::dial::DialPolicy::new(args.trust_dial, args.dial_allowlist.clone()),
+    ) {
+        Ok(ui) => ui,
+        Err(e) => return Ok(startup_failed(ExitReason::UiFailed, e.context("Can't start the UI"))),
+    };
+    if let Some(token) = &web_token {
+        ui.show_web_token(token);
+        // The TUI doesn't show JSON/binary broadcasts; web clients get them
+        ui.on_payload(move |from, content_type, data| {
+            let payload = web::Payload { from: from.to_string(), content_type, data: data.to_vec() };
+            let _ = web_payloads.send(payload);
+        });
+    }
+    if args.accept_only_verified {
+        ui.accept_only_verified();
+    }
+    ui.set_room_history_limit(args.room_history);
+    if let Some(profile) = &args.profile {
+        ui.set_profile(profile);
+    }
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    ui.watch_config(Arc::new(RwLock::new(config)), reload_requested.clone());
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{SignalKind, signal};
+        let Ok(mut hangups) = signal(SignalKind::hangup()) else {
+            tracing::warn!("Can't listen for SIGHUP; use /reload instead");
+            return;
+        };
+        while hangups.recv().await.is_some() {
+            reload_requested.store(true, Ordering::Relaxed);
+        }
+    });
 
     // Run UI — blocks until user quits
-    if let Err(e) = ui.run().await {
+    let ui_result = ui.run().await;
+    if let Err(e) = &ui_result {
         tracing::error!("UI error: {}", e);
     }
+    let network_lost = ui.network_lost();
 
     // UI exited — trigger graceful shutdown
     let _ = shutdown_sender
@@ -200,10 +626,95 @@ async fn main() -> Result<()> {
         .await;
 
     // Wait for the network task to finish
-    tokio::select! {
-        _ = network_task => {}
-        _ = tokio::time::sleep(std::time::Duration::from_secs(3)) => {}
+    let network_result = tokio::select! {
+        joined = network_task => {
+            let result = joined.unwrap_or_else(|e| Err(anyhow::anyhow!("network task panicked: {}", e)));
+            if let Err(e) = &result {
+                tracing::error!("Network error: {}", e);
+            }
+            Some(result)
+        }
+        _ = tokio::time::sleep(std::time::Duration::from_secs(3)) => {
+            tracing::warn!("Network did not shut down within 3s; exiting anyway");
+            None
+        }
+    };
+
+    let reason = ExitReason::from_shutdown(&ui_result, network_lost, network_result.as_ref());
+    tracing::info!("Exiting: {:?}", reason);
+    Ok(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_flags_join_on_startup() {
+        let args = Args::try_parse_from(["openwire", "--room", "kiosk", "--room-passphrase", "s3cret"]).unwrap();
+        match &startup_commands(&args)[..] {
+            [network::NetworkCommand::JoinWellKnownRoom { name, passphrase }] => {
+                assert_eq!(name, "kiosk");
+                assert_eq!(passphrase, "s3cret");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(Args::try_parse_from(["openwire", "--room", "kiosk"]).is_err());
+        assert!(startup_commands(&Args::try_parse_from(["openwire"]).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_bootstrap_repeats() {
+        let args = Args::try_parse_from([
+            "openwire", "--dht", "--bootstrap", "/ip4/10.0.0.1/tcp/4001", "-b", "/ip4/10.0.0.2/tcp/4001,/ip4/10.0.0.3/tcp/4001",
+        ])
+        .unwrap();
+        assert!(args.dht);
+        let dialed: Vec<String> = startup_commands(&args)
+            .into_iter()
+            .map(|cmd| match cmd {
+                network::NetworkCommand::Bootstrap(addr) => addr,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(dialed, ["/ip4/10.0.0.1/tcp/4001", "/ip4/10.0.0.2/tcp/4001", "/ip4/10.0.0.3/tcp/4001"]);
+    }
+
+    #[test]
+    fn test_lan_only_conflicts_with_dialing_out() {
+        for flags in [&["--bootstrap", "/ip4/10.0.0.1/tcp/4001"][..], &["--dht"], &["--p2p-relay", "/ip4/10.0.0.1/tcp/4001"]] {
+            let argv = ["openwire", "--lan-only"].iter().chain(flags);
+            let err = Args::try_parse_from(argv).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn test_aead_is_an_alias_for_cipher() {
+        let args = Args::try_parse_from(["openwire", "--aead", "aes"]).unwrap();
+        assert_eq!(args.cipher, encryption::CipherSuite::Aes256Gcm);
+        let args = Args::try_parse_from(["openwire"]).unwrap();
+        assert_eq!(args.cipher, encryption::CipherSuite::ChaCha20Poly1305);
     }
 
-    Ok(())
+    #[test]
+    fn test_exit_reason_from_shutdown() {
+        let ok = || -> Result<()> { Ok(()) };
+        let failed = || -> Result<()> { Err(anyhow::anyhow!("listener failed")) };
+
+        assert_eq!(ExitReason::from_shutdown(&ok(), false, Some(&ok())), ExitReason::UserQuit);
+        // A slow network shutdown after quitting is still a quit
+        assert_eq!(ExitReason::from_shutdown(&ok(), false, None), ExitReason::UserQuit);
+        assert_eq!(ExitReason::from_shutdown(&failed(), false, Some(&ok())), ExitReason::UiFailed);
+
+        // A fatal network error wins, whether the UI noticed it or not
+        let reason = ExitReason::from_shutdown(&ok(), false, Some(&failed()));
+        assert_eq!(reason, ExitReason::NetworkFailed);
+        assert_eq!(reason.code(), 3);
+        assert_eq!(ExitReason::from_shutdown(&failed(), true, None), ExitReason::NetworkFailed);
+
+        assert_eq!(ExitReason::UserQuit.code(), 0);
+        assert_eq!(ExitReason::Config.code(), 2);
+    }
 }
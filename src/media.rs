@@ -71,8 +71,11 @@ pub struct ImageMessage {
     pub sender_public_key: Vec<u8>,
     /// Timestamp
     pub timestamp: u64,
-    /// Signature over metadata + data
+    /// Signature over metadata + data + caption
     pub signature: Vec<u8>,
+    /// Text shown under the image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
 }
 
 impl ImageMessage {
@@ -82,6 +85,7 @@ impl ImageMessage {
         filename: String,
         format: ImageFormat,
         data: Vec<u8>,
+        caption: Option<String>,
     ) -> Result<Self> {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
@@ -98,14 +102,7 @@ impl ImageMessage {
             size: data.len(),
         };
 
-        // Sign: filename || format || size || timestamp || data
-        let mut sign_data = Vec::new();
-        sign_data.extend_from_slice(meta.filename.as_bytes());
-        sign_data.extend_from_slice(&[meta.format as u8]);
-        sign_data.extend_from_slice(&meta.size.to_le_bytes());
-        sign_data.extend_from_slice(&timestamp.to_le_bytes());
-        sign_data.extend_from_slice(&data);
-
+        let sign_data = Self::signed_bytes(&meta, timestamp, &data, caption.as_deref());
         let signature = identity.sign(&sign_data)?;
 
         Ok(Self {
@@ -114,9 +111,25 @@ impl ImageMessage {
             sender_public_key: identity.public_key().to_vec(),
             timestamp,
             signature: signature.to_bytes().to_vec(),
+            caption,
         })
     }
 
+    /// Sign: filename || format || size || timestamp || data [|| caption_len || caption]
+    fn signed_bytes(meta: &ImageMeta, timestamp: u64, data: &[u8], caption: Option<&str>) -> Vec<u8> {
+        let mut sign_data = Vec::new();
+        sign_data.extend_from_slice(meta.filename.as_bytes());
+        sign_data.extend_from_slice(&[meta.format as u8]);
+        sign_data.extend_from_slice(&meta.size.to_le_bytes());
+        sign_data.extend_from_slice(&timestamp.to_le_bytes());
+        sign_data.extend_from_slice(data);
+        if let Some(caption) = caption {
+            sign_data.extend_from_slice(&(caption.len() as u64).to_le_bytes());
+            sign_data.extend_from_slice(caption.as_bytes());
+        }
+        sign_data
+    }
+
     /// Verify the image signature
     pub fn verify(&self) -> Result<()> {
         if self.sender_public_key.len() != 32 {
@@ -133,12 +146,8 @@ impl ImageMessage {
         let mut sig_bytes = [0u8; 64];
         sig_bytes.copy_from_slice(&self.signature);
 
-        let mut sign_data = Vec::new();
-        sign_data.extend_from_slice(self.meta.filename.as_bytes());
-        sign_data.extend_from_slice(&[self.meta.format as u8]);
-        sign_data.extend_from_slice(&self.meta.size.to_le_bytes());
-        sign_data.extend_from_slice(&self.timestamp.to_le_bytes());
-        sign_data.extend_from_slice(&self.data);
+        let sign_data =
+            Self::signed_bytes(&self.meta, self.timestamp, &self.data, self.caption.as_deref());
 
         crate::crypto::verify_with_key(
             &sign_data,
@@ -216,34 +225,67 @@ fn parse_gif_dimensions(data: &[u8]) -> (u32, u32) {
     (width, height)
 }
 
-/// Generate ASCII art from image data (simple block-based)
-pub fn generate_ascii_art(width: u32, height: u32, _data: &[u8]) -> String {
-    // Simple placeholder ASCII art
-    let chars = ['█', '▓', '▒', '░', ' '];
-    let mut result = String::new();
+/// Block characters from darkest to brightest
+pub const ASCII_RAMP: [char; 5] = [' ', '░', '▒', '▓', '█'];
 
-    // Create a simple frame
+/// Generate ASCII art from image data (simple block-based).
+///
+/// With `image-support` the image is decoded and downsampled to the cell
+/// grid, one block character per cell by brightness. Without it, or if the
+/// data doesn't decode, a gradient placeholder of the same size is drawn.
+pub fn generate_ascii_art(width: u32, height: u32, data: &[u8]) -> String {
     let display_width = (width as usize / 8).clamp(10, 40);
     let display_height = (height as usize / 16).clamp(5, 20);
 
+    #[cfg(feature = "image-support")]
+    if let Ok(img) = image_support::load_image(data) {
+        let luma = img
+            .resize_exact(
+                display_width as u32,
+                display_height as u32,
+                image::imageops::FilterType::Triangle,
+            )
+            .to_luma8();
+        return luminance_art(luma.as_raw(), display_width, display_height);
+    }
+    #[cfg(not(feature = "image-support"))]
+    let _ = data;
+
+    // Placeholder: a gradient pattern for visual effect
+    framed(display_width, display_height, |x, y| {
+        ASCII_RAMP[ASCII_RAMP.len() - 1 - (x + y) % ASCII_RAMP.len()]
+    })
+}
+
+/// Low-res preview of a `width` x `height` grid of luminance values
+/// (row-major, 0 = black), one `ASCII_RAMP` character per value
+pub fn luminance_art(luma: &[u8], width: usize, height: usize) -> String {
+    framed(width, height, |x, y| {
+        let l = luma.get(y * width + x).copied().unwrap_or(0) as usize;
+        ASCII_RAMP[l * ASCII_RAMP.len() / 256]
+    })
+}
+
+/// Draw a box of `width` x `height` cells filled by `cell(x, y)`
+fn framed(width: usize, height: usize, cell: impl Fn(usize, usize) -> char) -> String {
+    let mut result = String::new();
+
     result.push('┌');
-    for _ in 0..display_width {
+    for _ in 0..width {
         result.push('─');
     }
     result.push_str("┐\n");
 
-    for y in 0..display_height {
+    for y in 0..height {
         result.push('│');
-        for x in 0..display_width {
-            // Create a gradient pattern for visual effect
-            let idx = (x + y) % chars.len();
-            result.push(chars[idx]);
+        for x in 0..width {
+            result.push(cell(x, y));
         }
         result.push_str("│\n");
     }
 
     result.push('└');
-    for _ in 0..display_width {
+    for _ in 0..width {
         result.push('─');
     }
     result.push('┘');
@@ -344,10 +386,58 @@ mod tests {
         assert_eq!(ImageFormat::Jpeg.mime_type(), "image/jpeg");
     }
 
+    #[test]
+    fn test_image_caption_is_signed() {
+        let identity = crate::crypto::Identity::generate().unwrap();
+        let mut msg = ImageMessage::new(
+            &identity,
+            "cat.png".to_string(),
+            ImageFormat::Png,
+            vec![0u8; 32],
+            Some("my cat".to_string()),
+        )
+        .unwrap();
+        assert!(msg.verify().is_ok());
+
+        msg.caption = Some("not my cat".to_string());
+        assert!(msg.verify().is_err());
+        msg.caption = None;
+        assert!(msg.verify().is_err());
+    }
+
     #[test]
     fn test_ascii_art_generation() {
         let art = generate_ascii_art(100, 100, &[]);
         assert!(art.contains('┌'));
         assert!(art.contains('└'));
     }
+
+    /// Characters inside the frame of a preview
+    fn cells(art: &str) -> Vec<char> {
+        art.lines()
+            .filter(|l| l.starts_with('│'))
+            .flat_map(|l| l.trim_matches('│').chars().collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn test_black_maps_to_darkest_characters() {
+        let art = luminance_art(&[0u8; 12], 4, 3);
+        assert_eq!(art.lines().count(), 5);
+        assert!(cells(&art).iter().all(|c| *c == ASCII_RAMP[0]));
+
+        let white = luminance_art(&[255u8; 12], 4, 3);
+        assert!(cells(&white).iter().all(|c| *c == ASCII_RAMP[ASCII_RAMP.len() - 1]));
+    }
+
+    #[cfg(feature = "image-support")]
+    #[test]
+    fn test_decoded_black_image_is_dark() {
+        let img = image::RgbImage::from_pixel(160, 80, image::Rgb([0, 0, 0]));
+        let mut png = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let art = generate_ascii_art(160, 80, &png);
+        assert!(cells(&art).iter().all(|c| *c == ASCII_RAMP[0]));
+    }
 }
@@ -0,0 +1,260 @@
+//! Peers we were connected to and where to reach them
+//!
+//! For every peer we remember the addresses we dialed it at or that it
+//! told us it listens on (through identify), and when we last saw it.
+//! Only chat peers, the ones that completed a key exchange with us, are
+//! redialed and saved: DHT, relay and bootstrap nodes are infrastructure
+//! that finds us again on its own. When a chat peer's connection drops it
+//! is redialed with `reconnect_backoff`, up to `MAX_RECONNECT_ATTEMPTS`
+//! times. The book is saved as JSON when a chat peer's addresses change or
+//! its last-seen time moves by `LAST_SEEN_SAVE_SECS`, so recently seen
+//! peers can be redialed after a restart; peers not seen for
+//! `RECENT_PEER_SECS` are dropped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use libp2p::{Multiaddr, PeerId};
+
+/// Addresses kept per peer; the least recently seen are dropped beyond this
+pub const MAX_ADDRS_PER_PEER: usize = 8;
+/// Peers seen within this many seconds are redialed on startup; older
+/// ones are dropped from the book
+pub const RECENT_PEER_SECS: u64 = 7 * 24 * 60 * 60;
+/// How far a peer's last-seen time may move before it's saved again
+pub const LAST_SEEN_SAVE_SECS: u64 = 60 * 60;
+/// Redials of a dropped peer before giving up until `/reconnect`
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 12;
+/// Delay before the first redial of a peer that dropped
+pub const RECONNECT_BASE: Duration = Duration::from_secs(1);
+/// Longest delay between two redials of the same peer
+pub const RECONNECT_MAX: Duration = Duration::from_secs(60);
+
+/// Delay before redial number `attempt` (counting from 0): doubles from
+/// `RECONNECT_BASE` up to `RECONNECT_MAX`
+pub fn reconnect_backoff(attempt: u32) -> Duration {
+    RECONNECT_BASE.saturating_mul(1 << attempt.min(16)).min(RECONNECT_MAX)
+}
+
+/// What we know about one peer
+#[derive(Debug, Clone, Default)]
+pub struct KnownPeer {
+    /// Most recently seen first
+    pub addrs: Vec<Multiaddr>,
+    /// Unix time we were last connected
+    pub last_seen: u64,
+    /// Completed a key exchange with us: an OpenWire user rather than
+    /// network infrastructure
+    pub chatted: bool,
+    /// `last_seen` as of the last save
+    saved_seen: u64,
+}
+
+/// How a peer is saved: addresses as strings, keyed by peer ID
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedPeer {
+    addrs: Vec<String>,
+    last_seen: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    /// Where the book is saved; `None` keeps it in memory only
+    path: Option<PathBuf>,
+    peers: HashMap<PeerId, KnownPeer>,
+}
+
+impl AddressBook {
+    /// Load the book at `path`, starting empty if the file doesn't exist
+    pub fn load(path: &Path) -> Result<Self> {
+        let saved: BTreeMap<String, SavedPeer> = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut peers = HashMap::new();
+        for (id, peer) in saved {
+            let peer_id: PeerId = id
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid peer ID in address book: {}", id))?;
+            let addrs = peer
+                .addrs
+                .iter()
+                .map(|a| a.parse().map_err(|_| anyhow::anyhow!("Invalid address of {} in address book: {}", id, a)))
+                .collect::<Result<_>>()?;
+            let last_seen = peer.last_seen;
+            peers.insert(peer_id, KnownPeer { addrs, last_seen, chatted: true, saved_seen: last_seen });
+        }
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            peers,
+        })
+    }
+
+    fn save(&mut self) -> Result<()> {
+        for peer in self.peers.values_mut() {
+            peer.saved_seen = peer.last_seen;
+        }
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let saved: BTreeMap<String, SavedPeer> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.chatted)
+            .map(|(id, peer)| {
+                let addrs = peer.addrs.iter().map(Multiaddr::to_string).collect();
+                (id.to_string(), SavedPeer { addrs, last_seen: peer.last_seen })
+            })
+            .collect();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(&saved)?)?;
+        Ok(())
+    }
+
+    /// Note that we're connected to `peer_id` at `now`, reachable at
+    /// `addrs` (which may be empty). Peers not seen for `RECENT_PEER_SECS`
+    /// are dropped along the way.
+    pub fn record(&mut self, peer_id: PeerId, addrs: impl IntoIterator<Item = Multiaddr>, now: u64) -> Result<()> {
+        let before = self.peers.len();
+        self.peers
+            .retain(|id, peer| *id == peer_id || now.saturating_sub(peer.last_seen) <= RECENT_PEER_SECS);
+        let pruned = self.peers.len() != before;
+
+        let peer = self.peers.entry(peer_id).or_default();
+        let old_addrs = peer.addrs.clone();
+        for addr in addrs {
+            peer.addrs.retain(|a| *a != addr);
+            peer.addrs.insert(0, addr);
+        }
+        peer.addrs.truncate(MAX_ADDRS_PER_PEER);
+        peer.last_seen = now;
+        let changed =
+            peer.chatted && (peer.addrs != old_addrs || now.saturating_sub(peer.saved_seen) >= LAST_SEEN_SAVE_SECS);
+        if changed || pruned {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Note that `peer_id` completed a key exchange, so it's worth
+    /// redialing and saving
+    pub fn mark_chatted(&mut self, peer_id: PeerId) -> Result<()> {
+        let peer = self.peers.entry(peer_id).or_default();
+        if peer.chatted {
+            return Ok(());
+        }
+        peer.chatted = true;
+        self.save()
+    }
+
+    /// Forget a peer. Returns false if it wasn't known.
+    pub fn remove(&mut self, peer_id: &PeerId) -> Result<bool> {
+        let Some(peer) = self.peers.remove(peer_id) else {
+            return Ok(false);
+        };
+        if peer.chatted {
+            self.save()?;
+        }
+        Ok(true)
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<&KnownPeer> {
+        self.peers.get(peer_id)
+    }
+
+    /// Chat peers with addresses that were seen since `now - max_age_secs`,
+    /// most recently seen first
+    pub fn recent(&self, now: u64, max_age_secs: u64) -> Vec<PeerId> {
+        let mut recent: Vec<(&PeerId, &KnownPeer)> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| {
+                peer.chatted && !peer.addrs.is_empty() && now.saturating_sub(peer.last_seen) <= max_age_secs
+            })
+            .collect();
+        recent.sort_by_key(|(_, peer)| std::cmp::Reverse(peer.last_seen));
+        recent.into_iter().map(|(id, _)| *id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_backoff_doubles_up_to_the_cap() {
+        let schedule: Vec<u64> = (0..9).map(|attempt| reconnect_backoff(attempt).as_secs()).collect();
+        assert_eq!(schedule, [1, 2, 4, 8, 16, 32, 60, 60, 60]);
+        assert_eq!(reconnect_backoff(u32::MAX), RECONNECT_MAX);
+    }
+
+    #[test]
+    fn test_address_book_persists() {
+        let dir = std::env::temp_dir().join(format!("openwire-addressbook-{}", std::process::id()));
+        let path = dir.join("known_peers.json");
+        let (old, new, gone) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let a: Multiaddr = "/ip4/10.0.0.2/tcp/4001".parse().unwrap();
+        let b: Multiaddr = "/ip4/192.168.1.9/tcp/4001".parse().unwrap();
+
+        let mut book = AddressBook::load(&path).unwrap();
+        for (peer, addrs, at) in [(old, vec![a.clone()], 1_000), (new, vec![a.clone(), b.clone()], 5_000)] {
+            book.record(peer, addrs, at).unwrap();
+            book.mark_chatted(peer).unwrap();
+        }
+        // Seen again at an address it had: moved to the front, not repeated
+        book.record(new, [a.clone()], 6_000).unwrap();
+        book.record(gone, [b.clone()], 6_000).unwrap();
+        book.mark_chatted(gone).unwrap();
+        assert!(book.remove(&gone).unwrap());
+        assert!(!book.remove(&gone).unwrap());
+        // Connected without a dialable address: not worth redialing
+        let silent = PeerId::random();
+        book.record(silent, [], 6_000).unwrap();
+        book.mark_chatted(silent).unwrap();
+        // Infrastructure we never exchanged keys with isn't kept
+        let relay = PeerId::random();
+        book.record(relay, [b.clone()], 6_000).unwrap();
+        assert!(book.recent(6_000, RECENT_PEER_SECS).iter().all(|p| *p != relay));
+
+        let reloaded = AddressBook::load(&path).unwrap();
+        assert_eq!(reloaded.get(&new).unwrap().addrs, [a.clone(), b.clone()]);
+        assert_eq!(reloaded.get(&new).unwrap().last_seen, 6_000);
+        assert!(reloaded.get(&gone).is_none());
+        assert!(reloaded.get(&relay).is_none());
+        assert_eq!(reloaded.recent(6_000, RECENT_PEER_SECS), [new, old]);
+        assert_eq!(reloaded.recent(6_000, 2_000), [new]);
+
+        // Seeing a peer again at known addresses is only saved once its
+        // last-seen time has moved far enough
+        std::fs::remove_file(&path).unwrap();
+        book.record(new, [a.clone()], 6_000 + LAST_SEEN_SAVE_SECS - 1).unwrap();
+        assert!(!path.exists());
+        book.record(new, [a.clone()], 6_000 + LAST_SEEN_SAVE_SECS).unwrap();
+        assert!(path.exists());
+        // Peers not seen for a week are dropped
+        book.record(new, [a.clone()], 1_000 + RECENT_PEER_SECS + 1).unwrap();
+        assert!(AddressBook::load(&path).unwrap().get(&old).is_none());
+
+        std::fs::write(&path, br#"{"not-a-peer-id": {"addrs": [], "last_seen": 0}}"#).unwrap();
+        assert!(AddressBook::load(&path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_addresses_per_peer_are_capped() {
+        let mut book = AddressBook::default();
+        let peer = PeerId::random();
+        let addrs: Vec<Multiaddr> = (0..MAX_ADDRS_PER_PEER + 2)
+            .map(|port| format!("/ip4/10.0.0.2/tcp/{}", port).parse().unwrap())
+            .collect();
+        book.record(peer, addrs.clone(), 1).unwrap();
+        let kept = &book.get(&peer).unwrap().addrs;
+        assert_eq!(kept.len(), MAX_ADDRS_PER_PEER);
+        assert_eq!(kept[0], *addrs.last().unwrap());
+    }
+}
@@ -0,0 +1,165 @@
+//! Multi-file transfers
+//!
+//! `/send a b c` and `/senddir <dir>` collect files into a `FileBatch`. Each
+//! file is still sent as its own `FileTransferMessage`; the batch id only ties
+//! the progress events together so the UI can show "3/5 files sent".
+
+use std::path::{Path, PathBuf};
+
+use super::MAX_FILE_SIZE;
+
+/// Most bytes a single batch may send in total
+pub const MAX_BATCH_SIZE: u64 = 16 * 1_048_576;
+
+/// Files to send together under one batch id
+#[derive(Debug, Clone)]
+pub struct FileBatch {
+    /// Shared id reported with every progress event
+    pub id: String,
+    /// Files to send, in order
+    pub files: Vec<PathBuf>,
+    /// Paths left out, with the reason shown to the user
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+impl FileBatch {
+    /// Batch of explicitly named files
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Self {
+        let mut batch = Self::empty();
+        let mut total = 0u64;
+        for path in paths {
+            batch.push(path.as_ref().to_path_buf(), &mut total);
+        }
+        batch
+    }
+
+    /// Batch of the regular files in `dir`, sorted by path. Subdirectories
+    /// are descended into only when `recursive` is set.
+    pub fn from_dir(dir: &Path, recursive: bool) -> anyhow::Result<Self> {
+        if !dir.is_dir() {
+            return Err(anyhow::anyhow!("Not a directory: {}", dir.display()));
+        }
+        let mut paths = Vec::new();
+        collect_dir(dir, recursive, &mut paths)?;
+        paths.sort();
+
+        let mut batch = Self::empty();
+        let mut total = 0u64;
+        for path in paths {
+            batch.push(path, &mut total);
+        }
+        Ok(batch)
+    }
+
+    fn empty() -> Self {
+        Self {
+            id: new_batch_id(),
+            files: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Add `path` if it's a regular file within the per-file and batch limits
+    fn push(&mut self, path: PathBuf, total: &mut u64) {
+        let meta = match std::fs::metadata(&path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                self.skipped.push((path, e.to_string()));
+                return;
+            }
+        };
+        if !meta.is_file() {
+            self.skipped.push((path, "not a regular file".to_string()));
+        } else if meta.len() > MAX_FILE_SIZE as u64 {
+            self.skipped.push((
+                path,
+                format!("too large ({} bytes, max {} bytes)", meta.len(), MAX_FILE_SIZE),
+            ));
+        } else if *total + meta.len() > MAX_BATCH_SIZE {
+            self.skipped.push((
+                path,
+                format!("batch limit of {} bytes reached", MAX_BATCH_SIZE),
+            ));
+        } else {
+            *total += meta.len();
+            self.files.push(path);
+        }
+    }
+}
+
+/// Gather the non-directory entries of `dir`, descending into
+/// subdirectories only when `recursive`
+fn collect_dir(dir: &Path, recursive: bool, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            if recursive {
+                collect_dir(&entry.path(), true, out)?;
+            }
+        } else {
+            out.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Short random id for a batch
+fn new_batch_id() -> String {
+    use rand::TryRng;
+    let mut bytes = [0u8; 4];
+    rand::rng()
+        .try_fill_bytes(&mut bytes)
+        .expect("Failed to generate batch ID bytes");
+    format!("batch-{}", hex::encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(new_batch_id());
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("b.txt"), b"bee").unwrap();
+        std::fs::write(dir.join("a.txt"), b"ay").unwrap();
+        std::fs::write(dir.join("nested").join("c.txt"), b"sea").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_dir_enumeration() {
+        let dir = scratch_dir();
+
+        let flat = FileBatch::from_dir(&dir, false).unwrap();
+        assert_eq!(flat.files, vec![dir.join("a.txt"), dir.join("b.txt")]);
+        assert!(flat.skipped.is_empty());
+
+        let deep = FileBatch::from_dir(&dir, true).unwrap();
+        assert_eq!(
+            deep.files,
+            vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("nested").join("c.txt")]
+        );
+
+        assert!(FileBatch::from_dir(&dir.join("a.txt"), false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_batch_id_and_skipped_paths() {
+        let dir = scratch_dir();
+        let batch = FileBatch::from_paths(&[
+            dir.join("a.txt"),
+            dir.join("nested"),
+            dir.join("missing.txt"),
+            dir.join("b.txt"),
+        ]);
+        assert!(batch.id.starts_with("batch-"));
+        assert_eq!(batch.files, vec![dir.join("a.txt"), dir.join("b.txt")]);
+        assert_eq!(batch.skipped.len(), 2);
+        assert_eq!(batch.skipped[0].1, "not a regular file");
+
+        // Every batch gets its own id
+        assert_ne!(batch.id, FileBatch::from_paths(&[dir.join("a.txt")]).id);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+//! Peers the user blocked with `/block`
+//!
+//! Nothing from a blocked peer is processed: gossipsub messages it relays
+//! or authored are dropped, and its room invites are refused. The list is
+//! saved as a JSON array of peer IDs after every change.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use libp2p::PeerId;
+
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    /// Where the list is saved; `None` keeps it in memory only
+    path: Option<PathBuf>,
+    peers: HashSet<PeerId>,
+}
+
+impl Blocklist {
+    /// Load the list at `path`, starting empty if the file doesn't exist
+    pub fn load(path: &Path) -> Result<Self> {
+        let ids: Vec<String> = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let peers = ids
+            .iter()
+            .map(|id| id.parse().map_err(|_| anyhow::anyhow!("Invalid peer ID in blocklist: {}", id)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            peers,
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut ids: Vec<String> = self.peers.iter().map(PeerId::to_string).collect();
+        ids.sort();
+        crate::crypto::write_private(path, &serde_json::to_vec_pretty(&ids)?)
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.peers.contains(peer_id)
+    }
+
+    /// Block a peer. Returns false if it already was.
+    pub fn insert(&mut self, peer_id: PeerId) -> Result<bool> {
+        if !self.peers.insert(peer_id) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Unblock a peer. Returns false if it wasn't blocked.
+    pub fn remove(&mut self, peer_id: &PeerId) -> Result<bool> {
+        if !self.peers.remove(peer_id) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Blocked peers, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &PeerId> {
+        self.peers.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocklist_persists() {
+        let dir = std::env::temp_dir().join(format!("openwire-blocklist-{}", std::process::id()));
+        let path = dir.join("blocklist.json");
+        let (spammer, other) = (PeerId::random(), PeerId::random());
+
+        let mut list = Blocklist::load(&path).unwrap();
+        assert!(list.insert(spammer).unwrap());
+        assert!(!list.insert(spammer).unwrap());
+        assert!(list.insert(other).unwrap());
+        assert!(list.remove(&other).unwrap());
+        assert!(!list.remove(&other).unwrap());
+
+        let reloaded = Blocklist::load(&path).unwrap();
+        assert!(reloaded.contains(&spammer));
+        assert!(!reloaded.contains(&other));
+
+        std::fs::write(&path, br#"["not-a-peer-id"]"#).unwrap();
+        assert!(Blocklist::load(&path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+//! Kademlia DHT for finding peers beyond the LAN (`--dht`)
+//!
+//! Nodes run their own DHT under `DHT_PROTOCOL` rather than joining the
+//! public IPFS one, so the routing table only holds OpenWire peers. Every
+//! node announces itself as a provider of `PROVIDER_KEY`; looking that key
+//! up finds other nodes that aren't in our routing table yet.
+
+use std::time::Duration;
+
+use libp2p::{PeerId, StreamProtocol, kad};
+
+/// Protocol name of the OpenWire DHT
+pub const DHT_PROTOCOL: StreamProtocol = StreamProtocol::new("/openwire/kad/1.0.0");
+/// How often the routing table is refreshed and other nodes are looked up
+pub const BOOTSTRAP_INTERVAL: Duration = Duration::from_secs(300);
+/// Record key every node provides, so nodes can find each other
+pub const PROVIDER_KEY: &[u8] = b"openwire-peers";
+
+/// The DHT behaviour for `local_peer_id`. It answers queries from other
+/// nodes, and bootstraps only when the network loop asks it to.
+pub fn behaviour(local_peer_id: PeerId) -> kad::Behaviour<kad::store::MemoryStore> {
+    let mut config = kad::Config::new(DHT_PROTOCOL);
+    config.set_periodic_bootstrap_interval(None);
+    let store = kad::store::MemoryStore::new(local_peer_id);
+    let mut kad = kad::Behaviour::with_config(local_peer_id, store, config);
+    kad.set_mode(Some(kad::Mode::Server));
+    kad
+}
+
+/// The provider record key
+pub fn provider_key() -> kad::RecordKey {
+    kad::RecordKey::new(&PROVIDER_KEY)
+}
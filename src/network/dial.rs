@@ -0,0 +1,123 @@
+//! Dial policy for user-initiated connections
+//!
+//! Decides whether a `/connect` address can be dialed straight away or needs
+//! the user to confirm it first. LAN and loopback addresses are always
+//! trusted; anything else must match the `--dial-allowlist` patterns unless
+//! `--trust-dial` is set.
+
+use libp2p::Multiaddr;
+use libp2p::multiaddr::Protocol;
+
+/// Which dial targets may be connected to without confirmation
+#[derive(Debug, Clone, Default)]
+pub struct DialPolicy {
+    /// Skip confirmation for every address (`--trust-dial`)
+    pub trust_all: bool,
+    /// Address patterns that are dialed without confirmation.
+    /// `*` matches any run of characters, e.g. `/ip4/203.0.113.*`.
+    pub allowlist: Vec<String>,
+}
+
+impl DialPolicy {
+    pub fn new(trust_all: bool, allowlist: Vec<String>) -> Self {
+        Self {
+            trust_all,
+            allowlist,
+        }
+    }
+
+    /// Whether dialing `addr` should wait for an explicit `/yes`
+    pub fn requires_confirmation(&self, addr: &str) -> bool {
+        if self.trust_all {
+            return false;
+        }
+        // Unparseable input is rejected by the network layer with a clear
+        // error, so there is nothing to confirm.
+        let Ok(multiaddr) = addr.parse::<Multiaddr>() else {
+            return false;
+        };
+        if is_local_address(&multiaddr) {
+            return false;
+        }
+        !self
+            .allowlist
+            .iter()
+            .any(|pattern| glob_match(pattern, addr))
+    }
+}
+
+/// True if the address targets loopback, a private LAN range, or link-local
+pub fn is_local_address(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| match p {
+        Protocol::Ip4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        Protocol::Ip6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+        }
+        Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host) => {
+            host == "localhost"
+        }
+        _ => false,
+    })
+}
+
+/// Minimal glob matcher supporting `*` wildcards
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard at all — must be an exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lan_and_loopback_need_no_confirmation() {
+        let policy = DialPolicy::default();
+        assert!(!policy.requires_confirmation("/ip4/127.0.0.1/tcp/4001"));
+        assert!(!policy.requires_confirmation("/ip4/192.168.1.20/tcp/4001"));
+        assert!(!policy.requires_confirmation("/ip4/10.0.0.5/tcp/4001"));
+        assert!(!policy.requires_confirmation("/ip6/::1/tcp/4001"));
+    }
+
+    #[test]
+    fn test_public_address_requires_confirmation() {
+        let policy = DialPolicy::default();
+        assert!(policy.requires_confirmation("/ip4/203.0.113.7/tcp/4001"));
+        assert!(policy.requires_confirmation("/dns4/example.com/tcp/4001"));
+    }
+
+    #[test]
+    fn test_allowlist_and_trust_all() {
+        let policy = DialPolicy::new(false, vec!["/ip4/203.0.113.*".into()]);
+        assert!(!policy.requires_confirmation("/ip4/203.0.113.7/tcp/4001"));
+        assert!(policy.requires_confirmation("/ip4/198.51.100.1/tcp/4001"));
+
+        let trusting = DialPolicy::new(true, Vec::new());
+        assert!(!trusting.requires_confirmation("/ip4/198.51.100.1/tcp/4001"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("/ip4/1.2.3.4/tcp/1", "/ip4/1.2.3.4/tcp/1"));
+        assert!(!glob_match("/ip4/1.2.3.4", "/ip4/1.2.3.4/tcp/1"));
+        assert!(glob_match("*/tcp/4001", "/ip4/1.2.3.4/tcp/4001"));
+        assert!(glob_match("/dns4/*.example.com/*", "/dns4/a.example.com/tcp/1"));
+        assert!(!glob_match("/dns4/*.example.com/*", "/dns4/example.org/tcp/1"));
+    }
+}
@@ -0,0 +1,86 @@
+//! Direct file transfer to one peer
+//!
+//! A file sent to a single peer goes over a libp2p request-response stream
+//! instead of the gossipsub file topic, so nobody else receives (or saves)
+//! it. The request is a signed `FileTransferMessage` like the broadcast
+//! path's, with its data encrypted to the receiver. The receiver answers
+//! once the file is saved, or with the reason it wasn't.
+
+use std::time::Duration;
+
+use libp2p::{PeerId, StreamProtocol, request_response};
+
+use super::{FileTransferMessage, MAX_FILE_SIZE};
+
+/// Protocol name negotiated for direct file transfers
+pub const FILE_PROTOCOL: StreamProtocol = StreamProtocol::new("/openwire/file/1.0.0");
+/// How long a peer gets to receive and save a file
+pub const FILE_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest encoded request read. File bytes are JSON numbers, so up to four
+/// characters each, plus room for the other fields.
+const MAX_REQUEST_BYTES: u64 = MAX_FILE_SIZE as u64 * 4 + 64 * 1024;
+/// Largest encoded response read
+const MAX_RESPONSE_BYTES: u64 = 64 * 1024;
+
+/// A file sent straight to one peer
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FileRequest {
+    pub file: FileTransferMessage,
+}
+
+/// The receiver's answer to a `FileRequest`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FileResponse {
+    /// The file was checked and saved
+    Saved,
+    /// The file was refused, with the reason
+    Rejected(String),
+}
+
+/// A direct transfer waiting for the receiver's answer
+#[derive(Debug, Clone)]
+pub struct OutgoingFile {
+    pub transfer_id: u64,
+    pub peer: PeerId,
+    pub filename: String,
+    pub size: u64,
+}
+
+/// Request-response over JSON, one request and response per stream
+pub type FileBehaviour = request_response::json::Behaviour<FileRequest, FileResponse>;
+
+/// JSON codec with our size limits
+fn codec() -> request_response::json::codec::Codec<FileRequest, FileResponse> {
+    request_response::json::codec::Codec::default()
+        .set_request_size_maximum(MAX_REQUEST_BYTES)
+        .set_response_size_maximum(MAX_RESPONSE_BYTES)
+}
+
+/// The behaviour that carries direct transfers, both ways
+pub fn behaviour() -> FileBehaviour {
+    request_response::Behaviour::with_codec(
+        codec(),
+        [(FILE_PROTOCOL, request_response::ProtocolSupport::Full)],
+        request_response::Config::default().with_request_timeout(FILE_REQUEST_TIMEOUT),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use request_response::Codec;
+
+    #[tokio::test]
+    async fn test_codec_roundtrip_and_size_limit() {
+        let mut codec = codec();
+        let mut buf = futures::io::Cursor::new(Vec::new());
+        let rejected = FileResponse::Rejected("blocked".into());
+        codec.write_response(&FILE_PROTOCOL, &mut buf, rejected.clone()).await.unwrap();
+        buf.set_position(0);
+        assert_eq!(codec.read_response(&FILE_PROTOCOL, &mut buf).await.unwrap(), rejected);
+
+        let oversized = FileResponse::Rejected("x".repeat(MAX_RESPONSE_BYTES as usize));
+        let mut buf = futures::io::Cursor::new(serde_json::to_vec(&oversized).unwrap());
+        assert!(codec.read_response(&FILE_PROTOCOL, &mut buf).await.is_err());
+    }
+}
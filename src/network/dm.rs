@@ -0,0 +1,117 @@
+//! Direct message framing and delivery acknowledgements
+//!
+//! The plaintext of a direct message (encrypted and signed for one peer)
+//! starts with `DM_PREFIX` and a kind byte: `m` is a message, followed by
+//! its id, a newline and the body; `a` is a `MessageAck` for the id that
+//! follows. Receivers ack every message that has an id, so the sender
+//! knows it arrived. Plaintext without the prefix comes from older peers;
+//! it is shown as is and not acked.
+
+use std::time::Duration;
+
+use rand::TryRng;
+
+/// Marks a framed direct message plaintext
+const DM_PREFIX: &[u8] = b"OWDM1:";
+/// How long a sent message may wait for its ack before it counts as failed
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest message id accepted
+const MAX_MESSAGE_ID_LEN: usize = 64;
+
+/// A random id for an outgoing message
+pub fn new_message_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rng()
+        .try_fill_bytes(&mut bytes)
+        .expect("Failed to generate message id");
+    hex::encode(bytes)
+}
+
+/// Ids are short ASCII tokens, e.g. hex or a UUID
+fn is_valid_message_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_MESSAGE_ID_LEN && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// The receiver's confirmation that the message with `message_id` arrived.
+/// It travels as a direct message itself, so it's signed by the receiver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageAck {
+    pub message_id: String,
+}
+
+impl MessageAck {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [DM_PREFIX, b"a", self.message_id.as_bytes()].concat()
+    }
+}
+
+/// A sent message waiting for its ack
+#[derive(Debug, Clone)]
+pub struct PendingAck {
+    /// The peer the message went to; only its ack counts
+    pub peer: String,
+    /// When the message is reported as failed
+    pub deadline: tokio::time::Instant,
+}
+
+/// What a direct message plaintext holds
+#[derive(Debug, PartialEq, Eq)]
+pub enum DirectFrame<'a> {
+    /// A message to show; `message_id` is `None` from peers that don't ack
+    Message { message_id: Option<&'a str>, body: &'a [u8] },
+    Ack(MessageAck),
+}
+
+/// Frame `body` as the message with `message_id`
+pub fn encode_message(message_id: &str, body: &[u8]) -> Vec<u8> {
+    [DM_PREFIX, b"m", message_id.as_bytes(), b"\n", body].concat()
+}
+
+/// Split a direct message plaintext. Anything that isn't a well-formed
+/// frame is an unacknowledged message.
+pub fn decode(data: &[u8]) -> DirectFrame<'_> {
+    let legacy = DirectFrame::Message { message_id: None, body: data };
+    let Some(rest) = data.strip_prefix(DM_PREFIX) else {
+        return legacy;
+    };
+    let (id, body) = match rest.split_first() {
+        Some((b'm', rest)) => match rest.iter().position(|&b| b == b'\n') {
+            Some(end) => (&rest[..end], Some(&rest[end + 1..])),
+            None => return legacy,
+        },
+        Some((b'a', id)) => (id, None),
+        _ => return legacy,
+    };
+    let Some(id) = std::str::from_utf8(id).ok().filter(|id| is_valid_message_id(id)) else {
+        return legacy;
+    };
+    match body {
+        Some(body) => DirectFrame::Message { message_id: Some(id), body },
+        None => DirectFrame::Ack(MessageAck { message_id: id.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_round_trip() {
+        let id = new_message_id();
+        assert_eq!(id.len(), 32);
+        assert_ne!(id, new_message_id());
+
+        let framed = encode_message(&id, b"hi\nthere");
+        assert_eq!(
+            decode(&framed),
+            DirectFrame::Message { message_id: Some(&id), body: b"hi\nthere" }
+        );
+        let ack = MessageAck { message_id: id.clone() };
+        assert_eq!(decode(&ack.to_bytes()), DirectFrame::Ack(ack));
+
+        // Older peers' plain text, and malformed frames, are shown as is
+        for data in [&b"psst"[..], b"OWDM1:", b"OWDM1:mno-newline", b"OWDM1:a", b"OWDM1:abad id", b"OWDM1:x1"] {
+            assert_eq!(decode(data), DirectFrame::Message { message_id: None, body: data });
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Typed payloads on the general topic and the lobby
+//!
+//! A broadcast is plain UTF-8 text unless it starts with `ENVELOPE_PREFIX`,
+//! followed by a content-type byte and the body. Text stays untagged so
+//! older peers keep reading chat; they show JSON and binary payloads as
+//! garbled text.
+
+/// Marks a tagged broadcast payload
+const ENVELOPE_PREFIX: &[u8] = b"OWENV1:";
+
+/// What a broadcast payload holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    /// Chat text, shown in the TUI
+    Text,
+    /// A JSON document for app integrations
+    Json,
+    /// Opaque bytes for app integrations
+    Binary,
+}
+
+impl ContentType {
+    fn tag(self) -> u8 {
+        match self {
+            Self::Text => b't',
+            Self::Json => b'j',
+            Self::Binary => b'b',
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            b't' => Some(Self::Text),
+            b'j' => Some(Self::Json),
+            b'b' => Some(Self::Binary),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Text => "text",
+            Self::Json => "json",
+            Self::Binary => "binary",
+        })
+    }
+}
+
+/// Wrap `body` for broadcasting. Text is sent as is.
+pub fn encode(content_type: ContentType, body: &[u8]) -> Vec<u8> {
+    if content_type == ContentType::Text {
+        return body.to_vec();
+    }
+    let mut data = Vec::with_capacity(ENVELOPE_PREFIX.len() + 1 + body.len());
+    data.extend_from_slice(ENVELOPE_PREFIX);
+    data.push(content_type.tag());
+    data.extend_from_slice(body);
+    data
+}
+
+/// Split a received broadcast into its content type and body. Untagged
+/// data, or an unknown tag, is text.
+pub fn decode(data: &[u8]) -> (ContentType, &[u8]) {
+    if let Some(rest) = data.strip_prefix(ENVELOPE_PREFIX)
+        && let Some((&tag, body)) = rest.split_first()
+        && let Some(content_type) = ContentType::from_tag(tag)
+    {
+        return (content_type, body);
+    }
+    (ContentType::Text, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let binary = [0u8, 0xff, 0x80, b'\n'];
+        for (content_type, body) in [
+            (ContentType::Json, br#"{"score":3}"#.as_slice()),
+            (ContentType::Binary, binary.as_slice()),
+            (ContentType::Text, "héllo".as_bytes()),
+        ] {
+            let data = encode(content_type, body);
+            assert_eq!(decode(&data), (content_type, body));
+        }
+
+        // Text stays readable by peers that predate envelopes
+        assert_eq!(encode(ContentType::Text, b"hi"), b"hi");
+        assert_eq!(decode(b"plain chat"), (ContentType::Text, b"plain chat".as_slice()));
+        assert_eq!(decode(b"OWENV1:"), (ContentType::Text, b"OWENV1:".as_slice()));
+        assert_eq!(decode(b"OWENV1:zx"), (ContentType::Text, b"OWENV1:zx".as_slice()));
+    }
+}
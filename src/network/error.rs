@@ -0,0 +1,141 @@
+//! Typed failures reported through `NetworkEvent::Error`
+//!
+//! Front-ends that only show the message use the `Display` impl; embedders
+//! and web clients can match on the variant instead of parsing text.
+
+use std::fmt;
+
+use libp2p::PeerId;
+
+/// What was being published when `NetworkError::PublishFailed` happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishKind {
+    Broadcast,
+    Lobby,
+    DirectMessage,
+    File,
+    Room,
+    RoomInvite,
+    KeyRequest,
+}
+
+impl fmt::Display for PublishKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Broadcast => "Broadcast",
+            Self::Lobby => "Lobby send",
+            Self::DirectMessage => "Send to peer",
+            Self::File => "File send",
+            Self::Room => "Room message",
+            Self::RoomInvite => "Room invite",
+            Self::KeyRequest => "Room key request",
+        })
+    }
+}
+
+/// A failure the network layer reports to the front-end
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NetworkError {
+    /// The text given isn't a valid peer ID
+    #[error("Invalid peer ID: {0}")]
+    InvalidPeerId(String),
+    /// We hold nothing for this peer
+    #[error("Peer not found: {0}")]
+    PeerNotFound(String),
+    /// The peer is known but not connected
+    #[error("Not connected to {0}")]
+    NotConnected(PeerId),
+    /// No keys have been exchanged with the peer, so it can't be encrypted to
+    #[error("No keys exchanged with {0} yet; wait for it to connect")]
+    NotEncryptable(String),
+    #[error("File not found: {0}")]
+    FileNotFound(String),
+    #[error("File too large ({size} bytes, max {max} bytes)")]
+    FileTooLarge { size: usize, max: usize },
+    /// Gossipsub (or building the message) failed
+    #[error("{kind} failed: {reason}")]
+    PublishFailed { kind: PublishKind, reason: String },
+    #[error("Unknown room: {0}")]
+    RoomNotFound(String),
+    /// Empty, too long, reserved, or not an ASCII token
+    #[error("Invalid room ID: '{0}'")]
+    InvalidRoomId(String),
+    /// Creating, joining, leaving or inviting to a room failed
+    #[error("Failed to {action}: {reason}")]
+    RoomFailed { action: &'static str, reason: String },
+    /// A room invite arrived but couldn't be used
+    #[error("Couldn't join room '{room_name}' from {from}: {reason}")]
+    InviteRejected {
+        room_name: String,
+        from: String,
+        reason: String,
+    },
+    /// `InviteToRoom` named our own peer id
+    #[error("You can't invite yourself to a room")]
+    SelfInvite,
+    /// Rooms are only joined through an invite
+    #[error("Room joining requires an invite. Ask a room member to invite you.")]
+    InviteRequired,
+    /// `--lobby` wasn't given
+    #[error("Not in the lobby (start with --lobby)")]
+    NotInLobby,
+    #[error("Couldn't save identity to {path}: {reason}")]
+    IdentitySaveFailed { path: String, reason: String },
+    #[error("Couldn't write the backup to {path}: {reason}")]
+    ExportFailed { path: String, reason: String },
+    /// `/trust` for a peer whose keys didn't change
+    #[error("No changed keys are waiting for {0}")]
+    NoChangedKeys(String),
+    #[error("Couldn't save the blocklist: {0}")]
+    BlocklistSaveFailed(String),
+    #[error("Couldn't save contacts: {0}")]
+    ContactsSaveFailed(String),
+    #[error("Connection failed: {0}")]
+    DialFailed(String),
+    #[error("Ping to {peer} failed: {reason}")]
+    PingFailed { peer: PeerId, reason: String },
+    #[error("GIF search unavailable: KLIPY_KEY not configured")]
+    GifUnavailable,
+    #[error("No GIFs found for: {0}")]
+    NoGifsFound(String),
+    #[error("GIF search failed: {0}")]
+    GifSearchFailed(String),
+}
+
+impl NetworkError {
+    /// Recover the typed error from `e`, or build one from its message
+    pub fn from_anyhow(e: anyhow::Error, fallback: impl FnOnce(String) -> Self) -> Self {
+        match e.downcast::<NetworkError>() {
+            Ok(typed) => typed,
+            Err(e) => fallback(e.to_string()),
+        }
+    }
+
+    /// [`from_anyhow`](Self::from_anyhow) falling back to `PublishFailed`
+    pub fn publish(kind: PublishKind, e: anyhow::Error) -> Self {
+        Self::from_anyhow(e, |reason| Self::PublishFailed { kind, reason })
+    }
+
+    /// [`from_anyhow`](Self::from_anyhow) falling back to `RoomFailed`
+    pub fn room(action: &'static str, e: anyhow::Error) -> Self {
+        Self::from_anyhow(e, |reason| Self::RoomFailed { action, reason })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_errors_survive_anyhow() {
+        let e: anyhow::Error = NetworkError::RoomNotFound("room-1".into()).into();
+        assert_eq!(
+            NetworkError::publish(PublishKind::Room, e),
+            NetworkError::RoomNotFound("room-1".into())
+        );
+
+        let e = anyhow::anyhow!("InsufficientPeers");
+        let err = NetworkError::publish(PublishKind::Broadcast, e);
+        assert_eq!(err.to_string(), "Broadcast failed: InsufficientPeers");
+    }
+}
@@ -0,0 +1,142 @@
+//! Flood detection for broadcasts
+//!
+//! An author that repeats the same message (ignoring case and spacing)
+//! `FLOOD_REPEATS` times within `FLOOD_WINDOW` is muted for
+//! `MUTE_COOLDOWN`: everything it broadcasts is dropped until then.
+//! Typing indicators, status re-announces and emoji-only reactions repeat
+//! by nature and aren't counted.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::Duration;
+
+use libp2p::PeerId;
+use tokio::time::Instant;
+
+/// How far back repeats are counted
+pub const FLOOD_WINDOW: Duration = Duration::from_secs(10);
+/// Repeats of one message within the window that count as flooding
+pub const FLOOD_REPEATS: usize = 5;
+/// How long a flooding peer stays muted
+pub const MUTE_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Control payloads that are sent again and again on purpose
+const EXEMPT_PREFIXES: [&[u8]; 2] = [b"TYPING:", super::STATUS_PREFIX];
+
+/// What to do with a broadcast
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Deliver,
+    /// This message tipped the peer over; it is muted from now on
+    Muted,
+    /// The peer is still muted
+    Drop,
+}
+
+/// Recent messages and temporary mutes per author
+#[derive(Debug, Default)]
+pub struct FloodGuard {
+    recent: HashMap<PeerId, VecDeque<(Instant, u64)>>,
+    muted: HashMap<PeerId, Instant>,
+}
+
+impl FloodGuard {
+    /// Record a broadcast written by `peer` and decide whether to deliver it
+    pub fn check(&mut self, peer: PeerId, content: &[u8], now: Instant) -> Verdict {
+        if let Some(&until) = self.muted.get(&peer) {
+            if now < until {
+                return Verdict::Drop;
+            }
+            self.muted.remove(&peer);
+        }
+        if is_exempt(content) {
+            return Verdict::Deliver;
+        }
+        // Forget peers that went quiet
+        self.recent
+            .retain(|_, seen| seen.back().is_some_and(|(at, _)| now.duration_since(*at) < FLOOD_WINDOW));
+
+        let fingerprint = fingerprint(content);
+        let seen = self.recent.entry(peer).or_default();
+        while seen.front().is_some_and(|(at, _)| now.duration_since(*at) >= FLOOD_WINDOW) {
+            seen.pop_front();
+        }
+        seen.push_back((now, fingerprint));
+        let repeats = seen.iter().filter(|(_, f)| *f == fingerprint).count();
+        if repeats < FLOOD_REPEATS {
+            return Verdict::Deliver;
+        }
+        self.recent.remove(&peer);
+        self.muted.insert(peer, now + MUTE_COOLDOWN);
+        Verdict::Muted
+    }
+}
+
+/// Control payloads and emoji-only messages
+fn is_exempt(content: &[u8]) -> bool {
+    if EXEMPT_PREFIXES.iter().any(|prefix| content.starts_with(prefix)) {
+        return true;
+    }
+    let text = String::from_utf8_lossy(content);
+    !text.trim().is_empty() && text.chars().all(|c| c.is_whitespace() || (!c.is_ascii() && !c.is_alphanumeric()))
+}
+
+/// Hash of the whole message, lowercased and with runs of whitespace
+/// collapsed, so "Spam" and " spam " count as the same message but
+/// "item 1" and "item 2" don't
+fn fingerprint(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    String::from_utf8_lossy(content)
+        .split_whitespace()
+        .flat_map(|word| word.chars().flat_map(char::to_lowercase).chain([' ']))
+        .for_each(|c| c.hash(&mut hasher));
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_messages_mute_until_cooldown() {
+        let mut guard = FloodGuard::default();
+        let spammer = PeerId::random();
+        let other = PeerId::random();
+        let start = Instant::now();
+
+        for i in 1..FLOOD_REPEATS {
+            let message = if i % 2 == 0 { "BUY  NOW!!" } else { " buy now!! " };
+            assert_eq!(guard.check(spammer, message.as_bytes(), start), Verdict::Deliver);
+        }
+        assert_eq!(guard.check(spammer, b"buy now!!", start), Verdict::Muted);
+        // Muted for anything, not just the repeated message
+        assert_eq!(guard.check(spammer, b"hello", start + Duration::from_secs(1)), Verdict::Drop);
+        // Other peers aren't affected
+        assert_eq!(guard.check(other, b"buy now", start), Verdict::Deliver);
+
+        let after = start + MUTE_COOLDOWN;
+        assert_eq!(guard.check(spammer, b"sorry", after), Verdict::Deliver);
+
+        // Repeats spread wider than the window are fine
+        for i in 0..FLOOD_REPEATS as u32 * 2 {
+            let at = after + FLOOD_WINDOW * (i + 1);
+            assert_eq!(guard.check(spammer, b"good morning", at), Verdict::Deliver);
+        }
+    }
+
+    #[test]
+    fn test_distinct_and_control_messages_dont_count() {
+        let mut guard = FloodGuard::default();
+        let peer = PeerId::random();
+        let now = Instant::now();
+        for i in 0..FLOOD_REPEATS * 2 {
+            // Numbered messages differ; typing, status and reactions repeat
+            for message in [format!("step {}", i), "TYPING:alice".into(), "STATUS:away".into(), "👍 🎉".into()] {
+                assert_eq!(guard.check(peer, message.as_bytes(), now), Verdict::Deliver);
+            }
+        }
+        assert!(is_exempt("❤️".as_bytes()));
+        assert!(!is_exempt(b"!!!"));
+        assert!(!is_exempt("ok 👍".as_bytes()));
+    }
+}
@@ -0,0 +1,101 @@
+//! Counters behind `/stats` and the web server's `/metrics` endpoint
+//!
+//! The network task updates them as it goes; readers (the web server, the
+//! stats command) share the same `Arc<Metrics>` and never have to ask the
+//! event loop, so a Prometheus scrape doesn't show up in the TUI.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Live network counters and gauges
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Peers with an open connection (gauge)
+    pub connected_peers: AtomicU64,
+    /// Rooms joined (gauge)
+    pub rooms: AtomicU64,
+    /// Gossipsub messages published
+    pub messages_sent: AtomicU64,
+    /// Gossipsub messages received
+    pub messages_received: AtomicU64,
+    /// Payload bytes published
+    pub bytes_sent: AtomicU64,
+    /// Payload bytes received
+    pub bytes_received: AtomicU64,
+    /// Messages dropped as unsigned, malformed or badly signed
+    pub rejected_messages: AtomicU64,
+    /// Publishes gossipsub refused
+    pub publish_errors: AtomicU64,
+}
+
+impl Metrics {
+    /// Add `n` to a counter
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Set a gauge
+    pub fn set(gauge: &AtomicU64, value: usize) {
+        gauge.store(value as u64, Ordering::Relaxed);
+    }
+
+    /// Prometheus text exposition format (version 0.0.4)
+    pub fn to_prometheus(&self) -> String {
+        let metrics: [(&str, &str, &str, &AtomicU64); 8] = [
+            ("openwire_connected_peers", "gauge", "Peers with an open connection", &self.connected_peers),
+            ("openwire_rooms", "gauge", "Rooms joined", &self.rooms),
+            ("openwire_messages_sent_total", "counter", "Gossipsub messages published", &self.messages_sent),
+            ("openwire_messages_received_total", "counter", "Gossipsub messages received", &self.messages_received),
+            ("openwire_bytes_sent_total", "counter", "Payload bytes published", &self.bytes_sent),
+            ("openwire_bytes_received_total", "counter", "Payload bytes received", &self.bytes_received),
+            (
+                "openwire_rejected_messages_total",
+                "counter",
+                "Messages dropped as unsigned or failing verification",
+                &self.rejected_messages,
+            ),
+            ("openwire_publish_errors_total", "counter", "Publishes gossipsub refused", &self.publish_errors),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_text_is_well_formed() {
+        let metrics = Metrics::default();
+        Metrics::add(&metrics.messages_sent, 3);
+        Metrics::set(&metrics.connected_peers, 2);
+        let text = metrics.to_prometheus();
+
+        assert!(text.ends_with('\n'));
+        let mut samples = 0;
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (_, kind) = rest.split_once(' ').unwrap();
+                assert!(kind == "counter" || kind == "gauge", "{}", line);
+            } else if !line.starts_with("# HELP ") {
+                let (name, value) = line.split_once(' ').unwrap();
+                assert!(name.starts_with("openwire_"), "{}", line);
+                assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+                value.parse::<u64>().unwrap();
+                samples += 1;
+            }
+        }
+        assert_eq!(samples, 8);
+        assert!(text.contains("\nopenwire_messages_sent_total 3\n"));
+        assert!(text.contains("openwire_connected_peers 2\n"));
+        for name in ["openwire_publish_errors_total", "openwire_rejected_messages_total", "openwire_rooms"] {
+            assert!(text.contains(&format!("# TYPE {} ", name)), "missing {}", name);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Reaching peers behind NAT (`--p2p-relay`)
+//!
+//! With a relay configured we reserve a `/p2p-circuit` address on it, so
+//! peers that can't dial us directly can reach us through the relay. When
+//! our own direct dial of a peer fails, it is retried through the relay.
+//! DCUtR then tries to hole-punch a relayed connection into a direct one,
+//! and AutoNAT tells us whether we're publicly reachable at all.
+
+use libp2p::{Multiaddr, PeerId, multiaddr::Protocol};
+
+/// How a connection to a peer is carried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionPath {
+    /// Dialed or accepted directly
+    Direct,
+    /// Carried through a relay, with its bandwidth and time limits
+    Relayed,
+    /// Started relayed, upgraded to direct by hole punching
+    HolePunched,
+}
+
+impl ConnectionPath {
+    /// Short label for the UI
+    pub fn label(self) -> &'static str {
+        match self {
+            ConnectionPath::Direct => "direct",
+            ConnectionPath::Relayed => "relayed",
+            ConnectionPath::HolePunched => "direct (hole-punched)",
+        }
+    }
+}
+
+/// The peer id at the end of `addr`, if it names one
+pub fn target_peer(addr: &Multiaddr) -> Option<PeerId> {
+    match addr.iter().last() {
+        Some(Protocol::P2p(peer_id)) => Some(peer_id),
+        _ => None,
+    }
+}
+
+/// Whether `addr` goes through a relay
+pub fn is_circuit(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
+
+/// Address that reaches the peer named by `addr` through `relay`. `None`
+/// when `addr` names no peer, already uses a relay, or is the relay itself.
+pub fn relayed_route(relay: &Multiaddr, addr: &Multiaddr) -> Option<Multiaddr> {
+    let peer = target_peer(addr)?;
+    if is_circuit(addr) || target_peer(relay) == Some(peer) {
+        return None;
+    }
+    Some(relay.clone().with(Protocol::P2pCircuit).with(Protocol::P2p(peer)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relayed_route() {
+        let relay_id = PeerId::random();
+        let peer = PeerId::random();
+        let relay: Multiaddr = format!("/ip4/198.51.100.1/tcp/4001/p2p/{}", relay_id).parse().unwrap();
+        let direct: Multiaddr = format!("/ip4/10.0.0.2/tcp/4001/p2p/{}", peer).parse().unwrap();
+
+        let route = relayed_route(&relay, &direct).unwrap();
+        assert_eq!(
+            route.to_string(),
+            format!("/ip4/198.51.100.1/tcp/4001/p2p/{}/p2p-circuit/p2p/{}", relay_id, peer)
+        );
+        assert!(is_circuit(&route));
+        assert_eq!(target_peer(&route), Some(peer));
+
+        // Nothing to relay to, already relayed, or the relay itself
+        assert_eq!(relayed_route(&relay, &"/ip4/10.0.0.2/tcp/4001".parse().unwrap()), None);
+        assert_eq!(relayed_route(&relay, &route), None);
+        assert_eq!(relayed_route(&relay, &relay), None);
+    }
+}
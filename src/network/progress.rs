@@ -0,0 +1,77 @@
+//! File transfer progress reporting
+//!
+//! Every transfer reports when it starts and when it ends. Updates in
+//! between are limited to one per `PROGRESS_INTERVAL` for each transfer,
+//! so a long transfer can't flood the event bus and one transfer's updates
+//! can't starve another's.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Least time between two progress updates for a transfer, other than its last
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Which way a file is going
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Sending,
+    Receiving,
+}
+
+/// Decides which progress updates are emitted
+#[derive(Debug, Default)]
+pub struct ProgressThrottle {
+    last: HashMap<u64, Instant>,
+}
+
+impl ProgressThrottle {
+    /// Whether to emit an update for `transfer_id`. A transfer's final
+    /// update always goes out.
+    pub fn allow(&mut self, transfer_id: u64, finished: bool, now: Instant) -> bool {
+        // Forget transfers that went quiet, e.g. ones that failed midway
+        self.last
+            .retain(|_, last| now.duration_since(*last) < PROGRESS_INTERVAL);
+        if finished {
+            self.last.remove(&transfer_id);
+            return true;
+        }
+        if self.last.contains_key(&transfer_id) {
+            return false;
+        }
+        self.last.insert(transfer_id, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_are_throttled_but_not_the_last() {
+        let mut throttle = ProgressThrottle::default();
+        let start = Instant::now();
+        assert!(throttle.allow(1, false, start));
+        assert!(!throttle.allow(1, false, start + Duration::from_millis(100)));
+        assert!(throttle.allow(1, true, start + Duration::from_millis(100)));
+        assert!(throttle.allow(2, false, start + Duration::from_millis(200)));
+        assert!(!throttle.allow(2, false, start + Duration::from_millis(300)));
+        assert!(throttle.allow(
+            2,
+            false,
+            start + Duration::from_millis(200) + PROGRESS_INTERVAL
+        ));
+    }
+
+    #[test]
+    fn test_transfers_are_throttled_separately() {
+        let mut throttle = ProgressThrottle::default();
+        let start = Instant::now();
+        assert!(throttle.allow(1, false, start));
+        assert!(throttle.allow(2, false, start));
+        assert!(!throttle.allow(1, false, start + Duration::from_millis(100)));
+        assert!(!throttle.allow(2, false, start + Duration::from_millis(100)));
+    }
+}
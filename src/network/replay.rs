@@ -0,0 +1,84 @@
+//! Replay detection for signed broadcasts
+//!
+//! Remembers the last `REPLAY_CACHE_SIZE` (sender key, timestamp, sequence
+//! number, content hash) entries seen. Freshness checks already drop
+//! anything older than the allowed clock skew, so the cache only has to span
+//! that window.
+//!
+//! Peers that predate sequence numbers send every message with `seq` 0.
+//! Two such messages with the same text in the same second can't be told
+//! apart, so they aren't recorded at all; the freshness window is their
+//! only replay protection.
+
+use std::collections::{HashSet, VecDeque};
+
+/// How many recent broadcasts are remembered
+pub const REPLAY_CACHE_SIZE: usize = 4096;
+
+type Entry = ([u8; 32], u64, u64, [u8; 32]);
+
+/// Recently seen signed broadcasts, oldest evicted first
+#[derive(Debug, Default)]
+pub struct ReplayCache {
+    seen: HashSet<Entry>,
+    order: VecDeque<Entry>,
+}
+
+impl ReplayCache {
+    /// Record a broadcast. Returns false if it was already seen. Messages
+    /// without a sequence number are never recorded.
+    pub fn insert(&mut self, sender_key: &[u8], timestamp: u64, seq: u64, content: &[u8]) -> bool {
+        use sha2::Digest;
+        if seq == 0 {
+            return true;
+        }
+        let mut key = [0u8; 32];
+        let len = sender_key.len().min(32);
+        key[..len].copy_from_slice(&sender_key[..len]);
+        let entry = (key, timestamp, seq, sha2::Sha256::digest(content).into());
+        if !self.seen.insert(entry) {
+            return false;
+        }
+        self.order.push_back(entry);
+        if self.order.len() > REPLAY_CACHE_SIZE
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.seen.remove(&oldest);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeats_are_caught_until_evicted() {
+        let mut cache = ReplayCache::default();
+        let alice = [1u8; 32];
+        assert!(cache.insert(&alice, 100, 1, b"hi"));
+        assert!(!cache.insert(&alice, 100, 1, b"hi"));
+        // Any difference makes it a new message, including the same text
+        // sent again within the second
+        assert!(cache.insert(&alice, 101, 1, b"hi"));
+        assert!(cache.insert(&alice, 100, 2, b"hi"));
+        assert!(cache.insert(&alice, 100, 1, b"hi!"));
+        assert!(cache.insert(&[2u8; 32], 100, 1, b"hi"));
+
+        for i in 0..REPLAY_CACHE_SIZE as u64 {
+            cache.insert(&alice, 1_000 + i, 1, b"filler");
+        }
+        assert_eq!(cache.seen.len(), REPLAY_CACHE_SIZE);
+        assert!(cache.insert(&alice, 100, 1, b"hi"));
+    }
+
+    #[test]
+    fn test_unsequenced_repeats_are_not_dropped() {
+        let mut cache = ReplayCache::default();
+        let old_peer = [3u8; 32];
+        assert!(cache.insert(&old_peer, 100, 0, b"ok"));
+        assert!(cache.insert(&old_peer, 100, 0, b"ok"));
+        assert!(cache.seen.is_empty());
+    }
+}
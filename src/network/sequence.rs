@@ -0,0 +1,116 @@
+//! Sequence numbers for signed broadcasts
+//!
+//! Each broadcast a peer signs carries the next number of its counter for
+//! that topic. A number at or below the last one delivered is a duplicate
+//! (or arrived after a newer message and is dropped the same way); a jump
+//! means messages were lost on the way. Counters start over when a client
+//! restarts, which shows as a lower number with a newer timestamp.
+//! Nothing is persisted, and only the `MAX_TRACKED_STREAMS` most recently
+//! active (sender, topic) pairs are kept.
+
+use std::collections::HashMap;
+
+use libp2p::PeerId;
+
+/// Senders and topics tracked at once; the one that went quiet longest is
+/// forgotten first, and counts as joined mid-stream if it comes back
+pub const MAX_TRACKED_STREAMS: usize = 4096;
+
+/// What to do with a sequenced broadcast
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Deliver,
+    /// Deliver, but this many messages before it never arrived
+    Gap(u64),
+    /// Already delivered, or older than one that was
+    Duplicate,
+}
+
+/// Last (sequence number, timestamp) delivered per sender and topic
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    last: HashMap<(PeerId, String), (u64, u64)>,
+}
+
+impl SequenceTracker {
+    /// Record broadcast `seq` from `sender` on `topic` and decide whether to
+    /// deliver it
+    pub fn check(&mut self, sender: PeerId, topic: &str, seq: u64, timestamp: u64) -> Verdict {
+        let key = (sender, topic.to_string());
+        let verdict = match self.last.get(&key) {
+            // Joined mid-stream: whatever came before isn't ours to miss
+            None => Verdict::Deliver,
+            Some(&(last, last_timestamp)) if seq <= last => {
+                if timestamp <= last_timestamp {
+                    return Verdict::Duplicate;
+                }
+                // The sender restarted and counts from 1 again
+                Verdict::Deliver
+            }
+            Some(&(last, _)) if seq > last + 1 => Verdict::Gap(seq - last - 1),
+            Some(_) => Verdict::Deliver,
+        };
+        if self.last.len() >= MAX_TRACKED_STREAMS
+            && !self.last.contains_key(&key)
+            && let Some(quietest) = self.last.iter().min_by_key(|(_, (_, at))| *at).map(|(k, _)| k.clone())
+        {
+            self.last.remove(&quietest);
+        }
+        self.last.insert(key, (seq, timestamp));
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates_and_gaps() {
+        let mut tracker = SequenceTracker::default();
+        let alice = PeerId::random();
+
+        assert_eq!(tracker.check(alice, "general", 1, 100), Verdict::Deliver);
+        assert_eq!(tracker.check(alice, "general", 2, 100), Verdict::Deliver);
+        assert_eq!(tracker.check(alice, "general", 2, 100), Verdict::Duplicate);
+        assert_eq!(tracker.check(alice, "general", 1, 100), Verdict::Duplicate);
+
+        assert_eq!(tracker.check(alice, "general", 5, 101), Verdict::Gap(2));
+        // One of the missing ones turning up late is dropped too
+        assert_eq!(tracker.check(alice, "general", 4, 101), Verdict::Duplicate);
+        assert_eq!(tracker.check(alice, "general", 6, 101), Verdict::Deliver);
+
+        // Senders and topics are counted apart
+        assert_eq!(tracker.check(PeerId::random(), "general", 9, 100), Verdict::Deliver);
+        assert_eq!(tracker.check(alice, "lobby", 1, 100), Verdict::Deliver);
+        assert_eq!(tracker.check(alice, "lobby", 3, 100), Verdict::Gap(1));
+    }
+
+    #[test]
+    fn test_restarted_sender_starts_over() {
+        let mut tracker = SequenceTracker::default();
+        let alice = PeerId::random();
+        for seq in 1..=40 {
+            assert_eq!(tracker.check(alice, "general", seq, 100), Verdict::Deliver);
+        }
+
+        // After a reconnect its counter is back at 1, with a newer timestamp
+        assert_eq!(tracker.check(alice, "general", 1, 160), Verdict::Deliver);
+        assert_eq!(tracker.check(alice, "general", 2, 160), Verdict::Deliver);
+        assert_eq!(tracker.check(alice, "general", 2, 160), Verdict::Duplicate);
+        assert_eq!(tracker.check(alice, "general", 4, 161), Verdict::Gap(1));
+    }
+
+    #[test]
+    fn test_tracked_streams_are_capped() {
+        let mut tracker = SequenceTracker::default();
+        let alice = PeerId::random();
+        assert_eq!(tracker.check(alice, "general", 1, 100), Verdict::Deliver);
+        for i in 0..MAX_TRACKED_STREAMS as u64 {
+            tracker.check(PeerId::random(), "general", 1, 200 + i);
+        }
+        assert_eq!(tracker.last.len(), MAX_TRACKED_STREAMS);
+        // The quietest stream was dropped, so alice starts fresh: no gap
+        assert_eq!(tracker.check(alice, "general", 9, 300), Verdict::Deliver);
+    }
+}
@@ -0,0 +1,150 @@
+//! Gossipsub topic naming and parsing
+//!
+//! Every topic OpenWire publishes on is built and parsed here, so dispatch in
+//! `handle_behaviour_event` matches on a `TopicKind` instead of comparing
+//! prefixes. Ids embedded in topic names are validated: a room id can't smuggle
+//! in another topic's name (e.g. `invite`, which would alias the room-invite
+//! topic) or arbitrary characters.
+
+use super::{FILE_TRANSFER_TOPIC, GENERAL_TOPIC, KEY_EXCHANGE_TOPIC, ROOM_DIRECTORY_TOPIC, ROOM_INVITE_TOPIC};
+
+/// Prefix of per-room topics: `openwire-room-<room_id>`
+const ROOM_TOPIC_PREFIX: &str = "openwire-room-";
+/// Prefix of per-peer direct-message topics: `openwire-peer-<tag>`
+const DM_TOPIC_PREFIX: &str = "openwire-peer-";
+/// Prefix of the lobby topic: `openwire-lobby-<network hash>`
+const LOBBY_TOPIC_PREFIX: &str = "openwire-lobby-";
+
+/// Longest room id accepted in a topic name
+const MAX_ROOM_ID_LEN: usize = 64;
+/// Longest peer tag accepted in a topic name
+const MAX_DM_TAG_LEN: usize = 128;
+
+/// What a gossipsub topic carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopicKind<'a> {
+    General,
+    KeyExchange,
+    File,
+    RoomInvite,
+    /// Announcements of listed rooms and requests to join them
+    RoomDirectory,
+    /// Encrypted room traffic for the given room id
+    Room(&'a str),
+    /// Direct messages for the given peer tag
+    Dm(&'a str),
+    /// The public lobby of the network with the given hash
+    Lobby(&'a str),
+}
+
+impl<'a> TopicKind<'a> {
+    /// Classify a topic string. Returns `None` for unknown topics and for
+    /// room/DM topics whose id portion fails validation.
+    pub fn parse(topic: &'a str) -> Option<Self> {
+        match topic {
+            GENERAL_TOPIC => return Some(Self::General),
+            KEY_EXCHANGE_TOPIC => return Some(Self::KeyExchange),
+            FILE_TRANSFER_TOPIC => return Some(Self::File),
+            ROOM_INVITE_TOPIC => return Some(Self::RoomInvite),
+            ROOM_DIRECTORY_TOPIC => return Some(Self::RoomDirectory),
+            _ => {}
+        }
+        if let Some(room_id) = topic.strip_prefix(ROOM_TOPIC_PREFIX) {
+            return is_valid_room_id(room_id).then_some(Self::Room(room_id));
+        }
+        if let Some(tag) = topic.strip_prefix(DM_TOPIC_PREFIX) {
+            return is_valid_dm_tag(tag).then_some(Self::Dm(tag));
+        }
+        if let Some(hash) = topic.strip_prefix(LOBBY_TOPIC_PREFIX) {
+            return is_valid_dm_tag(hash).then_some(Self::Lobby(hash));
+        }
+        None
+    }
+}
+
+/// Topic name for a room
+pub fn room_topic(room_id: &str) -> String {
+    format!("{}{}", ROOM_TOPIC_PREFIX, room_id)
+}
+
+/// Topic name for direct messages to a peer
+pub fn dm_topic(tag: &str) -> String {
+    format!("{}{}", DM_TOPIC_PREFIX, tag)
+}
+
+/// Lobby topic for a network id. Peers on the same protocol version share
+/// one lobby; the hash keeps the version string out of the topic name.
+pub fn lobby_topic(network_id: &str) -> String {
+    use sha2::Digest;
+    let hash = sha2::Sha256::digest(network_id.as_bytes());
+    format!("{}{}", LOBBY_TOPIC_PREFIX, hex::encode(&hash[..8]))
+}
+
+/// Room ids are short ASCII tokens (`room-<hex>` when generated locally).
+/// `invite` and `directory` are reserved because `openwire-room-invite` and
+/// `openwire-room-directory` are the invite and directory topics.
+pub fn is_valid_room_id(room_id: &str) -> bool {
+    !room_id.is_empty()
+        && room_id.len() <= MAX_ROOM_ID_LEN
+        && room_id != "invite"
+        && room_id != "directory"
+        && room_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Peer tags are hex or base58 peer ids
+fn is_valid_dm_tag(tag: &str) -> bool {
+    !tag.is_empty() && tag.len() <= MAX_DM_TAG_LEN && tag.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fixed_topics() {
+        assert_eq!(TopicKind::parse(GENERAL_TOPIC), Some(TopicKind::General));
+        assert_eq!(TopicKind::parse(KEY_EXCHANGE_TOPIC), Some(TopicKind::KeyExchange));
+        assert_eq!(TopicKind::parse(FILE_TRANSFER_TOPIC), Some(TopicKind::File));
+        assert_eq!(TopicKind::parse(ROOM_INVITE_TOPIC), Some(TopicKind::RoomInvite));
+        assert_eq!(TopicKind::parse(ROOM_DIRECTORY_TOPIC), Some(TopicKind::RoomDirectory));
+    }
+
+    #[test]
+    fn test_parse_room_and_dm_topics() {
+        let room = room_topic("room-0123456789abcdef");
+        assert_eq!(TopicKind::parse(&room), Some(TopicKind::Room("room-0123456789abcdef")));
+        let dm = dm_topic("abc123");
+        assert_eq!(TopicKind::parse(&dm), Some(TopicKind::Dm("abc123")));
+        let lobby = lobby_topic("/openwire/0.1.0");
+        assert!(matches!(TopicKind::parse(&lobby), Some(TopicKind::Lobby(hash)) if hash.len() == 16));
+        assert_eq!(lobby, lobby_topic("/openwire/0.1.0"));
+        assert_ne!(lobby, lobby_topic("/openwire/0.2.0"));
+    }
+
+    #[test]
+    fn test_parse_adversarial_topics() {
+        // Empty and reserved ids
+        assert_eq!(TopicKind::parse("openwire-room-"), None);
+        assert_eq!(TopicKind::parse("openwire-peer-"), None);
+        // A room id can never alias the invite topic
+        assert_eq!(room_topic("invite"), ROOM_INVITE_TOPIC);
+        assert!(!is_valid_room_id("invite"));
+        assert_eq!(room_topic("directory"), ROOM_DIRECTORY_TOPIC);
+        assert!(!is_valid_room_id("directory"));
+        // Separators and control characters
+        assert_eq!(TopicKind::parse("openwire-room-a/b"), None);
+        assert_eq!(TopicKind::parse("openwire-room-a b"), None);
+        assert_eq!(TopicKind::parse("openwire-room-a\nb"), None);
+        assert_eq!(TopicKind::parse("openwire-peer-../x"), None);
+        assert_eq!(TopicKind::parse("openwire-peer-abc-def"), None);
+        // Oversized ids
+        let long = format!("openwire-room-{}", "a".repeat(MAX_ROOM_ID_LEN + 1));
+        assert_eq!(TopicKind::parse(&long), None);
+        // Near misses of the fixed topics
+        assert_eq!(TopicKind::parse("openwire-general "), None);
+        assert_eq!(TopicKind::parse("OPENWIRE-GENERAL"), None);
+        assert_eq!(TopicKind::parse(""), None);
+    }
+}
@@ -0,0 +1,317 @@
+//! On-disk locations for everything OpenWire persists
+//!
+//! All files live under one base directory. By default that is the XDG data
+//! directory (`~/.local/share/openwire`) with config in the XDG config
+//! directory (`~/.config/openwire`); an existing `~/.openwire` is kept so
+//! upgrading doesn't lose a wallet or history. `--data-dir` puts everything,
+//! config and received files included, under the given root.
+//!
+//! `--profile <name>` moves everything but `config.toml` to
+//! `profiles/<name>/` in the config directory, so each profile has its own
+//! identity, contacts, rooms and history.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Directory name under the XDG base directories
+const APP_DIR: &str = "openwire";
+/// Default download directory, in the home directory
+const RECEIVED_DIR: &str = "openwire-received";
+/// Pre-XDG location, still used when it exists
+const LEGACY_DIR: &str = ".openwire";
+/// Directory under the config directory that holds one directory per profile
+const PROFILES_DIR: &str = "profiles";
+/// Most ` (n)` suffixes tried before a received file is refused
+const MAX_NAME_SUFFIX: u32 = 1000;
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+/// Base directories that every persisted path is derived from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    data: PathBuf,
+    config: PathBuf,
+    /// Where files received from peers are saved
+    received: PathBuf,
+    /// Set by [`with_profile`](Self::with_profile); `data` is then the profile's directory
+    profile: Option<String>,
+}
+
+impl Paths {
+    /// Paths rooted at `data_dir`, or the defaults when `None`
+    pub fn new(data_dir: Option<PathBuf>) -> Self {
+        match data_dir {
+            Some(root) => Self {
+                data: root.clone(),
+                received: root.join("received"),
+                config: root,
+                profile: None,
+            },
+            None => Self::default_dirs(),
+        }
+    }
+
+    fn default_dirs() -> Self {
+        let home = dirs_next::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        let legacy = home.join(LEGACY_DIR);
+        let received = home.join(RECEIVED_DIR);
+        if legacy.is_dir() {
+            return Self {
+                data: legacy.clone(),
+                config: legacy,
+                received,
+                profile: None,
+            };
+        }
+        Self {
+            data: dirs_next::data_dir()
+                .map(|d| d.join(APP_DIR))
+                .unwrap_or_else(|| legacy.clone()),
+            config: dirs_next::config_dir()
+                .map(|d| d.join(APP_DIR))
+                .unwrap_or(legacy),
+            received,
+            profile: None,
+        }
+    }
+
+    /// Keep everything but `config.toml` in the directory of profile `name`
+    pub fn with_profile(mut self, name: Option<String>) -> Self {
+        if let Some(name) = name {
+            self.data = self.config.join(PROFILES_DIR).join(&name);
+            self.profile = Some(name);
+        }
+        self
+    }
+
+    /// The active `--profile`, if any
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Base directory for data files
+    pub fn data_dir(&self) -> &Path {
+        &self.data
+    }
+
+    /// Persisted signing/encryption identity
+    pub fn identity_path(&self) -> PathBuf {
+        self.data.join("identity.key")
+    }
+
+    /// Joined rooms and their group keys
+    pub fn rooms_path(&self) -> PathBuf {
+        self.data.join("rooms.json")
+    }
+
+    /// Peers blocked with `/block`
+    pub fn blocklist_path(&self) -> PathBuf {
+        self.data.join("blocklist.json")
+    }
+
+    /// Where peers we were connected to can be reached
+    pub fn known_peers_path(&self) -> PathBuf {
+        self.data.join("known_peers.json")
+    }
+
+    /// Recent chat messages
+    pub fn history_path(&self) -> PathBuf {
+        self.data.join("chat_history.json")
+    }
+
+    /// User configuration
+    pub fn config_path(&self) -> PathBuf {
+        self.config.join("config.toml")
+    }
+
+    /// Address book of known peers and the names we gave them
+    pub fn contacts_path(&self) -> PathBuf {
+        match self.profile {
+            Some(_) => self.data.join("contacts.json"),
+            None => self.config.join("contacts.json"),
+        }
+    }
+
+    /// Casino chip wallet
+    pub fn wallet_path(&self) -> PathBuf {
+        self.data.join("wallet.json")
+    }
+
+    /// Casino transaction ledger
+    pub fn ledger_path(&self) -> PathBuf {
+        self.data.join("history.json")
+    }
+
+    /// Log file
+    pub fn log_path(&self) -> PathBuf {
+        self.data.join("openwire.log")
+    }
+
+    /// Where files received from peers are saved: `~/openwire-received`,
+    /// or `received/` under `--data-dir`. Shared by all profiles.
+    pub fn received_dir(&self) -> PathBuf {
+        self.received.clone()
+    }
+}
+
+/// Name to save a received file under: the part after the last `/` or `\`,
+/// without control characters. Names that are empty or only dots (`..`)
+/// become `file`, so nothing is written outside the directory.
+pub fn safe_filename(name: &str) -> String {
+    let last = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = last.chars().filter(|c| !c.is_control()).collect();
+    let cleaned = cleaned.trim();
+    if cleaned.chars().all(|c| c == '.') {
+        "file".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Write `data` to a new file in `dir` named after `name`. Taken names get
+/// ` (2)`, ` (3)`, … before the extension; existing files are never
+/// overwritten. Returns the path written.
+pub fn save_unique(dir: &Path, name: &str, data: &[u8]) -> std::io::Result<PathBuf> {
+    let name = safe_filename(name);
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name.as_str(), ""),
+    };
+    for n in 1..=MAX_NAME_SUFFIX {
+        let path = match n {
+            1 => dir.join(&name),
+            n => dir.join(format!("{} ({}){}", stem, n, ext)),
+        };
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(data)?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        format!("too many received files named {}", name),
+    ))
+}
+
+/// Check a `--profile` name: it becomes a directory name, so it must be a
+/// single path component
+pub fn parse_profile(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err("use letters, digits, '-', '_' and '.' (not leading), up to 64 characters".into())
+    }
+}
+
+/// Set the base directory from `--data-dir` and `--profile`. Must run before
+/// the first call to [`get`]; later calls keep the paths already chosen.
+pub fn init(data_dir: Option<PathBuf>, profile: Option<String>) -> &'static Paths {
+    PATHS.get_or_init(|| Paths::new(data_dir).with_profile(profile))
+}
+
+/// The process-wide paths, defaulting if [`init`] wasn't called
+pub fn get() -> &'static Paths {
+    PATHS.get_or_init(|| Paths::new(None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir_redirects_all_paths() {
+        let root = PathBuf::from("/tmp/openwire-test-root");
+        let paths = Paths::new(Some(root.clone()));
+        assert_eq!(paths.data_dir(), root.as_path());
+        for path in [
+            paths.identity_path(),
+            paths.rooms_path(),
+            paths.blocklist_path(),
+            paths.known_peers_path(),
+            paths.history_path(),
+            paths.config_path(),
+            paths.contacts_path(),
+            paths.wallet_path(),
+            paths.ledger_path(),
+            paths.log_path(),
+            paths.received_dir(),
+        ] {
+            assert_eq!(path.parent(), Some(root.as_path()), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_profiles_are_isolated() {
+        let root = PathBuf::from("/tmp/openwire-test-root");
+        let work = Paths::new(Some(root.clone())).with_profile(Some("work".into()));
+        let home = Paths::new(Some(root.clone())).with_profile(Some("home".into()));
+        assert_eq!(work.profile(), Some("work"));
+        assert_eq!(work.identity_path(), root.join("profiles/work/identity.key"));
+        assert_eq!(work.contacts_path(), root.join("profiles/work/contacts.json"));
+        // Settings are shared
+        assert_eq!(work.config_path(), home.config_path());
+        for (a, b) in [
+            (work.identity_path(), home.identity_path()),
+            (work.contacts_path(), home.contacts_path()),
+            (work.rooms_path(), home.rooms_path()),
+            (work.blocklist_path(), home.blocklist_path()),
+            (work.known_peers_path(), home.known_peers_path()),
+            (work.history_path(), home.history_path()),
+        ] {
+            assert_ne!(a, b);
+        }
+        assert!(Paths::new(Some(root)).with_profile(None).profile().is_none());
+
+        assert!(parse_profile("work-2").is_ok());
+        for bad in ["", "..", ".hidden", "a/b", "a\\b"] {
+            assert!(parse_profile(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_received_filenames_stay_in_the_directory() {
+        assert_eq!(safe_filename("photo.jpg"), "photo.jpg");
+        assert_eq!(safe_filename("../../.bashrc"), ".bashrc");
+        assert_eq!(safe_filename("..\\..\\evil.exe"), "evil.exe");
+        assert_eq!(safe_filename("/etc/passwd"), "passwd");
+        assert_eq!(safe_filename("bad\nname\u{7}.txt"), "badname.txt");
+        for name in ["", "..", ".", "a/..", "dir/", " \t"] {
+            assert_eq!(safe_filename(name), "file", "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_received_files_are_never_overwritten() {
+        let dir = std::env::temp_dir().join(format!("openwire-save-unique-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = save_unique(&dir, "photo.jpg", b"one").unwrap();
+        let second = save_unique(&dir, "other/photo.jpg", b"two").unwrap();
+        let third = save_unique(&dir, "photo.jpg", b"three").unwrap();
+        let plain = save_unique(&dir, "README", b"x").unwrap();
+        let plain_again = save_unique(&dir, "README", b"y").unwrap();
+        let names: Vec<_> = [&first, &second, &third, &plain, &plain_again]
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["photo.jpg", "photo (2).jpg", "photo (3).jpg", "README", "README (2)"]);
+        assert_eq!(std::fs::read(&first).unwrap(), b"one");
+        assert_eq!(std::fs::read(&third).unwrap(), b"three");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_default_paths_are_distinct() {
+        let paths = Paths::new(None);
+        assert_ne!(paths.history_path(), paths.ledger_path());
+        assert!(paths.history_path().starts_with(paths.data_dir()));
+    }
+}
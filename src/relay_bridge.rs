@@ -9,7 +9,7 @@
 //!
 //! 1. Connects to the relay and sends a `join` message.
 //! 2. Forwards relay → local: messages from relay peers are injected into the
-//!    TUI event queue as `NetworkEvent::MessageReceived`.
+//!    shared event bus as `NetworkEvent::MessageReceived`.
 //! 3. Forwards local → relay: messages from local gossipsub peers are
 //!    re-published to the relay (loop prevention via content-hash set).
 //! 4. Reconnects with exponential back-off on disconnect.
@@ -25,7 +25,8 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::{broadcast, mpsc};
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 
-use crate::network::{NetworkCommand, NetworkEvent};
+use crate::network::topic::TopicKind;
+use crate::network::{DisconnectReason, NetworkCommand, NetworkEvent};
 
 // ── Relay protocol types ─────────────────────────────────────────────────────
 
@@ -74,13 +75,14 @@ enum RelayIn {
 ///
 /// Connects to `relay_url`, joins as `nick`, then bridges events between the
 /// relay and the local libp2p network until an unrecoverable error occurs.
+/// With `lan_protected` (`--lan-password`) nothing local goes to the relay.
 /// The caller is expected to loop and call this again to reconnect.
 pub async fn run_relay_bridge(
     relay_url: String,
     nick: String,
     _network_tx: mpsc::Sender<NetworkCommand>,
     event_broadcast: broadcast::Sender<NetworkEvent>,
-    event_tx: mpsc::Sender<NetworkEvent>,
+    lan_protected: bool,
 ) -> Result<()> {
     tracing::info!("Relay bridge: connecting to {}", relay_url);
 
@@ -129,7 +131,10 @@ pub async fn run_relay_bridge(
 
         loop {
             match local_rx.recv().await {
-                Ok(NetworkEvent::MessageReceived { data, .. }) => {
+                Ok(NetworkEvent::MessageReceived { topic, data, .. }) => {
+                    if !forwards_to_relay(&topic, lan_protected) {
+                        continue;
+                    }
                     let h = hash_bytes(&data);
                     if relay_hashes.contains(&h) {
                         // This message came from the relay — don't echo back.
@@ -158,7 +163,7 @@ pub async fn run_relay_bridge(
                     }
 
                     clear_counter += 1;
-                    if clear_counter % 512 == 0 {
+                    if clear_counter.is_multiple_of(512) {
                         relay_hashes.clear();
                     }
                 }
@@ -223,10 +228,8 @@ pub async fn run_relay_bridge(
                             text.as_str(),
                             &nick,
                             &event_broadcast,
-                            &event_tx,
                             &hash_tx,
-                        )
-                        .await;
+                        );
                     }
                     Some(Ok(Message::Close(_))) => {
                         return Err(anyhow::anyhow!("Relay WebSocket closed"));
@@ -240,12 +243,18 @@ pub async fn run_relay_bridge(
 
 // ── Helpers ───────────────────────────────────────────────────────────────────
 
+/// Whether a local message on `topic` may go to the relay. Only general
+/// chat is bridged: the lobby belongs to the P2P network, and chat behind
+/// a LAN password is meant for the LAN alone.
+fn forwards_to_relay(topic: &str, lan_protected: bool) -> bool {
+    !lan_protected && TopicKind::parse(topic) == Some(TopicKind::General)
+}
+
 /// Process a single text frame received from the relay server.
-async fn handle_relay_message(
+fn handle_relay_message(
     text: &str,
     _local_nick: &str,
     event_broadcast: &broadcast::Sender<NetworkEvent>,
-    event_tx: &mpsc::Sender<NetworkEvent>,
     hash_tx: &mpsc::Sender<u64>,
 ) {
     let msg: RelayIn = match serde_json::from_str(text) {
@@ -265,16 +274,14 @@ async fn handle_relay_message(
                     tracing::debug!("Relay bridge: existing peer {} ({})", pid, nick_str);
                     let from_peer = peer_id_from_str(pid);
                     let disc = NetworkEvent::PeerDiscovered(from_peer);
-                    let _ = event_broadcast.send(disc.clone());
-                    let _ = event_tx.send(disc).await;
+                    let _ = event_broadcast.send(disc);
                     let msg = format!("[relay:{}] is online", nick_str);
                     let chat = NetworkEvent::MessageReceived {
                         from: from_peer,
                         topic: "openwire-general".to_string(),
                         data: msg.into_bytes(),
                     };
-                    let _ = event_broadcast.send(chat.clone());
-                    let _ = event_tx.send(chat).await;
+                    let _ = event_broadcast.send(chat);
                 }
             }
         }
@@ -297,8 +304,7 @@ async fn handle_relay_message(
                 data: display_bytes,
             };
 
-            let _ = event_broadcast.send(event.clone());
-            let _ = event_tx.send(event).await;
+            let _ = event_broadcast.send(event);
         }
 
         RelayIn::PeerJoined { peer_id, nick } => {
@@ -306,8 +312,7 @@ async fn handle_relay_message(
             let from_peer = peer_id_from_str(&peer_id);
             // PeerDiscovered → TUI shows "Peer joined" and increments peer count
             let disc = NetworkEvent::PeerDiscovered(from_peer);
-            let _ = event_broadcast.send(disc.clone());
-            let _ = event_tx.send(disc).await;
+            let _ = event_broadcast.send(disc);
             // Also show a named join message in the chat
             let msg = format!("[relay:{}] joined", nick);
             let chat = NetworkEvent::MessageReceived {
@@ -315,17 +320,18 @@ async fn handle_relay_message(
                 topic: "openwire-general".to_string(),
                 data: msg.into_bytes(),
             };
-            let _ = event_broadcast.send(chat.clone());
-            let _ = event_tx.send(chat).await;
+            let _ = event_broadcast.send(chat);
         }
 
         RelayIn::PeerLeft { peer_id } => {
             tracing::info!("Relay bridge: peer left {}", peer_id);
             let from_peer = peer_id_from_str(&peer_id);
             // PeerDisconnected → TUI removes from peer list and decrements count
-            let disc = NetworkEvent::PeerDisconnected(from_peer);
-            let _ = event_broadcast.send(disc.clone());
-            let _ = event_tx.send(disc).await;
+            let disc = NetworkEvent::PeerDisconnected {
+                peer_id: from_peer,
+                reason: DisconnectReason::Clean,
+            };
+            let _ = event_broadcast.send(disc);
         }
 
         RelayIn::Pong => {
@@ -533,6 +539,16 @@ mod tests {
         assert!(!text.starts_with("[relay:"));
     }
 
+    #[test]
+    fn test_only_open_general_chat_is_forwarded() {
+        assert!(forwards_to_relay("openwire-general", false));
+        assert!(!forwards_to_relay("openwire-general", true));
+        let lobby = crate::network::topic::lobby_topic("default");
+        assert!(!forwards_to_relay(&lobby, false));
+        assert!(!forwards_to_relay("openwire-room-abc", false));
+        assert!(!forwards_to_relay("not-a-topic", false));
+    }
+
     // ── Peer ID derivation ──────────────────────────────────────────────────
 
     #[test]
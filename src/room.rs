@@ -11,7 +11,7 @@
 use anyhow::Result;
 use chacha20poly1305::{
     ChaCha20Poly1305, Nonce,
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
 };
 use rand::TryRng;
 use serde::{Deserialize, Serialize};
@@ -19,6 +19,7 @@ use std::collections::{HashMap, HashSet};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::crypto::Identity;
+use crate::encryption::{NONCE_REKEY_THRESHOLD, NonceSequence};
 
 /// Size of the group encryption key (ChaCha20-Poly1305)
 pub const GROUP_KEY_SIZE: usize = 32;
@@ -26,6 +27,48 @@ pub const GROUP_KEY_SIZE: usize = 32;
 /// Size of the nonce for encryption
 pub const NONCE_SIZE: usize = 12;
 
+/// Default cap on joined rooms (each one is a gossipsub subscription)
+pub const DEFAULT_MAX_ROOMS: usize = 32;
+
+/// How long a received invite can still be accepted, in seconds
+pub const INVITE_VALIDITY_SECS: u64 = 24 * 60 * 60;
+
+/// How far a key request's timestamp may be from our clock, in seconds
+pub const KEY_REQUEST_MAX_AGE_SECS: u64 = 5 * 60;
+
+/// How far a room message's timestamp may be from our clock, in seconds
+pub const ROOM_MESSAGE_MAX_SKEW_SECS: u64 = 120;
+
+/// How long a room directory entry lives without a fresh announcement, in seconds
+pub const DIRECTORY_TTL_SECS: u64 = 5 * 60;
+
+/// Most rooms kept in the directory
+pub const MAX_DIRECTORY_ENTRIES: usize = 256;
+
+/// Most unaccepted invites kept for `/room accept`
+pub const MAX_PENDING_INVITES: usize = 16;
+
+/// Message ids remembered per room for checking edits and deletes
+pub const MAX_TRACKED_MESSAGES: usize = 1024;
+
+/// Prefix of room message content that edits or deletes an earlier message
+const CHANGE_PREFIX: &[u8] = b"CHANGE:";
+
+/// Prefix of room message content that announces joining or leaving
+const PRESENCE_PREFIX: &[u8] = b"PRESENCE:";
+
+/// Prefix of room message content that removes a member
+const KICK_PREFIX: &[u8] = b"KICK:";
+
+/// Prefix of a (plaintext) key request on a room topic
+const KEY_REQUEST_PREFIX: &[u8] = b"KEYREQ:";
+
+/// Prefix of a room announcement on the directory topic
+const ANNOUNCE_PREFIX: &[u8] = b"ROOMANNOUNCE:";
+
+/// Domain separator for well-known room ids and keys
+const WELL_KNOWN_NAMESPACE: &str = "openwire-well-known-room-v2";
+
 /// A unique room identifier (human-readable)
 pub type RoomId = String;
 
@@ -126,8 +169,9 @@ fn invite_key_decrypt(encrypted: &[u8], private_key: &[u8; 32]) -> Result<Vec<u8
 ///
 /// This key is used to encrypt all messages within a room.
 /// It's distributed to invited peers via encrypted key exchange.
+/// Each copy carries its own nonce sequence for the messages we send.
 #[derive(ZeroizeOnDrop)]
-pub struct GroupKey(pub [u8; GROUP_KEY_SIZE]);
+pub struct GroupKey(pub [u8; GROUP_KEY_SIZE], #[zeroize(skip)] NonceSequence);
 
 impl std::fmt::Debug for GroupKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -142,12 +186,12 @@ impl GroupKey {
         rand::rng()
             .try_fill_bytes(&mut key)
             .expect("Failed to generate key");
-        Self(key)
+        Self::from_bytes(key)
     }
 
     /// Create from bytes
     pub fn from_bytes(bytes: [u8; GROUP_KEY_SIZE]) -> Self {
-        Self(bytes)
+        Self(bytes, NonceSequence::new())
     }
 
     /// Get the key bytes
@@ -157,16 +201,28 @@ impl GroupKey {
 
     /// Encrypt a message for the room
     pub fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedRoomMessage> {
-        let mut nonce_bytes = [0u8; NONCE_SIZE];
-        rand::rng()
-            .try_fill_bytes(&mut nonce_bytes)
-            .expect("Failed to generate nonce");
+        self.encrypt_with(plaintext, false)
+    }
+
+    /// Encrypt a message for the room, deflating it first with `compress`
+    /// when that makes it smaller
+    pub fn encrypt_with(&self, plaintext: &[u8], compress: bool) -> Result<EncryptedRoomMessage> {
+        let nonce_bytes = self.1.next_nonce()?.0;
+        if self.1.used() == NONCE_REKEY_THRESHOLD {
+            tracing::warn!("A room key sealed {} messages; it should be replaced", NONCE_REKEY_THRESHOLD);
+        }
 
         let cipher = ChaCha20Poly1305::new_from_slice(&self.0)
             .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
 
+        let compressed = if compress { crate::encryption::compress(plaintext) } else { None };
+        let aad = crate::encryption::sealed_aad(None, compressed.is_some(), false);
+        let payload = Payload {
+            msg: compressed.as_deref().unwrap_or(plaintext),
+            aad: &aad,
+        };
         let ciphertext = cipher
-            .encrypt(Nonce::from_slice(&nonce_bytes), plaintext)
+            .encrypt(Nonce::from_slice(&nonce_bytes), payload)
             .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
 
         let timestamp = std::time::SystemTime::now()
@@ -177,6 +233,7 @@ impl GroupKey {
             nonce: nonce_bytes,
             ciphertext,
             timestamp,
+            compressed: compressed.is_some(),
         })
     }
 
@@ -185,22 +242,41 @@ impl GroupKey {
         let cipher = ChaCha20Poly1305::new_from_slice(&self.0)
             .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
 
+        let aad = crate::encryption::sealed_aad(None, encrypted.compressed, false);
+        let payload = Payload {
+            msg: encrypted.ciphertext.as_slice(),
+            aad: &aad,
+        };
         let plaintext = cipher
-            .decrypt(
-                Nonce::from_slice(&encrypted.nonce),
-                encrypted.ciphertext.as_slice(),
-            )
+            .decrypt(Nonce::from_slice(&encrypted.nonce), payload)
             .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
 
+        if encrypted.compressed {
+            return crate::encryption::decompress(&plaintext);
+        }
         Ok(plaintext)
     }
 }
 
+/// Serialized as hex, for backups (`CryptoManager::export_bundle`)
+impl Serialize for GroupKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        hex::serde::serialize(self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for GroupKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        hex::serde::deserialize(deserializer).map(Self::from_bytes)
+    }
+}
+
+/// A clone starts its own nonce sequence; sequences are never shared
 impl Clone for GroupKey {
     fn clone(&self) -> Self {
         let mut key = [0u8; GROUP_KEY_SIZE];
         key.copy_from_slice(&self.0);
-        Self(key)
+        Self::from_bytes(key)
     }
 }
 
@@ -213,6 +289,9 @@ pub struct EncryptedRoomMessage {
     pub ciphertext: Vec<u8>,
     /// Timestamp for ordering
     pub timestamp: u64,
+    /// The plaintext was deflated before sealing; see `EncryptedMessage::compressed`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed: bool,
 }
 
 impl EncryptedRoomMessage {
@@ -227,6 +306,46 @@ impl EncryptedRoomMessage {
     }
 }
 
+/// Who may invite peers to a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum InvitePolicy {
+    /// Every member can invite (and re-send the key)
+    #[default]
+    AnyMember,
+    /// Only the room's owner can; `/room create <name> --closed`
+    OwnerOnly,
+}
+
+/// Rules a room is created with. Invites carry them, so members enforce
+/// them on invites they receive later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RoomPolicy {
+    pub invites: InvitePolicy,
+    /// The owner announces the room on the directory topic and invites
+    /// whoever asks to join it by name (`--listed`)
+    #[serde(default)]
+    pub listed: bool,
+}
+
+impl RoomPolicy {
+    /// Only the owner invites
+    pub fn closed() -> Self {
+        Self { invites: InvitePolicy::OwnerOnly, ..Self::default() }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.invites == InvitePolicy::OwnerOnly
+    }
+}
+
+/// What a peer may do in a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomRole {
+    /// Created the room
+    Owner,
+    Member,
+}
+
 /// A message inviting a peer to a room
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomInvite {
@@ -244,6 +363,13 @@ pub struct RoomInvite {
     pub timestamp: u64,
     /// Signature
     pub signature: Vec<u8>,
+    /// Rules of the room. Only closed rooms send them (with their owner),
+    /// so invites to open rooms still verify for older peers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<RoomPolicy>,
+    /// Peer id of the room's owner, for closed rooms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_peer_id: Option<String>,
 }
 
 impl RoomInvite {
@@ -263,25 +389,54 @@ impl RoomInvite {
         // Encrypt the group key for the invitee
         let encrypted_key = invite_key_encrypt(group_key.as_bytes(), invitee_encryption_key)?;
 
-        // Sign the invite data (includes target_peer_id for access control)
-        let mut sign_data = Vec::new();
-        sign_data.extend_from_slice(room_id.as_bytes());
-        sign_data.extend_from_slice(room_name.as_bytes());
-        sign_data.extend_from_slice(target_peer_id.as_bytes());
-        sign_data.extend_from_slice(&encrypted_key);
-        sign_data.extend_from_slice(&timestamp.to_le_bytes());
-
-        let signature = identity.sign(&sign_data)?;
-
-        Ok(Self {
+        let mut invite = Self {
             room_id,
             room_name,
             target_peer_id,
             encrypted_key,
             inviter_public_key: identity.public_key().to_vec(),
             timestamp,
-            signature: signature.to_bytes().to_vec(),
-        })
+            signature: Vec::new(),
+            policy: None,
+            owner_peer_id: None,
+        };
+        invite.signature = identity.sign(&invite.sign_data())?.to_bytes().to_vec();
+        Ok(invite)
+    }
+
+    /// Carry the rules of a closed room and its owner, signed again
+    pub fn with_policy(mut self, identity: &Identity, policy: RoomPolicy, owner_peer_id: String) -> Result<Self> {
+        self.policy = Some(policy);
+        self.owner_peer_id = Some(owner_peer_id);
+        self.signature = identity.sign(&self.sign_data())?.to_bytes().to_vec();
+        Ok(self)
+    }
+
+    /// Signed bytes (target_peer_id included for access control), with
+    /// the policy and owner appended when the invite has them
+    fn sign_data(&self) -> Vec<u8> {
+        let mut sign_data = Vec::new();
+        sign_data.extend_from_slice(self.room_id.as_bytes());
+        sign_data.extend_from_slice(self.room_name.as_bytes());
+        sign_data.extend_from_slice(self.target_peer_id.as_bytes());
+        sign_data.extend_from_slice(&self.encrypted_key);
+        sign_data.extend_from_slice(&self.timestamp.to_le_bytes());
+        if let Some(policy) = self.policy {
+            sign_data.push(policy.invites as u8);
+            sign_data.push(policy.listed as u8);
+            sign_data.extend_from_slice(self.owner_peer_id.as_deref().unwrap_or_default().as_bytes());
+        }
+        sign_data
+    }
+
+    /// Peer id of whoever signed the invite
+    pub fn inviter_peer_id(&self) -> Result<String> {
+        let key: &[u8; 32] = self
+            .inviter_public_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid inviter public key length"))?;
+        Ok(crate::crypto::peer_id_from_signing_key(key)?.to_string())
     }
 
     /// Verify the invite signature
@@ -300,15 +455,8 @@ impl RoomInvite {
         let mut sig_bytes = [0u8; 64];
         sig_bytes.copy_from_slice(&self.signature);
 
-        let mut sign_data = Vec::new();
-        sign_data.extend_from_slice(self.room_id.as_bytes());
-        sign_data.extend_from_slice(self.room_name.as_bytes());
-        sign_data.extend_from_slice(self.target_peer_id.as_bytes());
-        sign_data.extend_from_slice(&self.encrypted_key);
-        sign_data.extend_from_slice(&self.timestamp.to_le_bytes());
-
         crate::crypto::verify_with_key(
-            &sign_data,
+            &self.sign_data(),
             &ed25519_dalek::Signature::from_bytes(&sig_bytes),
             &pub_key_bytes,
         )
@@ -327,7 +475,7 @@ impl RoomInvite {
         }
         let mut key = [0u8; GROUP_KEY_SIZE];
         key.copy_from_slice(&decrypted);
-        Ok(GroupKey(key))
+        Ok(GroupKey::from_bytes(key))
     }
 
     /// Serialize to bytes
@@ -356,36 +504,68 @@ pub struct RoomMessage {
     pub timestamp: u64,
     /// Signature over content
     pub signature: Vec<u8>,
+    /// Id that edits and deletes refer to. Empty from older peers.
+    #[serde(default)]
+    pub id: String,
 }
 
 impl RoomMessage {
-    /// Create a new room message
+    /// Create a new room message with a fresh id
     pub fn new(
         identity: &Identity,
         room_id: RoomId,
         sender_nick: String,
         content: Vec<u8>,
     ) -> Result<Self> {
+        Self::with_id(identity, room_id, sender_nick, content, None)
+    }
+
+    /// Create a room message carrying `id`, or a fresh id derived from our
+    /// key, the time and a per-process sequence number
+    pub fn with_id(
+        identity: &Identity,
+        room_id: RoomId,
+        sender_nick: String,
+        content: Vec<u8>,
+        id: Option<String>,
+    ) -> Result<Self> {
+        use sha2::Digest;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static SEQ: AtomicU64 = AtomicU64::new(0);
+
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs();
-
-        // Sign: room_id || content || timestamp
-        let mut sign_data = Vec::new();
-        sign_data.extend_from_slice(room_id.as_bytes());
-        sign_data.extend_from_slice(&content);
-        sign_data.extend_from_slice(&timestamp.to_le_bytes());
-
-        let signature = identity.sign(&sign_data)?;
-
-        Ok(Self {
+        let id = id.unwrap_or_else(|| {
+            let mut hasher = sha2::Sha256::new();
+            hasher.update(identity.public_key());
+            hasher.update(timestamp.to_be_bytes());
+            hasher.update(SEQ.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+            hex::encode(&hasher.finalize()[..4])
+        });
+
+        let mut msg = Self {
             room_id,
             sender_public_key: identity.public_key().to_vec(),
             sender_nick,
             content,
             timestamp,
-            signature: signature.to_bytes().to_vec(),
-        })
+            signature: Vec::new(),
+            id,
+        };
+        msg.signature = identity.sign(&msg.sign_data())?.to_bytes().to_vec();
+        Ok(msg)
+    }
+
+    /// Signed bytes: room_id || content || timestamp. The id isn't signed
+    /// so older peers still verify; a copied id only ever points at the
+    /// copier's own message.
+    fn sign_data(&self) -> Vec<u8> {
+        let mut sign_data = Vec::new();
+        sign_data.extend_from_slice(self.room_id.as_bytes());
+        sign_data.extend_from_slice(&self.content);
+        sign_data.extend_from_slice(&self.timestamp.to_le_bytes());
+        sign_data
     }
 
     /// Verify the message signature
@@ -404,13 +584,8 @@ impl RoomMessage {
         let mut sig_bytes = [0u8; 64];
         sig_bytes.copy_from_slice(&self.signature);
 
-        let mut sign_data = Vec::new();
-        sign_data.extend_from_slice(self.room_id.as_bytes());
-        sign_data.extend_from_slice(&self.content);
-        sign_data.extend_from_slice(&self.timestamp.to_le_bytes());
-
         crate::crypto::verify_with_key(
-            &sign_data,
+            &self.sign_data(),
             &ed25519_dalek::Signature::from_bytes(&sig_bytes),
             &pub_key_bytes,
         )
@@ -427,8 +602,260 @@ impl RoomMessage {
     }
 }
 
+/// An edit or delete of an earlier room message, sent as room message
+/// content. Only the original author's edits are applied. Best-effort:
+/// peers that were offline keep the old copy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomChange {
+    Edit { id: String, text: String },
+    Delete { id: String },
+}
+
+impl RoomChange {
+    /// Id of the message being changed
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Edit { id, .. } | Self::Delete { id } => id,
+        }
+    }
+
+    /// Serialize into room message content
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = CHANGE_PREFIX.to_vec();
+        data.extend_from_slice(&serde_json::to_vec(self).unwrap_or_default());
+        data
+    }
+
+    /// Parse room message content, if it is a change
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data.strip_prefix(CHANGE_PREFIX)?).ok()
+    }
+}
+
+/// A member announcing that it joined or left a room, sent as room message
+/// content so only key holders can announce themselves. Members answer a
+/// join they hadn't seen with their own presence, so newcomers learn who
+/// was there before them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomPresence {
+    /// The announcing peer; must be the message's signer
+    pub peer_id: String,
+    /// Its nickname
+    pub nick: String,
+    /// False when leaving
+    pub joined: bool,
+}
+
+impl RoomPresence {
+    /// Serialize into room message content
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = PRESENCE_PREFIX.to_vec();
+        data.extend_from_slice(&serde_json::to_vec(self).unwrap_or_default());
+        data
+    }
+
+    /// Parse room message content, if it is a presence announcement
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data.strip_prefix(PRESENCE_PREFIX)?).ok()
+    }
+}
+
+/// The owner of a closed room removing a member, sent as room message
+/// content under the old group key just before the owner hands the others
+/// a new one. Members stop treating the peer as a key holder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomKick {
+    /// The removed peer
+    pub peer_id: String,
+}
+
+impl RoomKick {
+    /// Serialize into room message content
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = KICK_PREFIX.to_vec();
+        data.extend_from_slice(&serde_json::to_vec(self).unwrap_or_default());
+        data
+    }
+
+    /// Parse room message content, if it is a kick
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data.strip_prefix(KICK_PREFIX)?).ok()
+    }
+}
+
+/// A member's request for the room's group key, e.g. after missing the
+/// invite. Sent in the clear on the room topic since the requester can't
+/// encrypt for the room yet. Members that know the requester holds the
+/// key answer with a fresh invite.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRequest {
+    /// The room whose key is wanted
+    pub room_id: RoomId,
+    /// The requester's signing public key
+    pub requester_public_key: Vec<u8>,
+    /// Timestamp
+    pub timestamp: u64,
+    /// Signature
+    pub signature: Vec<u8>,
+}
+
+impl KeyRequest {
+    /// Create a signed key request
+    pub fn new(identity: &Identity, room_id: RoomId) -> Result<Self> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let signature = identity.sign(&Self::sign_data(&room_id, timestamp))?;
+        Ok(Self {
+            room_id,
+            requester_public_key: identity.public_key().to_vec(),
+            timestamp,
+            signature: signature.to_bytes().to_vec(),
+        })
+    }
+
+    fn sign_data(room_id: &str, timestamp: u64) -> Vec<u8> {
+        let mut data = KEY_REQUEST_PREFIX.to_vec();
+        data.extend_from_slice(room_id.as_bytes());
+        data.extend_from_slice(&timestamp.to_le_bytes());
+        data
+    }
+
+    /// Verify the signature and that the request isn't stale
+    pub fn verify(&self, now: u64) -> Result<()> {
+        let public_key: [u8; 32] = self
+            .requester_public_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid requester public key length"))?;
+        let signature: [u8; 64] = self
+            .signature
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid signature length"))?;
+        if now.abs_diff(self.timestamp) > KEY_REQUEST_MAX_AGE_SECS {
+            anyhow::bail!("Stale key request");
+        }
+        crate::crypto::verify_with_key(
+            &Self::sign_data(&self.room_id, self.timestamp),
+            &ed25519_dalek::Signature::from_bytes(&signature),
+            &public_key,
+        )
+    }
+
+    /// Peer id of the requester
+    pub fn requester_peer_id(&self) -> Result<libp2p::PeerId> {
+        let public_key: [u8; 32] = self
+            .requester_public_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid requester public key length"))?;
+        crate::crypto::peer_id_from_signing_key(&public_key)
+    }
+
+    /// Serialize for the room topic
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = KEY_REQUEST_PREFIX.to_vec();
+        data.extend_from_slice(&serde_json::to_vec(self)?);
+        Ok(data)
+    }
+
+    /// Parse room topic data, if it is a key request
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data.strip_prefix(KEY_REQUEST_PREFIX)?).ok()
+    }
+}
+
+/// An owner's beacon for a listed room, sent in the clear on the directory
+/// topic so peers can find it by name (`/room join-name`). A key request
+/// on the room's topic then asks the owner for an invite.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomAnnounce {
+    pub room_id: RoomId,
+    pub room_name: String,
+    /// Peer id of the owner; must match the signing key
+    pub owner: String,
+    /// The owner's signing public key
+    pub owner_public_key: Vec<u8>,
+    /// Timestamp
+    pub timestamp: u64,
+    /// Signature
+    pub signature: Vec<u8>,
+}
+
+impl RoomAnnounce {
+    /// Create a signed announcement of a room we own
+    pub fn new(identity: &Identity, room_id: RoomId, room_name: String) -> Result<Self> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let owner = crate::crypto::peer_id_from_signing_key(&identity.public_key_bytes())?.to_string();
+        let mut announce = Self {
+            room_id,
+            room_name,
+            owner,
+            owner_public_key: identity.public_key().to_vec(),
+            timestamp,
+            signature: Vec::new(),
+        };
+        announce.signature = identity.sign(&announce.sign_data())?.to_bytes().to_vec();
+        Ok(announce)
+    }
+
+    fn sign_data(&self) -> Vec<u8> {
+        let mut data = ANNOUNCE_PREFIX.to_vec();
+        data.extend_from_slice(&(self.room_id.len() as u64).to_le_bytes());
+        data.extend_from_slice(self.room_id.as_bytes());
+        data.extend_from_slice(&(self.room_name.len() as u64).to_le_bytes());
+        data.extend_from_slice(self.room_name.as_bytes());
+        data.extend_from_slice(&self.timestamp.to_le_bytes());
+        data
+    }
+
+    /// Verify the signature, that it names the signer as owner, and that
+    /// it hasn't expired
+    pub fn verify(&self, now: u64) -> Result<()> {
+        let public_key: [u8; 32] = self
+            .owner_public_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid owner public key length"))?;
+        let signature: [u8; 64] = self
+            .signature
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid signature length"))?;
+        if now.abs_diff(self.timestamp) > DIRECTORY_TTL_SECS {
+            anyhow::bail!("Stale room announcement");
+        }
+        if crate::crypto::peer_id_from_signing_key(&public_key)?.to_string() != self.owner {
+            anyhow::bail!("Room announcement names another owner");
+        }
+        if !crate::network::topic::is_valid_room_id(&self.room_id) {
+            anyhow::bail!("Invalid room id in announcement");
+        }
+        crate::crypto::verify_with_key(
+            &self.sign_data(),
+            &ed25519_dalek::Signature::from_bytes(&signature),
+            &public_key,
+        )
+    }
+
+    /// Serialize for the directory topic
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = ANNOUNCE_PREFIX.to_vec();
+        data.extend_from_slice(&serde_json::to_vec(self)?);
+        Ok(data)
+    }
+
+    /// Parse directory topic data, if it is an announcement
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data.strip_prefix(ANNOUNCE_PREFIX)?).ok()
+    }
+}
+
 /// A chat room
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Room {
     /// Unique room identifier
     pub id: RoomId,
@@ -438,8 +865,17 @@ pub struct Room {
     pub group_key: GroupKey,
     /// Set of peer IDs that are members
     pub members: HashSet<String>,
+    /// Nicknames members announced with a [`RoomPresence`], by peer ID
+    #[serde(skip)]
+    pub nicks: HashMap<String, String>,
     /// Whether we created this room
     pub is_owner: bool,
+    /// Peer id of the owner of a closed room we were invited to
+    #[serde(default)]
+    pub owner_peer_id: Option<String>,
+    /// Rules the room was created with
+    #[serde(default)]
+    pub policy: RoomPolicy,
     /// When we joined the room
     pub joined_at: u64,
 }
@@ -458,7 +894,10 @@ impl Room {
             name,
             group_key,
             members: HashSet::new(),
+            nicks: HashMap::new(),
             is_owner: true,
+            owner_peer_id: None,
+            policy: RoomPolicy::default(),
             joined_at: now,
         })
     }
@@ -474,7 +913,57 @@ impl Room {
             name: invite.room_name,
             group_key,
             members: HashSet::new(),
+            nicks: HashMap::new(),
             is_owner: false,
+            owner_peer_id: invite.owner_peer_id,
+            policy: invite.policy.unwrap_or_default(),
+            joined_at: now,
+        })
+    }
+
+    /// A well-known room: id and group key are derived from `name` and
+    /// `passphrase`, so everyone using the same pair ends up in the same
+    /// room without an invite.
+    ///
+    /// Both come from an Argon2id stretch of the passphrase
+    /// ([`derive_password_key`](crate::encryption::derive_password_key)),
+    /// salted with the room name, so every offline guess against the public
+    /// room id costs a full derivation. Still much weaker than an invited
+    /// room: a guessable passphrase falls eventually, and anyone who learns
+    /// the pair can read the room, past traffic included.
+    pub fn well_known(name: String, passphrase: &str) -> Result<Self> {
+        use hkdf::Hkdf;
+        use sha2::{Digest, Sha256};
+
+        // Fixed per-room salt; the namespace keeps it apart from LAN network ids
+        let salt = format!("{}/{}", WELL_KNOWN_NAMESPACE, name);
+        let mut stretched = crate::encryption::derive_password_key(passphrase, &salt)?;
+
+        let digest = Sha256::new()
+            .chain_update(WELL_KNOWN_NAMESPACE.as_bytes())
+            .chain_update(b"id")
+            .chain_update(stretched)
+            .finalize();
+        let id = format!("room-{}", hex::encode(&digest[..8]));
+
+        let hkdf = Hkdf::<Sha256>::new(Some(WELL_KNOWN_NAMESPACE.as_bytes()), &stretched);
+        let mut key = [0u8; GROUP_KEY_SIZE];
+        hkdf.expand(b"openwire-well-known-room-key", &mut key)
+            .map_err(|e| anyhow::anyhow!("HKDF failed: {}", e))?;
+        stretched.zeroize();
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            id,
+            name,
+            group_key: GroupKey::from_bytes(key),
+            members: HashSet::new(),
+            nicks: HashMap::new(),
+            is_owner: false,
+            owner_peer_id: None,
+            policy: RoomPolicy::default(),
             joined_at: now,
         })
     }
@@ -488,14 +977,37 @@ impl Room {
         format!("room-{}", hex::encode(bytes))
     }
 
-    /// Add a member to the room
-    pub fn add_member(&mut self, peer_id: String) {
-        self.members.insert(peer_id);
+    /// Add a member to the room. Returns false if already a member.
+    pub fn add_member(&mut self, peer_id: String) -> bool {
+        self.members.insert(peer_id)
+    }
+
+    /// Remove a member from the room. Returns false if not a member.
+    pub fn remove_member(&mut self, peer_id: &str) -> bool {
+        self.nicks.remove(peer_id);
+        self.members.remove(peer_id)
+    }
+
+    /// Role of another peer, as far as we know
+    pub fn role_of(&self, peer_id: &str) -> RoomRole {
+        if self.owner_peer_id.as_deref() == Some(peer_id) {
+            RoomRole::Owner
+        } else {
+            RoomRole::Member
+        }
+    }
+
+    /// Our own role
+    pub fn our_role(&self) -> RoomRole {
+        if self.is_owner { RoomRole::Owner } else { RoomRole::Member }
     }
 
-    /// Remove a member from the room
-    pub fn remove_member(&mut self, peer_id: &str) {
-        self.members.remove(peer_id);
+    /// Check that `inviter_peer_id` may hand out invites (and the key)
+    pub fn check_inviter(&self, inviter_peer_id: &str) -> Result<()> {
+        if self.policy.is_closed() && self.role_of(inviter_peer_id) != RoomRole::Owner {
+            anyhow::bail!("Only the owner of {} can invite to it", self.name);
+        }
+        Ok(())
     }
 
     /// Check if a peer is a member
@@ -505,7 +1017,7 @@ impl Room {
 
     /// Get the gossipsub topic for this room
     pub fn topic(&self) -> String {
-        format!("openwire-room-{}", self.id)
+        crate::network::topic::room_topic(&self.id)
     }
 }
 
@@ -515,6 +1027,27 @@ pub struct RoomManager {
     rooms: HashMap<RoomId, Room>,
     /// Our encryption private key (for decrypting invites)
     encryption_private_key: [u8; 32],
+    /// Maximum number of rooms we can be in at once
+    max_rooms: usize,
+    /// Compress messages we send (`--compress`)
+    compress: bool,
+    /// Invites we received but couldn't join yet, for `/room accept`
+    pending_invites: HashMap<RoomId, RoomInvite>,
+    /// The invite that brought us into each room, pending again if we
+    /// decline the room by leaving it
+    joined_invites: HashMap<RoomId, RoomInvite>,
+    /// Recent message ids per room with their author's signing key, oldest first
+    authors: HashMap<RoomId, std::collections::VecDeque<(String, Vec<u8>)>>,
+    /// Peers per room we know hold the group key: ones we invited, the
+    /// one who invited us, and authors of messages we could decrypt
+    authorized: HashMap<RoomId, HashSet<String>>,
+    /// Room messages accepted within the skew window, as (timestamp,
+    /// signature), oldest first; a re-delivered copy matches one of them
+    seen: HashMap<RoomId, std::collections::VecDeque<(u64, Vec<u8>)>>,
+    /// Listed rooms other peers announced, by room id
+    directory: HashMap<RoomId, RoomAnnounce>,
+    /// Where joined rooms are saved; `None` keeps them in memory only
+    path: Option<std::path::PathBuf>,
 }
 
 impl RoomManager {
@@ -523,30 +1056,348 @@ impl RoomManager {
         Self {
             rooms: HashMap::new(),
             encryption_private_key,
+            max_rooms: DEFAULT_MAX_ROOMS,
+            compress: false,
+            pending_invites: HashMap::new(),
+            joined_invites: HashMap::new(),
+            authors: HashMap::new(),
+            authorized: HashMap::new(),
+            seen: HashMap::new(),
+            directory: HashMap::new(),
+            path: None,
         }
     }
 
-    /// Create a new room
-    pub fn create_room(&mut self, name: String) -> Result<&Room> {
-        let room = Room::new(name)?;
+    /// Rooms saved at `path` by an earlier run, none if the file doesn't exist
+    pub fn load_saved(path: &std::path::Path) -> Result<Vec<Room>> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Save the joined rooms and their group keys to `path` after every
+    /// change to them
+    pub fn set_path(&mut self, path: std::path::PathBuf) {
+        self.path = Some(path);
+        self.save();
+    }
+
+    /// A failed save is logged, not returned: the change itself took effect
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut rooms: Vec<&Room> = self.rooms.values().collect();
+        rooms.sort_by(|a, b| a.id.cmp(&b.id));
+        let saved = serde_json::to_vec_pretty(&rooms)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| crate::crypto::write_private(path, &zeroize::Zeroizing::new(json)));
+        if let Err(e) = saved {
+            tracing::warn!("Failed to save rooms to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Change the joined-room cap. Rooms already joined are kept.
+    pub fn set_max_rooms(&mut self, max_rooms: usize) {
+        self.max_rooms = max_rooms;
+    }
+
+    /// Compress the messages we send when that makes them smaller. Peers
+    /// that predate compression can't read compressed messages.
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// Fail if adding `room_id` would exceed the room cap
+    fn ensure_capacity(&self, room_id: &str) -> Result<()> {
+        if !self.rooms.contains_key(room_id) && self.rooms.len() >= self.max_rooms {
+            return Err(anyhow::anyhow!(
+                "Room limit reached ({} rooms). Leave a room before joining another.",
+                self.max_rooms
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether joining `room_id` would stay within the room cap
+    pub fn has_capacity_for(&self, room_id: &str) -> bool {
+        self.ensure_capacity(room_id).is_ok()
+    }
+
+    /// Create a new room with `policy`; we are its owner
+    pub fn create_room(&mut self, name: String, policy: RoomPolicy) -> Result<&Room> {
+        let mut room = Room::new(name)?;
+        room.policy = policy;
+        self.ensure_capacity(&room.id)?;
         let id = room.id.clone();
         self.rooms.insert(id.clone(), room);
+        self.save();
         Ok(self.rooms.get(&id).unwrap())
     }
 
-    /// Join a room from an invite
+    /// Enter the well-known room for `name` + `passphrase` (see
+    /// [`Room::well_known`]). Already being in it is not an error.
+    pub fn create_well_known(&mut self, name: String, passphrase: &str) -> Result<&Room> {
+        self.add_well_known(Room::well_known(name, passphrase)?)
+    }
+
+    /// Enter an already derived well-known room, so the slow key
+    /// derivation can run without holding the manager's lock.
+    pub fn add_well_known(&mut self, room: Room) -> Result<&Room> {
+        self.ensure_capacity(&room.id)?;
+        let id = room.id.clone();
+        self.rooms.entry(id.clone()).or_insert(room);
+        self.save();
+        Ok(self.rooms.get(&id).unwrap())
+    }
+
+    /// Join a room from an invite, authorizing the inviter. An invite to a
+    /// room we're already in only replaces its group key, and only when it
+    /// comes from the owner or a peer already authorized in the room.
+    /// A closed room only takes invites from its owner: one we're in
+    /// keeps the rules we joined with, a new one has the invite's.
     pub fn join_room(&mut self, invite: RoomInvite) -> Result<&Room> {
         invite.verify()?;
+        self.ensure_capacity(&invite.room_id)?;
+        let inviter = invite.inviter_peer_id()?;
         let group_key = invite.decrypt_key(&self.encryption_private_key)?;
-        let room = Room::from_invite(invite, group_key)?;
+        let id = invite.room_id.clone();
+        let known_member = self.is_authorized(&id, &inviter);
+        if let Some(room) = self.rooms.get_mut(&id) {
+            room.check_inviter(&inviter)?;
+            // Anyone could otherwise take over the room with a key of their own
+            if !known_member && room.role_of(&inviter) != RoomRole::Owner {
+                anyhow::bail!("{} isn't a known member of {}, so it can't change the room key", inviter, room.name);
+            }
+            self.pending_invites.remove(&id);
+            room.group_key = group_key;
+        } else {
+            let room = Room::from_invite(invite.clone(), group_key)?;
+            room.check_inviter(&inviter)?;
+            self.pending_invites.remove(&id);
+            self.joined_invites.insert(id.clone(), invite);
+            self.rooms.insert(id.clone(), room);
+            self.authorize(&id, &inviter);
+        }
+        self.save();
+        Ok(self.rooms.get(&id).unwrap())
+    }
+
+    /// Keep an invite that couldn't be joined so it can be accepted later.
+    /// The oldest invite is dropped once `MAX_PENDING_INVITES` are kept.
+    pub fn add_pending_invite(&mut self, invite: RoomInvite) {
+        self.pending_invites.insert(invite.room_id.clone(), invite);
+        if self.pending_invites.len() > MAX_PENDING_INVITES
+            && let Some(oldest) = self
+                .pending_invites
+                .values()
+                .min_by_key(|i| i.timestamp)
+                .map(|i| i.room_id.clone())
+        {
+            self.pending_invites.remove(&oldest);
+        }
+    }
+
+    /// Unexpired pending invites, oldest first. Expired ones are dropped.
+    pub fn pending_invites(&mut self, now: u64) -> Vec<&RoomInvite> {
+        self.pending_invites
+            .retain(|_, invite| now.saturating_sub(invite.timestamp) <= INVITE_VALIDITY_SECS);
+        let mut invites: Vec<&RoomInvite> = self.pending_invites.values().collect();
+        invites.sort_by_key(|i| i.timestamp);
+        invites
+    }
+
+    /// Join the room of a pending invite. The invite stays pending if the
+    /// join fails (e.g. the room cap is still reached).
+    pub fn accept_pending_invite(&mut self, room_id: &str, now: u64) -> Result<&Room> {
+        let invite = self
+            .pending_invites(now)
+            .into_iter()
+            .find(|i| i.room_id == room_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No pending invite for room {}", room_id))?;
+        self.join_room(invite)
+    }
+
+    /// Rejoin a room from a backup. A room we're already in only gets the
+    /// backed-up group key.
+    pub fn restore_room(&mut self, room: Room) -> Result<&Room> {
+        self.ensure_capacity(&room.id)?;
         let id = room.id.clone();
-        self.rooms.insert(id.clone(), room);
+        match self.rooms.get_mut(&id) {
+            Some(existing) => existing.group_key = room.group_key,
+            None => {
+                self.rooms.insert(id.clone(), room);
+            }
+        }
+        self.save();
         Ok(self.rooms.get(&id).unwrap())
     }
 
     /// Leave a room
     pub fn leave_room(&mut self, room_id: &str) -> Option<Room> {
-        self.rooms.remove(room_id)
+        self.authors.remove(room_id);
+        self.authorized.remove(room_id);
+        self.seen.remove(room_id);
+        self.joined_invites.remove(room_id);
+        let left = self.rooms.remove(room_id);
+        if left.is_some() {
+            self.save();
+        }
+        left
+    }
+
+    /// Leave a room we were invited to, keeping the invite pending so it
+    /// can still be accepted within its validity window
+    pub fn decline_room(&mut self, room_id: &str) -> Option<Room> {
+        let invite = self.joined_invites.remove(room_id);
+        let left = self.leave_room(room_id);
+        if left.is_some()
+            && let Some(invite) = invite
+        {
+            self.add_pending_invite(invite);
+        }
+        left
+    }
+
+    /// Accept a (verified) message only once, and only if its timestamp is
+    /// within `ROOM_MESSAGE_MAX_SKEW_SECS` of `now`. The signature covers
+    /// the sender's content and timestamp, so a copy gossip delivers again
+    /// carries the same one while two messages sent in the same second don't.
+    pub fn check_fresh(&mut self, room_id: &str, msg: &RoomMessage, now: u64) -> Result<()> {
+        let skew = now.abs_diff(msg.timestamp);
+        if skew > ROOM_MESSAGE_MAX_SKEW_SECS {
+            anyhow::bail!("Room message timestamp {}s off", skew);
+        }
+        let seen = self.seen.entry(room_id.to_string()).or_default();
+        // Anything older than the window is rejected above, so forget it
+        while seen
+            .front()
+            .is_some_and(|(t, _)| now.saturating_sub(*t) > ROOM_MESSAGE_MAX_SKEW_SECS)
+        {
+            seen.pop_front();
+        }
+        if seen.iter().any(|(t, sig)| *t == msg.timestamp && *sig == msg.signature) {
+            anyhow::bail!("Duplicate room message");
+        }
+        if seen.len() >= MAX_TRACKED_MESSAGES {
+            seen.pop_front();
+        }
+        seen.push_back((msg.timestamp, msg.signature.clone()));
+        Ok(())
+    }
+
+    /// Rooms we own and list, as (room_id, name)
+    pub fn listed_rooms(&self) -> Vec<(RoomId, String)> {
+        let mut rooms: Vec<(RoomId, String)> = self
+            .rooms
+            .values()
+            .filter(|room| room.is_owner && room.policy.listed)
+            .map(|room| (room.id.clone(), room.name.clone()))
+            .collect();
+        rooms.sort();
+        rooms
+    }
+
+    /// Whether we answer requests to join `room_id` by name
+    pub fn is_listed(&self, room_id: &str) -> bool {
+        self.rooms
+            .get(room_id)
+            .is_some_and(|room| room.is_owner && room.policy.listed)
+    }
+
+    /// Add a (verified) announcement to the directory. A room keeps the
+    /// owner it was first announced by until that entry expires.
+    pub fn record_announce(&mut self, announce: RoomAnnounce, now: u64) -> Result<()> {
+        announce.verify(now)?;
+        self.expire_directory(now);
+        if let Some(known) = self.directory.get(&announce.room_id)
+            && known.owner != announce.owner
+        {
+            anyhow::bail!("Room {} is already announced by {}", announce.room_id, known.owner);
+        }
+        self.directory.insert(announce.room_id.clone(), announce);
+        if self.directory.len() > MAX_DIRECTORY_ENTRIES
+            && let Some(oldest) = self
+                .directory
+                .values()
+                .min_by_key(|a| a.timestamp)
+                .map(|a| a.room_id.clone())
+        {
+            self.directory.remove(&oldest);
+        }
+        Ok(())
+    }
+
+    /// Unexpired directory entries whose id is `query` or whose name
+    /// matches it (ignoring case), sorted by name then id. Several rooms
+    /// can share a name.
+    pub fn find_in_directory(&mut self, query: &str, now: u64) -> Vec<RoomAnnounce> {
+        self.expire_directory(now);
+        let mut found: Vec<RoomAnnounce> = self
+            .directory
+            .values()
+            .filter(|a| a.room_id == query || a.room_name.eq_ignore_ascii_case(query))
+            .cloned()
+            .collect();
+        found.sort_by(|a, b| (&a.room_name, &a.room_id).cmp(&(&b.room_name, &b.room_id)));
+        found
+    }
+
+    fn expire_directory(&mut self, now: u64) {
+        self.directory
+            .retain(|_, a| now.saturating_sub(a.timestamp) <= DIRECTORY_TTL_SECS);
+    }
+
+    /// Remember who wrote a message so later changes to it can be checked.
+    /// Ids are only unique per author: another member reusing one names a
+    /// message of its own. Returns false if the id is empty or the author
+    /// already used it.
+    pub fn record_author(&mut self, room_id: &str, msg: &RoomMessage) -> bool {
+        if msg.id.is_empty() {
+            return false;
+        }
+        let authors = self.authors.entry(room_id.to_string()).or_default();
+        if authors.iter().any(|(id, key)| *id == msg.id && *key == msg.sender_public_key) {
+            return false;
+        }
+        if authors.len() >= MAX_TRACKED_MESSAGES {
+            authors.pop_front();
+        }
+        authors.push_back((msg.id.clone(), msg.sender_public_key.clone()));
+        true
+    }
+
+    /// Check that the signer of a (verified) message carrying `change`
+    /// wrote a message with the id it targets
+    pub fn check_change(&self, room_id: &str, msg: &RoomMessage, change: &RoomChange) -> Result<()> {
+        let written = self
+            .authors
+            .get(room_id)
+            .is_some_and(|a| a.iter().any(|(id, key)| id == change.target() && *key == msg.sender_public_key));
+        if !written {
+            anyhow::bail!("No message {} by this author to change", change.target());
+        }
+        Ok(())
+    }
+
+    /// Note that `peer_id` holds the group key of a room we're in
+    pub fn authorize(&mut self, room_id: &str, peer_id: &str) {
+        if self.rooms.contains_key(room_id) {
+            self.authorized
+                .entry(room_id.to_string())
+                .or_default()
+                .insert(peer_id.to_string());
+        }
+    }
+
+    /// Whether `peer_id` may be sent the group key of `room_id`
+    pub fn is_authorized(&self, room_id: &str, peer_id: &str) -> bool {
+        self.authorized
+            .get(room_id)
+            .is_some_and(|peers| peers.contains(peer_id))
     }
 
     /// Get a room by ID
@@ -554,6 +1405,50 @@ impl RoomManager {
         self.rooms.get(room_id)
     }
 
+    /// Peer ids we believe are in a room, sorted. Empty for unknown rooms.
+    pub fn members_of(&self, room_id: &str) -> Vec<String> {
+        let mut members: Vec<String> = self
+            .rooms
+            .get(room_id)
+            .map(|room| room.members.iter().cloned().collect())
+            .unwrap_or_default();
+        members.sort();
+        members
+    }
+
+    /// Members of a room with the nicknames they announced, sorted by peer
+    /// ID. Empty for unknown rooms.
+    pub fn member_list(&self, room_id: &str) -> Vec<(String, Option<String>)> {
+        let Some(room) = self.rooms.get(room_id) else {
+            return Vec::new();
+        };
+        let mut members: Vec<(String, Option<String>)> = room
+            .members
+            .iter()
+            .map(|peer| (peer.clone(), room.nicks.get(peer).cloned()))
+            .collect();
+        members.sort();
+        members
+    }
+
+    /// Apply a presence announcement signed by `author`. Returns true for a
+    /// join we hadn't seen announced, which we answer with our own.
+    pub fn apply_presence(&mut self, room_id: &str, author: &str, presence: &RoomPresence) -> Result<bool> {
+        if presence.peer_id != author {
+            return Err(anyhow::anyhow!("Presence for {} signed by {}", presence.peer_id, author));
+        }
+        let room = self
+            .rooms
+            .get_mut(room_id)
+            .ok_or_else(|| anyhow::anyhow!("Not in room {}", room_id))?;
+        if !presence.joined {
+            room.remove_member(author);
+            return Ok(false);
+        }
+        room.add_member(author.to_string());
+        Ok(room.nicks.insert(author.to_string(), presence.nick.clone()).is_none())
+    }
+
     /// Get a mutable room by ID
     pub fn get_room_mut(&mut self, room_id: &str) -> Option<&mut Room> {
         self.rooms.get_mut(room_id)
@@ -574,7 +1469,8 @@ impl RoomManager {
         self.rooms.len()
     }
 
-    /// Create an invite for a peer (only room members can invite)
+    /// Create an invite for a peer. Members can invite unless the room
+    /// is closed, where only its owner can.
     pub fn create_invite(
         &self,
         room_id: &str,
@@ -587,22 +1483,86 @@ impl RoomManager {
             .get(room_id)
             .ok_or_else(|| anyhow::anyhow!("Room not found: {}", room_id))?;
 
-        // Note: We implicitly allow invite creation since we're in the room
-        // (if we weren't, we wouldn't have the room in our rooms map)
+        self.check_can_invite(room_id)?;
 
-        RoomInvite::new(
+        let invite = RoomInvite::new(
             identity,
             room.id.clone(),
             room.name.clone(),
             invitee_peer_id.to_string(),
             &room.group_key,
             invitee_encryption_key,
-        )
+        )?;
+        if !room.policy.is_closed() {
+            return Ok(invite);
+        }
+        let us = crate::crypto::peer_id_from_signing_key(&identity.public_key_bytes())?;
+        invite.with_policy(identity, room.policy, us.to_string())
     }
 
-    /// Check if we can invite to a room (must be a member)
-    pub fn can_invite_to_room(&self, room_id: &str) -> bool {
-        self.rooms.contains_key(room_id)
+    /// Remove `peer_id` from a closed room we own and switch to a new group
+    /// key. Returns the peers still authorized, who need the new key.
+    pub fn kick(&mut self, room_id: &str, peer_id: &str) -> Result<Vec<String>> {
+        self.check_can_kick(room_id)?;
+        let room = self
+            .rooms
+            .get_mut(room_id)
+            .ok_or_else(|| anyhow::anyhow!("You are not a member of this room"))?;
+        let authorized = self.authorized.entry(room_id.to_string()).or_default();
+        let was_authorized = authorized.remove(peer_id);
+        if !room.remove_member(peer_id) && !was_authorized {
+            anyhow::bail!("{} isn't in {}", peer_id, room.name);
+        }
+        room.group_key = GroupKey::generate();
+        let mut remaining: Vec<String> = authorized.iter().cloned().collect();
+        remaining.sort();
+        self.save();
+        Ok(remaining)
+    }
+
+    /// Check that we can kick from a room: it must be closed, so members
+    /// know its owner, and we must own it
+    pub fn check_can_kick(&self, room_id: &str) -> Result<()> {
+        let room = self
+            .rooms
+            .get(room_id)
+            .ok_or_else(|| anyhow::anyhow!("You are not a member of this room"))?;
+        if !room.policy.is_closed() {
+            anyhow::bail!("Only closed rooms have an owner members accept kicks from");
+        }
+        if room.our_role() != RoomRole::Owner {
+            anyhow::bail!("Only the owner of {} can kick", room.name);
+        }
+        Ok(())
+    }
+
+    /// Apply a kick signed by `author`, which must be the room's owner
+    pub fn apply_kick(&mut self, room_id: &str, author: &str, kick: &RoomKick) -> Result<()> {
+        let room = self
+            .rooms
+            .get_mut(room_id)
+            .ok_or_else(|| anyhow::anyhow!("Not in room {}", room_id))?;
+        if room.role_of(author) != RoomRole::Owner {
+            anyhow::bail!("Kick in {} from {}, who isn't its owner", room.name, author);
+        }
+        room.remove_member(&kick.peer_id);
+        if let Some(authorized) = self.authorized.get_mut(room_id) {
+            authorized.remove(&kick.peer_id);
+        }
+        Ok(())
+    }
+
+    /// Check that we can invite to a room: we must be in it, and own it
+    /// if it is closed
+    pub fn check_can_invite(&self, room_id: &str) -> Result<()> {
+        let room = self
+            .rooms
+            .get(room_id)
+            .ok_or_else(|| anyhow::anyhow!("You are not a member of this room"))?;
+        if room.policy.is_closed() && room.our_role() != RoomRole::Owner {
+            anyhow::bail!("Only the owner of {} can invite to it", room.name);
+        }
+        Ok(())
     }
 
     /// Encrypt a message for a room
@@ -617,7 +1577,7 @@ impl RoomManager {
             .ok_or_else(|| anyhow::anyhow!("Room not found: {}", room_id))?;
 
         let plaintext = message.to_bytes()?;
-        room.group_key.encrypt(&plaintext)
+        room.group_key.encrypt_with(&plaintext, self.compress)
     }
 
     /// Decrypt a message from a room
@@ -651,6 +1611,25 @@ mod tests {
         assert_eq!(plaintext.to_vec(), decrypted);
     }
 
+    #[test]
+    fn test_group_key_compression() {
+        let key = GroupKey::generate();
+        let paste = "error: could not compile `openwire`\n".repeat(100);
+        let sealed = key.encrypt_with(paste.as_bytes(), true).unwrap();
+        assert!(sealed.compressed);
+        assert!(sealed.ciphertext.len() < paste.len() / 10);
+        assert_eq!(key.decrypt(&sealed).unwrap(), paste.as_bytes());
+
+        let short = key.encrypt_with(b"hi", true).unwrap();
+        assert!(!short.compressed);
+        assert_eq!(key.decrypt(&short).unwrap(), b"hi");
+        assert!(!String::from_utf8_lossy(&short.to_bytes().unwrap()).contains("compressed"));
+
+        let mut flipped = sealed;
+        flipped.compressed = false;
+        assert!(key.decrypt(&flipped).is_err());
+    }
+
     #[test]
     fn test_room_creation() {
         let room = Room::new("Test Room".to_string()).unwrap();
@@ -693,11 +1672,37 @@ mod tests {
         assert_eq!(room.group_key.as_bytes(), decrypted_key.as_bytes());
     }
 
+    #[test]
+    fn test_restore_room_from_backup() {
+        let room = Room::new("Backed up".into()).unwrap();
+        let json = serde_json::to_string(&room).unwrap();
+        assert!(json.contains(&hex::encode(room.group_key.as_bytes())));
+        let restored: Room = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.id, room.id);
+        assert_eq!(restored.group_key.as_bytes(), room.group_key.as_bytes());
+
+        let mut manager = RoomManager::new([0u8; 32]);
+        manager.restore_room(restored.clone()).unwrap();
+        assert!(manager.in_room(&room.id));
+        // Restoring again only updates the key
+        let mut rekeyed = restored;
+        rekeyed.group_key = GroupKey::generate();
+        manager.restore_room(rekeyed.clone()).unwrap();
+        assert_eq!(manager.room_count(), 1);
+        assert_eq!(
+            manager.get_room(&room.id).unwrap().group_key.as_bytes(),
+            rekeyed.group_key.as_bytes()
+        );
+
+        manager.set_max_rooms(1);
+        assert!(manager.restore_room(Room::new("One too many".into()).unwrap()).is_err());
+    }
+
     #[test]
     fn test_room_manager() {
         let mut manager = RoomManager::new([0u8; 32]);
 
-        let room = manager.create_room("Test".to_string()).unwrap();
+        let room = manager.create_room("Test".to_string(), RoomPolicy::default()).unwrap();
         let room_id = room.id.clone();
         assert!(room_id.starts_with("room-"));
 
@@ -705,6 +1710,421 @@ mod tests {
         assert!(manager.in_room(&room_id));
     }
 
+    #[test]
+    fn test_well_known_room_is_deterministic() {
+        use x25519_dalek::StaticSecret;
+        let mut alice = RoomManager::new(*StaticSecret::random_from_rng(&mut rand::rng()).as_bytes());
+        let mut bob = RoomManager::new(*StaticSecret::random_from_rng(&mut rand::rng()).as_bytes());
+
+        let a = alice.create_well_known("meetup".into(), "correct horse").unwrap().id.clone();
+        let b = bob.create_well_known("meetup".into(), "correct horse").unwrap().id.clone();
+        assert_eq!(a, b);
+        assert!(crate::network::topic::is_valid_room_id(&a));
+        assert!(!alice.get_room(&a).unwrap().is_owner);
+
+        // Both derive the same group key
+        let msg = alice.get_room(&a).unwrap().group_key.encrypt(b"hi").unwrap();
+        assert_eq!(bob.get_room(&b).unwrap().group_key.decrypt(&msg).unwrap(), b"hi");
+
+        // Other inputs give other rooms; re-entering is a no-op
+        let other = Room::well_known("meetup".into(), "correct horsf").unwrap();
+        assert_ne!(other.id, a);
+        assert_ne!(Room::well_known("meetu".into(), "pcorrect horse").unwrap().id, a);
+        alice.create_well_known("meetup".into(), "correct horse").unwrap();
+        assert_eq!(alice.room_count(), 1);
+    }
+
+    #[test]
+    fn test_rooms_persist() {
+        use x25519_dalek::StaticSecret;
+        let path = std::env::temp_dir().join(format!("openwire-rooms-{}.json", std::process::id()));
+        assert!(RoomManager::load_saved(&path).unwrap().is_empty());
+
+        let mut manager = RoomManager::new(*StaticSecret::random_from_rng(&mut rand::rng()).as_bytes());
+        let kept = manager.create_room("kept".into(), RoomPolicy::default()).unwrap().id.clone();
+        manager.set_path(path.clone());
+        let left = manager.create_room("left".into(), RoomPolicy::default()).unwrap().id.clone();
+        assert_eq!(RoomManager::load_saved(&path).unwrap().len(), 2);
+        manager.leave_room(&left);
+
+        let saved = RoomManager::load_saved(&path).unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].id, kept);
+        // The group key survives the round trip
+        let msg = manager.get_room(&kept).unwrap().group_key.encrypt(b"hi").unwrap();
+        assert_eq!(saved[0].group_key.decrypt(&msg).unwrap(), b"hi");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_closed_room_only_owner_invites() {
+        use x25519_dalek::{PublicKey, StaticSecret};
+
+        let (a_key, b_key, c_key) = (
+            StaticSecret::random_from_rng(&mut rand::rng()),
+            StaticSecret::random_from_rng(&mut rand::rng()),
+            StaticSecret::random_from_rng(&mut rand::rng()),
+        );
+        let public = |secret: &StaticSecret| *PublicKey::from(secret).as_bytes();
+        let (alice_id, bob_id) = (Identity::generate().unwrap(), Identity::generate().unwrap());
+        let alice_peer = crate::crypto::peer_id_from_signing_key(&alice_id.public_key_bytes())
+            .unwrap()
+            .to_string();
+        let mut alice = RoomManager::new(*a_key.as_bytes());
+        let mut bob = RoomManager::new(*b_key.as_bytes());
+        let mut carol = RoomManager::new(*c_key.as_bytes());
+        let room_id = alice.create_room("Inner".into(), RoomPolicy::closed()).unwrap().id.clone();
+
+        // The owner's invite carries the policy to the new member
+        let invite = alice.create_invite(&room_id, &alice_id, "bob", &public(&b_key)).unwrap();
+        assert_eq!(invite.policy, Some(RoomPolicy::closed()));
+        let room = bob.join_room(invite).unwrap();
+        assert!(room.policy.is_closed());
+        assert_eq!(room.role_of(&alice_peer), RoomRole::Owner);
+        assert_eq!(room.our_role(), RoomRole::Member);
+
+        // Bob is in the room but can't invite
+        assert!(bob.check_can_invite(&room_id).is_err());
+        assert!(bob.create_invite(&room_id, &bob_id, "carol", &public(&c_key)).is_err());
+
+        // Nor get past Carol by signing an invite himself
+        let group_key = &bob.get_room(&room_id).unwrap().group_key;
+        let forge = || {
+            RoomInvite::new(&bob_id, room_id.clone(), "Inner".into(), "carol".into(), group_key, &public(&c_key)).unwrap()
+        };
+        let claimed = forge().with_policy(&bob_id, RoomPolicy::closed(), alice_peer.clone()).unwrap();
+        assert!(carol.join_room(claimed).is_err());
+        assert_eq!(carol.room_count(), 0);
+
+        // Once in, Carol keeps the room's rules even if an invite leaves them out
+        let invite = alice.create_invite(&room_id, &alice_id, "carol", &public(&c_key)).unwrap();
+        carol.join_room(invite).unwrap();
+        assert!(carol.join_room(forge()).is_err());
+
+        // Any member of an open room can invite
+        let open = alice.create_room("Open".into(), RoomPolicy::default()).unwrap().id.clone();
+        let invite = alice.create_invite(&open, &alice_id, "bob", &public(&b_key)).unwrap();
+        assert!(invite.policy.is_none());
+        bob.join_room(invite).unwrap();
+        let invite = bob.create_invite(&open, &bob_id, "carol", &public(&c_key)).unwrap();
+        carol.join_room(invite).unwrap();
+    }
+
+    #[test]
+    fn test_invite_policy_is_signed() {
+        let alice = Identity::generate().unwrap();
+        let mut manager = RoomManager::new([0u8; 32]);
+        let policy = RoomPolicy { listed: true, ..RoomPolicy::closed() };
+        let room_id = manager.create_room("Inner".into(), policy).unwrap().id.clone();
+        let mut invite = manager.create_invite(&room_id, &alice, "bob", &[9u8; 32]).unwrap();
+        invite.verify().unwrap();
+        invite.policy = Some(RoomPolicy::closed());
+        assert!(invite.verify().is_err());
+    }
+
+    #[test]
+    fn test_only_owner_kicks() {
+        let (alice, bob, carol) = (Identity::generate().unwrap(), Identity::generate().unwrap(), Identity::generate().unwrap());
+        let peer = |who: &Identity| crate::crypto::peer_id_from_signing_key(&who.public_key_bytes()).unwrap().to_string();
+        let secret = x25519_dalek::StaticSecret::random_from_rng(&mut rand::rng());
+        let public = *x25519_dalek::PublicKey::from(&secret).as_bytes();
+
+        let mut owner = RoomManager::new([7u8; 32]);
+        let room_id = owner.create_room("Inner".into(), RoomPolicy::closed()).unwrap().id.clone();
+        let invite = owner.create_invite(&room_id, &alice, &peer(&bob), &public).unwrap();
+        let mut member = RoomManager::new(*secret.as_bytes());
+        member.join_room(invite).unwrap();
+        for manager in [&mut owner, &mut member] {
+            manager.authorize(&room_id, &peer(&bob));
+            manager.authorize(&room_id, &peer(&carol));
+            manager.get_room_mut(&room_id).unwrap().add_member(peer(&carol));
+        }
+
+        // A member can neither kick nor have a kick it signed applied
+        assert!(member.kick(&room_id, &peer(&carol)).is_err());
+        let kick = RoomKick { peer_id: peer(&carol) };
+        assert_eq!(RoomKick::from_bytes(&kick.to_bytes()), Some(kick.clone()));
+        assert!(member.apply_kick(&room_id, &peer(&bob), &kick).is_err());
+        assert!(member.is_authorized(&room_id, &peer(&carol)));
+
+        // The owner's kick removes Carol and changes the key for everyone else
+        let old_key = owner.get_room(&room_id).unwrap().group_key.clone();
+        assert_eq!(owner.kick(&room_id, &peer(&carol)).unwrap(), vec![peer(&bob)]);
+        assert_ne!(owner.get_room(&room_id).unwrap().group_key.as_bytes(), old_key.as_bytes());
+        assert!(!owner.get_room(&room_id).unwrap().is_member(&peer(&carol)));
+        assert!(!owner.is_authorized(&room_id, &peer(&carol)));
+        assert!(owner.kick(&room_id, &peer(&carol)).is_err());
+
+        member.apply_kick(&room_id, &peer(&alice), &kick).unwrap();
+        assert!(!member.get_room(&room_id).unwrap().is_member(&peer(&carol)));
+        assert!(!member.is_authorized(&room_id, &peer(&carol)));
+
+        // Open rooms have no owner members would take a kick from
+        let open = owner.create_room("Open".into(), RoomPolicy::default()).unwrap().id.clone();
+        owner.authorize(&open, &peer(&bob));
+        assert!(owner.kick(&open, &peer(&bob)).is_err());
+    }
+
+    #[test]
+    fn test_room_directory_finds_rooms_by_name() {
+        let mut manager = RoomManager::new([0u8; 32]);
+        let (alice, bob) = (Identity::generate().unwrap(), Identity::generate().unwrap());
+        let announce = |who: &Identity, id: &str, name: &str| RoomAnnounce::new(who, id.into(), name.into()).unwrap();
+        let now = announce(&alice, "room-a1", "Lunch").timestamp;
+
+        let lunch = announce(&alice, "room-a1", "Lunch");
+        let parsed = RoomAnnounce::from_bytes(&lunch.to_bytes().unwrap()).unwrap();
+        manager.record_announce(parsed, now).unwrap();
+        assert_eq!(manager.find_in_directory("lunch", now)[0].room_id, "room-a1");
+        assert!(manager.find_in_directory("Dinner", now).is_empty());
+
+        // Same name, different rooms: both are listed, and either can be picked by id
+        manager.record_announce(announce(&bob, "room-b1", "Lunch"), now).unwrap();
+        let ids: Vec<String> = manager.find_in_directory("Lunch", now).into_iter().map(|a| a.room_id).collect();
+        assert_eq!(ids, ["room-a1", "room-b1"]);
+        assert_eq!(manager.find_in_directory("room-b1", now).len(), 1);
+
+        // Tampered, misattributed or hijacked announcements are refused
+        let mut renamed = announce(&alice, "room-a2", "Lunch");
+        renamed.room_name = "Payroll".into();
+        assert!(manager.record_announce(renamed, now).is_err());
+        let mut stolen = announce(&bob, "room-b2", "Lunch");
+        stolen.owner = lunch.owner.clone();
+        assert!(manager.record_announce(stolen, now).is_err());
+        assert!(manager.record_announce(announce(&bob, "room-a1", "Lunch"), now).is_err());
+        assert!(manager.record_announce(announce(&alice, "directory", "Lunch"), now).is_err());
+
+        // Entries expire unless announced again. Later announcements may
+        // carry a later second than `now`, so look well past all of them.
+        let expired = now + DIRECTORY_TTL_SECS + 60;
+        assert!(manager.find_in_directory("Lunch", expired).is_empty());
+        assert!(manager.record_announce(lunch, expired).is_err());
+    }
+
+    #[test]
+    fn test_members_of() {
+        let mut manager = RoomManager::new([0u8; 32]);
+        let id = manager.create_room("Team".into(), RoomPolicy::default()).unwrap().id.clone();
+        assert!(manager.members_of(&id).is_empty());
+
+        let room = manager.get_room_mut(&id).unwrap();
+        room.add_member("peer-b".into());
+        room.add_member("peer-a".into());
+        assert_eq!(manager.members_of(&id), vec!["peer-a", "peer-b"]);
+        assert!(manager.members_of("room-unknown").is_empty());
+    }
+
+    #[test]
+    fn test_room_cap_rejects_create_and_join() {
+        use x25519_dalek::{PublicKey, StaticSecret};
+
+        let secret = StaticSecret::random_from_rng(&mut rand::rng());
+        let public = PublicKey::from(&secret);
+        let mut manager = RoomManager::new(*secret.as_bytes());
+        manager.set_max_rooms(2);
+
+        let first = manager.create_room("One".to_string(), RoomPolicy::default()).unwrap().id.clone();
+        manager.create_room("Two".to_string(), RoomPolicy::default()).unwrap();
+
+        let err = manager.create_room("Three".to_string(), RoomPolicy::default()).unwrap_err();
+        assert!(err.to_string().contains("Room limit reached"));
+
+        let inviter = Identity::generate().unwrap();
+        let room = Room::new("Invited".to_string()).unwrap();
+        let invite = RoomInvite::new(
+            &inviter,
+            room.id.clone(),
+            room.name.clone(),
+            "me".to_string(),
+            &room.group_key,
+            public.as_bytes(),
+        )
+        .unwrap();
+        assert!(manager.join_room(invite).is_err());
+
+        // Existing rooms are unaffected
+        assert_eq!(manager.room_count(), 2);
+        assert!(manager.in_room(&first));
+        assert!(!manager.in_room(&room.id));
+    }
+
+    #[test]
+    fn test_pending_invite_accepted_later() {
+        use x25519_dalek::{PublicKey, StaticSecret};
+
+        let secret = StaticSecret::random_from_rng(&mut rand::rng());
+        let public = PublicKey::from(&secret);
+        let mut manager = RoomManager::new(*secret.as_bytes());
+        manager.set_max_rooms(0);
+
+        let inviter = Identity::generate().unwrap();
+        let room = Room::new("Later".to_string()).unwrap();
+        let invite = RoomInvite::new(
+            &inviter,
+            room.id.clone(),
+            room.name.clone(),
+            "me".to_string(),
+            &room.group_key,
+            public.as_bytes(),
+        )
+        .unwrap();
+        let now = invite.timestamp;
+
+        // Turned away at the cap, the invite is kept
+        assert!(!manager.has_capacity_for(&room.id));
+        assert!(manager.join_room(invite.clone()).is_err());
+        manager.add_pending_invite(invite.clone());
+        assert!(manager.accept_pending_invite(&room.id, now).is_err());
+        assert_eq!(manager.pending_invites(now).len(), 1);
+
+        manager.set_max_rooms(1);
+        assert!(manager.accept_pending_invite("room-other", now).is_err());
+        let joined = manager.accept_pending_invite(&room.id, now).unwrap();
+        assert_eq!(joined.name, "Later");
+        assert!(manager.pending_invites(now).is_empty());
+
+        // Declining the room keeps its invite for later
+        assert!(manager.decline_room(&room.id).is_some());
+        assert!(!manager.in_room(&room.id));
+        assert_eq!(manager.pending_invites(now).len(), 1);
+        manager.accept_pending_invite(&room.id, now).unwrap();
+
+        // Expired invites are dropped
+        manager.leave_room(&room.id);
+        assert!(manager.pending_invites(now).is_empty());
+        manager.add_pending_invite(invite);
+        let later = now + INVITE_VALIDITY_SECS + 1;
+        assert!(manager.pending_invites(later).is_empty());
+        assert!(manager.accept_pending_invite(&room.id, later).is_err());
+    }
+
+    #[test]
+    fn test_room_presence_transitions() {
+        let mut manager = RoomManager::new([7u8; 32]);
+        let room_id = manager.create_room("Team".into(), RoomPolicy::default()).unwrap().id.clone();
+        let presence = |joined| RoomPresence { peer_id: "peer-b".into(), nick: "bob".into(), joined };
+        assert_eq!(RoomPresence::from_bytes(&presence(true).to_bytes()), Some(presence(true)));
+        assert!(RoomPresence::from_bytes(b"PRESENCE?").is_none());
+
+        // A first join is answered, a repeat isn't
+        assert!(manager.apply_presence(&room_id, "peer-b", &presence(true)).unwrap());
+        assert!(!manager.apply_presence(&room_id, "peer-b", &presence(true)).unwrap());
+        manager.get_room_mut(&room_id).unwrap().add_member("peer-c".into());
+        assert_eq!(
+            manager.member_list(&room_id),
+            vec![("peer-b".into(), Some("bob".into())), ("peer-c".into(), None)]
+        );
+
+        // Only the peer itself can announce it
+        assert!(manager.apply_presence(&room_id, "peer-c", &presence(false)).is_err());
+        assert!(manager.apply_presence("room-elsewhere", "peer-b", &presence(true)).is_err());
+
+        assert!(!manager.apply_presence(&room_id, "peer-b", &presence(false)).unwrap());
+        assert_eq!(manager.member_list(&room_id), vec![("peer-c".into(), None)]);
+        // Coming back is a new join again
+        assert!(manager.apply_presence(&room_id, "peer-b", &presence(true)).unwrap());
+    }
+
+    #[test]
+    fn test_room_messages_are_fresh_and_seen_once() {
+        let mut manager = RoomManager::new([7u8; 32]);
+        let identity = Identity::generate().unwrap();
+        let room_id = manager.create_room("Team".into(), RoomPolicy::default()).unwrap().id.clone();
+        let msg = |text: &str| RoomMessage::new(&identity, room_id.clone(), "bob".into(), text.into()).unwrap();
+        let hello = msg("hello");
+        let now = hello.timestamp;
+
+        manager.check_fresh(&room_id, &hello, now).unwrap();
+        assert!(manager.check_fresh(&room_id, &hello, now + 1).is_err());
+        // Same sender, same second, different text
+        manager.check_fresh(&room_id, &msg("again"), now).unwrap();
+
+        // Stale or from the future
+        let skew = ROOM_MESSAGE_MAX_SKEW_SECS;
+        assert!(manager.check_fresh(&room_id, &msg("late"), now + skew + 3).is_err());
+        assert!(manager.check_fresh(&room_id, &msg("early"), now.saturating_sub(skew + 1)).is_err());
+
+        // Copies older than the window are forgotten
+        let later = now + skew + 5;
+        let mut fresh = msg("later");
+        fresh.timestamp = later;
+        manager.check_fresh(&room_id, &fresh, later).unwrap();
+        assert_eq!(manager.seen[&room_id].len(), 1);
+    }
+
+    #[test]
+    fn test_key_request_and_rekey() {
+        let member = Identity::generate().unwrap();
+        let requester = Identity::generate().unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let request = KeyRequest::new(&requester, "room-aa".into()).unwrap();
+        let parsed = KeyRequest::from_bytes(&request.to_bytes().unwrap()).unwrap();
+        parsed.verify(now).unwrap();
+        assert_eq!(
+            parsed.requester_peer_id().unwrap(),
+            crate::crypto::peer_id_from_signing_key(&requester.public_key_bytes()).unwrap()
+        );
+        assert!(parsed.verify(now + KEY_REQUEST_MAX_AGE_SECS + 1).is_err());
+        let mut tampered = parsed.clone();
+        tampered.room_id = "room-bb".into();
+        assert!(tampered.verify(now).is_err());
+        assert!(KeyRequest::from_bytes(b"not a request").is_none());
+
+        // Authorization only sticks for rooms we're in, and goes on leave
+        let mut manager = RoomManager::new([7u8; 32]);
+        let room_id = manager.create_room("Team".into(), RoomPolicy::default()).unwrap().id.clone();
+        manager.authorize(&room_id, "peer-b");
+        manager.authorize("room-elsewhere", "peer-b");
+        assert!(manager.is_authorized(&room_id, "peer-b"));
+        assert!(!manager.is_authorized("room-elsewhere", "peer-b"));
+
+        // A re-sent invite to a joined room only swaps the key
+        let secret = x25519_dalek::StaticSecret::random_from_rng(&mut rand::rng());
+        let public = *x25519_dalek::PublicKey::from(&secret).as_bytes();
+        let mut joined = RoomManager::new(*secret.as_bytes());
+        let invite = manager
+            .create_invite(&room_id, &member, "peer-b", &public)
+            .unwrap();
+        joined.join_room(invite.clone()).unwrap();
+        let member_id = crate::crypto::peer_id_from_signing_key(&member.public_key_bytes()).unwrap().to_string();
+        assert!(joined.is_authorized(&room_id, &member_id));
+        joined.get_room_mut(&room_id).unwrap().add_member("peer-c".into());
+        joined.get_room_mut(&room_id).unwrap().group_key = GroupKey::generate();
+        let room = joined.join_room(invite).unwrap();
+        assert_eq!(
+            room.group_key.as_bytes(),
+            manager.get_room(&room_id).unwrap().group_key.as_bytes()
+        );
+        assert!(room.is_member("peer-c"));
+
+        // A stranger can't swap in a key of its own, even with a valid signature
+        let stranger = Identity::generate().unwrap();
+        let rekey = RoomInvite::new(
+            &stranger,
+            room_id.clone(),
+            "Team".into(),
+            "peer-b".into(),
+            &GroupKey::generate(),
+            &public,
+        )
+        .unwrap();
+        rekey.verify().unwrap();
+        assert!(joined.join_room(rekey).is_err());
+        assert_eq!(
+            joined.get_room(&room_id).unwrap().group_key.as_bytes(),
+            manager.get_room(&room_id).unwrap().group_key.as_bytes()
+        );
+        let stranger_id = crate::crypto::peer_id_from_signing_key(&stranger.public_key_bytes()).unwrap().to_string();
+        assert!(!joined.is_authorized(&room_id, &stranger_id));
+
+        manager.leave_room(&room_id);
+        assert!(!manager.is_authorized(&room_id, "peer-b"));
+    }
+
     #[test]
     fn test_room_message_signing() {
         let identity = Identity::generate().unwrap();
@@ -721,12 +2141,50 @@ mod tests {
         assert!(msg.verify().is_ok());
     }
 
+    #[test]
+    fn test_only_author_can_change_message() {
+        let alice = Identity::generate().unwrap();
+        let mallory = Identity::generate().unwrap();
+        let mut manager = RoomManager::new([0u8; 32]);
+        let room_id = manager.create_room("Test".to_string(), RoomPolicy::default()).unwrap().id.clone();
+
+        let original =
+            RoomMessage::new(&alice, room_id.clone(), "Alice".into(), b"helo".to_vec()).unwrap();
+        assert!(manager.record_author(&room_id, &original));
+
+        let change = RoomChange::Edit { id: original.id.clone(), text: "hello".into() };
+        let edit = RoomMessage::new(&alice, room_id.clone(), "Alice".into(), change.to_bytes()).unwrap();
+        assert!(edit.verify().is_ok());
+        assert_eq!(RoomChange::from_bytes(&edit.content), Some(change.clone()));
+        assert!(manager.check_change(&room_id, &edit, &change).is_ok());
+
+        let forged = RoomChange::Delete { id: original.id.clone() };
+        let forged_msg =
+            RoomMessage::new(&mallory, room_id.clone(), "Alice".into(), forged.to_bytes()).unwrap();
+        assert!(manager.check_change(&room_id, &forged_msg, &forged).is_err());
+
+        // Reusing an id only names a message of the reuser's own, which
+        // doesn't block the author from recording or changing theirs
+        let squat = RoomMessage::with_id(
+            &mallory,
+            room_id.clone(),
+            "Mallory".into(),
+            b"mine".to_vec(),
+            Some(original.id.clone()),
+        )
+        .unwrap();
+        assert!(manager.record_author(&room_id, &squat));
+        assert!(!manager.record_author(&room_id, &squat));
+        assert!(!manager.record_author(&room_id, &original));
+        assert!(manager.check_change(&room_id, &edit, &change).is_ok());
+    }
+
     #[test]
     fn test_encrypted_room_message_roundtrip() {
         let identity = Identity::generate().unwrap();
         let mut manager = RoomManager::new([0u8; 32]);
 
-        let room = manager.create_room("Test".to_string()).unwrap();
+        let room = manager.create_room("Test".to_string(), RoomPolicy::default()).unwrap();
         let room_id = room.id.clone();
 
         let msg = RoomMessage::new(
@@ -0,0 +1,246 @@
+//! Slash-command registry
+//!
+//! Single source of truth for the commands the TUI understands. Drives
+//! `/help`, `/help <command>`, Tab completion and the "did you mean"
+//! suggestion for unknown commands.
+
+/// One usage line of a slash command
+pub struct CommandSpec {
+    /// Command name without the leading slash, e.g. `room`
+    pub name: &'static str,
+    /// Usage line shown in help, e.g. `/room invite <peer> <room>`
+    pub usage: &'static str,
+    /// One-line description
+    pub description: &'static str,
+    /// Help section heading
+    pub section: &'static str,
+}
+
+const fn cmd(
+    section: &'static str,
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        usage,
+        description,
+        section,
+    }
+}
+
+/// All commands, in help order. A command may have several usage lines.
+pub const COMMANDS: &[CommandSpec] = &[
+    cmd("MESSAGING COMMANDS", "send", "/send <file>... [:: caption]", "Send one or more files to peers"),
+    cmd("MESSAGING COMMANDS", "sendto", "/sendto <peer> <file> [:: caption]", "Send a file encrypted to one peer only"),
+    cmd("MESSAGING COMMANDS", "senddir", "/senddir <dir> [--recursive]", "Send every file in a directory"),
+    cmd("MESSAGING COMMANDS", "image", "/image <file> [:: caption]", "Send an image to peers"),
+    cmd("MESSAGING COMMANDS", "gif", "/gif <search>", "Search and send GIF"),
+    cmd("MESSAGING COMMANDS", "connect", "/connect <addr>", "Connect to peer by address"),
+    cmd("MESSAGING COMMANDS", "reconnect", "/reconnect", "Redial recently seen peers that aren't connected"),
+    cmd("MESSAGING COMMANDS", "yes", "/yes", "Confirm a pending connect"),
+    cmd("MESSAGING COMMANDS", "no", "/no", "Cancel a pending connect"),
+    cmd("MESSAGING COMMANDS", "dm", "/dm <peer> [msg]", "Read a peer's direct messages, or send one"),
+    cmd("MESSAGING COMMANDS", "whisper", "/whisper <id> <msg>", "Send private message (alias: /w)"),
+    cmd("MESSAGING COMMANDS", "lobby", "/lobby <msg>", "Say hello in the public lobby (needs --lobby)"),
+    cmd("MESSAGING COMMANDS", "announce", "/announce [--all] <text>", "Post to every joined room (--all: general chat too)"),
+    cmd("MESSAGING COMMANDS", "edit", "/edit <id> <text>", "Edit one of your room messages (peers offline keep the old copy)"),
+    cmd("MESSAGING COMMANDS", "delete", "/delete <id>", "Delete one of your room messages (best-effort)"),
+    cmd("MESSAGING COMMANDS", "pin", "/pin [id]", "Pin a message (no id: list recent message ids)"),
+    cmd("MESSAGING COMMANDS", "unpin", "/unpin <id>", "Unpin a message"),
+    cmd("MESSAGING COMMANDS", "pins", "/pins", "List pinned messages"),
+    cmd("MESSAGING COMMANDS", "whoami", "/whoami", "Show the active profile, your peer ID and public keys"),
+    cmd("MESSAGING COMMANDS", "show-identity", "/show-identity", "Show your peer ID and public keys"),
+    cmd("MESSAGING COMMANDS", "save-identity", "/save-identity [path]", "Write your keys to a file (default: the --identity file)"),
+    cmd("MESSAGING COMMANDS", "export", "/export <file> <passphrase>", "Back up your keys and rooms, encrypted; restore with --import"),
+    cmd("MESSAGING COMMANDS", "block", "/block [peer]", "Drop a peer's messages and invites, or list blocked peers"),
+    cmd("MESSAGING COMMANDS", "unblock", "/unblock <peer>", "Stop blocking a peer"),
+    cmd("MESSAGING COMMANDS", "contacts", "/contacts", "List peers you've exchanged keys with"),
+    cmd("MESSAGING COMMANDS", "contact", "/contact rename <peer> <name>", "Save a name for a peer, shown instead of its ID"),
+    cmd("MESSAGING COMMANDS", "fingerprint", "/fingerprint", "Show the fingerprint of your keys"),
+    cmd("MESSAGING COMMANDS", "verify", "/verify <peer> [confirm]", "Compare safety numbers out of band, then mark the peer verified"),
+    cmd("MESSAGING COMMANDS", "trust", "/trust <peer>", "Accept a peer's changed keys after checking with them"),
+    cmd("MESSAGING COMMANDS", "forget", "/forget <peer>", "Drop a peer's keys; they are exchanged again on reconnect"),
+    cmd("MESSAGING COMMANDS", "ping", "/ping <peer>", "Measure round-trip time to a peer"),
+    cmd("MESSAGING COMMANDS", "status", "/status <text>|clear", "Set a status message peers see next to your name"),
+    cmd("MESSAGING COMMANDS", "dnd", "/dnd", "Toggle do-not-disturb"),
+    cmd("MESSAGING COMMANDS", "reload", "/reload", "Re-read config.toml and OPENWIRE_* settings (also on SIGHUP)"),
+    cmd("MESSAGING COMMANDS", "quit", "/quit", "Exit the application (alias: /q)"),
+    cmd("PRIVATE ROOMS", "room", "/room create <name> [--closed] [--listed]", "Create room; --closed: only you invite, --listed: others can join by name (alias: /create)"),
+    cmd("PRIVATE ROOMS", "room", "/room meet <name> <passphrase>", "Join the shared room for a passphrase (less private)"),
+    cmd("PRIVATE ROOMS", "room", "/room invite <peer> <room>", "Invite peer"),
+    cmd("PRIVATE ROOMS", "room", "/room kick <peer> <room>", "Remove a peer from a closed room you own"),
+    cmd("PRIVATE ROOMS", "room", "/room join <room_id>", "Join room"),
+    cmd("PRIVATE ROOMS", "room", "/room join-name <name>", "Ask to join a listed room by its name"),
+    cmd("PRIVATE ROOMS", "room", "/room list", "List rooms"),
+    cmd("PRIVATE ROOMS", "room", "/room pending", "List invites that couldn't be joined yet or were declined with /room leave"),
+    cmd("PRIVATE ROOMS", "room", "/room accept <room_id>", "Join the room of a pending invite"),
+    cmd("PRIVATE ROOMS", "room", "/room key <room_id>", "Ask members to re-send a room's key"),
+    cmd("PRIVATE ROOMS", "room", "/room members [room]", "List who is in a room"),
+    cmd("PRIVATE ROOMS", "room", "/room history [room] [count]", "Show a room's recent messages"),
+    cmd("PRIVATE ROOMS", "room", "/room leave <room>", "Leave room"),
+    cmd("PRIVATE ROOMS", "room", "/room mute|unmute <room>", "Silence a noisy room"),
+    cmd("PRIVATE ROOMS", "members", "/members", "Show or hide the selected room's members"),
+    cmd("GAMES", "game", "/game tictactoe <room_id>", "Start a game"),
+    cmd("GAMES", "game", "/game rematch", "Play again"),
+    cmd("GAMES", "move", "/move <1-9>", "Make a move"),
+    cmd("BLACKJACK", "blackjack", "/blackjack", "Start game in first room"),
+    cmd("BLACKJACK", "bj", "/bj bet <amount>", "Place your bet"),
+    cmd("BLACKJACK", "bj", "/bj deal", "Deal cards (host only)"),
+    cmd("BLACKJACK", "bj", "/bj hit", "Take a card"),
+    cmd("BLACKJACK", "bj", "/bj stand", "End your turn"),
+    cmd("BLACKJACK", "bj", "/bj newround", "Start new round"),
+    cmd("ROULETTE", "roulette", "/roulette", "Show roulette table"),
+    cmd("ROULETTE", "roulette", "/roulette bet <type> <amt>", "Place bet"),
+    cmd("ROULETTE", "roulette", "/roulette spin", "Spin the wheel"),
+    cmd("ANDAR BAHAR", "ab", "/ab andar <amount>", "Bet on Andar"),
+    cmd("ANDAR BAHAR", "ab", "/ab bahar <amount>", "Bet on Bahar"),
+    cmd("ANDAR BAHAR", "ab", "/ab deal", "Deal cards"),
+    cmd("SLOTS", "slots", "/slots spin <amount>", "Spin the reels"),
+    cmd("WALLET", "wallet", "/wallet", "Show chip balance (alias: /chips)"),
+    cmd("HELP", "help", "/help [command]", "Show all commands, or one command's usage"),
+    cmd("HELP", "rules", "/rules <game>", "Show how to play a game"),
+    cmd("HELP", "history", "/history", "Show recent game history"),
+    cmd("HELP", "version", "/version", "Show our and peers' versions"),
+    cmd("HELP", "stats", "/stats", "Show network counters"),
+];
+
+/// Alternative spellings that map onto a registry name
+const ALIASES: &[(&str, &str)] = &[
+    ("q", "quit"),
+    ("w", "whisper"),
+    ("create", "room"),
+    ("chips", "wallet"),
+];
+
+/// Canonical registry name for `name` (with or without a leading slash)
+pub fn canonical(name: &str) -> Option<&'static str> {
+    let name = name.trim_start_matches('/');
+    if let Some(spec) = COMMANDS.iter().find(|c| c.name == name) {
+        return Some(spec.name);
+    }
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map(|(_, target)| *target)
+}
+
+/// All usage lines for a command
+pub fn usage_of(name: &str) -> Vec<&'static CommandSpec> {
+    match canonical(name) {
+        Some(name) => COMMANDS.iter().filter(|c| c.name == name).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Distinct command names, in registry order
+pub fn names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = Vec::new();
+    for spec in COMMANDS {
+        if !names.contains(&spec.name) {
+            names.push(spec.name);
+        }
+    }
+    names
+}
+
+/// Command names starting with `prefix` (leading slash optional)
+pub fn complete(prefix: &str) -> Vec<&'static str> {
+    let prefix = prefix.trim_start_matches('/');
+    names()
+        .into_iter()
+        .filter(|n| n.starts_with(prefix))
+        .collect()
+}
+
+/// Closest known command to a mistyped one, within a small edit distance
+pub fn suggest(name: &str) -> Option<&'static str> {
+    let name = name.trim_start_matches('/');
+    let max_distance = if name.len() <= 3 { 1 } else { 2 };
+    names()
+        .into_iter()
+        .chain(ALIASES.iter().map(|(alias, _)| *alias))
+        .map(|n| (edit_distance(name, n), n))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| canonical(n).unwrap_or(n))
+}
+
+/// Help text for every command, grouped by section
+pub fn help_lines() -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut section = "";
+    for spec in COMMANDS {
+        if spec.section != section {
+            if !section.is_empty() {
+                lines.push(String::new());
+            }
+            section = spec.section;
+            lines.push(format!("{}:", section));
+        }
+        lines.push(format_usage(spec));
+    }
+    lines
+}
+
+/// A single `  /usage   - description` help line
+pub fn format_usage(spec: &CommandSpec) -> String {
+    format!("  {:<28}- {}", spec.usage, spec.description)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest_typo() {
+        assert_eq!(suggest("/rom"), Some("room"));
+        assert_eq!(suggest("conect"), Some("connect"));
+        assert_eq!(suggest("whipser"), Some("whisper"));
+        assert_eq!(suggest("/xyzzy"), None);
+    }
+
+    #[test]
+    fn test_canonical_resolves_aliases() {
+        assert_eq!(canonical("/q"), Some("quit"));
+        assert_eq!(canonical("create"), Some("room"));
+        assert_eq!(canonical("/nope"), None);
+    }
+
+    #[test]
+    fn test_usage_of_room_lists_subcommands() {
+        let usage = usage_of("room");
+        assert!(usage.len() > 3);
+        assert!(usage.iter().all(|c| c.usage.starts_with("/room")));
+    }
+
+    #[test]
+    fn test_complete_prefix() {
+        assert_eq!(complete("/ro"), vec!["room", "roulette"]);
+        assert_eq!(complete("/conn"), vec!["connect"]);
+        assert!(complete("/zz").is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("room", "room"), 0);
+        assert_eq!(edit_distance("rom", "room"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
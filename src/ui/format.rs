@@ -0,0 +1,262 @@
+//! Chat text formatting helpers
+//!
+//! Emoji shortcode expansion and triple-backtick code fences. Fenced text is
+//! shown verbatim, so it is never run through emoji expansion. Also fitting
+//! names into the fixed-width side panels and wrapping messages to the
+//! message pane, and writing byte counts for file transfers.
+
+use ratatui::text::{Line, Span};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Shortcodes expanded in regular chat text
+const EMOJI_SHORTCODES: &[(&str, &str)] = &[
+    (":smile:", "😄"),
+    (":laughing:", "😆"),
+    (":wink:", "😉"),
+    (":heart:", "❤️"),
+    (":thumbsup:", "👍"),
+    (":thumbsdown:", "👎"),
+    (":fire:", "🔥"),
+    (":tada:", "🎉"),
+    (":eyes:", "👀"),
+    (":rocket:", "🚀"),
+    (":wave:", "👋"),
+    (":thinking:", "🤔"),
+];
+
+const FENCE: &str = "```";
+
+/// The body of a message wrapped in triple-backtick fences, if it is one.
+///
+/// A language tag directly after the opening fence (` ```rust ...`) is kept
+/// as part of the body; only a single leading newline is dropped.
+pub fn code_fence(text: &str) -> Option<&str> {
+    let trimmed = text.trim();
+    let inner = trimmed.strip_prefix(FENCE)?.strip_suffix(FENCE)?;
+    if inner.trim().is_empty() {
+        return None;
+    }
+    Some(inner.strip_prefix('\n').unwrap_or(inner))
+}
+
+/// Replace known `:shortcode:` sequences with emoji
+pub fn expand_emoji(text: &str) -> String {
+    if !text.contains(':') {
+        return text.to_string();
+    }
+    let mut out = text.to_string();
+    for (code, emoji) in EMOJI_SHORTCODES {
+        if out.contains(code) {
+            out = out.replace(code, emoji);
+        }
+    }
+    out
+}
+
+/// Format chat text for display: fenced code is returned verbatim and
+/// flagged as preformatted, everything else gets emoji expansion.
+pub fn format_chat_text(text: &str) -> (String, bool) {
+    match code_fence(text) {
+        Some(code) => (code.to_string(), true),
+        None => (expand_emoji(text), false),
+    }
+}
+
+/// Fit `s` into `max` terminal columns. Text that doesn't fit is cut on a
+/// char boundary and ends in "…"; wide characters (CJK, most emoji) count
+/// as two columns, so the result never spills past `max`.
+pub fn truncate_display(s: &str, max: usize) -> String {
+    if s.width() <= max {
+        return s.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > max - 1 {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+/// Longest nickname kept by [`sanitize_nick`], in chars
+pub const MAX_NICK_CHARS: usize = 32;
+
+/// Reduce a nickname to something safe to draw: control characters
+/// (newlines, escapes, tabs) become spaces, runs of whitespace collapse,
+/// and the result is trimmed and capped at [`MAX_NICK_CHARS`]
+pub fn sanitize_nick(nick: &str) -> String {
+    sanitize_line(nick, MAX_NICK_CHARS)
+}
+
+/// A peer's status message made safe to draw, like [`sanitize_nick`] but
+/// capped at `MAX_STATUS_CHARS`
+pub fn sanitize_status(status: &str) -> String {
+    sanitize_line(status, crate::network::MAX_STATUS_CHARS)
+}
+
+fn sanitize_line(text: &str, max_chars: usize) -> String {
+    let cleaned: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(max_chars)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// Break a styled line into rows at most `width` columns wide, keeping
+/// each span's style. Wide characters are never split across rows; an
+/// empty line is still one row.
+pub fn wrap_spans(spans: Vec<Span<'_>>, width: usize) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let mut row: Vec<Span<'static>> = Vec::new();
+    let mut used = 0;
+    for span in spans {
+        let mut chunk = String::new();
+        for c in span.content.chars() {
+            let w = c.width().unwrap_or(0);
+            if used + w > width && used > 0 {
+                if !chunk.is_empty() {
+                    row.push(Span::styled(std::mem::take(&mut chunk), span.style));
+                }
+                rows.push(Line::from(std::mem::take(&mut row)));
+                used = 0;
+            }
+            chunk.push(c);
+            used += w;
+        }
+        if !chunk.is_empty() {
+            row.push(Span::styled(chunk, span.style));
+        }
+    }
+    rows.push(Line::from(row));
+    rows
+}
+
+/// A byte count for display: `512 B`, `12.0 KB`, `3.4 MB`
+pub fn format_size(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    let b = bytes as f64;
+    if b < KB {
+        format!("{} B", bytes)
+    } else if b < KB * KB {
+        format!("{:.1} KB", b / KB)
+    } else {
+        format!("{:.1} MB", b / (KB * KB))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malicious_nick_is_sanitized() {
+        let nick = sanitize_nick("evil\n\x1b[2J\r\tnick\u{7}");
+        assert_eq!(nick, "evil [2J nick");
+        assert!(!nick.chars().any(char::is_control));
+        let title = format!(" OpenWire — {} (12D3KooW…) ", nick);
+        assert_eq!(wrap_spans(vec![Span::raw(title)], 80).len(), 1);
+
+        assert_eq!(sanitize_nick("  alice  "), "alice");
+        assert_eq!(sanitize_nick("\n\t").len(), 0);
+        assert_eq!(sanitize_nick(&"x".repeat(100)).chars().count(), MAX_NICK_CHARS);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(12 * 1024 + 10), "12.0 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 + 400 * 1024), "3.4 MB");
+    }
+
+    #[test]
+    fn test_code_fence_detection() {
+        assert_eq!(code_fence("```let x = 1;```"), Some("let x = 1;"));
+        assert_eq!(code_fence("  ```  indented  ```  "), Some("  indented  "));
+        assert_eq!(code_fence("```\nfn main() {}\n```"), Some("fn main() {}\n"));
+        assert_eq!(code_fence("``````"), None);
+        assert_eq!(code_fence("```unterminated"), None);
+        assert_eq!(code_fence("plain text"), None);
+    }
+
+    #[test]
+    fn test_emoji_expansion() {
+        assert_eq!(expand_emoji("ship it :rocket:"), "ship it 🚀");
+        assert_eq!(expand_emoji("no codes here"), "no codes here");
+        assert_eq!(expand_emoji(":unknown:"), ":unknown:");
+    }
+
+    #[test]
+    fn test_truncate_display() {
+        assert_eq!(truncate_display("alice", 10), "alice");
+        assert_eq!(truncate_display("alice", 5), "alice");
+        assert_eq!(truncate_display("alice", 4), "ali…");
+        assert_eq!(truncate_display("alice", 1), "…");
+        assert_eq!(truncate_display("alice", 0), "");
+        assert_eq!(truncate_display("", 3), "");
+    }
+
+    #[test]
+    fn test_truncate_display_multibyte() {
+        // Multibyte but single-width: cut on a char boundary
+        assert_eq!(truncate_display("héllo wörld", 6), "héllo…");
+        // Wide characters take two columns each
+        assert_eq!(truncate_display("日本語のニック", 7), "日本語…");
+        assert_eq!(truncate_display("日本語", 6), "日本語");
+        // A wide char that would straddle the limit is dropped
+        assert_eq!(truncate_display("a日本", 3), "a…");
+        assert_eq!(truncate_display("🚀🚀🚀", 4), "🚀…");
+        for max in 0..12 {
+            assert!(truncate_display("日本語 and 🚀 mixed", max).width() <= max);
+        }
+    }
+
+    #[test]
+    fn test_wrap_spans_row_count() {
+        let rows = |text: &str, width| wrap_spans(vec![Span::raw(text.to_string())], width);
+        assert_eq!(rows("", 10).len(), 1);
+        assert_eq!(rows("0123456789", 10).len(), 1);
+        assert_eq!(rows("0123456789a", 10).len(), 2);
+        assert_eq!(rows(&"x".repeat(95), 10).len(), 10);
+
+        // Wide characters move to the next row rather than straddle it
+        let wide = rows("ab日本語", 5);
+        assert_eq!(wide.len(), 2);
+        assert_eq!(wide[0].width(), 4);
+        assert_eq!(wide[1].to_string(), "本語");
+
+        // Styles carry over from the span that was split
+        let style = ratatui::style::Style::default().fg(ratatui::style::Color::Green);
+        let split = wrap_spans(vec![Span::raw("> "), Span::styled("hello world", style)], 8);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[1].spans[0].style, style);
+        assert_eq!(split.iter().map(|l| l.to_string()).collect::<String>(), "> hello world");
+    }
+
+    #[test]
+    fn test_emoji_skipped_inside_code_block() {
+        let (text, pre) = format_chat_text("```match x { :smile: => 1 }```");
+        assert!(pre);
+        assert_eq!(text, "match x { :smile: => 1 }");
+
+        let (text, pre) = format_chat_text("hello :smile:");
+        assert!(!pre);
+        assert_eq!(text, "hello 😄");
+    }
+}
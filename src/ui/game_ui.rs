@@ -409,8 +409,7 @@ fn render_action_bar(f: &mut Frame, area: Rect, ov: &mut GameOverlay) {
     for entry in ov.button_areas.iter_mut() {
         let (label, key) = (&entry.0, entry.2);
         let disp = if key == ' ' { format!("[Space]{}", label) }
-            else if key == '\n' || key == '\x1b' { label.clone() }
-            else if key == '0' { label.clone() }
+            else if key == '\n' || key == '\x1b' || key == '0' { label.clone() }
             else { format!("[{}]{}", key.to_uppercase(), label) };
         let w = disp.len() as u16;
         entry.1 = Rect::new(xo, btn_y, w, 1);
@@ -447,12 +446,12 @@ pub fn handle_game_key(
                 KeyCode::Backspace => { overlay.bet_input.pop(); GameKeyResult::Consumed }
                 KeyCode::Enter => {
                     // Parse the number, validate 0-36
-                    if let Ok(n) = overlay.bet_input.parse::<u8>() {
-                        if n <= 36 {
-                            overlay.straight_number = Some(n);
-                            overlay.straight_entering_amount = true;
-                            overlay.bet_input.clear();
-                        }
+                    if let Ok(n) = overlay.bet_input.parse::<u8>()
+                        && n <= 36
+                    {
+                        overlay.straight_number = Some(n);
+                        overlay.straight_entering_amount = true;
+                        overlay.bet_input.clear();
                     }
                     GameKeyResult::Consumed
                 }
@@ -4,6 +4,8 @@
 //! with a 3-pane layout: messages, peers, and input.
 
 use anyhow::Result;
+pub mod commands;
+pub mod format;
 pub mod game_ui;
 
 use crossterm::{
@@ -14,34 +16,223 @@ use crossterm::{
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{
-        Block, Borders, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState,
-        Wrap,
+        Block, Borders, List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Wrap,
     },
 };
 use std::io;
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError};
+use unicode_width::UnicodeWidthStr;
+use tokio::sync::{broadcast, mpsc};
 
+use crate::config::{Reload, SharedConfig};
 use crate::game::{
     AndarBaharAction, AndarBaharBet, AndarBaharCountRange, AndarBaharEngine, AndarBaharPhase, AndarBaharSide,
     Blackjack, BlackjackAction, BlackjackPhase, CasinoState, GameAction, PlayerStatus, RouletteAction, RouletteBet,
     RouletteBetType, RouletteEngine, RoulettePhase, SlotsEngine, TicTacToe, TransactionLedger, Wallet,
 };
-use crate::network::{NetworkCommand, NetworkEvent};
+use crate::network::batch::FileBatch;
+use crate::network::dial::DialPolicy;
+use crate::network::envelope::ContentType;
+use crate::network::nat::ConnectionPath;
+use crate::network::progress::TransferDirection;
+use crate::network::topic::TopicKind;
+use crate::network::{KeyExchangeFailure, NetworkCommand, NetworkEvent};
+use crate::room::{RoomChange, RoomPolicy};
+
+/// Widest a sender name gets in the message pane before it's cut with "…"
+const MAX_SENDER_WIDTH: usize = 16;
+
+/// How long a game challenge waits for an Accept or Decline
+const CHALLENGE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Messages kept per room unless `--room-history` says otherwise
+pub const ROOM_HISTORY_LIMIT: usize = 500;
+
+/// Lines `/room history` prints when no count is given
+const ROOM_HISTORY_SHOWN: usize = 20;
+
+/// Messages held per unverified peer; older ones are dropped
+const MAX_HELD_PER_PEER: usize = 100;
+
+/// Unverified peers whose messages are held; messages from further
+/// peers are dropped
+const MAX_HELD_PEERS: usize = 32;
+
+/// Severity of a system message, which picks its icon and colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl SystemLevel {
+    /// Icon shown in the sender column
+    pub fn icon(self) -> &'static str {
+        match self {
+            SystemLevel::Info => "★",
+            SystemLevel::Success => "✔",
+            SystemLevel::Warning => "⚠",
+            SystemLevel::Error => "✖",
+        }
+    }
+
+    /// Style for the message text
+    pub fn style(self) -> Style {
+        let color = match self {
+            SystemLevel::Info => Color::Yellow,
+            SystemLevel::Success => Color::Green,
+            SystemLevel::Warning => Color::LightYellow,
+            SystemLevel::Error => Color::Red,
+        };
+        Style::default().fg(color)
+    }
+}
+
+/// Panel that receives navigation keys, cycled with Tab / Shift+Tab
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Focus {
+    #[default]
+    Input,
+    Messages,
+    Peers,
+    Rooms,
+}
+
+impl Focus {
+    /// Next panel in Tab order, wrapping around
+    pub fn next(self) -> Self {
+        match self {
+            Focus::Input => Focus::Messages,
+            Focus::Messages => Focus::Peers,
+            Focus::Peers => Focus::Rooms,
+            Focus::Rooms => Focus::Input,
+        }
+    }
+
+    /// Previous panel in Tab order, wrapping around
+    pub fn prev(self) -> Self {
+        match self {
+            Focus::Input => Focus::Rooms,
+            Focus::Messages => Focus::Input,
+            Focus::Peers => Focus::Messages,
+            Focus::Rooms => Focus::Peers,
+        }
+    }
+}
+
+/// One row of a room's member list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RosterEntry {
+    /// Nick, or short peer id when the nick isn't known
+    pub name: String,
+    /// Currently connected (always true for ourselves)
+    pub online: bool,
+    /// Created the room. Only known for rooms we created.
+    pub owner: bool,
+}
+
+/// Short hex id of a displayed message, referenced by `/pin`
+pub type MessageId = String;
 
 /// A chat message for display
 #[derive(Clone)]
 pub struct ChatMessage {
+    pub id: MessageId,
     pub time: String,
+    /// When it was written (Unix seconds); the sender's clock for room messages
+    pub timestamp: u64,
     pub sender: String,
     pub content: String,
     pub is_system: bool,
+    /// Severity of a system message (`Info` for chat messages)
+    pub level: SystemLevel,
     pub is_file: bool,
+    /// Fenced code: rendered verbatim in a monospace style
+    pub preformatted: bool,
+    /// For our own broadcasts and direct messages: local id and how far
+    /// the message got
+    pub delivery: Option<(u64, Delivery)>,
+    /// Room the message was posted to
+    pub room: Option<String>,
+    /// For room messages from others: who signed it and the id they gave it
+    pub origin: Option<RoomOrigin>,
+}
+
+/// The author of a room message and the id it chose. Ids are only unique
+/// per author, so edits and deletes must match both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomOrigin {
+    /// Peer id of the signer
+    pub author: String,
+    pub id: String,
+}
+
+/// A message held back until its sender is verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldMessage {
+    pub sender: String,
+    pub content: String,
+    /// Set for room messages, so they're released into their room
+    pub room: Option<HeldRoomMessage>,
+}
+
+/// Where a held room message belongs once released
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldRoomMessage {
+    pub room_id: String,
+    pub origin: Option<RoomOrigin>,
+    /// When the author sent it
+    pub timestamp: u64,
+}
+
+impl HeldMessage {
+    /// A message for the main log (broadcasts and direct messages)
+    pub fn chat(sender: &str, content: &str) -> Self {
+        Self { sender: sender.to_string(), content: content.to_string(), room: None }
+    }
+
+    /// A message posted to `room_id`
+    pub fn room(room_id: &str, sender: &str, content: &str, origin: Option<RoomOrigin>, timestamp: u64) -> Self {
+        Self {
+            room: Some(HeldRoomMessage { room_id: room_id.to_string(), origin, timestamp }),
+            ..Self::chat(sender, content)
+        }
+    }
+}
+
+/// Outcome of publishing a message we sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Pending,
+    Sent,
+    /// A direct message the peer acknowledged
+    Acked,
+    Failed,
+}
+
+impl Delivery {
+    /// Marker shown after the message
+    pub fn marker(self) -> Span<'static> {
+        match self {
+            Delivery::Pending => Span::styled(" ⏳", Style::default().fg(Color::DarkGray)),
+            Delivery::Sent => Span::styled(" ✓", Style::default().fg(Color::Green)),
+            Delivery::Acked => Span::styled(" ✓✓", Style::default().fg(Color::Green)),
+            Delivery::Failed => Span::styled(" ⚠", Style::default().fg(Color::Red)),
+        }
+    }
 }
 
+/// Receives JSON and binary broadcasts: (sender peer id, content type, body)
+pub type PayloadHook = Box<dyn FnMut(&str, ContentType, &[u8]) + Send>;
+
 /// UI State management
 pub struct UiState {
     /// Current input buffer
@@ -65,6 +256,10 @@ pub struct UiState {
     pub scroll_offset: usize,
     /// Auto-scroll to bottom when new messages arrive
     pub auto_scroll: bool,
+    /// Rows the messages took on the last render, counting those above
+    /// the wrapped tail as one row each; `scroll_offset` counts these
+    /// rows, not messages
+    pub message_rows: usize,
     /// Active tic-tac-toe game (room_id -> game)
     pub active_game: Option<TicTacToe>,
     /// Active blackjack game
@@ -83,14 +278,100 @@ pub struct UiState {
     pub typing_peers: std::collections::HashMap<String, std::time::Instant>,
     /// Peer nicks from relay: (full_peer_id -> nick)
     pub peer_nicks: std::collections::HashMap<String, String>,
-    /// Path to persist chat history
-    pub message_history_path: std::path::PathBuf,
+    /// Saved contacts, ordered by peer ID; their nicknames take precedence
+    /// over the ones peers announce
+    pub contacts: Vec<(String, crate::crypto::Contact)>,
+    /// Print the contacts when the next list arrives (`/contacts`)
+    pub contacts_requested: bool,
+    /// Peers blocked this session or listed with `/block`
+    pub blocked: std::collections::HashSet<String>,
+    /// Status messages peers have set, sanitized (full_peer_id -> status)
+    pub peer_status: std::collections::HashMap<String, String>,
+    /// Our own status message, set with `/status`
+    pub status: Option<String>,
+    /// Peers presenting each nick, in the order first seen (nick -> full peer ids)
+    pub nick_owners: std::collections::HashMap<String, Vec<String>>,
+    /// Nicks we've already warned are shared by several peers
+    pub shared_nicks_warned: std::collections::HashSet<String>,
+    /// Identify info per peer: (full_peer_id -> (agent_version, protocol_version))
+    pub peer_versions: std::collections::HashMap<String, (String, String)>,
+    /// Peers we've already warned speak another protocol version
+    pub version_warned: std::collections::HashSet<String>,
+    /// How we're connected to each peer; relayed peers are marked in the Peers panel
+    pub peer_paths: std::collections::HashMap<String, ConnectionPath>,
+    /// Peers we've already warned about for sending unverifiable messages
+    pub rejected_peers: std::collections::HashSet<String>,
+    /// Where chat history is saved; `None` keeps it in memory only
+    pub message_history_path: Option<std::path::PathBuf>,
     /// Game overlay state (visual game UI on top of chat)
     pub game_overlay: game_ui::GameOverlay,
+    /// Which `/connect` targets may be dialed without confirmation
+    pub dial_policy: DialPolicy,
+    /// Address awaiting `/yes` or `/no` before it is dialed
+    pub pending_dial: Option<String>,
+    /// Unread room messages since the user last sent input (room_id -> count)
+    pub unread: std::collections::HashMap<String, usize>,
+    /// Rooms whose messages neither count as unread nor notify
+    pub muted: std::collections::HashSet<String>,
+    /// Unread direct messages per peer id, cleared by `/dm <peer>`
+    pub dm_unread: std::collections::HashMap<String, usize>,
+    /// Peer whose direct messages are being read (`/dm <peer>`)
+    pub dm_view: Option<String>,
+    /// `--accept-only-verified`: hold messages from peers not yet verified
+    pub only_verified: bool,
+    /// `--profile` the identity was loaded from
+    pub profile: Option<String>,
+    /// The network task has exited; the UI loop quits on its next turn
+    pub network_disconnected: bool,
+    /// Handlers for broadcasts that aren't chat text; the TUI never shows those
+    pub payload_hooks: Vec<PayloadHook>,
+    /// Our unanswered game challenges: (room_id -> when sent)
+    pub pending_challenges: std::collections::HashMap<String, std::time::Instant>,
+    /// Peers confirmed with `/verify <peer> confirm`. Mirrors the network's
+    /// `PeerInfo::verified`: only its events change it.
+    pub verified_peers: std::collections::HashSet<String>,
+    /// Messages held back per unverified peer id
+    pub held_messages: std::collections::HashMap<String, Vec<HeldMessage>>,
+    /// Do-not-disturb: suppress all notifications
+    pub dnd: bool,
+    /// A notification (terminal bell) is due on the next frame
+    pub notify_pending: bool,
+    /// Panel receiving navigation keys
+    pub focus: Focus,
+    /// Highlighted row of the Peers panel
+    pub peer_selected: usize,
+    /// Highlighted row of the Rooms panel
+    pub room_selected: usize,
+    /// Room picked in the Rooms panel, whose members are listed
+    pub active_room: Option<String>,
+    /// Show the member list of `active_room`
+    pub show_members: bool,
+    /// Remote peers subscribed to each room (room_id -> peer ids)
+    pub room_members: std::collections::HashMap<String, std::collections::BTreeSet<String>>,
+    /// Rooms we created
+    pub owned_rooms: std::collections::HashSet<String>,
+    /// Id given to the next broadcast we send, to match its publish result
+    pub next_message_id: u64,
+    /// Messages added so far, mixed into each `MessageId`
+    pub message_seq: u64,
+    /// Pinned messages, oldest pin first
+    pub pinned: Vec<MessageId>,
+    /// Our own room messages that can still be edited (id -> room_id)
+    pub room_sent: std::collections::HashMap<MessageId, String>,
+    /// Recent messages of each room, oldest first, for `/room history`
+    pub room_history: std::collections::HashMap<String, std::collections::VecDeque<ChatMessage>>,
+    /// Messages kept per room before the oldest is dropped
+    pub room_history_limit: usize,
+    /// Progress line of each unfinished file transfer (transfer id -> message)
+    pub transfers: std::collections::HashMap<u64, MessageId>,
+    /// Local delivery id of each direct message awaiting its ack, by the
+    /// `message_id` it was sent with
+    pub pending_dms: std::collections::HashMap<String, u64>,
 }
 
 impl UiState {
     pub fn new(nick: String, local_peer_id: String, web_port: Option<u16>, relay: bool) -> Self {
+        let nick = format::sanitize_nick(&nick);
         let mut state = Self {
             input: String::new(),
             cursor_pos: 0,
@@ -102,6 +383,7 @@ impl UiState {
             local_peer_id,
             scroll_offset: 0,
             auto_scroll: true,
+            message_rows: 0,
             active_game: None,
             blackjack_game: None,
             roulette_game: None,
@@ -111,11 +393,49 @@ impl UiState {
             casino_state: CasinoState::new(),
             typing_peers: std::collections::HashMap::new(),
             peer_nicks: std::collections::HashMap::new(),
-            message_history_path: dirs_next::home_dir()
-                .unwrap_or_else(|| std::path::PathBuf::from("."))
-                .join(".openwire")
-                .join("chat_history.json"),
+            peer_status: std::collections::HashMap::new(),
+            contacts: Vec::new(),
+            contacts_requested: false,
+            blocked: std::collections::HashSet::new(),
+            status: None,
+            nick_owners: std::collections::HashMap::new(),
+            shared_nicks_warned: std::collections::HashSet::new(),
+            peer_versions: std::collections::HashMap::new(),
+            version_warned: std::collections::HashSet::new(),
+            peer_paths: std::collections::HashMap::new(),
+            rejected_peers: std::collections::HashSet::new(),
+            message_history_path: Some(crate::paths::get().history_path()),
             game_overlay: game_ui::GameOverlay::new(),
+            dial_policy: DialPolicy::default(),
+            pending_dial: None,
+            unread: std::collections::HashMap::new(),
+            muted: std::collections::HashSet::new(),
+            dm_unread: std::collections::HashMap::new(),
+            dm_view: None,
+            only_verified: false,
+            profile: None,
+            network_disconnected: false,
+            payload_hooks: Vec::new(),
+            pending_challenges: std::collections::HashMap::new(),
+            verified_peers: std::collections::HashSet::new(),
+            held_messages: std::collections::HashMap::new(),
+            dnd: false,
+            notify_pending: false,
+            focus: Focus::Input,
+            peer_selected: 0,
+            room_selected: 0,
+            active_room: None,
+            show_members: true,
+            room_members: std::collections::HashMap::new(),
+            owned_rooms: std::collections::HashSet::new(),
+            next_message_id: 1,
+            message_seq: 0,
+            pinned: Vec::new(),
+            room_sent: std::collections::HashMap::new(),
+            room_history: std::collections::HashMap::new(),
+            room_history_limit: ROOM_HISTORY_LIMIT,
+            transfers: std::collections::HashMap::new(),
+            pending_dms: std::collections::HashMap::new(),
         };
         state.add_system_message("Welcome to OpenWire! End-to-end encrypted P2P messenger.");
         state.add_system_message("Peers on the same LAN are discovered automatically via mDNS.");
@@ -130,16 +450,126 @@ impl UiState {
                 "☁ Relay bridge active → you are visible to openwire-web users on Vercel",
             );
         }
+        // Our own nick counts when spotting peers that share one
+        let (me, nick) = (state.local_peer_id.clone(), state.nick.clone());
+        state.note_nick(&me, &nick);
         state
     }
 
     pub fn add_system_message(&mut self, msg: &str) {
+        self.info(msg);
+    }
+
+    pub fn info(&mut self, msg: &str) {
+        self.add_system(SystemLevel::Info, msg);
+    }
+
+    pub fn success(&mut self, msg: &str) {
+        self.add_system(SystemLevel::Success, msg);
+    }
+
+    pub fn warn(&mut self, msg: &str) {
+        self.add_system(SystemLevel::Warning, msg);
+    }
+
+    pub fn error(&mut self, msg: &str) {
+        self.add_system(SystemLevel::Error, msg);
+    }
+
+    /// Note a challenge we sent to `room_id`, replacing any earlier one there
+    pub fn challenge_sent(&mut self, room_id: &str, now: std::time::Instant) {
+        self.pending_challenges.insert(room_id.to_string(), now);
+    }
+
+    /// Our challenge in `room_id` got an Accept or Decline. Returns false
+    /// if we weren't waiting on one there.
+    pub fn challenge_answered(&mut self, room_id: &str) -> bool {
+        self.pending_challenges.remove(room_id).is_some()
+    }
+
+    /// Cancel challenges nobody answered within `CHALLENGE_TIMEOUT`
+    pub fn expire_challenges(&mut self, now: std::time::Instant) {
+        let expired: Vec<String> = self
+            .pending_challenges
+            .iter()
+            .filter(|(_, sent)| now.saturating_duration_since(**sent) >= CHALLENGE_TIMEOUT)
+            .map(|(room_id, _)| room_id.clone())
+            .collect();
+        for room_id in expired {
+            self.pending_challenges.remove(&room_id);
+            let room = self
+                .rooms
+                .iter()
+                .find(|(id, _)| *id == room_id)
+                .map(|(_, name)| name.clone())
+                .unwrap_or(room_id);
+            self.warn(&format!(
+                "⏳ No one accepted your Tic-Tac-Toe challenge in {} — cancelled",
+                room
+            ));
+        }
+    }
+
+    /// Pass a JSON or binary broadcast to every registered hook
+    pub fn dispatch_payload(&mut self, from: &str, content_type: ContentType, data: &[u8]) {
+        if self.payload_hooks.is_empty() {
+            tracing::debug!("No hook for {} payload ({} bytes) from {}", content_type, data.len(), from);
+        }
+        for hook in &mut self.payload_hooks {
+            hook(from, content_type, data);
+        }
+    }
+
+    /// Hand a command to the network task. Fails if the task has exited,
+    /// after telling the user and flagging the UI loop to quit.
+    pub async fn send_command(
+        &mut self,
+        sender: &mpsc::Sender<NetworkCommand>,
+        cmd: NetworkCommand,
+    ) -> Result<()> {
+        if sender.send(cmd).await.is_err() {
+            return Err(self.network_gone());
+        }
+        Ok(())
+    }
+
+    /// `send_command` for non-async callers. A full queue drops the command.
+    pub fn try_send_command(
+        &mut self,
+        sender: &mpsc::Sender<NetworkCommand>,
+        cmd: NetworkCommand,
+    ) -> Result<()> {
+        match sender.try_send(cmd) {
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(self.network_gone()),
+            Err(mpsc::error::TrySendError::Full(_)) => anyhow::bail!("Network busy"),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn network_gone(&mut self) -> anyhow::Error {
+        if !self.network_disconnected {
+            self.network_disconnected = true;
+            self.error("Network disconnected — exiting");
+        }
+        anyhow::anyhow!("Network disconnected")
+    }
+
+    /// Add a system message with the given severity
+    pub fn add_system(&mut self, level: SystemLevel, msg: &str) {
+        let id = self.new_message_id(level.icon(), msg);
         self.messages.push(ChatMessage {
+            id,
             time: Self::now(),
-            sender: "★".to_string(),
+            timestamp: crate::network::unix_now(),
+            sender: level.icon().to_string(),
             content: msg.to_string(),
             is_system: true,
+            level,
             is_file: false,
+            preformatted: false,
+            delivery: None,
+            room: None,
+            origin: None,
         });
         // Reset scroll to bottom if auto-scroll is enabled
         if self.auto_scroll {
@@ -152,23 +582,307 @@ impl UiState {
     }
 
     pub fn add_chat_message(&mut self, sender: &str, content: &str) {
+        let (content, preformatted) = format::format_chat_text(content);
+        let id = self.new_message_id(sender, &content);
         self.messages.push(ChatMessage {
+            id,
             time: Self::now(),
+            timestamp: crate::network::unix_now(),
             sender: sender.to_string(),
-            content: content.to_string(),
+            content,
             is_system: false,
+            level: SystemLevel::Info,
             is_file: false,
+            preformatted,
+            delivery: None,
+            room: None,
+            origin: None,
         });
         // Reset scroll to bottom if auto-scroll is enabled
         if self.auto_scroll {
             self.scroll_offset = 0;
         }
-        if self.messages.len() % 10 == 0 {
+        if self.messages.len().is_multiple_of(10) {
             self.save_message_history();
         }
     }
 
+    /// Echo a chat message we're sending, pending until the network reports
+    /// on it. Returns the id to send with it.
+    pub fn add_outgoing_message(&mut self, content: &str) -> u64 {
+        let nick = self.nick.clone();
+        self.add_pending_message(&nick, content)
+    }
+
+    /// Echo a direct message we're sending, pending until the network
+    /// reports on it. Returns the `message_id` to send it with.
+    pub fn add_outgoing_dm(&mut self, sender: &str, content: &str) -> String {
+        let id = self.add_pending_message(sender, content);
+        let message_id = crate::network::dm::new_message_id();
+        self.pending_dms.insert(message_id.clone(), id);
+        message_id
+    }
+
+    fn add_pending_message(&mut self, sender: &str, content: &str) -> u64 {
+        let id = self.next_message_id;
+        self.next_message_id += 1;
+        self.add_chat_message(sender, content);
+        if let Some(m) = self.messages.last_mut() {
+            m.delivery = Some((id, Delivery::Pending));
+        }
+        id
+    }
+
+    /// Show a message posted to `room_id` at `timestamp` and keep it in
+    /// the room's history, dropping the oldest past `room_history_limit`.
+    /// `origin` names another member's message for its later edits; our
+    /// own messages go out under their local id. Gossip can deliver a
+    /// little out of order, so it goes before any later message of the
+    /// room rather than simply last. Returns its id.
+    pub fn add_room_message(
+        &mut self,
+        room_id: &str,
+        sender: &str,
+        content: &str,
+        origin: Option<RoomOrigin>,
+        timestamp: u64,
+    ) -> MessageId {
+        self.add_chat_message(sender, content);
+        let Some(mut m) = self.messages.pop() else { return MessageId::new() };
+        m.origin = origin;
+        m.room = Some(room_id.to_string());
+        m.timestamp = timestamp;
+        if let Some(time) = chrono::DateTime::from_timestamp(timestamp as i64, 0) {
+            m.time = time.with_timezone(&chrono::Local).format("%H:%M").to_string();
+        }
+
+        // Only the room's own messages are reordered; others stay put
+        let mut at = self.messages.len();
+        for (i, other) in self.messages.iter().enumerate().rev() {
+            if other.room.as_deref() == Some(room_id) {
+                if other.timestamp <= timestamp {
+                    break;
+                }
+                at = i;
+            }
+        }
+        self.messages.insert(at, m.clone());
+
+        let id = m.id.clone();
+        let history = self.room_history.entry(room_id.to_string()).or_default();
+        let at = history.iter().rposition(|h| h.timestamp <= timestamp).map_or(0, |i| i + 1);
+        history.insert(at, m);
+        while history.len() > self.room_history_limit {
+            history.pop_front();
+        }
+        id
+    }
+
+    /// Messages for the message pane: while a room is viewed, its own
+    /// messages and system messages; otherwise everything
+    pub fn visible_messages(&self) -> Vec<&ChatMessage> {
+        match &self.active_room {
+            Some(room_id) => self
+                .messages
+                .iter()
+                .filter(|m| m.is_system || m.room.as_ref() == Some(room_id))
+                .collect(),
+            None => self.messages.iter().collect(),
+        }
+    }
+
+    /// `/room history [room] [count]`: print the room's recent messages
+    pub fn show_room_history(&mut self, args: &str) {
+        let mut parts = args.split_whitespace();
+        let room = parts.next();
+        let room_id = match (room, &self.active_room) {
+            (Some(room), _) => self.find_room_id(room).unwrap_or_else(|| room.to_string()),
+            (None, Some(active)) => active.clone(),
+            (None, None) => {
+                self.add_system_message("Usage: /room history <room_id> [count]");
+                return;
+            }
+        };
+        let count = match parts.next().map(str::parse::<usize>) {
+            None => ROOM_HISTORY_SHOWN,
+            Some(Ok(n)) if n > 0 => n,
+            Some(_) => {
+                self.add_system_message("Usage: /room history <room_id> [count]");
+                return;
+            }
+        };
+        let lines: Vec<String> = self
+            .room_history_tail(&room_id, count)
+            .into_iter()
+            .map(|m| format!("  [{}] {}: {}", m.time, m.sender, m.content))
+            .collect();
+        if lines.is_empty() {
+            self.info(&format!("🏠 No messages kept for room {}", room_id));
+            return;
+        }
+        self.info(&format!("🏠 Last {} message(s) in {}:", lines.len(), room_id));
+        for line in lines {
+            self.add_system_message(&line);
+        }
+    }
+
+    /// Note a new connection to `peer_id`. A direct connection wins over a
+    /// relayed one that is still open. Returns whether the path changed.
+    pub fn set_connection_path(&mut self, peer_id: &str, path: ConnectionPath) -> bool {
+        let current = self.peer_paths.get(peer_id).copied();
+        if current == Some(path) || (path == ConnectionPath::Relayed && current.is_some()) {
+            return false;
+        }
+        self.peer_paths.insert(peer_id.to_string(), path);
+        true
+    }
+
+    /// Show how far a file transfer has got, updating its line in place
+    pub fn transfer_progress(
+        &mut self,
+        transfer_id: u64,
+        filename: &str,
+        bytes_done: u64,
+        bytes_total: u64,
+        direction: TransferDirection,
+    ) {
+        let (icon, verb, done) = match direction {
+            TransferDirection::Sending => ("📤", "Sending", "Sent"),
+            TransferDirection::Receiving => ("📥", "Receiving", "Received"),
+        };
+        if bytes_done >= bytes_total {
+            let line = format!("{} {} {} ({})", icon, done, filename, format::format_size(bytes_total));
+            self.set_transfer_line(transfer_id, SystemLevel::Success, &line);
+            self.transfers.remove(&transfer_id);
+        } else {
+            let percent = bytes_done * 100 / bytes_total;
+            let line = format!(
+                "{} {} {} — {}% ({}/{})",
+                icon,
+                verb,
+                filename,
+                percent,
+                format::format_size(bytes_done),
+                format::format_size(bytes_total)
+            );
+            self.set_transfer_line(transfer_id, SystemLevel::Info, &line);
+        }
+    }
+
+    /// Mark a file transfer's line as failed
+    pub fn transfer_failed(&mut self, transfer_id: u64, filename: &str, direction: TransferDirection, reason: &str) {
+        let (icon, verb) = match direction {
+            TransferDirection::Sending => ("📤", "Sending"),
+            TransferDirection::Receiving => ("📥", "Receiving"),
+        };
+        let line = format!("{} {} {} failed: {}", icon, verb, filename, reason);
+        self.set_transfer_line(transfer_id, SystemLevel::Warning, &line);
+        self.transfers.remove(&transfer_id);
+    }
+
+    /// Replace the text of a transfer's line, adding the line if it has none
+    fn set_transfer_line(&mut self, transfer_id: u64, level: SystemLevel, text: &str) {
+        let existing = self
+            .transfers
+            .get(&transfer_id)
+            .and_then(|id| self.messages.iter_mut().find(|m| &m.id == id));
+        if let Some(message) = existing {
+            message.content = text.to_string();
+            message.sender = level.icon().to_string();
+            message.level = level;
+            return;
+        }
+        self.add_system(level, text);
+        if let Some(message) = self.messages.last() {
+            self.transfers.insert(transfer_id, message.id.clone());
+        }
+    }
+
+    /// The last `count` messages of `room_id`, oldest first
+    pub fn room_history_tail(&self, room_id: &str, count: usize) -> Vec<&ChatMessage> {
+        let Some(history) = self.room_history.get(room_id) else { return Vec::new() };
+        history.iter().skip(history.len().saturating_sub(count)).collect()
+    }
+
+    /// Id for the next message: 8 hex digits, unique within the session
+    fn new_message_id(&mut self, sender: &str, content: &str) -> MessageId {
+        use sha2::Digest;
+        self.message_seq += 1;
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(self.message_seq.to_be_bytes());
+        hasher.update(sender.as_bytes());
+        hasher.update(content.as_bytes());
+        hex::encode(&hasher.finalize()[..4])
+    }
+
+    /// The chat message whose id starts with `prefix`
+    fn find_message(&self, prefix: &str) -> Result<&ChatMessage> {
+        let mut matches = self.messages.iter().filter(|m| !m.is_system && m.id.starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            _ if prefix.is_empty() => anyhow::bail!("Give a message id, e.g. /pin 3fa2"),
+            (Some(m), None) => Ok(m),
+            (Some(_), Some(_)) => anyhow::bail!("Message id '{}' is ambiguous; type more of it", prefix),
+            (None, _) => anyhow::bail!("No message with id '{}'", prefix),
+        }
+    }
+
+    /// Pin the message whose id starts with `prefix`
+    pub fn pin(&mut self, prefix: &str) -> Result<MessageId> {
+        let id = self.find_message(prefix)?.id.clone();
+        if self.pinned.contains(&id) {
+            anyhow::bail!("Message {} is already pinned", id);
+        }
+        self.pinned.push(id.clone());
+        Ok(id)
+    }
+
+    /// Unpin the pinned message whose id starts with `prefix`
+    pub fn unpin(&mut self, prefix: &str) -> Result<MessageId> {
+        let mut matches = self.pinned.iter().enumerate().filter(|(_, id)| id.starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            (Some((i, _)), None) if !prefix.is_empty() => Ok(self.pinned.remove(i)),
+            (Some(_), Some(_)) => anyhow::bail!("Pin id '{}' is ambiguous; type more of it", prefix),
+            _ => anyhow::bail!("No pinned message with id '{}'", prefix),
+        }
+    }
+
+    /// One line per pinned message still in the message list
+    pub fn pin_lines(&self) -> Vec<String> {
+        self.pinned
+            .iter()
+            .filter_map(|id| self.messages.iter().find(|m| m.id == *id))
+            .map(|m| format!("📌 {} [{}] {}: {}", m.id, m.time, m.sender, m.content))
+            .collect()
+    }
+
+    /// Record the publish result of an outgoing message
+    pub fn set_delivery(&mut self, id: u64, delivery: Delivery) {
+        if let Some(m) = self
+            .messages
+            .iter_mut()
+            .rev()
+            .find(|m| m.delivery.is_some_and(|(i, _)| i == id))
+        {
+            m.delivery = Some((id, delivery));
+        }
+    }
+
+    /// Update the direct message sent as `message_id`. Once acked or
+    /// failed it no longer changes.
+    pub fn set_dm_delivery(&mut self, message_id: &str, delivery: Delivery) {
+        let Some(&id) = self.pending_dms.get(message_id) else {
+            return;
+        };
+        if delivery != Delivery::Sent {
+            self.pending_dms.remove(message_id);
+        }
+        self.set_delivery(id, delivery);
+    }
+
     fn save_message_history(&self) {
+        let Some(path) = &self.message_history_path else {
+            return;
+        };
         let filtered: Vec<_> = self.messages.iter().filter(|m| !m.is_system).collect();
         let start = filtered.len().saturating_sub(200);
         let to_save: Vec<serde_json::Value> = filtered[start..]
@@ -182,20 +896,28 @@ impl UiState {
             })
             .collect();
         if let Ok(json) = serde_json::to_string_pretty(&to_save) {
-            if let Some(parent) = self.message_history_path.parent() {
+            if let Some(parent) = path.parent() {
                 let _ = std::fs::create_dir_all(parent);
             }
-            let _ = std::fs::write(&self.message_history_path, json);
+            let _ = std::fs::write(path, json);
         }
     }
 
     pub fn add_file_message(&mut self, sender: &str, filename: &str) {
+        let id = self.new_message_id(sender, filename);
         self.messages.push(ChatMessage {
+            id,
             time: Self::now(),
+            timestamp: crate::network::unix_now(),
             sender: sender.to_string(),
             content: format!("📎 File: {}", filename),
             is_system: false,
+            level: SystemLevel::Info,
             is_file: true,
+            preformatted: false,
+            delivery: None,
+            room: None,
+            origin: None,
         });
         // Reset scroll to bottom if auto-scroll is enabled
         if self.auto_scroll {
@@ -203,96 +925,825 @@ impl UiState {
         }
     }
 
-    fn now() -> String {
-        chrono::Local::now().format("%H:%M").to_string()
+    /// Ask to dial `addr`. Returns the address if it may be dialed now;
+    /// otherwise it is held in `pending_dial` until the user confirms.
+    pub fn request_dial(&mut self, addr: &str) -> Option<String> {
+        if self.dial_policy.requires_confirmation(addr) {
+            self.pending_dial = Some(addr.to_string());
+            self.add_system_message(&format!("Connect to {}? (/yes /no)", addr));
+            None
+        } else {
+            self.pending_dial = None;
+            Some(addr.to_string())
+        }
     }
-}
 
-/// The UI Application
-pub struct UiApp {
-    terminal: Terminal<CrosstermBackend<io::Stdout>>,
-    state: UiState,
-    command_sender: mpsc::Sender<NetworkCommand>,
-    event_receiver: mpsc::Receiver<NetworkEvent>,
-    /// Throttle typing broadcasts to once per 2 seconds
-    last_typing_broadcast: std::time::Instant,
-    /// Whether mouse capture is currently enabled (only when overlay visible)
-    mouse_captured: bool,
-}
+    /// Answer the pending dial prompt. Returns the address to dial if accepted.
+    pub fn resolve_pending_dial(&mut self, accept: bool) -> Option<String> {
+        let Some(addr) = self.pending_dial.take() else {
+            self.add_system_message("No pending connection to confirm.");
+            return None;
+        };
+        if accept {
+            Some(addr)
+        } else {
+            self.add_system_message(&format!("Cancelled connection to {}", addr));
+            None
+        }
+    }
 
-impl UiApp {
-    pub fn new(
-        nick: String,
-        local_peer_id: String,
-        command_sender: mpsc::Sender<NetworkCommand>,
-        event_receiver: mpsc::Receiver<NetworkEvent>,
-        web_port: Option<u16>,
-        relay: bool,
-    ) -> Result<Self> {
-        enable_raw_mode()?;
-        let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen)?;
-        let backend = CrosstermBackend::new(stdout);
-        let terminal = Terminal::new(backend)?;
+    /// Count an incoming room message as unread and notify, unless the room is muted
+    pub fn record_room_message(&mut self, room_id: &str) {
+        if self.muted.contains(room_id) {
+            return;
+        }
+        *self.unread.entry(room_id.to_string()).or_insert(0) += 1;
+        self.notify();
+    }
 
-        Ok(Self {
-            terminal,
-            state: UiState::new(nick, local_peer_id, web_port, relay),
-            command_sender,
-            event_receiver,
-            last_typing_broadcast: std::time::Instant::now()
-                - std::time::Duration::from_secs(10),
-            mouse_captured: false,
-        })
+    /// Count a direct message as unread and notify, unless its peer is
+    /// the one being read
+    pub fn record_dm(&mut self, peer_id: &str) {
+        if self.dm_view.as_deref() == Some(peer_id) {
+            return;
+        }
+        *self.dm_unread.entry(peer_id.to_string()).or_insert(0) += 1;
+        self.notify();
     }
 
-    /// Run the UI event loop
-    pub async fn run(&mut self) -> Result<()> {
-        loop {
-            self.render()?;
+    /// Record the nick `peer_id` presents. When another peer already uses
+    /// it, warn once; both are then shown with a key suffix (`Alice#3f`).
+    pub fn note_nick(&mut self, peer_id: &str, nick: &str) {
+        let nick = format::sanitize_nick(nick);
+        if nick.is_empty() {
+            return;
+        }
+        if let Some(old) = self.peer_nicks.insert(peer_id.to_string(), nick.clone()) {
+            if old == nick {
+                return;
+            }
+            self.drop_nick_owner(&old, peer_id);
+        }
+        let owners = self.nick_owners.entry(nick.clone()).or_default();
+        owners.push(peer_id.to_string());
+        if owners.len() > 1 && self.shared_nicks_warned.insert(nick.clone()) {
+            let owners = owners.clone();
+            let names: Vec<String> = owners
+                .iter()
+                .filter_map(|p| self.display_nick(p))
+                .collect();
+            self.warn(&format!(
+                "Several peers use the nickname '{}'; they're shown as {}",
+                nick,
+                names.join(", ")
+            ));
+        }
+    }
 
-            // Process any pending network events (non-blocking)
-            while let Ok(event) = self.event_receiver.try_recv() {
-                self.handle_network_event(event);
+    /// Record a peer's status message; `None` or blank clears it
+    pub fn set_peer_status(&mut self, peer_id: &str, status: Option<&str>) {
+        match status.map(format::sanitize_status).filter(|s| !s.is_empty()) {
+            Some(status) => {
+                self.peer_status.insert(peer_id.to_string(), status);
+            }
+            None => {
+                self.peer_status.remove(peer_id);
             }
+        }
+    }
 
-            // Toggle mouse capture: enable only when game overlay is visible
-            let want_mouse = self.state.game_overlay.visible;
-            if want_mouse && !self.mouse_captured {
-                let _ = execute!(io::stdout(), EnableMouseCapture);
-                self.mouse_captured = true;
-            } else if !want_mouse && self.mouse_captured {
-                let _ = execute!(io::stdout(), DisableMouseCapture);
-                self.mouse_captured = false;
+    /// Forget a departed peer's nick
+    pub fn forget_nick(&mut self, peer_id: &str) -> Option<String> {
+        let nick = self.peer_nicks.remove(peer_id)?;
+        self.drop_nick_owner(&nick, peer_id);
+        Some(nick)
+    }
+
+    fn drop_nick_owner(&mut self, nick: &str, peer_id: &str) {
+        if let Some(owners) = self.nick_owners.get_mut(nick) {
+            owners.retain(|p| p != peer_id);
+            if owners.is_empty() {
+                self.nick_owners.remove(nick);
             }
+        }
+    }
 
-            // Poll for input events with a small timeout
-            if event::poll(std::time::Duration::from_millis(50))? {
-                let ev = event::read()?;
+    /// How a peer's nick is shown: as is when unique, otherwise followed by
+    /// the shortest peer id suffix that tells its owners apart
+    pub fn display_nick(&self, peer_id: &str) -> Option<String> {
+        if let Some(name) = self.contact_name(peer_id) {
+            return Some(name.to_string());
+        }
+        let nick = self.peer_nicks.get(peer_id)?;
+        let owners = self.nick_owners.get(nick).map(Vec::as_slice).unwrap_or_default();
+        if owners.len() < 2 {
+            return Some(nick.clone());
+        }
+        let suffix = |p: &str, n: usize| p[p.len().saturating_sub(n)..].to_string();
+        let longest = owners.iter().map(|p| p.len()).max().unwrap_or(0);
+        let len = (2..longest)
+            .find(|&n| {
+                let suffixes: std::collections::HashSet<String> =
+                    owners.iter().map(|p| suffix(p, n)).collect();
+                suffixes.len() == owners.len()
+            })
+            .unwrap_or(longest);
+        Some(format!("{}#{}", nick, suffix(peer_id, len)))
+    }
 
-                // ── Mouse events (game overlay buttons) ─────────────────
-                if let Event::Mouse(mouse) = &ev {
-                    if self.state.game_overlay.visible {
-                        if let Some(action_key) = game_ui::handle_game_mouse(*mouse, &self.state.game_overlay) {
-                            if action_key == '\x1b' {
-                                // "Esc=Chat" button clicked — close overlay
-                                self.state.game_overlay.visible = false;
-                            } else if action_key == '\n' && !self.state.game_overlay.bet_input.is_empty() {
-                                // "Enter=Confirm" button clicked during bet entry
-                                self.handle_overlay_bet_confirm().await;
-                            } else {
-                                self.handle_overlay_action_key(action_key).await;
-                            }
-                        }
-                    }
-                    continue; // don't fall through to key handling
-                }
+    /// The nickname we saved for a peer with `/contact rename`
+    pub fn contact_name(&self, peer_id: &str) -> Option<&str> {
+        self.contacts
+            .iter()
+            .find(|(id, _)| id == peer_id)
+            .and_then(|(_, c)| c.nickname.as_deref())
+    }
 
-                // ── Key events ──────────────────────────────────────────
-                if let Event::Key(key) = ev {
+    /// A connected peer or saved contact by peer ID prefix or shown name
+    pub fn find_peer_or_contact(&self, target: &str) -> Option<String> {
+        if target.is_empty() {
+            return None;
+        }
+        self.peers
+            .iter()
+            .chain(self.contacts.iter().map(|(id, _)| id))
+            .find(|p| p.starts_with(target) || self.display_nick(p).is_some_and(|n| n == target))
+            .cloned()
+    }
+
+    /// One line per saved contact for `/contacts`
+    pub fn contact_lines(&self) -> Vec<String> {
+        if self.contacts.is_empty() {
+            return vec!["No contacts yet; peers are added when you exchange keys".to_string()];
+        }
+        self.contacts
+            .iter()
+            .map(|(id, contact)| {
+                let seen = if self.peers.contains(id) {
+                    "online".to_string()
+                } else {
+                    chrono::DateTime::from_timestamp(contact.last_seen as i64, 0)
+                        .map(|dt| format!("last seen {}", dt.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")))
+                        .unwrap_or_default()
+                };
+                format!(
+                    "👤 {} ({}) — {}",
+                    contact.nickname.as_deref().unwrap_or("(no name)"),
+                    &id[..12.min(id.len())],
+                    seen
+                )
+            })
+            .collect()
+    }
+
+    /// Hold a message from `peer_id` if only verified peers are shown and
+    /// it isn't one. Returns true when the caller should not display it.
+    /// Past `MAX_HELD_PER_PEER` the oldest held message is dropped, and
+    /// messages from more than `MAX_HELD_PEERS` peers aren't kept at all.
+    pub fn hold_if_unverified(&mut self, peer_id: &str, message: HeldMessage) -> bool {
+        if !self.only_verified || peer_id == self.local_peer_id || self.verified_peers.contains(peer_id) {
+            return false;
+        }
+        if !self.held_messages.contains_key(peer_id) && self.held_messages.len() >= MAX_HELD_PEERS {
+            return true;
+        }
+        let sender = message.sender.clone();
+        let held = self.held_messages.entry(peer_id.to_string()).or_default();
+        if held.len() >= MAX_HELD_PER_PEER {
+            held.remove(0);
+        }
+        held.push(message);
+        if held.len() == 1 {
+            let short = &peer_id[..8.min(peer_id.len())];
+            self.info(&format!(
+                "Holding messages from {} (unverified) — /verify {} confirm to show them",
+                sender, short
+            ));
+        }
+        true
+    }
+
+    /// Show held messages where they'd have gone on arrival
+    fn release_held(&mut self, held: Vec<HeldMessage>) {
+        for message in held {
+            match message.room {
+                Some(room) => {
+                    self.add_room_message(&room.room_id, &message.sender, &message.content, room.origin, room.timestamp);
+                    self.record_room_message(&room.room_id);
+                }
+                None => self.add_chat_message(&message.sender, &message.content),
+            }
+        }
+    }
+
+    /// Apply the settings a `/reload` read and say what changed
+    pub fn apply_reload(&mut self, args: &crate::Args, reload: Result<Reload, clap::Error>) {
+        let reload = match reload {
+            Ok(reload) => reload,
+            Err(e) => {
+                let message = e.to_string();
+                let reason = message.lines().next().unwrap_or_default();
+                self.error(&format!(
+                    "Config not reloaded: {}",
+                    reason.trim_start_matches("error: ")
+                ));
+                return;
+            }
+        };
+        for key in &reload.applied {
+            match key.as_str() {
+                "nick" => {
+                    self.nick = format::sanitize_nick(&args.nick);
+                    let (me, nick) = (self.local_peer_id.clone(), self.nick.clone());
+                    self.note_nick(&me, &nick);
+                }
+                "trust_dial" | "dial_allowlist" => {
+                    self.dial_policy = DialPolicy::new(args.trust_dial, args.dial_allowlist.clone());
+                }
+                "accept_only_verified" => {
+                    self.only_verified = args.accept_only_verified;
+                    if !self.only_verified {
+                        let held = std::mem::take(&mut self.held_messages).into_values().flatten().collect();
+                        self.release_held(held);
+                    }
+                }
+                _ => {}
+            }
+        }
+        tracing::info!(
+            "Config reloaded; applied {:?}, needs restart {:?}",
+            reload.applied,
+            reload.needs_restart
+        );
+        if reload.applied.is_empty() && reload.needs_restart.is_empty() {
+            self.info("🔄 Config reloaded — nothing changed");
+            return;
+        }
+        if !reload.applied.is_empty() {
+            self.info(&format!("🔄 Config reloaded: {} updated", reload.applied.join(", ")));
+        }
+        if !reload.needs_restart.is_empty() {
+            self.warn(&format!(
+                "Restart OpenWire to apply: {}",
+                reload.needs_restart.join(", ")
+            ));
+        }
+    }
+
+    /// Record `peer_id` as verified and show anything held from it
+    pub fn mark_verified(&mut self, peer_id: &str) {
+        self.verified_peers.insert(peer_id.to_string());
+        let held = self.held_messages.remove(peer_id).unwrap_or_default();
+        self.release_held(held);
+    }
+
+    /// `peer_id` is no longer verified: its keys changed or were forgotten
+    pub fn unmark_verified(&mut self, peer_id: &str) -> bool {
+        self.verified_peers.remove(peer_id)
+    }
+
+    /// Read a peer's direct messages, clearing their unread count
+    pub fn open_dm(&mut self, peer_id: &str) {
+        self.dm_unread.remove(peer_id);
+        self.dm_view = Some(peer_id.to_string());
+    }
+
+    /// Request a notification unless do-not-disturb is on
+    pub fn notify(&mut self) {
+        if !self.dnd {
+            self.notify_pending = true;
+        }
+    }
+
+    /// Resolve a (possibly shortened) room id against joined rooms
+    pub fn find_room_id(&self, prefix: &str) -> Option<String> {
+        self.rooms
+            .iter()
+            .find(|(id, _)| id == prefix)
+            .or_else(|| self.rooms.iter().find(|(id, _)| id.starts_with(prefix)))
+            .map(|(id, _)| id.clone())
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focus = self.focus.next();
+    }
+
+    pub fn focus_prev(&mut self) {
+        self.focus = self.focus.prev();
+    }
+
+    /// Move the highlight of the focused list panel by `delta` rows,
+    /// stopping at either end
+    pub fn move_selection(&mut self, delta: isize) {
+        let (selected, len) = match self.focus {
+            Focus::Peers => (&mut self.peer_selected, self.peers.len()),
+            Focus::Rooms => (&mut self.room_selected, self.rooms.len()),
+            Focus::Input | Focus::Messages => return,
+        };
+        let max = len.saturating_sub(1);
+        *selected = (*selected).min(max).saturating_add_signed(delta).min(max);
+    }
+
+    /// Highlighted peer, clamped to the current list
+    pub fn selected_peer(&self) -> Option<&String> {
+        let last = self.peers.len().checked_sub(1)?;
+        self.peers.get(self.peer_selected.min(last))
+    }
+
+    /// Highlighted room, clamped to the current list
+    pub fn selected_room(&self) -> Option<&(String, String)> {
+        let last = self.rooms.len().checked_sub(1)?;
+        self.rooms.get(self.room_selected.min(last))
+    }
+
+    /// Act on the highlighted item (Enter in the Peers or Rooms panel).
+    /// A room is marked read and becomes the target for invites; a peer
+    /// fills in `/room invite` for the highlighted room, to be confirmed
+    /// with Enter.
+    pub fn activate_selection(&mut self) {
+        match self.focus {
+            Focus::Peers => {
+                let Some(peer) = self.selected_peer() else { return };
+                let short = &peer[..peer.len().min(12)];
+                self.input = match self.selected_room() {
+                    Some((room_id, _)) => format!("/room invite {} {}", short, room_id),
+                    None => format!("/room invite {} ", short),
+                };
+                self.cursor_pos = self.input.len();
+                self.focus = Focus::Input;
+            }
+            Focus::Rooms => {
+                let Some((room_id, name)) = self.selected_room().cloned() else { return };
+                // Enter on the room being viewed goes back to all messages
+                if self.active_room.as_ref() == Some(&room_id) {
+                    self.active_room = None;
+                    self.scroll_offset = 0;
+                    self.auto_scroll = true;
+                    self.info(&format!("🏠 Showing all messages again (was {})", name));
+                    return;
+                }
+                self.unread.remove(&room_id);
+                self.scroll_offset = 0;
+                self.auto_scroll = true;
+                self.active_room = Some(room_id.clone());
+                self.dm_view = None;
+                self.info(&format!(
+                    "🏠 Selected {} ({}); only its messages are shown until Enter on it again. Enter on a peer invites them here; /members toggles its member list.",
+                    name, room_id
+                ));
+            }
+            Focus::Input | Focus::Messages => {}
+        }
+    }
+
+    /// Member list of a room: ourselves first, then the other members by
+    /// name. Presence comes from the Peers list.
+    pub fn room_roster(&self, room_id: &str) -> Vec<RosterEntry> {
+        let mut others: Vec<RosterEntry> = self
+            .room_members
+            .get(room_id)
+            .into_iter()
+            .flatten()
+            .map(|peer| RosterEntry {
+                name: self.display_nick(peer).unwrap_or_else(|| {
+                    peer[..peer.len().min(12)].to_string()
+                }),
+                online: self.peers.contains(peer),
+                owner: false,
+            })
+            .collect();
+        others.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut roster = vec![RosterEntry {
+            name: format!("{} (you)", self.nick),
+            online: true,
+            owner: self.owned_rooms.contains(room_id),
+        }];
+        roster.extend(others);
+        roster
+    }
+
+    // ── Input line editing ───────────────────────────────────────────
+    // `cursor_pos` is a byte offset into `input`, always on a char boundary.
+
+    /// Insert `c` at the cursor
+    pub fn insert_char(&mut self, c: char) {
+        self.input.insert(self.cursor_pos, c);
+        self.cursor_pos += c.len_utf8();
+    }
+
+    /// Byte offset of the char before the cursor
+    fn prev_boundary(&self) -> Option<usize> {
+        self.input[..self.cursor_pos].char_indices().next_back().map(|(i, _)| i)
+    }
+
+    /// Byte offset just past the char at the cursor
+    fn next_boundary(&self) -> Option<usize> {
+        self.input[self.cursor_pos..]
+            .chars()
+            .next()
+            .map(|c| self.cursor_pos + c.len_utf8())
+    }
+
+    /// Backspace: delete the char before the cursor
+    pub fn delete_back(&mut self) {
+        if let Some(prev) = self.prev_boundary() {
+            self.input.replace_range(prev..self.cursor_pos, "");
+            self.cursor_pos = prev;
+        }
+    }
+
+    /// Delete: remove the char at the cursor
+    pub fn delete_forward(&mut self) {
+        if let Some(next) = self.next_boundary() {
+            self.input.replace_range(self.cursor_pos..next, "");
+        }
+    }
+
+    pub fn cursor_left(&mut self) {
+        if let Some(prev) = self.prev_boundary() {
+            self.cursor_pos = prev;
+        }
+    }
+
+    pub fn cursor_right(&mut self) {
+        if let Some(next) = self.next_boundary() {
+            self.cursor_pos = next;
+        }
+    }
+
+    /// Ctrl+A / Home
+    pub fn cursor_home(&mut self) {
+        self.cursor_pos = 0;
+    }
+
+    /// Ctrl+E / End
+    pub fn cursor_end(&mut self) {
+        self.cursor_pos = self.input.len();
+    }
+
+    /// Start of the word before the cursor: skip whitespace, then the word
+    fn word_start_before(&self) -> usize {
+        let before = &self.input[..self.cursor_pos];
+        let trimmed = before.trim_end();
+        trimmed
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8())
+    }
+
+    /// End of the word after the cursor: skip whitespace, then the word
+    fn word_end_after(&self) -> usize {
+        let after = &self.input[self.cursor_pos..];
+        let skipped = after.len() - after.trim_start().len();
+        after[skipped..]
+            .char_indices()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(self.input.len(), |(i, _)| self.cursor_pos + skipped + i)
+    }
+
+    /// Ctrl+Left: jump to the start of the previous word
+    pub fn word_left(&mut self) {
+        self.cursor_pos = self.word_start_before();
+    }
+
+    /// Ctrl+Right: jump to the end of the next word
+    pub fn word_right(&mut self) {
+        self.cursor_pos = self.word_end_after();
+    }
+
+    /// Ctrl+W: delete the word before the cursor
+    pub fn delete_word_back(&mut self) {
+        let start = self.word_start_before();
+        self.input.replace_range(start..self.cursor_pos, "");
+        self.cursor_pos = start;
+    }
+
+    /// Ctrl+U: delete from the start of the line to the cursor
+    pub fn kill_to_start(&mut self) {
+        self.input.replace_range(..self.cursor_pos, "");
+        self.cursor_pos = 0;
+    }
+
+    /// Ctrl+K: delete from the cursor to the end of the line
+    pub fn kill_to_end(&mut self) {
+        self.input.truncate(self.cursor_pos);
+    }
+
+    /// Rows in the message pane. Every message takes at least one, so
+    /// before the first render this is the message count.
+    pub fn total_rows(&self) -> usize {
+        self.message_rows.max(self.visible_messages().len())
+    }
+
+    /// Keep the scroll offset within what a message pane of `pane_height`
+    /// rows can show, e.g. after the terminal grew
+    pub fn clamp_scroll(&mut self, pane_height: usize) {
+        let max_scroll = self.total_rows().saturating_sub(pane_height);
+        self.scroll_offset = self.scroll_offset.min(max_scroll);
+        if self.scroll_offset == 0 {
+            self.auto_scroll = true;
+        }
+    }
+
+    /// Room messages that post `text` to every joined room. Each unmuted
+    /// room gets a local echo; muted rooms still receive the message.
+    pub fn announce(&mut self, text: &str) -> Vec<NetworkCommand> {
+        let count = self.rooms.len();
+        let label = format!(
+            "{} (announced to {} room{})",
+            text,
+            count,
+            if count == 1 { "" } else { "s" }
+        );
+        let mut commands = Vec::with_capacity(count);
+        for (room_id, _) in self.rooms.clone() {
+            if self.muted.contains(&room_id) {
+                commands.push(NetworkCommand::room_message(room_id, text.as_bytes()));
+                continue;
+            }
+            // The echo's id goes out with the message so /edit can refer to it
+            let sender = format!("[{}] {}", room_id, self.nick);
+            let id = self.add_room_message(&room_id, &sender, &label, None, crate::network::unix_now());
+            self.room_sent.insert(id.clone(), room_id.clone());
+            commands.push(NetworkCommand::room_chat(room_id, id, text));
+        }
+        commands
+    }
+
+    /// Show an edit or delete of a message in `room_id` by `author`, or
+    /// one of ours when `None`. Only that author's message with the id is
+    /// changed. Returns false if it isn't shown (scrolled away, never seen
+    /// or someone else's).
+    pub fn apply_room_change(&mut self, room_id: &str, author: Option<&str>, change: &RoomChange) -> bool {
+        let target = |m: &ChatMessage| match (author, &m.origin) {
+            (Some(author), Some(origin)) => origin.author == author && origin.id == change.target(),
+            (None, None) => m.id == change.target(),
+            _ => false,
+        };
+        if let Some(m) = self
+            .room_history
+            .get_mut(room_id)
+            .and_then(|history| history.iter_mut().rev().find(|m| target(m)))
+        {
+            Self::change_message(m, change);
+        }
+        let Some(m) = self
+            .messages
+            .iter_mut()
+            .rev()
+            .find(|m| !m.is_system && m.room.as_deref() == Some(room_id) && target(m))
+        else {
+            return false;
+        };
+        Self::change_message(m, change);
+        true
+    }
+
+    fn change_message(m: &mut ChatMessage, change: &RoomChange) {
+        match change {
+            RoomChange::Edit { text, .. } => {
+                let (content, preformatted) = format::format_chat_text(text);
+                m.content = if preformatted { content } else { format!("{} (edited)", content) };
+                m.preformatted = preformatted;
+            }
+            RoomChange::Delete { .. } => {
+                m.content = "(message deleted)".to_string();
+                m.preformatted = false;
+            }
+        }
+    }
+
+    /// Edit (`Some(text)`) or delete (`None`) one of our room messages.
+    /// Updates the local copy and returns the command telling the room.
+    pub fn change_own_message(&mut self, prefix: &str, text: Option<&str>) -> Result<NetworkCommand> {
+        let id = self.find_message(prefix)?.id.clone();
+        let Some(room_id) = self.room_sent.get(&id).cloned() else {
+            anyhow::bail!("Message {} isn't one of your room messages", id);
+        };
+        let change = match text {
+            Some(text) => RoomChange::Edit { id: id.clone(), text: text.to_string() },
+            None => {
+                self.room_sent.remove(&id);
+                RoomChange::Delete { id: id.clone() }
+            }
+        };
+        self.apply_room_change(&room_id, None, &change);
+        Ok(NetworkCommand::room_message(room_id, change.to_bytes()))
+    }
+
+    /// Forget a room we left
+    pub fn remove_room(&mut self, room_id: &str) {
+        self.rooms.retain(|(id, _)| id != room_id);
+        self.room_members.remove(room_id);
+        self.room_history.remove(room_id);
+        self.owned_rooms.remove(room_id);
+        if self.active_room.as_deref() == Some(room_id) {
+            self.active_room = None;
+        }
+    }
+
+    fn now() -> String {
+        chrono::Local::now().format("%H:%M").to_string()
+    }
+}
+
+/// The UI Application
+pub struct UiApp {
+    terminal: Terminal<CrosstermBackend<io::Stdout>>,
+    state: UiState,
+    command_sender: mpsc::Sender<NetworkCommand>,
+    event_receiver: broadcast::Receiver<NetworkEvent>,
+    /// Throttle typing broadcasts to once per 2 seconds
+    last_typing_broadcast: std::time::Instant,
+    /// Whether mouse capture is currently enabled (only when overlay visible)
+    mouse_captured: bool,
+    /// Settings `/reload` re-reads
+    config: Option<SharedConfig>,
+    /// Set by the SIGHUP handler
+    reload_requested: Arc<AtomicBool>,
+}
+
+impl UiApp {
+    pub fn new(
+        nick: String,
+        local_peer_id: String,
+        command_sender: mpsc::Sender<NetworkCommand>,
+        event_receiver: broadcast::Receiver<NetworkEvent>,
+        web_port: Option<u16>,
+        relay: bool,
+        dial_policy: DialPolicy,
+    ) -> Result<Self> {
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let backend = CrosstermBackend::new(stdout);
+        let terminal = Terminal::new(backend)?;
+
+        let mut state = UiState::new(nick, local_peer_id, web_port, relay);
+        state.dial_policy = dial_policy;
+
+        Ok(Self {
+            terminal,
+            state,
+            command_sender,
+            event_receiver,
+            last_typing_broadcast: std::time::Instant::now()
+                - std::time::Duration::from_secs(10),
+            mouse_captured: false,
+            config: None,
+            reload_requested: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Hold messages from peers until they are verified (`--accept-only-verified`)
+    pub fn accept_only_verified(&mut self) {
+        self.state.only_verified = true;
+        self.state.info("🔒 Only verified peers are shown. Check a peer with /verify <peer>, then /verify <peer> confirm.");
+    }
+
+    /// Let `/reload` re-read `config`; setting `reload_requested` (SIGHUP)
+    /// reloads it on the next tick
+    pub fn watch_config(&mut self, config: SharedConfig, reload_requested: Arc<AtomicBool>) {
+        self.config = Some(config);
+        self.reload_requested = reload_requested;
+    }
+
+    /// Re-read the config file and environment, applying what can change.
+    /// A new nick is passed on to the network for room messages.
+    async fn reload_config(&mut self) {
+        let Some(config) = self.config.clone() else {
+            self.state.warn("No configuration to reload");
+            return;
+        };
+        let nick = self.state.nick.clone();
+        {
+            let mut config = config.write().unwrap_or_else(PoisonError::into_inner);
+            let reload = config.reload();
+            self.state.apply_reload(config.args(), reload);
+        }
+        if self.state.nick != nick {
+            let nick = self.state.nick.clone();
+            self.send(NetworkCommand::SetNick { nick }).await;
+        }
+    }
+
+    /// `--room-history`: messages kept per room
+    pub fn set_room_history_limit(&mut self, limit: usize) {
+        self.state.room_history_limit = limit;
+    }
+
+    /// Name the `--profile` in use, for `/whoami`
+    pub fn set_profile(&mut self, profile: &str) {
+        self.state.profile = Some(profile.to_string());
+        self.state.info(&format!("👤 Profile: {}", profile));
+    }
+
+    /// Tell the user the token the web API's room endpoints expect
+    pub fn show_web_token(&mut self, token: &str) {
+        self.state.info(&format!(
+            "🔑 Web API token: {} (send as `Authorization: Bearer <token>`)",
+            token
+        ));
+    }
+
+    /// Register a handler for JSON and binary broadcasts; the TUI itself
+    /// only shows text
+    pub fn on_payload(&mut self, hook: impl FnMut(&str, ContentType, &[u8]) + Send + 'static) {
+        self.state.payload_hooks.push(Box::new(hook));
+    }
+
+    /// Whether the UI stopped because the network task went away
+    pub fn network_lost(&self) -> bool {
+        self.state.network_disconnected
+    }
+
+    /// Send a command to the network task. A failure has already been
+    /// reported by `UiState::send_command`, which also stops the UI loop.
+    async fn send(&mut self, cmd: NetworkCommand) {
+        let _ = self.state.send_command(&self.command_sender, cmd).await;
+    }
+
+    /// Run the UI event loop
+    pub async fn run(&mut self) -> Result<()> {
+        // Saved contact names, for peers as they reconnect
+        self.send(NetworkCommand::ListContacts).await;
+        loop {
+            self.render()?;
+
+            // Leave the disconnect notice up briefly before exiting
+            if self.state.network_disconnected {
+                tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+                break;
+            }
+
+            if self.state.notify_pending {
+                self.state.notify_pending = false;
+                use std::io::Write;
+                let mut stdout = io::stdout();
+                let _ = stdout.write_all(b"\x07");
+                let _ = stdout.flush();
+            }
+
+            // Process any pending network events (non-blocking)
+            loop {
+                match self.event_receiver.try_recv() {
+                    Ok(event) => self.handle_network_event(event),
+                    Err(broadcast::error::TryRecvError::Lagged(n)) => {
+                        tracing::warn!("UI fell behind and missed {} network events", n);
+                    }
+                    Err(_) => break,
+                }
+            }
+            self.state.expire_challenges(std::time::Instant::now());
+            if self.reload_requested.swap(false, Ordering::Relaxed) {
+                self.reload_config().await;
+            }
+
+            // Toggle mouse capture: enable only when game overlay is visible
+            let want_mouse = self.state.game_overlay.visible;
+            if want_mouse && !self.mouse_captured {
+                let _ = execute!(io::stdout(), EnableMouseCapture);
+                self.mouse_captured = true;
+            } else if !want_mouse && self.mouse_captured {
+                let _ = execute!(io::stdout(), DisableMouseCapture);
+                self.mouse_captured = false;
+            }
+
+            // Poll for input events with a small timeout
+            if event::poll(std::time::Duration::from_millis(50))? {
+                let ev = event::read()?;
+
+                // ── Resize: lay out for the new size right away ─────────
+                if let Event::Resize(width, height) = ev {
+                    self.terminal.resize(Rect::new(0, 0, width, height))?;
+                    self.state.clamp_scroll(Self::message_pane_height(height));
+                    continue; // next iteration re-renders
+                }
+
+                // ── Mouse events (game overlay buttons) ─────────────────
+                if let Event::Mouse(mouse) = &ev {
+                    if self.state.game_overlay.visible
+                        && let Some(action_key) = game_ui::handle_game_mouse(*mouse, &self.state.game_overlay) {
+                        if action_key == '\x1b' {
+                            // "Esc=Chat" button clicked — close overlay
+                            self.state.game_overlay.visible = false;
+                        } else if action_key == '\n' && !self.state.game_overlay.bet_input.is_empty() {
+                            // "Enter=Confirm" button clicked during bet entry
+                            self.handle_overlay_bet_confirm().await;
+                        } else {
+                            self.handle_overlay_action_key(action_key).await;
+                        }
+                    }
+                    continue; // don't fall through to key handling
+                }
+
+                // ── Key events ──────────────────────────────────────────
+                if let Event::Key(key) = ev {
                     // Ctrl+C always quits
                     if key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL {
-                        let _ = self.command_sender.send(NetworkCommand::Shutdown).await;
+                        self.send(NetworkCommand::Shutdown).await;
                         break;
                     }
 
@@ -332,17 +1783,59 @@ impl UiApp {
 
     /// Handle a normal (non-overlay) key event. Returns true if should quit.
     async fn handle_normal_key(&mut self, key: crossterm::event::KeyEvent) -> bool {
+        // List panels take Up/Down/Enter; Esc returns to the input
+        if self.state.focus != Focus::Input {
+            match key.code {
+                KeyCode::Esc => {
+                    self.state.focus = Focus::Input;
+                    return false;
+                }
+                KeyCode::Up if self.state.focus != Focus::Messages => {
+                    self.state.move_selection(-1);
+                    return false;
+                }
+                KeyCode::Down if self.state.focus != Focus::Messages => {
+                    self.state.move_selection(1);
+                    return false;
+                }
+                KeyCode::Enter if self.state.focus != Focus::Messages => {
+                    self.state.activate_selection();
+                    return false;
+                }
+                _ => {}
+            }
+        }
         match (key.code, key.modifiers) {
             (KeyCode::Esc, _) => {
-                let _ = self.command_sender.send(NetworkCommand::Shutdown).await;
+                self.send(NetworkCommand::Shutdown).await;
                 return true;
             }
             (KeyCode::Enter, _) => {
                 return self.handle_submit().await;
             }
+            (KeyCode::Tab, _)
+                if self.state.focus == Focus::Input && Self::is_completable(&self.state.input) =>
+            {
+                self.complete_command();
+            }
+            (KeyCode::Tab, _) => {
+                self.state.focus_next();
+            }
+            (KeyCode::BackTab, _) => {
+                self.state.focus_prev();
+            }
+            // Readline-style editing
+            (KeyCode::Char('a'), KeyModifiers::CONTROL) => self.state.cursor_home(),
+            (KeyCode::Char('e'), KeyModifiers::CONTROL) => self.state.cursor_end(),
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => self.state.kill_to_start(),
+            (KeyCode::Char('k'), KeyModifiers::CONTROL) => self.state.kill_to_end(),
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => self.state.delete_word_back(),
+            (KeyCode::Left, KeyModifiers::CONTROL) => self.state.word_left(),
+            (KeyCode::Right, KeyModifiers::CONTROL) => self.state.word_right(),
             (KeyCode::Char(c), _) => {
-                self.state.input.insert(self.state.cursor_pos, c);
-                self.state.cursor_pos += 1;
+                // Typing always goes to the input, whichever panel is focused
+                self.state.focus = Focus::Input;
+                self.state.insert_char(c);
                 // Throttled typing indicator broadcast
                 let now = std::time::Instant::now();
                 if now.duration_since(self.last_typing_broadcast)
@@ -351,45 +1844,22 @@ impl UiApp {
                     self.last_typing_broadcast = now;
                     let typing_msg = format!("TYPING:{}", self.state.nick);
                     let nick = self.state.nick.clone();
-                    let _ = self
-                        .command_sender
-                        .send(NetworkCommand::Broadcast {
-                            data: typing_msg.into_bytes(),
-                            nick,
-                        })
-                        .await;
-                }
-            }
-            (KeyCode::Backspace, _) => {
-                if self.state.cursor_pos > 0 {
-                    self.state.cursor_pos -= 1;
-                    self.state.input.remove(self.state.cursor_pos);
-                }
-            }
-            (KeyCode::Delete, _) => {
-                if self.state.cursor_pos < self.state.input.len() {
-                    self.state.input.remove(self.state.cursor_pos);
-                }
-            }
-            (KeyCode::Left, _) => {
-                if self.state.cursor_pos > 0 {
-                    self.state.cursor_pos -= 1;
-                }
-            }
-            (KeyCode::Right, _) => {
-                if self.state.cursor_pos < self.state.input.len() {
-                    self.state.cursor_pos += 1;
+                    self.send(NetworkCommand::Broadcast {
+                        data: typing_msg.into_bytes(),
+                        nick,
+                    })
+                    .await;
                 }
             }
-            (KeyCode::Home, _) => {
-                self.state.cursor_pos = 0;
-            }
-            (KeyCode::End, _) => {
-                self.state.cursor_pos = self.state.input.len();
-            }
+            (KeyCode::Backspace, _) => self.state.delete_back(),
+            (KeyCode::Delete, _) => self.state.delete_forward(),
+            (KeyCode::Left, _) => self.state.cursor_left(),
+            (KeyCode::Right, _) => self.state.cursor_right(),
+            (KeyCode::Home, _) => self.state.cursor_home(),
+            (KeyCode::End, _) => self.state.cursor_end(),
             (KeyCode::Up, _) => {
                 self.state.auto_scroll = false;
-                let max_scroll = self.state.messages.len().saturating_sub(1);
+                let max_scroll = self.state.total_rows().saturating_sub(1);
                 if self.state.scroll_offset < max_scroll {
                     self.state.scroll_offset += 1;
                 }
@@ -404,7 +1874,7 @@ impl UiApp {
             }
             (KeyCode::PageUp, _) => {
                 self.state.auto_scroll = false;
-                let max_scroll = self.state.messages.len().saturating_sub(1);
+                let max_scroll = self.state.total_rows().saturating_sub(1);
                 self.state.scroll_offset = (self.state.scroll_offset + 10).min(max_scroll);
             }
             (KeyCode::PageDown, _) => {
@@ -479,13 +1949,11 @@ impl UiApp {
                             challenger_nick: self.state.nick.clone(),
                             room_id,
                         };
-                        let _ = self
-                            .command_sender
-                            .send(NetworkCommand::SendRoomMessage {
-                                room_id: self.state.active_game.as_ref().unwrap().room_id.clone(),
-                                data: action.to_bytes(),
-                            })
-                            .await;
+                        self.send(NetworkCommand::room_message(
+                            self.state.active_game.as_ref().unwrap().room_id.clone(),
+                            action.to_bytes(),
+                        ))
+                        .await;
                     }
                 }
                 _ => {}
@@ -557,6 +2025,12 @@ impl UiApp {
         self.state.game_overlay.bet_input.clear();
     }
 
+    async fn dial(&mut self, addr: String) {
+        self.state
+            .add_system_message(&format!("Connecting to {}", addr));
+        self.send(NetworkCommand::Connect(addr)).await;
+    }
+
     /// Handle submit (Enter key). Returns true if should quit.
     async fn handle_submit(&mut self) -> bool {
         let input = self.state.input.trim().to_string();
@@ -566,25 +2040,83 @@ impl UiApp {
 
         self.state.input.clear();
         self.state.cursor_pos = 0;
+        // Sending input means the user has caught up on the message pane
+        self.state.unread.clear();
 
-        if let Some(path) = input.strip_prefix("/send ") {
+        if let Some(arg) = input.strip_prefix("/send ") {
             // File transfer command
-            let path = path.trim();
+            let (path, caption) = Self::split_caption(arg);
             if path.is_empty() {
-                self.state.add_system_message("Usage: /send <file_path>");
+                self.state.add_system_message("Usage: /send <file_path>... [:: caption]");
+                return false;
+            }
+            // Several paths unless the whole argument names one file (which may contain spaces)
+            let paths: Vec<&str> = path.split_whitespace().collect();
+            if paths.len() > 1 && !std::path::Path::new(path).exists() {
+                let batch = FileBatch::from_paths(&paths);
+                self.send_batch(batch, caption).await;
+                return false;
+            }
+            self.state
+                .add_system_message(&format!("Sending file: {}", path));
+            self.send(NetworkCommand::SendFile {
+                path: path.to_string(),
+                caption: caption.map(str::to_string),
+                peer_id: None,
+            })
+            .await;
+            false
+        } else if let Some(arg) = input.strip_prefix("/sendto ") {
+            let (arg, caption) = Self::split_caption(arg);
+            let (target, path) = arg.split_once(' ').unwrap_or((arg, ""));
+            let path = path.trim();
+            if target.is_empty() || path.is_empty() {
+                self.state.add_system_message("Usage: /sendto <peer> <file_path> [:: caption]");
+                return false;
+            }
+            let found_peer = self
+                .state
+                .peers
+                .iter()
+                .find(|p| p.starts_with(target) || self.state.display_nick(p).is_some_and(|n| n == target))
+                .cloned();
+            let Some(peer_id) = found_peer else {
+                self.state.warn(&format!("Peer '{}' not found. Check the Peers panel.", target));
+                return false;
+            };
+            let name = self
+                .state
+                .display_nick(&peer_id)
+                .unwrap_or_else(|| Self::short_id(&peer_id, 8));
+            self.state
+                .add_system_message(&format!("🔒 Sending file to {}: {}", name, path));
+            self.send(NetworkCommand::SendFile {
+                path: path.to_string(),
+                caption: caption.map(str::to_string),
+                peer_id: Some(peer_id),
+            })
+            .await;
+            false
+        } else if let Some(arg) = input.strip_prefix("/senddir ") {
+            let (arg, caption) = Self::split_caption(arg);
+            let recursive = arg.split_whitespace().any(|w| w == "--recursive" || w == "-r");
+            let dir = arg
+                .split_whitespace()
+                .filter(|w| *w != "--recursive" && *w != "-r")
+                .collect::<Vec<_>>()
+                .join(" ");
+            if dir.is_empty() {
+                self.state
+                    .add_system_message("Usage: /senddir <dir> [--recursive] [:: caption]");
                 return false;
             }
-            self.state
-                .add_system_message(&format!("Sending file: {}", path));
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SendFile {
-                    path: path.to_string(),
-                })
-                .await;
+            match FileBatch::from_dir(std::path::Path::new(&dir), recursive) {
+                Ok(batch) => self.send_batch(batch, caption).await,
+                Err(e) => self.state.warn(&e.to_string()),
+            }
             false
         } else if input == "/quit" || input == "/q" {
-            let _ = self.command_sender.send(NetworkCommand::Shutdown).await;
+            self.send(NetworkCommand::Shutdown).await;
             true
         } else if let Some(addr) = input.strip_prefix("/connect ") {
             let addr = addr.trim();
@@ -593,13 +2125,167 @@ impl UiApp {
                     .add_system_message("Usage: /connect <multiaddress>");
                 return false;
             }
-            self.state
-                .add_system_message(&format!("Connecting to {}", addr));
+            if let Some(addr) = self.state.request_dial(addr) {
+                self.dial(addr).await;
+            }
+            false
+        } else if input == "/reconnect" {
+            self.send(NetworkCommand::ReconnectAll).await;
+            false
+        } else if input == "/yes" || input == "/no" {
+            if let Some(addr) = self.state.resolve_pending_dial(input == "/yes") {
+                self.dial(addr).await;
+            }
+            false
+        } else if input == "/pin" {
+            // List recent messages so their ids can be picked
+            let recent: Vec<String> = self
+                .state
+                .messages
+                .iter()
+                .filter(|m| !m.is_system)
+                .rev()
+                .take(10)
+                .map(|m| format!("  {} {}: {}", m.id, m.sender, format::truncate_display(&m.content, 50)))
+                .collect();
+            if recent.is_empty() {
+                self.state.info("No messages to pin yet");
+            } else {
+                self.state.info("Recent messages — /pin <id> to pin one:");
+                for line in recent.into_iter().rev() {
+                    self.state.add_system_message(&line);
+                }
+            }
+            false
+        } else if let Some(prefix) = input.strip_prefix("/pin ") {
+            match self.state.pin(prefix.trim()) {
+                Ok(id) => self.state.success(&format!("📌 Pinned {} — /pins to review", id)),
+                Err(e) => self.state.warn(&e.to_string()),
+            }
+            false
+        } else if let Some(prefix) = input.strip_prefix("/unpin ") {
+            match self.state.unpin(prefix.trim()) {
+                Ok(id) => self.state.success(&format!("Unpinned {}", id)),
+                Err(e) => self.state.warn(&e.to_string()),
+            }
+            false
+        } else if let Some(args) = input.strip_prefix("/edit ") {
+            let (prefix, text) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+            if text.trim().is_empty() {
+                self.state.warn("Usage: /edit <id> <new text>");
+                return false;
+            }
+            match self.state.change_own_message(prefix, Some(text.trim())) {
+                Ok(cmd) => {
+                    self.send(cmd).await;
+                }
+                Err(e) => self.state.warn(&e.to_string()),
+            }
+            false
+        } else if let Some(prefix) = input.strip_prefix("/delete ") {
+            match self.state.change_own_message(prefix.trim(), None) {
+                Ok(cmd) => {
+                    self.send(cmd).await;
+                }
+                Err(e) => self.state.warn(&e.to_string()),
+            }
+            false
+        } else if let Some(args) = input.strip_prefix("/export ") {
+            let Some((path, passphrase)) = args.trim().split_once(' ') else {
+                self.state.warn("Usage: /export <file> <passphrase>");
+                return false;
+            };
             let _ = self
-                .command_sender
-                .send(NetworkCommand::Connect(addr.to_string()))
+                .send(NetworkCommand::ExportBundle {
+                    path: path.to_string(),
+                    passphrase: passphrase.trim().to_string(),
+                })
                 .await;
             false
+        } else if input == "/save-identity" || input.starts_with("/save-identity ") {
+            let path = input["/save-identity".len()..].trim();
+            let path = (!path.is_empty()).then(|| path.to_string());
+            self.send(NetworkCommand::SaveIdentity { path }).await;
+            false
+        } else if input == "/block" {
+            self.send(NetworkCommand::ListBlocked).await;
+            false
+        } else if let Some(target) = input.strip_prefix("/block ") {
+            let target = target.trim();
+            // Full IDs work for peers we've never seen
+            let peer_id = self
+                .state
+                .find_peer_or_contact(target)
+                .or_else(|| target.parse::<libp2p::PeerId>().ok().map(|p| p.to_string()));
+            match peer_id {
+                Some(peer_id) => {
+                    self.send(NetworkCommand::Block { peer_id }).await;
+                }
+                None => self.state.warn(&format!("Peer '{}' not found. Usage: /block <peer>", target)),
+            }
+            false
+        } else if let Some(target) = input.strip_prefix("/unblock ") {
+            let target = target.trim();
+            let peer_id = self
+                .state
+                .blocked
+                .iter()
+                .find(|p| p.starts_with(target) || self.state.display_nick(p).is_some_and(|n| n == target))
+                .cloned()
+                .unwrap_or_else(|| target.to_string());
+            self.send(NetworkCommand::Unblock { peer_id }).await;
+            false
+        } else if let Some(target) = input.strip_prefix("/trust ") {
+            let target = target.trim();
+            match self.state.find_peer_or_contact(target) {
+                Some(peer_id) => {
+                    self.send(NetworkCommand::TrustPeer { peer_id }).await;
+                }
+                None => self.state.warn(&format!("Peer '{}' not found. Usage: /trust <peer>", target)),
+            }
+            false
+        } else if input == "/contacts" {
+            self.state.contacts_requested = true;
+            self.send(NetworkCommand::ListContacts).await;
+            false
+        } else if let Some(args) = input.strip_prefix("/contact rename ") {
+            let (target, name) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+            let name = format::sanitize_nick(name);
+            match self.state.find_peer_or_contact(target) {
+                Some(peer_id) => {
+                    self.state.info(&match name.as_str() {
+                        "" => format!("👤 Cleared the name of {}", Self::short_id(&peer_id, 8)),
+                        _ => format!("👤 {} is now saved as '{}'", Self::short_id(&peer_id, 8), name),
+                    });
+                    self.send(NetworkCommand::RenameContact { peer_id, name })
+                        .await;
+                }
+                None => self.state.warn(&format!(
+                    "Peer '{}' not found. Usage: /contact rename <peer> <name>",
+                    target
+                )),
+            }
+            false
+        } else if input == "/fingerprint" {
+            self.send(NetworkCommand::ShowFingerprint).await;
+            false
+        } else if input == "/whoami" {
+            let profile = self.state.profile.as_deref().unwrap_or("default");
+            self.state.info(&format!("Profile:        {}", profile));
+            self.send(NetworkCommand::ShowIdentity).await;
+            false
+        } else if input == "/show-identity" {
+            self.send(NetworkCommand::ShowIdentity).await;
+            false
+        } else if input == "/pins" {
+            let lines = self.state.pin_lines();
+            if lines.is_empty() {
+                self.state.info("No pinned messages. /pin lists recent ones to pick from.");
+            }
+            for line in lines {
+                self.state.add_system_message(&line);
+            }
+            false
         } else if input == "/help" {
             self.state
                 .add_system_message("═══════════════════════════════════════════");
@@ -608,89 +2294,35 @@ impl UiApp {
             self.state
                 .add_system_message("═══════════════════════════════════════════");
             self.state.add_system_message("");
-            self.state.add_system_message("MESSAGING COMMANDS:");
-            self.state
-                .add_system_message("  /send <file>     - Send a file to peers");
-            self.state
-                .add_system_message("  /image <file>    - Send an image to peers");
-            self.state
-                .add_system_message("  /gif <search>    - Search and send GIF");
-            self.state
-                .add_system_message("  /connect <addr>  - Connect to peer by address");
-            self.state
-                .add_system_message("  /quit or /q      - Exit the application");
-            self.state.add_system_message("");
-            self.state.add_system_message("PRIVATE ROOMS:");
-            self.state
-                .add_system_message("  /room create <name>         - Create room (alias: /create <name>)");
-            self.state
-                .add_system_message("  /room invite <peer> <room>  - Invite peer");
-            self.state
-                .add_system_message("  /room join <room_id>        - Join room");
-            self.state
-                .add_system_message("  /room list                  - List rooms");
-            self.state
-                .add_system_message("  /room leave <room>          - Leave room");
-            self.state.add_system_message("");
-            self.state.add_system_message("GAMES:");
-            self.state
-                .add_system_message("  /game tictactoe <room_id>   - Start a game");
-            self.state
-                .add_system_message("  /game rematch               - Play again");
-            self.state
-                .add_system_message("  /move <1-9>                 - Make a move");
-            self.state.add_system_message("");
-            self.state.add_system_message("BLACKJACK:");
-            self.state
-                .add_system_message("  /blackjack                  - Start game in first room");
-            self.state
-                .add_system_message("  /bj bet <amount>            - Place your bet");
-            self.state
-                .add_system_message("  /bj deal                    - Deal cards (host only)");
-            self.state
-                .add_system_message("  /bj hit                     - Take a card");
-            self.state
-                .add_system_message("  /bj stand                   - End your turn");
-            self.state
-                .add_system_message("  /bj newround                - Start new round");
+            for line in commands::help_lines() {
+                self.state.add_system_message(&line);
+            }
             self.state.add_system_message("");
-            self.state.add_system_message("ROULETTE:");
+            self.state.add_system_message("MESSAGE SCROLLING:");
             self.state
-                .add_system_message("  /roulette                   - Show roulette table");
+                .add_system_message("  Up / Down        - Scroll one line");
             self.state
-                .add_system_message("  /roulette bet <type> <amt>  - Place bet");
+                .add_system_message("  PageUp/PageDown  - Scroll ten lines");
             self.state
-                .add_system_message("  /roulette spin              - Spin the wheel");
+                .add_system_message("  Tab              - Complete a command name");
             self.state.add_system_message("");
-            self.state.add_system_message("ANDAR BAHAR:");
+            self.state.add_system_message("EDITING:");
             self.state
-                .add_system_message("  /ab andar <amount>          - Bet on Andar");
+                .add_system_message("  Ctrl+A / Ctrl+E  - Start / end of line");
             self.state
-                .add_system_message("  /ab bahar <amount>          - Bet on Bahar");
+                .add_system_message("  Ctrl+Left/Right  - Previous / next word");
             self.state
-                .add_system_message("  /ab deal                    - Deal cards");
-            self.state.add_system_message("");
-            self.state.add_system_message("SLOTS:");
-            self.state
-                .add_system_message("  /slots spin <amount>        - Spin the reels");
-            self.state.add_system_message("");
-            self.state.add_system_message("WALLET:");
+                .add_system_message("  Ctrl+W           - Delete previous word");
             self.state
-                .add_system_message("  /wallet  or  /chips         - Show chip balance");
+                .add_system_message("  Ctrl+U / Ctrl+K  - Delete to start / end of line");
             self.state.add_system_message("");
-            self.state.add_system_message("HELP:");
-            self.state
-                .add_system_message("  /rules <game>  - Show how to play a game");
+            self.state.add_system_message("PANELS:");
             self.state
-                .add_system_message("  /history       - Show recent game history");
+                .add_system_message("  Tab / Shift+Tab  - Focus next / previous panel");
             self.state
-                .add_system_message("  /whisper <id> <msg>  - Send private message");
-            self.state.add_system_message("");
-            self.state.add_system_message("MESSAGE SCROLLING:");
-            self.state
-                .add_system_message("  Up / Down        - Scroll one line");
+                .add_system_message("  Up / Down, Enter - Select and act in Peers or Rooms");
             self.state
-                .add_system_message("  PageUp/PageDown  - Scroll ten lines");
+                .add_system_message("  Esc              - Back to the message input");
             self.state.add_system_message("");
             self.state.add_system_message("NETWORK INFO:");
             self.state
@@ -698,22 +2330,25 @@ impl UiApp {
             self.state
                 .add_system_message("  Remote peers: share your multiaddress");
             false
-        } else if let Some(path) = input.strip_prefix("/image ") {
+        } else if let Some(name) = input.strip_prefix("/help ") {
+            self.show_command_help(name.trim());
+            false
+        } else if let Some(arg) = input.strip_prefix("/image ") {
             // Image transfer command
-            let path = path.trim();
+            let (path, caption) = Self::split_caption(arg);
             if path.is_empty() {
-                self.state.add_system_message("Usage: /image <file_path>");
+                self.state.add_system_message("Usage: /image <file_path> [:: caption]");
                 return false;
             }
             // Images are sent as files with a marker
             self.state
                 .add_system_message(&format!("🖼️ Sending image: {}", path));
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SendFile {
-                    path: path.to_string(),
-                })
-                .await;
+            self.send(NetworkCommand::SendFile {
+                path: path.to_string(),
+                caption: caption.map(str::to_string),
+                peer_id: None,
+            })
+            .await;
             false
         } else if let Some(query) = input.strip_prefix("/gif ") {
             // GIF search command via Klipy
@@ -724,12 +2359,187 @@ impl UiApp {
             }
             self.state
                 .add_system_message(&format!("🔍 Searching GIFs for: {}", query));
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SearchGif {
-                    query: query.to_string(),
+            self.send(NetworkCommand::SearchGif {
+                query: query.to_string(),
+            })
+            .await;
+            false
+        } else if let Some(target) = input.strip_prefix("/forget ") {
+            let target = target.trim();
+            // Forgetting works for peers that already left, given the full id
+            let found_peer = self
+                .state
+                .peers
+                .iter()
+                .find(|p| p.starts_with(target) || self.state.display_nick(p).is_some_and(|n| n == target))
+                .cloned()
+                .or_else(|| target.parse::<libp2p::PeerId>().ok().map(|p| p.to_string()));
+            match found_peer {
+                Some(peer_id) if !target.is_empty() => {
+                    self.send(NetworkCommand::ForgetPeer { peer_id }).await;
+                }
+                _ => {
+                    self.state.warn(&format!(
+                        "Peer '{}' not found. Use a name from the Peers panel or a full peer ID.",
+                        target
+                    ));
+                }
+            }
+            false
+        } else if let Some(args) = input.strip_prefix("/verify ") {
+            let mut parts = args.split_whitespace();
+            let target = parts.next().unwrap_or("");
+            let confirm = parts.next() == Some("confirm");
+            let found_peer = self
+                .state
+                .peers
+                .iter()
+                .find(|p| p.starts_with(target) || self.state.display_nick(p).is_some_and(|n| n == target))
+                .cloned();
+            match found_peer {
+                Some(peer_id) if !target.is_empty() => {
+                    if confirm {
+                        self.send(NetworkCommand::VerifyPeer { peer_id }).await;
+                    } else {
+                        self.send(NetworkCommand::ShowSafetyNumber { peer_id })
+                            .await;
+                    }
+                }
+                _ => {
+                    self.state.warn(&format!("Peer '{}' not found. Usage: /verify <peer> [confirm]", target));
+                }
+            }
+            false
+        } else if let Some(target) = input.strip_prefix("/ping ") {
+            let target = target.trim();
+            let found_peer = self
+                .state
+                .peers
+                .iter()
+                .find(|p| p.starts_with(target) || self.state.display_nick(p).is_some_and(|n| n == target))
+                .cloned();
+            match found_peer {
+                Some(peer_id) if !target.is_empty() => {
+                    self.state.add_system_message(&format!(
+                        "🏓 Pinging {}… (result on the next ping cycle)",
+                        Self::short_id(&peer_id, 8)
+                    ));
+                    self.send(NetworkCommand::Ping { peer_id }).await;
+                }
+                _ => {
+                    self.state.warn(&format!(
+                        "Peer '{}' not found. Check the Peers panel.",
+                        target
+                    ));
+                }
+            }
+            false
+        } else if input == "/version" {
+            self.state.info(&crate::network::version_string());
+            let mut peers: Vec<_> = self
+                .state
+                .peer_versions
+                .iter()
+                .map(|(id, v)| (id.clone(), v.clone()))
+                .collect();
+            peers.sort();
+            if peers.is_empty() {
+                self.state.info("  No peers have reported a version yet");
+            }
+            for (peer_id, (agent, protocol)) in peers {
+                let line = format!("  {}: {} ({})", Self::short_id(&peer_id, 8), agent, protocol);
+                if protocol == crate::network::PROTOCOL_VERSION {
+                    self.state.info(&line);
+                } else {
+                    self.state.warn(&format!("{} — protocol differs from ours", line));
+                }
+            }
+            false
+        } else if input == "/members" {
+            if self.state.active_room.is_none() {
+                self.state.info(
+                    "Select a room in the Rooms panel (Tab to it, then Enter) to see its members",
+                );
+            } else {
+                self.state.show_members = !self.state.show_members;
+            }
+            false
+        } else if let Some(msg) = input.strip_prefix("/lobby ") {
+            let msg = msg.trim();
+            if !msg.is_empty() {
+                let nick = self.state.nick.clone();
+                self.state
+                    .add_chat_message(&nick, &format!("[lobby] {}", msg));
+                self.send(NetworkCommand::SendLobby {
+                    data: msg.as_bytes().to_vec(),
                 })
                 .await;
+            }
+            false
+        } else if let Some(rest) = input.strip_prefix("/announce ") {
+            let (general, text) = match rest.trim().strip_prefix("--all ") {
+                Some(text) => (true, text.trim()),
+                None => (false, rest.trim()),
+            };
+            if text.is_empty() {
+                self.state.info("Usage: /announce [--all] <text>");
+            } else if self.state.rooms.is_empty() && !general {
+                self.state
+                    .warn("You haven't joined any rooms. Use /announce --all to post to everyone.");
+            } else {
+                for cmd in self.state.announce(text) {
+                    self.send(cmd).await;
+                }
+                if general {
+                    let id = self.state.add_outgoing_message(text);
+                    let nick = self.state.nick.clone();
+                    self.send(NetworkCommand::SendChat {
+                        id,
+                        data: text.as_bytes().to_vec(),
+                        nick,
+                    })
+                    .await;
+                }
+            }
+            false
+        } else if input == "/reload" {
+            self.reload_config().await;
+            false
+        } else if input == "/status" || input.starts_with("/status ") {
+            let text = format::sanitize_status(input["/status".len()..].trim());
+            if text.is_empty() {
+                match &self.state.status {
+                    Some(status) => self.state.info(&format!("Your status: {} (/status clear to remove)", status)),
+                    None => self.state.info("Usage: /status <text> | /status clear"),
+                }
+                return false;
+            }
+            let requested = input["/status".len()..].trim();
+            if requested.chars().count() > crate::network::MAX_STATUS_CHARS {
+                self.state.warn(&format!(
+                    "Status is too long (max {} characters)",
+                    crate::network::MAX_STATUS_CHARS
+                ));
+                return false;
+            }
+            let status = (text != "clear").then_some(text);
+            self.state.info(&match &status {
+                Some(status) => format!("💬 Status set: {}", status),
+                None => "💬 Status cleared".to_string(),
+            });
+            self.state.status = status.clone();
+            self.send(NetworkCommand::SetStatus { text: status }).await;
+            false
+        } else if input == "/stats" {
+            self.send(NetworkCommand::GetStats).await;
+            false
+        } else if input == "/dnd" {
+            self.state.dnd = !self.state.dnd;
+            self.state.add_system_message(if self.state.dnd {
+                "🔕 Do not disturb on — notifications suppressed"
+            } else {
+                "🔔 Do not disturb off"
+            });
             false
         } else if let Some(room_cmd) = input.strip_prefix("/room ") {
             self.handle_room_command(room_cmd.trim()).await;
@@ -759,6 +2569,48 @@ impl UiApp {
         } else if input == "/wallet" || input == "/chips" {
             self.handle_wallet_command().await;
             false
+        } else if input == "/dm" || input.starts_with("/dm ") {
+            let rest = input["/dm".len()..].trim();
+            let (target, text) = rest.split_once(' ').unwrap_or((rest, ""));
+            if target.is_empty() {
+                self.state.dm_view = None;
+                self.state.info("Usage: /dm <peer> [message]");
+                return false;
+            }
+            let found_peer = self
+                .state
+                .peers
+                .iter()
+                .find(|p| p.starts_with(target) || self.state.display_nick(p).is_some_and(|n| n == target))
+                .cloned();
+            let Some(peer_id) = found_peer else {
+                self.state.warn(&format!("Peer '{}' not found. Check the Peers panel.", target));
+                return false;
+            };
+            let name = self
+                .state
+                .display_nick(&peer_id)
+                .unwrap_or_else(|| Self::short_id(&peer_id, 8));
+            let unread = self.state.dm_unread.get(&peer_id).copied().unwrap_or(0);
+            self.state.open_dm(&peer_id);
+            let text = text.trim();
+            if text.is_empty() {
+                self.state.info(&format!(
+                    "✉ Reading direct messages from {} ({} unread)",
+                    name, unread
+                ));
+            } else {
+                let message_id = self
+                    .state
+                    .add_outgoing_dm(&format!("{}->{}", self.state.nick.clone(), name), text);
+                self.send(NetworkCommand::SendToPeer {
+                    peer_id,
+                    data: text.as_bytes().to_vec(),
+                    message_id: Some(message_id),
+                })
+                .await;
+            }
+            false
         } else if let Some(rest) = input
             .strip_prefix("/whisper ")
             .or_else(|| input.strip_prefix("/w "))
@@ -781,7 +2633,7 @@ impl UiApp {
                 })
                 .cloned();
             if let Some(peer_id) = found_peer {
-                self.state.add_chat_message(
+                let message_id = self.state.add_outgoing_dm(
                     &format!(
                         "{}->{}",
                         self.state.nick.clone(),
@@ -791,15 +2643,14 @@ impl UiApp {
                 );
                 let whisper_payload =
                     format!("[whisper from {}] {}", self.state.nick, msg);
-                let _ = self
-                    .command_sender
-                    .send(NetworkCommand::SendToPeer {
-                        peer_id,
-                        data: whisper_payload.into_bytes(),
-                    })
-                    .await;
+                self.send(NetworkCommand::SendToPeer {
+                    peer_id,
+                    data: whisper_payload.into_bytes(),
+                    message_id: Some(message_id),
+                })
+                .await;
             } else {
-                self.state.add_system_message(&format!(
+                self.state.warn(&format!(
                     "Peer '{}' not found. Check the Peers panel.",
                     target
                 ));
@@ -811,36 +2662,149 @@ impl UiApp {
         } else if input == "/history" || input.starts_with("/history ") {
             self.handle_history_command().await;
             false
+        } else if let Some(name) = Self::command_name(&input) {
+            if commands::canonical(name).is_some() {
+                // Known command with missing or malformed arguments
+                self.show_command_help(name);
+            } else if let Some(suggestion) = commands::suggest(name) {
+                self.state.warn(&format!(
+                    "Unknown command `/{}`, did you mean `/{}`?",
+                    name, suggestion
+                ));
+            } else {
+                self.state.warn(&format!(
+                    "Unknown command `/{}`. Type /help for a list of commands.",
+                    name
+                ));
+            }
+            false
         } else {
             // Regular chat message
-            self.state
-                .add_chat_message(&self.state.nick.clone(), &input);
+            let id = self.state.add_outgoing_message(&input);
             let nick = self.state.nick.clone();
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::Broadcast {
-                    data: input.into_bytes(),
-                    nick,
-                })
-                .await;
+            self.send(NetworkCommand::SendChat {
+                id,
+                data: input.into_bytes(),
+                nick,
+            })
+            .await;
             false
         }
     }
 
+    /// Report skipped paths, then hand the batch to the network
+    async fn send_batch(&mut self, batch: FileBatch, caption: Option<&str>) {
+        for (path, reason) in &batch.skipped {
+            self.state
+                .warn(&format!("Skipping {}: {}", path.display(), reason));
+        }
+        if batch.files.is_empty() {
+            self.state.warn("Nothing to send");
+            return;
+        }
+        self.state.add_system_message(&format!(
+            "📦 Sending {} files ({})",
+            batch.files.len(),
+            batch.id
+        ));
+        self.send(NetworkCommand::SendBatch {
+            batch,
+            caption: caption.map(str::to_string),
+        })
+        .await;
+    }
+
+    /// Split `<path> :: <caption>` into the path and an optional caption
+    fn split_caption(arg: &str) -> (&str, Option<&str>) {
+        match arg.split_once("::") {
+            Some((path, caption)) => {
+                let caption = caption.trim();
+                (path.trim(), (!caption.is_empty()).then_some(caption))
+            }
+            None => (arg.trim(), None),
+        }
+    }
+
+    /// The command word of a slash-command input, e.g. `room` for `/room list`
+    fn command_name(input: &str) -> Option<&str> {
+        let word = input.strip_prefix('/')?.split_whitespace().next()?;
+        word.chars()
+            .all(|c| c.is_ascii_alphabetic())
+            .then_some(word)
+    }
+
+    /// Print the usage lines of a single command
+    fn show_command_help(&mut self, name: &str) {
+        let usage = commands::usage_of(name);
+        if usage.is_empty() {
+            let hint = commands::suggest(name)
+                .map(|s| format!(" Did you mean `/{}`?", s))
+                .unwrap_or_default();
+            self.state.add_system_message(&format!(
+                "No help for `/{}`.{}",
+                name.trim_start_matches('/'),
+                hint
+            ));
+            return;
+        }
+        for spec in usage {
+            self.state.add_system_message(&commands::format_usage(spec));
+        }
+    }
+
+    /// Whether the input is a partial command name that Tab can complete
+    fn is_completable(input: &str) -> bool {
+        input.starts_with('/') && !input.contains(' ')
+    }
+
+    /// Tab-complete a partially typed command name
+    fn complete_command(&mut self) {
+        let input = self.state.input.clone();
+        if !Self::is_completable(&input) {
+            return;
+        }
+        let matches = commands::complete(&input);
+        match matches.as_slice() {
+            [] => {}
+            [only] => {
+                self.state.input = format!("/{} ", only);
+                self.state.cursor_pos = self.state.input.len();
+            }
+            many => {
+                // Extend to the longest common prefix, then list candidates
+                let first = many[0];
+                let common = (0..first.len())
+                    .rev()
+                    .map(|n| &first[..=n])
+                    .find(|p| many.iter().all(|m| m.starts_with(p)))
+                    .unwrap_or("");
+                if common.len() + 1 > input.len() {
+                    self.state.input = format!("/{}", common);
+                    self.state.cursor_pos = self.state.input.len();
+                }
+                let list: Vec<String> = many.iter().map(|m| format!("/{}", m)).collect();
+                self.state.add_system_message(&list.join("  "));
+            }
+        }
+    }
+
     /// Handle room commands
     async fn handle_room_command(&mut self, cmd: &str) {
-        if let Some(name) = cmd.strip_prefix("create ") {
-            let name = name.trim();
+        if let Some(args) = cmd.strip_prefix("create ") {
+            // --closed: only we can invite; --listed: found by /room join-name
+            let flag = |name: &str| args.split_whitespace().any(|word| word == name);
+            let name = args
+                .split_whitespace()
+                .filter(|word| !matches!(*word, "--closed" | "--listed"))
+                .collect::<Vec<_>>()
+                .join(" ");
             if name.is_empty() {
-                self.state.add_system_message("Usage: /room create <name>");
+                self.state.add_system_message("Usage: /room create <name> [--closed] [--listed]");
                 return;
             }
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::CreateRoom {
-                    name: name.to_string(),
-                })
-                .await;
+            let mut policy = if flag("--closed") { RoomPolicy::closed() } else { RoomPolicy::default() };
+            policy.listed = flag("--listed");
+            self.send(NetworkCommand::CreateRoom { name, policy }).await;
         } else if let Some(args) = cmd.strip_prefix("invite ") {
             let parts: Vec<&str> = args.split_whitespace().collect();
             if parts.len() < 2 {
@@ -866,30 +2830,122 @@ impl UiApp {
                     "🏠 Inviting {} to room {}",
                     short_peer_id, room_id
                 ));
-                let _ = self
-                    .command_sender
-                    .send(NetworkCommand::InviteToRoom { room_id, peer_id })
+                self.send(NetworkCommand::InviteToRoom { room_id, peer_id })
                     .await;
             } else {
-                self.state.add_system_message(&format!(
-                    "⚠ Peer '{}' not found. Check the Peers panel.",
+                self.state.warn(&format!(
+                    "Peer '{}' not found. Check the Peers panel.",
                     short_peer_id
                 ));
             }
+        } else if let Some(args) = cmd.strip_prefix("meet ") {
+            let Some((name, passphrase)) = args.trim().split_once(' ') else {
+                self.state
+                    .add_system_message("Usage: /room meet <name> <passphrase>");
+                return;
+            };
+            self.send(NetworkCommand::JoinWellKnownRoom {
+                name: name.to_string(),
+                passphrase: passphrase.trim().to_string(),
+            })
+            .await;
         } else if cmd == "list" {
-            let _ = self.command_sender.send(NetworkCommand::ListRooms).await;
+            self.send(NetworkCommand::ListRooms).await;
+        } else if cmd == "pending" {
+            self.send(NetworkCommand::ListPendingInvites).await;
+        } else if let Some(room_id) = cmd.strip_prefix("accept ") {
+            let room_id = room_id.trim();
+            if room_id.is_empty() {
+                self.state.add_system_message("Usage: /room accept <room_id>");
+                return;
+            }
+            self.send(NetworkCommand::AcceptInvite {
+                room_id: room_id.to_string(),
+            })
+            .await;
+        } else if let Some(room) = cmd
+            .strip_prefix("members")
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+        {
+            let room = room.trim();
+            let room_id = match (room.is_empty(), &self.state.active_room) {
+                (true, Some(active)) => active.clone(),
+                (true, None) => {
+                    self.state.add_system_message("Usage: /room members <room_id>");
+                    return;
+                }
+                (false, _) => self
+                    .state
+                    .find_room_id(room)
+                    .unwrap_or_else(|| room.to_string()),
+            };
+            self.send(NetworkCommand::ListRoomMembers { room_id }).await;
+        } else if let Some(args) = cmd
+            .strip_prefix("history")
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+        {
+            self.state.show_room_history(args.trim());
+        } else if let Some(name) = cmd.strip_prefix("join-name ") {
+            let name = name.trim();
+            if name.is_empty() {
+                self.state.add_system_message("Usage: /room join-name <name>");
+                return;
+            }
+            self.send(NetworkCommand::JoinRoomByName {
+                name: name.to_string(),
+            })
+            .await;
         } else if let Some(room_id) = cmd.strip_prefix("join ") {
             let room_id = room_id.trim();
             if room_id.is_empty() {
                 self.state.add_system_message("Usage: /room join <room_id>");
                 return;
             }
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::JoinRoom {
-                    room_id: room_id.to_string(),
-                })
-                .await;
+            self.send(NetworkCommand::JoinRoom {
+                room_id: room_id.to_string(),
+            })
+            .await;
+        } else if let Some(room) = cmd.strip_prefix("key ") {
+            let room = room.trim();
+            if room.is_empty() {
+                self.state.add_system_message("Usage: /room key <room_id>");
+                return;
+            }
+            let room_id = self
+                .state
+                .find_room_id(room)
+                .unwrap_or_else(|| room.to_string());
+            self.send(NetworkCommand::RequestRoomKey { room_id }).await;
+        } else if let Some(room_id) = cmd.strip_prefix("mute ") {
+            self.set_room_muted(room_id.trim(), true);
+        } else if let Some(room_id) = cmd.strip_prefix("unmute ") {
+            self.set_room_muted(room_id.trim(), false);
+        } else if let Some(args) = cmd.strip_prefix("kick ") {
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            if parts.len() < 2 {
+                self.state
+                    .add_system_message("Usage: /room kick <peer_id> <room_id>");
+                return;
+            }
+            let (short_peer_id, room_id) = (parts[0], parts[1].to_string());
+            let full_peer_id = self
+                .state
+                .room_members
+                .get(&room_id)
+                .into_iter()
+                .flatten()
+                .chain(self.state.peers.iter())
+                .find(|p| p.starts_with(short_peer_id))
+                .cloned();
+            if let Some(peer_id) = full_peer_id {
+                self.send(NetworkCommand::KickFromRoom { room_id, peer_id })
+                    .await;
+            } else {
+                self.state.warn(&format!(
+                    "Peer '{}' not found in room {}.",
+                    short_peer_id, room_id
+                ));
+            }
         } else if let Some(room_id) = cmd.strip_prefix("leave ") {
             let room_id = room_id.trim();
             if room_id.is_empty() {
@@ -897,17 +2953,39 @@ impl UiApp {
                     .add_system_message("Usage: /room leave <room_id>");
                 return;
             }
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::LeaveRoom {
-                    room_id: room_id.to_string(),
-                })
-                .await;
+            self.send(NetworkCommand::LeaveRoom {
+                room_id: room_id.to_string(),
+            })
+            .await;
+            self.state.remove_room(room_id);
             self.state
                 .add_system_message(&format!("🏠 Left room: {}", room_id));
         } else {
             self.state
-                .add_system_message("Room commands: create, invite, join, list, leave");
+                .add_system_message("Room commands: create, meet, invite, kick, join, list, members, pending, accept, leave, mute, unmute");
+        }
+    }
+
+    fn set_room_muted(&mut self, room_id: &str, muted: bool) {
+        if room_id.is_empty() {
+            self.state
+                .add_system_message("Usage: /room mute <room_id> | /room unmute <room_id>");
+            return;
+        }
+        let Some(room_id) = self.state.find_room_id(room_id) else {
+            self.state.warn(&format!(
+                "Room '{}' not found. Use /room list to see your rooms.",
+                room_id
+            ));
+            return;
+        };
+        if muted {
+            self.state.unread.remove(&room_id);
+            self.state.muted.insert(room_id.clone());
+            self.state.add_system_message(&format!("🔇 Muted room {}", room_id));
+        } else {
+            self.state.muted.remove(&room_id);
+            self.state.add_system_message(&format!("🔔 Unmuted room {}", room_id));
         }
     }
 
@@ -949,12 +3027,7 @@ impl UiApp {
                     challenger_nick: self.state.nick.clone(),
                     room_id: room_id.clone(),
                 };
-                let _ = self
-                    .command_sender
-                    .send(NetworkCommand::SendRoomMessage {
-                        room_id,
-                        data: action.to_bytes(),
-                    })
+                self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                     .await;
             } else {
                 self.state
@@ -982,13 +3055,9 @@ impl UiApp {
             challenger_nick: self.state.nick.clone(),
             room_id: room_id.to_string(),
         };
-        let _ = self
-            .command_sender
-            .send(NetworkCommand::SendRoomMessage {
-                room_id: room_id.to_string(),
-                data: action.to_bytes(),
-            })
+        self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
             .await;
+        self.state.challenge_sent(room_id, std::time::Instant::now());
     }
 
     /// Handle /move command
@@ -1019,7 +3088,7 @@ impl UiApp {
             };
 
             if let Some(err) = turn_err {
-                self.state.add_system_message(&err);
+                self.state.warn(&err);
                 return;
             }
 
@@ -1031,7 +3100,7 @@ impl UiApp {
                     (game.room_id.clone(), lines)
                 }
                 Err(e) => {
-                    self.state.add_system_message(&format!("⚠ {}", e));
+                    self.state.warn(&e.to_string());
                     return;
                 }
             }
@@ -1048,12 +3117,7 @@ impl UiApp {
             room_id: room_id.clone(),
             player: self.state.local_peer_id.clone(),
         };
-        let _ = self
-            .command_sender
-            .send(NetworkCommand::SendRoomMessage {
-                room_id,
-                data: action.to_bytes(),
-            })
+        self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
             .await;
     }
 
@@ -1095,12 +3159,7 @@ impl UiApp {
                 host: self.state.local_peer_id.clone(),
                 host_nick: self.state.nick.clone(),
             };
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SendRoomMessage {
-                    room_id,
-                    data: action.to_bytes(),
-                })
+            self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                 .await;
             return;
         }
@@ -1130,13 +3189,12 @@ impl UiApp {
                 return;
             }
             self.state.wallet = wallet;
-            if let Some(ref mut game) = self.state.blackjack_game {
-                if let Err(e) = game.place_bet(&self.state.local_peer_id, amount) {
-                    // Refund wallet on invalid bet
-                    self.state.wallet.credit(amount);
-                    self.state.add_system_message(&format!("Can't bet: {}", e));
-                    return;
-                }
+            if let Some(ref mut game) = self.state.blackjack_game
+                && let Err(e) = game.place_bet(&self.state.local_peer_id, amount) {
+                // Refund wallet on invalid bet
+                self.state.wallet.credit(amount);
+                self.state.warn(&format!("Can't bet: {}", e));
+                return;
             }
             self.state
                 .add_system_message(&format!("Bet placed: ${}. Balance: {}", amount, self.state.wallet.balance));
@@ -1217,20 +3275,19 @@ impl UiApp {
                 let mut wallet = self.state.wallet.clone();
                 wallet.refresh_if_needed();
                 if let Err(e) = wallet.debit(extra_bet) {
-                    self.state.add_system_message(&format!("Can't double: {}", e));
+                    self.state.warn(&format!("Can't double: {}", e));
                     return;
                 }
                 self.state.wallet = wallet;
             }
-            if let Some(ref mut game) = self.state.blackjack_game {
-                if let Err(e) = game.double_down(&self.state.local_peer_id) {
-                    // Refund the wallet on failure
-                    if extra_bet > 0 {
-                        self.state.wallet.credit(extra_bet);
-                    }
-                    self.state.add_system_message(&format!("Can't double: {}", e));
-                    return;
+            if let Some(ref mut game) = self.state.blackjack_game
+                && let Err(e) = game.double_down(&self.state.local_peer_id) {
+                // Refund the wallet on failure
+                if extra_bet > 0 {
+                    self.state.wallet.credit(extra_bet);
                 }
+                self.state.warn(&format!("Can't double: {}", e));
+                return;
             }
             self.render_blackjack();
             self.broadcast_bj_state().await;
@@ -1256,20 +3313,19 @@ impl UiApp {
                 let mut wallet = self.state.wallet.clone();
                 wallet.refresh_if_needed();
                 if let Err(e) = wallet.debit(extra_bet) {
-                    self.state.add_system_message(&format!("Can't split: {}", e));
+                    self.state.warn(&format!("Can't split: {}", e));
                     return;
                 }
                 self.state.wallet = wallet;
             }
-            if let Some(ref mut game) = self.state.blackjack_game {
-                if let Err(e) = game.split(&self.state.local_peer_id) {
-                    // Refund the wallet on failure
-                    if extra_bet > 0 {
-                        self.state.wallet.credit(extra_bet);
-                    }
-                    self.state.add_system_message(&format!("Can't split: {}", e));
-                    return;
+            if let Some(ref mut game) = self.state.blackjack_game
+                && let Err(e) = game.split(&self.state.local_peer_id) {
+                // Refund the wallet on failure
+                if extra_bet > 0 {
+                    self.state.wallet.credit(extra_bet);
                 }
+                self.state.warn(&format!("Can't split: {}", e));
+                return;
             }
             self.render_blackjack();
             self.broadcast_bj_state().await;
@@ -1281,23 +3337,22 @@ impl UiApp {
                 .map(|p| p.bet / 2)
                 .unwrap_or(0);
             if ins_cost == 0 {
-                self.state.add_system_message("Can't buy insurance: no bet placed.");
+                self.state.warn("Can't buy insurance: no bet placed.");
                 return;
             }
             let mut wallet = self.state.wallet.clone();
             wallet.refresh_if_needed();
             if let Err(e) = wallet.debit(ins_cost) {
-                self.state.add_system_message(&format!("Can't buy insurance: {}", e));
+                self.state.warn(&format!("Can't buy insurance: {}", e));
                 return;
             }
             self.state.wallet = wallet;
-            if let Some(ref mut game) = self.state.blackjack_game {
-                if let Err(e) = game.buy_insurance(&self.state.local_peer_id) {
-                    // Refund on failure
-                    self.state.wallet.credit(ins_cost);
-                    self.state.add_system_message(&format!("Insurance: {}", e));
-                    return;
-                }
+            if let Some(ref mut game) = self.state.blackjack_game
+                && let Err(e) = game.buy_insurance(&self.state.local_peer_id) {
+                // Refund on failure
+                self.state.wallet.credit(ins_cost);
+                self.state.add_system_message(&format!("Insurance: {}", e));
+                return;
             }
             self.state.add_system_message(&format!(
                 "Insurance bought for ${}. Balance: {}", ins_cost, self.state.wallet.balance
@@ -1391,12 +3446,7 @@ impl UiApp {
             let room_id = game.room_id.clone();
             let state_json = serde_json::to_string(game).unwrap_or_default();
             let action = BlackjackAction::State { state_json };
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SendRoomMessage {
-                    room_id,
-                    data: action.to_bytes(),
-                })
+            self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                 .await;
         }
     }
@@ -1405,12 +3455,7 @@ impl UiApp {
         if let Some(ref game) = self.state.blackjack_game {
             let state_json = serde_json::to_string(game).unwrap_or_default();
             let action = BlackjackAction::State { state_json };
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SendRoomMessage {
-                    room_id: room_id.to_string(),
-                    data: action.to_bytes(),
-                })
+            self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                 .await;
         }
     }
@@ -1472,12 +3517,10 @@ impl UiApp {
                 };
                 let nick = self.state.nick.clone();
                 // We can't await here (non-async fn), so use try_send
-                let _ = self
-                    .command_sender
-                    .try_send(NetworkCommand::SendRoomMessage {
-                        room_id: room_id.to_string(),
-                        data: accept.to_bytes(),
-                    });
+                let _ = self.state.try_send_command(
+                    &self.command_sender,
+                    NetworkCommand::room_message(room_id, accept.to_bytes()),
+                );
                 let _ = nick; // suppress warning
             }
             GameAction::Accept {
@@ -1485,7 +3528,13 @@ impl UiApp {
                 accepter_nick,
                 room_id: action_room,
             } => {
-                // Someone accepted our challenge — create the game if we don't have one
+                // Someone accepted our challenge — create the game if we
+                // don't have one. Accepts of expired, declined or already
+                // accepted challenges start nothing.
+                if !self.state.challenge_answered(&action_room) {
+                    tracing::debug!("Ignoring {}'s accept: no open challenge in {}", accepter_nick, action_room);
+                    return;
+                }
                 if self.state.active_game.is_none() {
                     let game = TicTacToe::new(
                         (self.state.local_peer_id.clone(), self.state.nick.clone()),
@@ -1521,8 +3570,8 @@ impl UiApp {
                             }
                         }
                         Err(e) => {
-                            self.state.add_system_message(&format!(
-                                "⚠ Invalid move from {}: {}",
+                            self.state.warn(&format!(
+                                "Invalid move from {}: {}",
                                 sender_nick, e
                             ));
                         }
@@ -1537,9 +3586,16 @@ impl UiApp {
                     .add_system_message(&format!("🏳️ {} resigned!", sender_nick));
                 self.state.active_game = None;
             }
-            GameAction::Decline { .. } => {
-                self.state
-                    .add_system_message(&format!("{} declined the game.", sender_nick));
+            GameAction::Decline { room_id: action_room } => {
+                if self.state.challenge_answered(&action_room) {
+                    self.state.add_system_message(&format!(
+                        "{} declined your challenge — no game started.",
+                        sender_nick
+                    ));
+                } else {
+                    self.state
+                        .add_system_message(&format!("{} declined the game.", sender_nick));
+                }
             }
         }
     }
@@ -1611,9 +3667,21 @@ impl UiApp {
     /// Handle incoming network events
     fn handle_network_event(&mut self, event: NetworkEvent) {
         match event {
-            NetworkEvent::MessageReceived { from, data, .. } => {
+            NetworkEvent::MessageReceived { from, topic, data } => {
+                // Our own sends loop back for the bridges; they're already shown
+                if from.to_string() == self.state.local_peer_id {
+                    return;
+                }
                 let content = String::from_utf8_lossy(&data).to_string();
-                let short = Self::short_id(&from.to_string(), 8);
+                let short = match self.state.contact_name(&from.to_string()) {
+                    Some(name) => name.to_string(),
+                    None => Self::short_id(&from.to_string(), 8),
+                };
+                if matches!(TopicKind::parse(&topic), Some(TopicKind::Lobby(_))) {
+                    self.state
+                        .add_chat_message(&short, &format!("[lobby] {}", content));
+                    return;
+                }
                 // Handle typing indicator
                 if content.starts_with("TYPING:") {
                     let typer_nick = content
@@ -1640,19 +3708,19 @@ impl UiApp {
                 // Extract nick from [relay:Nick] or [web:Nick] prefix for display
                 let (relay_nick, inner_content) = if content.starts_with("[relay:") {
                     if let Some(bracket_end) = content.find("] ") {
-                        let nick = &content[7..bracket_end]; // skip "[relay:"
+                        let nick = format::sanitize_nick(&content[7..bracket_end]); // skip "[relay:"
                         let rest = &content[bracket_end + 2..];
-                        self.state.peer_nicks.insert(from.to_string(), nick.to_string());
-                        (Some(nick.to_string()), rest.to_string())
+                        self.state.note_nick(&from.to_string(), &nick);
+                        (self.state.display_nick(&from.to_string()), rest.to_string())
                     } else {
                         (None, content.clone())
                     }
                 } else if content.starts_with("[web:") {
                     if let Some(bracket_end) = content.find("] ") {
-                        let nick = &content[5..bracket_end]; // skip "[web:"
+                        let nick = format::sanitize_nick(&content[5..bracket_end]); // skip "[web:"
                         let rest = &content[bracket_end + 2..];
-                        self.state.peer_nicks.insert(from.to_string(), nick.to_string());
-                        (Some(nick.to_string()), rest.to_string())
+                        self.state.note_nick(&from.to_string(), &nick);
+                        (self.state.display_nick(&from.to_string()), rest.to_string())
                     } else {
                         (None, content.clone())
                     }
@@ -1671,19 +3739,18 @@ impl UiApp {
                 match Self::try_parse_web_json(&content) {
                     Some(Some(display)) => {
                         // For AI messages like "[AI:😅 Jethalal] text", extract the AI nick as sender
-                        if display.starts_with("[AI:") {
-                            if let Some(bracket_end) = display.find("] ") {
-                                let ai_nick = &display[4..bracket_end];
-                                let ai_text = &display[bracket_end + 2..];
-                                self.state.add_chat_message(ai_nick, ai_text);
-                                return;
-                            }
+                        if display.starts_with("[AI:")
+                            && let Some(bracket_end) = display.find("] ") {
+                            let ai_nick = &display[4..bracket_end];
+                            let ai_text = &display[bracket_end + 2..];
+                            self.state.add_chat_message(ai_nick, ai_text);
+                            return;
                         }
                         // For [@mention] or [ticker] etc, use relay nick or peer ID
                         let sender = relay_nick.as_deref().unwrap_or(&short);
                         self.state.add_chat_message(sender, &display);
                     }
-                    Some(None) => return, // Internal protocol message — suppress entirely
+                    Some(None) => {} // Internal protocol message — suppress entirely
                     None => {
                         // Check for @mention of our nick
                         let mention_marker = if inner_content
@@ -1695,49 +3762,483 @@ impl UiApp {
                             ""
                         };
                         let sender = relay_nick.as_deref().unwrap_or(&short);
-                        self.state.add_chat_message(sender, &format!("{}{}", mention_marker, inner_content));
+                        let text = format!("{}{}", mention_marker, inner_content);
+                        if self.state.hold_if_unverified(&from.to_string(), HeldMessage::chat(sender, &text)) {
+                            return;
+                        }
+                        self.state.add_chat_message(sender, &text);
+                        if !mention_marker.is_empty() {
+                            self.state.notify();
+                        }
                     }
                 }
             }
-            NetworkEvent::FileReceived { from, filename, .. } => {
+            NetworkEvent::PayloadReceived {
+                from,
+                content_type,
+                data,
+                ..
+            } => {
+                self.state
+                    .dispatch_payload(&from.to_string(), content_type, &data);
+            }
+            NetworkEvent::FileReceived {
+                from,
+                filename,
+                caption,
+                saved_path,
+                ..
+            } => {
                 let short = Self::short_id(&from.to_string(), 8);
                 self.state.add_file_message(&short, &filename);
+                if let Some(caption) = caption {
+                    self.state.add_chat_message(&short, &caption);
+                }
+                self.state
+                    .success(&format!("File saved to {}", saved_path.display()));
+            }
+            NetworkEvent::FileTransferProgress {
+                transfer_id,
+                filename,
+                bytes_done,
+                bytes_total,
+                direction,
+            } => {
                 self.state
-                    .add_system_message(&format!("File saved to ~/openwire-received/{}", filename));
+                    .transfer_progress(transfer_id, &filename, bytes_done, bytes_total, direction);
+            }
+            NetworkEvent::FileTransferFailed {
+                transfer_id,
+                filename,
+                direction,
+                reason,
+            } => {
+                self.state.transfer_failed(transfer_id, &filename, direction, &reason);
+            }
+            NetworkEvent::BatchProgress {
+                batch_id,
+                sent,
+                total,
+                filename,
+            } => {
+                if sent == total {
+                    self.state
+                        .success(&format!("📦 {}/{} files sent ({})", sent, total, batch_id));
+                } else {
+                    self.state.add_system_message(&format!(
+                        "📦 {}/{} files sent ({}: {})",
+                        sent, total, batch_id, filename
+                    ));
+                }
             }
             NetworkEvent::PeerDiscovered(peer_id) | NetworkEvent::PeerConnected(peer_id) => {
                 let id_str = peer_id.to_string();
                 if !self.state.peers.contains(&id_str) {
                     self.state.peers.push(id_str.clone());
                     let short = Self::short_id(&id_str, 8);
-                    let display = self.state.peer_nicks.get(&id_str)
+                    let display = self.state.display_nick(&id_str)
                         .map(|n| format!("{} ({})", n, short))
                         .unwrap_or_else(|| short);
                     self.state
                         .add_system_message(&format!("Peer joined: {}", display));
                 }
             }
-            NetworkEvent::PeerDisconnected(peer_id) => {
+            NetworkEvent::ConnectionPath { peer_id, path } => {
+                let id_str = peer_id.to_string();
+                if self.state.set_connection_path(&id_str, path) {
+                    let name = self
+                        .state
+                        .display_nick(&id_str)
+                        .unwrap_or_else(|| Self::short_id(&id_str, 8));
+                    match path {
+                        ConnectionPath::Direct => {}
+                        ConnectionPath::Relayed => self.state.info(&format!(
+                            "🔀 Connected to {} through a relay; slower until a direct path is found",
+                            name
+                        )),
+                        ConnectionPath::HolePunched => {
+                            self.state.success(&format!("⚡ Connection to {} is now direct", name))
+                        }
+                    }
+                }
+            }
+            NetworkEvent::PeerDisconnected { peer_id, reason } => {
                 let id_str = peer_id.to_string();
                 self.state.peers.retain(|p| p != &id_str);
+                self.state.peer_versions.remove(&id_str);
+                self.state.peer_paths.remove(&id_str);
+                self.state.peer_status.remove(&id_str);
                 let short = Self::short_id(&id_str, 8);
-                let display = self.state.peer_nicks.remove(&id_str)
+                let display = self.state.forget_nick(&id_str)
                     .map(|n| format!("{} ({})", n, short))
                     .unwrap_or_else(|| short);
+                if reason.is_clean() {
+                    self.state
+                        .add_system_message(&format!("Peer left: {}", display));
+                } else {
+                    self.state.warn(&format!(
+                        "Lost connection to {} ({})",
+                        display, reason
+                    ));
+                }
+            }
+            NetworkEvent::KeyExchangeFailed { peer_id, reason } => {
+                let short = Self::short_id(&peer_id.to_string(), 8);
+                let hint = match reason {
+                    KeyExchangeFailure::ClockSkew { .. } => " — check that both clocks are correct",
+                    KeyExchangeFailure::InvalidSignature(_) => " — possible tampering",
+                    KeyExchangeFailure::WrongPeer { .. } => " — possible impersonation",
+                    KeyExchangeFailure::Malformed(_) => "",
+                };
+                self.state.warn(&format!(
+                    "Couldn't verify keys from {}: {}{}",
+                    short, reason, hint
+                ));
+            }
+            NetworkEvent::KeysExchanged(peer_id) => {
+                let short = Self::short_id(&peer_id.to_string(), 8);
+                self.state
+                    .success(&format!("🔐 Keys exchanged with {}", short));
+            }
+            NetworkEvent::ListenAddress(addr) => {
+                self.state
+                    .add_system_message(&format!("📡 Listening on: {}", addr));
+            }
+            NetworkEvent::PingResult { peer_id, rtt_ms } => {
+                let id_str = peer_id.to_string();
+                let short = Self::short_id(&id_str, 8);
+                let display = self.state.display_nick(&id_str)
+                    .map(|n| format!("{} ({})", n, short))
+                    .unwrap_or(short);
+                self.state
+                    .add_system_message(&format!("🏓 Pong from {}: {} ms", display, rtt_ms));
+            }
+            NetworkEvent::PeerIdentified {
+                peer_id,
+                agent_version,
+                protocol_version,
+            } => {
+                let id_str = peer_id.to_string();
+                // Identify repeats on every connection and periodically
+                if protocol_version != crate::network::PROTOCOL_VERSION
+                    && self.state.version_warned.insert(id_str.clone())
+                {
+                    self.state.warn(&format!(
+                        "{} speaks protocol {} (we speak {}); messages may not interoperate",
+                        Self::short_id(&id_str, 8),
+                        protocol_version,
+                        crate::network::PROTOCOL_VERSION
+                    ));
+                }
+                self.state
+                    .peer_versions
+                    .insert(id_str, (agent_version, protocol_version));
+            }
+            NetworkEvent::Reconnecting { reason } => {
+                self.state
+                    .warn(&format!("Network interrupted ({}) — reconnecting…", reason));
+            }
+            NetworkEvent::Reconnected => {
+                self.state.success("Back online");
+            }
+            NetworkEvent::PeerMuted { peer_id, cooldown } => {
+                let id_str = peer_id.to_string();
+                self.state.warn(&format!(
+                    "🔇 {} is flooding the chat; their messages are hidden for {}s",
+                    self.state
+                        .display_nick(&id_str)
+                        .unwrap_or_else(|| Self::short_id(&id_str, 8)),
+                    cooldown.as_secs()
+                ));
+            }
+            NetworkEvent::MessagesMissed { peer_id, count, .. } => {
+                let id_str = peer_id.to_string();
+                self.state.warn(&format!(
+                    "{} message{} from {} may have been missed",
+                    count,
+                    if count == 1 { "" } else { "s" },
+                    self.state
+                        .display_nick(&id_str)
+                        .unwrap_or_else(|| Self::short_id(&id_str, 8)),
+                ));
+            }
+            NetworkEvent::MessageRejected { from, reason } => {
+                // Warn once per peer; the running total is in /stats
+                let id_str = from.to_string();
+                if self.state.rejected_peers.insert(id_str.clone()) {
+                    self.state.warn(&format!(
+                        "Dropped unverifiable message from {} ({}). Further ones are counted in /stats",
+                        Self::short_id(&id_str, 8),
+                        reason
+                    ));
+                }
+            }
+            NetworkEvent::Stats(stats) => {
+                self.state.info(&format!(
+                    "Connected peers: {} · Rejected messages: {} · Strict auth: {}",
+                    stats.connected_peers,
+                    stats.rejected_messages,
+                    if stats.strict_auth { "on" } else { "off" }
+                ));
+                self.state.info(&format!(
+                    "Sent: {} messages ({} bytes) · Received: {} messages ({} bytes) · Publish errors: {}",
+                    stats.messages_sent,
+                    stats.bytes_sent,
+                    stats.messages_received,
+                    stats.bytes_received,
+                    stats.publish_errors
+                ));
+            }
+            NetworkEvent::RoomMemberLeft { room_id, peer_id } => {
+                if !self.state.muted.contains(&room_id) {
+                    let room = self
+                        .state
+                        .rooms
+                        .iter()
+                        .find(|(id, _)| *id == room_id)
+                        .map(|(_, name)| name.clone())
+                        .unwrap_or_else(|| Self::short_id(&room_id, 8));
+                    self.state.info(&format!(
+                        "👋 {} left {}",
+                        self.state
+                            .display_nick(&peer_id)
+                            .unwrap_or_else(|| Self::short_id(&peer_id, 8)),
+                        room
+                    ));
+                }
+            }
+            NetworkEvent::RoomMemberKicked { room_id, peer_id } => {
+                let room = self
+                    .state
+                    .rooms
+                    .iter()
+                    .find(|(id, _)| *id == room_id)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| Self::short_id(&room_id, 8));
+                if peer_id == self.state.local_peer_id {
+                    self.state.remove_room(&room_id);
+                    self.state.warn(&format!("🚪 The owner removed you from {}", room));
+                } else {
+                    self.state.info(&format!(
+                        "🚪 {} was removed from {}",
+                        self.state
+                            .display_nick(&peer_id)
+                            .unwrap_or_else(|| Self::short_id(&peer_id, 8)),
+                        room
+                    ));
+                }
+            }
+            NetworkEvent::RoomMembersChanged { room_id, members } => {
+                for (peer, nick) in &members {
+                    if let Some(nick) = nick {
+                        self.state.note_nick(peer, nick);
+                    }
+                }
+                self.state
+                    .room_members
+                    .insert(room_id, members.into_iter().map(|(peer, _)| peer).collect());
+            }
+            NetworkEvent::MessageSent { id } => {
+                self.state.set_delivery(id, Delivery::Sent);
+            }
+            NetworkEvent::MessageFailed { id, reason } => {
+                tracing::debug!("Message {} not delivered: {}", id, reason);
+                self.state.set_delivery(id, Delivery::Failed);
+            }
+            NetworkEvent::DirectMessageSent { message_id } => {
+                self.state.set_dm_delivery(&message_id, Delivery::Sent);
+            }
+            NetworkEvent::MessageDelivered { message_id, .. } => {
+                self.state.set_dm_delivery(&message_id, Delivery::Acked);
+            }
+            NetworkEvent::DirectMessageFailed { message_id, reason } => {
+                tracing::debug!("Direct message {} not delivered: {}", message_id, reason);
+                self.state.set_dm_delivery(&message_id, Delivery::Failed);
+            }
+            NetworkEvent::RoomReady { room_id } => {
+                let name = self
+                    .state
+                    .rooms
+                    .iter()
+                    .find(|(id, _)| *id == room_id)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| Self::short_id(&room_id, 8));
+                self.state.success(&format!("🏠 Room {} is ready", name));
+            }
+            NetworkEvent::ConnectFailed {
+                addr,
+                reason,
+                retry_in_secs,
+            } => {
+                let retry = retry_in_secs
+                    .map(|secs| format!(" — retrying in {}s", secs))
+                    .unwrap_or_default();
+                // Transport failures already name the address
+                let detail = if reason.starts_with(&addr) {
+                    reason
+                } else {
+                    format!("{}: {}", addr, reason)
+                };
                 self.state
-                    .add_system_message(&format!("Peer left: {}", display));
+                    .error(&format!("Couldn't connect to {}{}", detail, retry));
+            }
+            NetworkEvent::DirectMessageReceived { from, content } => {
+                let peer_id = from.to_string();
+                let name = self
+                    .state
+                    .display_nick(&peer_id)
+                    .unwrap_or_else(|| Self::short_id(&peer_id, 8));
+                let sender = format!("[DM] {}", name);
+                let text = String::from_utf8_lossy(&content);
+                if self.state.hold_if_unverified(&peer_id, HeldMessage::chat(&sender, &text)) {
+                    return;
+                }
+                self.state.add_chat_message(&sender, &text);
+                self.state.record_dm(&peer_id);
+            }
+            NetworkEvent::PeerForgotten(peer_id) => {
+                self.state.unmark_verified(&peer_id.to_string());
+                self.state.success(&format!(
+                    "Forgot the keys of {}. They'll be exchanged again when the peer reconnects.",
+                    Self::short_id(&peer_id.to_string(), 8)
+                ));
+            }
+            NetworkEvent::IdentitySaved(path) => {
+                self.state.success(&format!(
+                    "🔑 Identity saved to {}. It is loaded on the next start when it's in the data directory.",
+                    path.display()
+                ));
+                self.state.warn("The file holds your private keys; keep it secret.");
+            }
+            NetworkEvent::BundleExported { path, rooms } => {
+                self.state.success(&format!(
+                    "💾 Backed up your keys and {} room(s) to {}. Restore with --import {}",
+                    rooms,
+                    path.display(),
+                    path.display()
+                ));
+                self.state.warn("Anyone with the file and its passphrase can take over your identity.");
+            }
+            NetworkEvent::IdentityInfo {
+                peer_id,
+                signing_public_key,
+                encryption_public_key,
+            } => {
+                self.state.info(&format!("Peer ID:        {}", peer_id));
+                self.state.info(&format!("Signing key:    {}", hex::encode(signing_public_key)));
+                self.state.info(&format!("Encryption key: {}", hex::encode(encryption_public_key)));
+            }
+            NetworkEvent::PeerKeyChanged { peer_id, old_key, new_key } => {
+                let id_str = peer_id.to_string();
+                let name = self
+                    .state
+                    .display_nick(&id_str)
+                    .unwrap_or_else(|| Self::short_id(&id_str, 8));
+                self.state.warn(&format!(
+                    "⚠️  THE KEYS OF {} HAVE CHANGED. Someone may be impersonating them.",
+                    name.to_uppercase()
+                ));
+                self.state.warn(&format!("   Known:   {}", old_key));
+                self.state.warn(&format!("   Offered: {}", new_key));
+                self.state.warn(&format!(
+                    "   Confirm with them out of band, then run /trust {} to accept the new keys",
+                    Self::short_id(&id_str, 8)
+                ));
+                self.state.notify();
+            }
+            NetworkEvent::PeerBlocked { peer_id, blocked } => {
+                let id_str = peer_id.to_string();
+                let name = self
+                    .state
+                    .display_nick(&id_str)
+                    .unwrap_or_else(|| Self::short_id(&id_str, 8));
+                if blocked {
+                    self.state.blocked.insert(id_str);
+                    self.state.success(&format!("🚫 Blocked {}; their messages and invites are dropped", name));
+                } else {
+                    self.state.blocked.remove(&id_str);
+                    self.state.success(&format!("Unblocked {}", name));
+                }
+            }
+            NetworkEvent::ReconnectingKnownPeers { count } => {
+                if count == 0 {
+                    self.state.info("No known peers to reconnect to");
+                } else {
+                    self.state.info(&format!("🔄 Reconnecting to {} known peer(s)…", count));
+                }
+            }
+            NetworkEvent::BlockedPeers(peers) => {
+                self.state.blocked = peers.iter().map(libp2p::PeerId::to_string).collect();
+                if peers.is_empty() {
+                    self.state.info("No blocked peers");
+                }
+                for peer_id in peers {
+                    let id_str = peer_id.to_string();
+                    let name = self.state.display_nick(&id_str).unwrap_or_default();
+                    self.state.info(&format!("🚫 {} {}", id_str, name));
+                }
+            }
+            NetworkEvent::Contacts(contacts) => {
+                self.state.contacts = contacts;
+                if std::mem::take(&mut self.state.contacts_requested) {
+                    for line in self.state.contact_lines() {
+                        self.state.info(&line);
+                    }
+                }
+            }
+            NetworkEvent::Fingerprint(fingerprint) => {
+                self.state.info(&format!("Your fingerprint: {}", fingerprint));
+            }
+            NetworkEvent::SafetyNumber {
+                peer_id,
+                own,
+                theirs,
+                safety_number,
+            } => {
+                let id_str = peer_id.to_string();
+                let name = self
+                    .state
+                    .display_nick(&id_str)
+                    .unwrap_or_else(|| Self::short_id(&id_str, 8));
+                self.state.info(&format!("Safety number with {}:", name));
+                self.state.info(&format!("  {}", safety_number));
+                self.state.info(&format!("Your fingerprint:  {}", own));
+                self.state.info(&format!("Their fingerprint: {}", theirs));
+                self.state.info(&format!(
+                    "Read these aloud with {} over another channel; if they match, /verify {} confirm",
+                    name, name
+                ));
             }
-            NetworkEvent::KeysExchanged(peer_id) => {
-                let short = Self::short_id(&peer_id.to_string(), 8);
-                self.state
-                    .add_system_message(&format!("🔐 Keys exchanged with {}", short));
+            NetworkEvent::PeerVerified(peer_id) => {
+                let peer_id = peer_id.to_string();
+                let held = self.state.held_messages.get(&peer_id).map_or(0, Vec::len);
+                self.state.success(&format!(
+                    "✔ {} verified{}",
+                    Self::short_id(&peer_id, 8),
+                    if held > 0 { format!(", showing {} held message(s)", held) } else { String::new() }
+                ));
+                self.state.mark_verified(&peer_id);
+            }
+            NetworkEvent::PeerUnverified(peer_id) => {
+                let peer_id = peer_id.to_string();
+                if self.state.unmark_verified(&peer_id) {
+                    let short = Self::short_id(&peer_id, 8);
+                    self.state.warn(&format!(
+                        "{} has new keys and is no longer verified — /verify {} to check them",
+                        short, short
+                    ));
+                }
             }
-            NetworkEvent::ListenAddress(addr) => {
-                self.state
-                    .add_system_message(&format!("📡 Listening on: {}", addr));
+            NetworkEvent::Stopped => {
+                self.state.info("Network stopped");
+            }
+            NetworkEvent::PeerStatus { peer_id, status } => {
+                self.state.set_peer_status(&peer_id.to_string(), status.as_deref());
             }
+            // Answers `NetworkHandle::connections` for embedders
+            NetworkEvent::Connections { .. } | NetworkEvent::Rooms { .. } => {}
             NetworkEvent::Error(e) => {
-                self.state.add_system_message(&format!("⚠ Error: {}", e));
+                self.state.error(&e.to_string());
             }
             NetworkEvent::RoomInviteReceived {
                 from,
@@ -1761,14 +4262,21 @@ impl UiApp {
                 self.state.add_system_message(&format!("║ ID: {}", room_id));
                 self.state
                     .add_system_message("║ You have joined this room automatically!");
+                self.state
+                    .add_system_message("║ /room leave declines it; /room accept rejoins");
                 self.state
                     .add_system_message("╚══════════════════════════════════════════╝");
             }
+            NetworkEvent::RoomMessageChanged { from, room_id, change } => {
+                self.state.apply_room_change(&room_id, Some(&from.to_string()), &change);
+            }
             NetworkEvent::RoomMessageReceived {
-                from: _,
+                from,
                 room_id,
                 sender_nick,
                 content,
+                message_id,
+                timestamp,
             } => {
                 // Check if this is a game action
                 if GameAction::is_game_message(&content) {
@@ -1789,18 +4297,126 @@ impl UiApp {
                     }
                 } else {
                     let content_str = String::from_utf8_lossy(&content).to_string();
-                    self.state
-                        .add_chat_message(&format!("[{}] {}", room_id, sender_nick), &content_str);
+                    self.state.note_nick(&from.to_string(), &sender_nick);
+                    let name = self
+                        .state
+                        .display_nick(&from.to_string())
+                        .unwrap_or_else(|| sender_nick.clone());
+                    let sender = format!("[{}] {}", room_id, name);
+                    let origin = message_id.map(|id| RoomOrigin { author: from.to_string(), id });
+                    let held = HeldMessage::room(&room_id, &sender, &content_str, origin.clone(), timestamp);
+                    if self.state.hold_if_unverified(&from.to_string(), held) {
+                        return;
+                    }
+                    self.state.add_room_message(&room_id, &sender, &content_str, origin, timestamp);
+                    self.state.record_room_message(&room_id);
                 }
             }
             NetworkEvent::RoomCreated { room_id, room_name } => {
-                // Add room to UI state
-                self.state.rooms.push((room_id.clone(), room_name.clone()));
-                self.state.add_system_message(&format!(
+                // Add room to UI state. A known id is the confirmation of an
+                // invite sent from that room, not a room we created.
+                if !self.state.rooms.iter().any(|(id, _)| *id == room_id) {
+                    self.state.rooms.push((room_id.clone(), room_name.clone()));
+                    self.state.owned_rooms.insert(room_id.clone());
+                }
+                self.state.success(&format!(
                     "🏠 Room '{}' created! ID: {}",
                     room_name, room_id
                 ));
             }
+            NetworkEvent::RoomInviteSkipped { room_id, peer_id } => {
+                let room = self
+                    .state
+                    .rooms
+                    .iter()
+                    .find(|(id, _)| *id == room_id)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| Self::short_id(&room_id, 8));
+                self.state.info(&format!(
+                    "🏠 {} is already in room {}; no invite sent",
+                    self.state
+                        .display_nick(&peer_id)
+                        .unwrap_or_else(|| Self::short_id(&peer_id, 8)),
+                    room
+                ));
+            }
+            NetworkEvent::RoomJoinRequested { room_id, room_name } => {
+                self.state.info(&format!(
+                    "🏠 Asked the owner of {} ({}) to let you in; you'll join once their invite arrives",
+                    room_name, room_id
+                ));
+            }
+            NetworkEvent::RoomNameMatches { name, rooms } => {
+                self.state.info(&format!(
+                    "🏠 {} rooms are called '{}'. Pick one with /room join-name <room_id>:",
+                    rooms.len(),
+                    name
+                ));
+                for (room_id, room_name, owner) in rooms {
+                    let owner = self
+                        .state
+                        .display_nick(&owner)
+                        .unwrap_or_else(|| Self::short_id(&owner, 8));
+                    self.state.add_system_message(&format!("  {} — {} (owner {})", room_id, room_name, owner));
+                }
+            }
+            NetworkEvent::RoomKeyRequested { room_id } => {
+                self.state.info(&format!(
+                    "🔑 Asked the members of {} to re-send its key. Only members who know you were invited can answer.",
+                    room_id
+                ));
+            }
+            NetworkEvent::PendingInvites { invites } => {
+                if invites.is_empty() {
+                    self.state.info("No pending room invites");
+                }
+                for (room_id, room_name) in invites {
+                    self.state.info(&format!(
+                        "📨 {} ({}) — /room accept {}",
+                        room_name, room_id, room_id
+                    ));
+                }
+            }
+            NetworkEvent::RoomJoined { room_id, room_name } => {
+                if !self.state.rooms.iter().any(|(id, _)| *id == room_id) {
+                    self.state.rooms.push((room_id.clone(), room_name.clone()));
+                }
+                self.state.success(&format!(
+                    "🏠 Joined well-known room '{}' ({})",
+                    room_name, room_id
+                ));
+                self.state.warn(
+                    "Anyone who knows or guesses this name and passphrase can read the room. Use /room create and invites for private conversations.",
+                );
+            }
+            NetworkEvent::RoomMembers { room_id, members } => {
+                let room = self
+                    .state
+                    .rooms
+                    .iter()
+                    .find(|(id, _)| *id == room_id)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| room_id.clone());
+                if members.is_empty() {
+                    self.state
+                        .add_system_message(&format!("👥 {}: no other members seen yet", room));
+                } else {
+                    let names: Vec<String> = members
+                        .iter()
+                        .map(|peer| {
+                            self.state
+                                .display_nick(peer)
+                                .unwrap_or_else(|| Self::short_id(peer, 12))
+                        })
+                        .collect();
+                    self.state.add_system_message(&format!(
+                        "👥 {} ({}): {}",
+                        room,
+                        names.len(),
+                        names.join(", ")
+                    ));
+                }
+            }
             NetworkEvent::RoomList { rooms } => {
                 // Update UI state with rooms
                 self.state.rooms = rooms.clone();
@@ -1817,15 +4433,27 @@ impl UiApp {
             NetworkEvent::ImageReceived {
                 from,
                 filename,
-                data: _,
+                data,
+                caption,
             } => {
                 let short = format!("{}…", &from.to_string()[..8.min(from.to_string().len())]);
                 self.state.add_system_message(&format!(
                     "🖼️ Image '{}' received from {}",
                     filename, short
                 ));
+                if !crate::media::image_support::supports_images() {
+                    // No inline graphics: show a block-character preview
+                    let (width, height) =
+                        crate::media::image_support::get_dimensions(&data).unwrap_or((320, 160));
+                    let art = crate::media::generate_ascii_art(width, height, &data);
+                    self.state.add_chat_message(&short, &format!("```{}```", art));
+                }
+                if let Some(caption) = caption {
+                    self.state.add_chat_message(&short, &caption);
+                }
+                let dir = crate::paths::get().received_dir();
                 self.state
-                    .add_system_message("Saved to ~/openwire-received/");
+                    .add_system_message(&format!("Saved to {}", dir.display()));
             }
             NetworkEvent::GifSearchResult { query, gifs } => {
                 if gifs.is_empty() {
@@ -1866,18 +4494,117 @@ impl UiApp {
         }
     }
 
+    /// Rows of messages visible in a terminal `height` rows tall: the
+    /// message pane minus its borders, above the 3-row input box
+    fn message_pane_height(height: u16) -> usize {
+        height.saturating_sub(3 + 2) as usize
+    }
+
+    /// Wrap `messages` newest first until there are `wanted` rows, so only
+    /// the tail the pane can show is wrapped. Returns the rows and how many
+    /// older messages were left unwrapped.
+    fn wrap_tail(
+        messages: &[&ChatMessage],
+        wanted: usize,
+        pinned: &[MessageId],
+        sender_width: usize,
+        width: usize,
+    ) -> (Vec<Line<'static>>, usize) {
+        let mut wrapped = Vec::new();
+        let mut rows = 0;
+        for m in messages.iter().rev() {
+            if rows >= wanted {
+                break;
+            }
+            let lines = Self::message_lines(m, pinned, sender_width, width);
+            rows += lines.len();
+            wrapped.push(lines);
+        }
+        let unwrapped = messages.len() - wrapped.len();
+        (wrapped.into_iter().rev().flatten().collect(), unwrapped)
+    }
+
+    /// A message as rows at most `width` columns wide
+    fn message_lines(m: &ChatMessage, pinned: &[MessageId], sender_width: usize, width: usize) -> Vec<Line<'static>> {
+        let style = if m.is_system {
+            m.level.style()
+        } else if m.is_file {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::White)
+        };
+
+        let sender_style = if m.is_system {
+            m.level.style().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD)
+        };
+
+        let mut header = vec![
+            Span::styled(
+                format!("[{}] ", m.time),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::styled(
+                format!("{}: ", format::truncate_display(&m.sender, sender_width)),
+                sender_style,
+            ),
+        ];
+
+        if m.preformatted {
+            // Code block: header line, then each code line verbatim
+            // behind a subtle left border
+            let code_style = Style::default().fg(Color::LightGreen).bg(Color::Black);
+            let border_style = Style::default().fg(Color::DarkGray);
+            header.extend(m.delivery.map(|(_, d)| d.marker()));
+            let mut lines = format::wrap_spans(header, width);
+            for l in m.content.lines() {
+                lines.extend(format::wrap_spans(
+                    vec![
+                        Span::styled("  │ ", border_style),
+                        Span::styled(l, code_style),
+                    ],
+                    width,
+                ));
+            }
+            return lines;
+        }
+
+        let mut spans = header;
+        if pinned.contains(&m.id) {
+            spans.push(Span::raw("📌 "));
+        }
+        spans.push(Span::styled(m.content.as_str(), style));
+        spans.extend(m.delivery.map(|(_, d)| d.marker()));
+        format::wrap_spans(spans, width)
+    }
+
     /// Render the current state
     fn render(&mut self) -> Result<()> {
         // Temporarily take the overlay out to avoid borrow conflicts in the draw closure
         let mut overlay = std::mem::replace(&mut self.state.game_overlay, game_ui::GameOverlay::new());
 
-        let nick = self.state.nick.clone();
+        // Sanitized again here so nothing can break the title border
+        let nick = format::sanitize_nick(&self.state.nick);
         let peer_id_short = if self.state.local_peer_id.len() > 8 {
             format!("{}…", &self.state.local_peer_id[..8])
         } else {
             self.state.local_peer_id.clone()
         };
 
+        let focus = self.state.focus;
+        // Focused panel gets a bright, bold border
+        let border = |panel: Focus, color: Color| {
+            if panel == focus {
+                Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(color)
+            }
+        };
+        let highlight = Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD);
+
         self.terminal.draw(|f| {
             let size = f.area();
 
@@ -1895,68 +4622,54 @@ impl UiApp {
 
             // -- Messages Panel --
             let msg_area_height = left_chunks[0].height.saturating_sub(2) as usize; // Subtract borders
+            let msg_area_width = left_chunks[0].width.saturating_sub(2) as usize;
+
+            // Long nicks get cut so the message itself stays visible
+            let sender_width = (left_chunks[0].width as usize / 4).clamp(4, MAX_SENDER_WIDTH);
+            // Older messages count as a row each, which they take at least,
+            // so scrolling up wraps more as it goes
+            let wanted = self.state.scroll_offset + msg_area_height;
+            let (msg_rows, unwrapped) = Self::wrap_tail(
+                &self.state.visible_messages(),
+                wanted,
+                &self.state.pinned,
+                sender_width,
+                msg_area_width,
+            );
+            self.state.message_rows = unwrapped + msg_rows.len();
+            self.state.clamp_scroll(msg_area_height);
 
-            // Calculate which messages to show based on scroll offset
-            let total_messages = self.state.messages.len();
-            let start_idx = if total_messages > msg_area_height {
-                total_messages.saturating_sub(msg_area_height + self.state.scroll_offset)
-            } else {
-                0
-            };
-            let end_idx = total_messages
-                .saturating_sub(self.state.scroll_offset)
-                .min(total_messages);
-
-            let msg_items: Vec<ListItem> = self.state.messages[start_idx..end_idx]
-                .iter()
-                .map(|m| {
-                    let style = if m.is_system {
-                        Style::default().fg(Color::Yellow)
-                    } else if m.is_file {
-                        Style::default().fg(Color::Cyan)
-                    } else {
-                        Style::default().fg(Color::White)
-                    };
-
-                    let sender_style = if m.is_system {
-                        Style::default()
-                            .fg(Color::Yellow)
-                            .add_modifier(Modifier::BOLD)
-                    } else {
-                        Style::default()
-                            .fg(Color::Green)
-                            .add_modifier(Modifier::BOLD)
-                    };
-
-                    ListItem::new(Line::from(vec![
-                        Span::styled(
-                            format!("[{}] ", m.time),
-                            Style::default().fg(Color::DarkGray),
-                        ),
-                        Span::styled(format!("{}: ", m.sender), sender_style),
-                        Span::styled(&m.content, style),
-                    ]))
-                })
-                .collect();
+            // Calculate which rows to show based on scroll offset
+            let total_rows = self.state.message_rows;
+            let end_idx = msg_rows.len().saturating_sub(self.state.scroll_offset);
+            let start_idx = end_idx.saturating_sub(msg_area_height);
 
+            // Name the room being viewed
+            let viewing = self
+                .state
+                .active_room
+                .as_ref()
+                .and_then(|id| self.state.rooms.iter().find(|(rid, _)| rid == id))
+                .map(|(_, name)| format!("🏠 {} — ", name))
+                .unwrap_or_default();
             let messages_block = Block::default()
-                .title(format!(" OpenWire — {} ({}) ", nick, peer_id_short))
+                .title(format!(" {}OpenWire — {} ({}) ", viewing, nick, peer_id_short))
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Blue));
+                .border_style(border(Focus::Messages, Color::Blue));
 
-            let messages = List::new(msg_items).block(messages_block);
+            let messages = Paragraph::new(msg_rows[start_idx..end_idx].to_vec()).block(messages_block);
             f.render_widget(messages, left_chunks[0]);
 
             // Render scrollbar for messages
-            if total_messages > msg_area_height {
+            if total_rows > msg_area_height {
                 let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
                     .begin_symbol(Some("▲"))
                     .end_symbol(Some("▼"))
                     .track_symbol(Some("│"))
                     .thumb_symbol("█");
 
-                let mut scrollbar_state = ScrollbarState::new(total_messages)
-                    .position(total_messages.saturating_sub(end_idx));
+                let mut scrollbar_state = ScrollbarState::new(total_rows)
+                    .position(unwrapped + start_idx);
 
                 f.render_stateful_widget(
                     scrollbar,
@@ -1987,7 +4700,7 @@ impl UiApp {
                     Block::default()
                         .title(" Message ")
                         .borders(Borders::ALL)
-                        .border_style(Style::default().fg(Color::Cyan)),
+                        .border_style(border(Focus::Input, Color::Cyan)),
                 )
                 .wrap(Wrap { trim: false });
             f.render_widget(input, left_chunks[1]);
@@ -1995,17 +4708,36 @@ impl UiApp {
             // Set cursor position
             if !self.state.input.is_empty() {
                 f.set_cursor_position((
-                    left_chunks[1].x + self.state.cursor_pos as u16 + 1,
+                    left_chunks[1].x
+                        + UnicodeWidthStr::width(&self.state.input[..self.state.cursor_pos]) as u16
+                        + 1,
                     left_chunks[1].y + 1,
                 ));
             }
 
-            // -- Right Panel: Peers + Rooms --
+            // -- Right Panel: Peers + Members (when a room is selected) + Rooms --
+            let roster = self
+                .state
+                .active_room
+                .as_ref()
+                .filter(|_| self.state.show_members)
+                .and_then(|id| self.state.rooms.iter().find(|(rid, _)| rid == id))
+                .map(|(id, name)| (name.clone(), self.state.room_roster(id)));
+            let members_height = roster
+                .as_ref()
+                .map_or(0, |(_, r)| (r.len() as u16 + 2).min(10));
             let right_chunks = Layout::default()
                 .direction(Direction::Vertical)
-                .constraints([Constraint::Min(5), Constraint::Length(8)])
+                .constraints([
+                    Constraint::Min(5),
+                    Constraint::Length(members_height),
+                    Constraint::Length(8),
+                ])
                 .split(main_chunks[1]);
 
+            // Columns inside the side panels' borders
+            let side_width = main_chunks[1].width.saturating_sub(2) as usize;
+
             // -- Peers Panel --
             let now_typing = std::time::Instant::now();
             let typing_items: Vec<ListItem> = self
@@ -2017,7 +4749,7 @@ impl UiApp {
                 })
                 .map(|(nick, _)| {
                     ListItem::new(Line::from(vec![Span::styled(
-                        format!("{} is typing...", nick),
+                        format::truncate_display(&format!("{} is typing...", nick), side_width),
                         Style::default().fg(Color::DarkGray),
                     )]))
                 })
@@ -2028,17 +4760,43 @@ impl UiApp {
                 .peers
                 .iter()
                 .map(|p| {
-                    let display = if let Some(nick) = self.state.peer_nicks.get(p) {
-                        nick.clone()
-                    } else if p.len() > 12 {
-                        format!("{}…", &p[..12])
-                    } else {
-                        p.clone()
+                    let display = self
+                        .state
+                        .display_nick(p)
+                        .unwrap_or_else(|| Self::short_id(p, 12));
+                    let unread = self
+                        .state
+                        .dm_unread
+                        .get(p)
+                        .filter(|c| **c > 0)
+                        .map(|count| format!(" ({})", count))
+                        .unwrap_or_default();
+                    let name_width = side_width.saturating_sub(2 + unread.len());
+                    let name = format::truncate_display(&display, name_width);
+                    let status_width = name_width.saturating_sub(name.width() + 3);
+                    let dot = match self.state.peer_paths.get(p) {
+                        Some(ConnectionPath::Relayed) => Color::Yellow,
+                        _ => Color::Green,
                     };
-                    ListItem::new(Line::from(vec![
-                        Span::styled("● ", Style::default().fg(Color::Green)),
-                        Span::styled(display, Style::default().fg(Color::White)),
-                    ]))
+                    let mut spans = vec![
+                        Span::styled("● ", Style::default().fg(dot)),
+                        Span::styled(name, Style::default().fg(Color::White)),
+                    ];
+                    if let Some(status) = self.state.peer_status.get(p)
+                        && status_width > 1
+                    {
+                        spans.push(Span::styled(
+                            format!(" — {}", format::truncate_display(status, status_width)),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    if !unread.is_empty() {
+                        spans.push(Span::styled(
+                            unread,
+                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    ListItem::new(Line::from(spans))
                 })
                 .collect();
             peer_items.extend(typing_items);
@@ -2046,10 +4804,14 @@ impl UiApp {
             let peers_block = Block::default()
                 .title(format!(" Peers ({}) ", self.state.peers.len()))
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Magenta));
+                .border_style(border(Focus::Peers, Color::Magenta));
 
-            let peers = List::new(peer_items).block(peers_block);
-            f.render_widget(peers, right_chunks[0]);
+            let peers = List::new(peer_items).block(peers_block).highlight_style(highlight);
+            let mut peer_list = ListState::default();
+            if focus == Focus::Peers && !self.state.peers.is_empty() {
+                peer_list.select(Some(self.state.peer_selected.min(self.state.peers.len() - 1)));
+            }
+            f.render_stateful_widget(peers, right_chunks[0], &mut peer_list);
 
             // -- Rooms Panel --
             let room_items: Vec<ListItem> = self
@@ -2057,29 +4819,77 @@ impl UiApp {
                 .rooms
                 .iter()
                 .map(|(id, name)| {
-                    let short_id = if id.len() > 8 {
-                        format!("{}…", &id[..8])
-                    } else {
-                        id.clone()
-                    };
-                    ListItem::new(Line::from(vec![
-                        Span::styled("🏠 ", Style::default().fg(Color::Yellow)),
-                        Span::styled(name, Style::default().fg(Color::White)),
-                        Span::styled(
-                            format!(" ({})", short_id),
-                            Style::default().fg(Color::DarkGray),
-                        ),
-                    ]))
+                    let icon = if self.state.muted.contains(id) { "🔇 " } else { "🏠 " };
+                    let unread = self
+                        .state
+                        .unread
+                        .get(id)
+                        .filter(|c| **c > 0)
+                        .map(|count| format!(" {}", count))
+                        .unwrap_or_default();
+                    // The name gets the room it needs; the short id only
+                    // shows if there's space left after it
+                    let name_width = side_width.saturating_sub(3 + unread.len());
+                    let name = format::truncate_display(name, name_width);
+                    let id_part = format!(" ({})", Self::short_id(id, 8));
+                    let mut spans = vec![
+                        Span::styled(icon, Style::default().fg(Color::Yellow)),
+                        Span::styled(name.clone(), Style::default().fg(Color::White)),
+                    ];
+                    if UnicodeWidthStr::width(name.as_str()) + id_part.chars().count() <= name_width {
+                        spans.push(Span::styled(id_part, Style::default().fg(Color::DarkGray)));
+                    }
+                    if !unread.is_empty() {
+                        spans.push(Span::styled(
+                            unread,
+                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    ListItem::new(Line::from(spans))
                 })
                 .collect();
 
             let rooms_block = Block::default()
                 .title(format!(" Rooms ({}) ", self.state.rooms.len()))
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Yellow));
-
-            let rooms = List::new(room_items).block(rooms_block);
-            f.render_widget(rooms, right_chunks[1]);
+                .border_style(border(Focus::Rooms, Color::Yellow));
+
+            let rooms = List::new(room_items).block(rooms_block).highlight_style(highlight);
+            let mut room_list = ListState::default();
+            if focus == Focus::Rooms && !self.state.rooms.is_empty() {
+                room_list.select(Some(self.state.room_selected.min(self.state.rooms.len() - 1)));
+            }
+            f.render_stateful_widget(rooms, right_chunks[2], &mut room_list);
+
+            // -- Members Panel --
+            if let Some((room_name, roster)) = roster {
+                let member_items: Vec<ListItem> = roster
+                    .iter()
+                    .map(|m| {
+                        let (dot, dot_color) = if m.online {
+                            ("● ", Color::Green)
+                        } else {
+                            ("○ ", Color::DarkGray)
+                        };
+                        let mut spans = vec![Span::styled(dot, Style::default().fg(dot_color))];
+                        let mut name_width = side_width.saturating_sub(2);
+                        if m.owner {
+                            spans.push(Span::styled("👑 ", Style::default().fg(Color::Yellow)));
+                            name_width = name_width.saturating_sub(3);
+                        }
+                        spans.push(Span::styled(
+                            format::truncate_display(&m.name, name_width),
+                            Style::default().fg(Color::White),
+                        ));
+                        ListItem::new(Line::from(spans))
+                    })
+                    .collect();
+                let members_block = Block::default()
+                    .title(format!(" {} ({}) ", room_name, roster.len()))
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::LightBlue));
+                f.render_widget(List::new(member_items).block(members_block), right_chunks[1]);
+            }
 
             // ── Game overlay (renders on top of everything) ─────────
             game_ui::render_game_overlay(f, size, &self.state, &mut overlay);
@@ -2097,14 +4907,13 @@ impl UiApp {
                     // Only update if we are a participant (already have a local game)
                     if self.state.blackjack_game.is_some() {
                         // Preserve local player's bet amount to prevent peer manipulation
-                        if let Some(ref local_game) = self.state.blackjack_game {
-                            if let Some(local_player) = local_game.players.iter()
-                                .find(|p| p.peer_id == self.state.local_peer_id) {
-                                if let Some(remote_player) = game.players.iter_mut()
-                                    .find(|p| p.peer_id == self.state.local_peer_id) {
-                                    remote_player.bet = local_player.bet;
-                                }
-                            }
+                        if let Some(ref local_game) = self.state.blackjack_game
+                            && let Some(local_player) = local_game.players.iter()
+                                .find(|p| p.peer_id == self.state.local_peer_id)
+                            && let Some(remote_player) = game.players.iter_mut()
+                                .find(|p| p.peer_id == self.state.local_peer_id)
+                        {
+                            remote_player.bet = local_player.bet;
                         }
                         self.state.blackjack_game = Some(game);
                         self.render_blackjack();
@@ -2298,12 +5107,7 @@ impl UiApp {
                     amount, self.state.wallet.balance
                 ));
                 let action = RouletteAction::Bet { bet };
-                let _ = self
-                    .command_sender
-                    .send(NetworkCommand::SendRoomMessage {
-                        room_id: rid,
-                        data: action.to_bytes(),
-                    })
+                self.send(NetworkCommand::room_message(rid, action.to_bytes()))
                     .await;
             }
         } else if cmd == "spin" {
@@ -2365,13 +5169,11 @@ impl UiApp {
                             self.state.nick, net
                         );
                         let nick = self.state.nick.clone();
-                        let _ = self
-                            .command_sender
-                            .send(NetworkCommand::Broadcast {
-                                data: ticker.into_bytes(),
-                                nick,
-                            })
-                            .await;
+                        self.send(NetworkCommand::Broadcast {
+                            data: ticker.into_bytes(),
+                            nick,
+                        })
+                        .await;
                     } else if *net == 0 {
                         self.state.add_system_message(&format!(
                             "Push — stake returned. Balance: {}",
@@ -2395,12 +5197,7 @@ impl UiApp {
             // User presses Space again to start a new round (handled in overlay action).
 
             let action = RouletteAction::Spin;
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SendRoomMessage {
-                    room_id,
-                    data: action.to_bytes(),
-                })
+            self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                 .await;
         } else {
             self.state.add_system_message(
@@ -2508,12 +5305,7 @@ impl UiApp {
                     game.place_bet(bet.clone());
                 }
                 let action = AndarBaharAction::Bet { bet };
-                let _ = self
-                    .command_sender
-                    .send(NetworkCommand::SendRoomMessage {
-                        room_id,
-                        data: action.to_bytes(),
-                    })
+                self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                     .await;
             }
         } else if let Some(amount_str) = cmd.strip_prefix("bahar ") {
@@ -2524,12 +5316,7 @@ impl UiApp {
                     game.place_bet(bet.clone());
                 }
                 let action = AndarBaharAction::Bet { bet };
-                let _ = self
-                    .command_sender
-                    .send(NetworkCommand::SendRoomMessage {
-                        room_id,
-                        data: action.to_bytes(),
-                    })
+                self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                     .await;
             }
         } else if cmd == "deal" {
@@ -2594,12 +5381,7 @@ impl UiApp {
             // User presses Space/D for new round.
 
             let action = AndarBaharAction::Deal;
-            let _ = self
-                .command_sender
-                .send(NetworkCommand::SendRoomMessage {
-                    room_id,
-                    data: action.to_bytes(),
-                })
+            self.send(NetworkCommand::room_message(room_id, action.to_bytes()))
                 .await;
         } else if let Some(rest) = cmd.strip_prefix("count ") {
             let parts: Vec<&str> = rest.split_whitespace().collect();
@@ -2754,13 +5536,11 @@ impl UiApp {
                     self.state.nick, payout
                 );
                 let nick = self.state.nick.clone();
-                let _ = self
-                    .command_sender
-                    .send(NetworkCommand::Broadcast {
-                        data: ticker.into_bytes(),
-                        nick,
-                    })
-                    .await;
+                self.send(NetworkCommand::Broadcast {
+                    data: ticker.into_bytes(),
+                    nick,
+                })
+                .await;
             } else {
                 self.state.casino_state.record_payout("slots", payout);
             }
@@ -2939,3 +5719,826 @@ impl Drop for UiApp {
         let _ = self.terminal.show_cursor();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> UiState {
+        let mut state = UiState::new("tester".into(), "local-peer".into(), None, false);
+        state.message_history_path = None;
+        state
+    }
+
+    #[test]
+    fn test_contact_names_replace_ids_and_nicks() {
+        let mut state = test_state();
+        let contact = |nickname: Option<&str>| crate::crypto::Contact {
+            nickname: nickname.map(str::to_string),
+            signing_public_key: [1; 32],
+            encryption_public_key: [2; 32],
+            last_seen: 1_700_000_000,
+        };
+        state.peers.push("12D3KooWAlice".into());
+        state.note_nick("12D3KooWAlice", "al1ce");
+        state.contacts = vec![
+            ("12D3KooWAlice".into(), contact(Some("Alice"))),
+            ("12D3KooWCarol".into(), contact(None)),
+        ];
+
+        assert_eq!(state.display_nick("12D3KooWAlice").as_deref(), Some("Alice"));
+        assert_eq!(state.display_nick("12D3KooWCarol"), None);
+        // Offline contacts can be found to rename
+        assert_eq!(state.find_peer_or_contact("12D3KooWCa").as_deref(), Some("12D3KooWCarol"));
+        assert_eq!(state.find_peer_or_contact("Alice").as_deref(), Some("12D3KooWAlice"));
+        assert_eq!(state.find_peer_or_contact(""), None);
+
+        let lines = state.contact_lines();
+        assert!(lines[0].contains("Alice") && lines[0].contains("online"), "{}", lines[0]);
+        assert!(lines[1].contains("(no name)") && lines[1].contains("last seen"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn test_peer_status_is_sanitized_and_clearable() {
+        let mut state = test_state();
+        state.set_peer_status("peer-a", Some("  brb\x1b[2J\n  coffee  "));
+        assert_eq!(state.peer_status.get("peer-a").map(String::as_str), Some("brb [2J coffee"));
+
+        let long = "z".repeat(crate::network::MAX_STATUS_CHARS + 20);
+        state.set_peer_status("peer-a", Some(&long));
+        assert_eq!(
+            state.peer_status["peer-a"].chars().count(),
+            crate::network::MAX_STATUS_CHARS
+        );
+
+        state.set_peer_status("peer-a", Some("   "));
+        assert!(!state.peer_status.contains_key("peer-a"));
+        state.set_peer_status("peer-b", Some("away"));
+        state.set_peer_status("peer-b", None);
+        assert!(state.peer_status.is_empty());
+    }
+
+    #[test]
+    fn test_binary_payload_goes_to_hooks_not_chat() {
+        use std::sync::{Arc, Mutex};
+
+        let mut state = test_state();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        state.payload_hooks.push(Box::new(move |from, content_type, data| {
+            sink.lock().unwrap().push((from.to_string(), content_type, data.to_vec()));
+        }));
+
+        let bytes = [0xde, 0xad, 0x00, 0xbe, 0xef];
+        let wire = crate::network::envelope::encode(ContentType::Binary, &bytes);
+        let (content_type, body) = crate::network::envelope::decode(&wire);
+        let before = state.messages.len();
+        state.dispatch_payload("12D3KooWBot", content_type, body);
+
+        assert_eq!(state.messages.len(), before);
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![("12D3KooWBot".to_string(), ContentType::Binary, bytes.to_vec())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closed_command_channel_reports_disconnect() {
+        let mut state = test_state();
+        let (sender, receiver) = mpsc::channel(4);
+        state
+            .send_command(&sender, NetworkCommand::GetStats)
+            .await
+            .unwrap();
+        assert!(!state.network_disconnected);
+
+        // The network task exiting drops its receiver
+        drop(receiver);
+        assert!(state.send_command(&sender, NetworkCommand::GetStats).await.is_err());
+        assert!(state.try_send_command(&sender, NetworkCommand::GetStats).is_err());
+        assert!(state.network_disconnected);
+        let notices = state
+            .messages
+            .iter()
+            .filter(|m| m.content.contains("Network disconnected"))
+            .count();
+        assert_eq!(notices, 1);
+    }
+
+    #[test]
+    fn test_unanswered_challenges_time_out() {
+        let mut state = test_state();
+        let start = std::time::Instant::now();
+        state.challenge_sent("room-a", start);
+        state.challenge_sent("room-b", start);
+
+        // A decline clears the challenge so it never times out
+        assert!(state.challenge_answered("room-a"));
+        assert!(!state.challenge_answered("room-a"));
+
+        let before = state.messages.len();
+        state.expire_challenges(start + CHALLENGE_TIMEOUT / 2);
+        assert_eq!(state.messages.len(), before);
+
+        state.expire_challenges(start + CHALLENGE_TIMEOUT);
+        assert_eq!(state.messages.len(), before + 1);
+        assert!(state.messages.last().unwrap().content.contains("room-b"));
+        assert!(!state.challenge_answered("room-b"));
+
+        state.expire_challenges(start + CHALLENGE_TIMEOUT * 2);
+        assert_eq!(state.messages.len(), before + 1);
+    }
+
+    #[test]
+    fn test_reload_applies_nick_and_reports_restart_settings() {
+        let mut state = test_state();
+        let argv = |args: &[&str]| -> Vec<std::ffi::OsString> {
+            std::iter::once("openwire").chain(args.iter().copied()).map(Into::into).collect()
+        };
+        let none = |_: &str| None;
+        let merged = crate::config::merge(argv(&[]), &none, Some("accept_only_verified = true")).unwrap();
+        let mut config = crate::config::Config::new(argv(&[]), merged);
+
+        state.only_verified = true;
+        assert!(state.hold_if_unverified("peer-a", HeldMessage::chat("alice", "held")));
+        let file = "nick = \"Reloaded\"\nport = 4100\naccept_only_verified = false";
+        let reload = config.reload_from(&none, Some(file));
+        state.apply_reload(config.args(), reload);
+        assert_eq!(state.nick, "Reloaded");
+        assert_eq!(state.display_nick(&state.local_peer_id).as_deref(), Some("Reloaded"));
+        assert!(!state.only_verified);
+        assert!(state.messages.iter().any(|m| m.content == "held"));
+        assert!(state.messages.iter().any(|m| m.content.contains("Restart OpenWire to apply: port")));
+
+        let reload = config.reload_from(&none, Some("port = nope"));
+        state.apply_reload(config.args(), reload);
+        assert!(state.messages.last().unwrap().content.starts_with("Config not reloaded"));
+        assert_eq!(state.nick, "Reloaded");
+    }
+
+    #[test]
+    fn test_unverified_messages_held_until_verified() {
+        let mut state = test_state();
+        let chat = HeldMessage::chat;
+        assert!(!state.hold_if_unverified("peer-a", chat("alice", "shown")));
+
+        state.only_verified = true;
+        let before = state.messages.len();
+        assert!(state.hold_if_unverified("peer-a", chat("alice", "first")));
+        let origin = RoomOrigin { author: "peer-a".into(), id: "m1".into() };
+        let held = HeldMessage::room("room-1", "[room-1] alice", "second", Some(origin.clone()), 1_700_000_000);
+        assert!(state.hold_if_unverified("peer-a", held));
+        assert!(!state.hold_if_unverified("local-peer", chat("tester", "mine")));
+        // One notice, no chat lines
+        assert_eq!(state.messages.len(), before + 1);
+        assert!(state.messages.iter().all(|m| m.content != "first"));
+
+        state.mark_verified("peer-a");
+        let shown: Vec<&str> = state.messages[before + 1..].iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(shown, ["first", "second"]);
+        // The room message lands in its room, keeping its origin and time
+        let released = state.messages.iter().find(|m| m.content == "second").unwrap();
+        assert_eq!(released.room.as_deref(), Some("room-1"));
+        assert_eq!(released.origin, Some(origin));
+        assert_eq!(released.timestamp, 1_700_000_000);
+        assert_eq!(state.room_history["room-1"].len(), 1);
+        assert!(!state.hold_if_unverified("peer-a", chat("alice", "third")));
+
+        // Changed keys have to be verified again
+        assert!(state.unmark_verified("peer-a"));
+        assert!(state.hold_if_unverified("peer-a", chat("alice", "fourth")));
+    }
+
+    #[test]
+    fn test_held_messages_are_capped() {
+        let mut state = test_state();
+        state.only_verified = true;
+        for i in 0..MAX_HELD_PER_PEER + 5 {
+            assert!(state.hold_if_unverified("peer-a", HeldMessage::chat("alice", &i.to_string())));
+        }
+        let held = &state.held_messages["peer-a"];
+        assert_eq!(held.len(), MAX_HELD_PER_PEER);
+        assert_eq!(held[0].content, "5");
+
+        for i in 1..MAX_HELD_PEERS + 3 {
+            assert!(state.hold_if_unverified(&format!("peer-{}", i), HeldMessage::chat("bob", "hi")));
+        }
+        assert_eq!(state.held_messages.len(), MAX_HELD_PEERS);
+    }
+
+    #[test]
+    fn test_direct_connection_path_wins() {
+        let mut state = test_state();
+        assert!(state.set_connection_path("peer-a", ConnectionPath::Relayed));
+        assert!(!state.set_connection_path("peer-a", ConnectionPath::Relayed));
+        assert!(state.set_connection_path("peer-a", ConnectionPath::HolePunched));
+        // A second, relayed connection doesn't demote the direct one
+        assert!(!state.set_connection_path("peer-a", ConnectionPath::Relayed));
+        assert_eq!(state.peer_paths["peer-a"], ConnectionPath::HolePunched);
+
+        assert!(state.set_connection_path("peer-b", ConnectionPath::Direct));
+        assert!(!state.set_connection_path("peer-b", ConnectionPath::Relayed));
+    }
+
+    #[test]
+    fn test_transfer_progress_updates_one_line() {
+        let mut state = test_state();
+        let before = state.messages.len();
+        state.transfer_progress(7, "report.pdf", 0, 2048, TransferDirection::Sending);
+        state.transfer_progress(7, "report.pdf", 1024, 2048, TransferDirection::Sending);
+        assert_eq!(state.messages.len(), before + 1);
+        assert_eq!(state.messages[before].content, "📤 Sending report.pdf — 50% (1.0 KB/2.0 KB)");
+
+        state.transfer_progress(7, "report.pdf", 2048, 2048, TransferDirection::Sending);
+        assert_eq!(state.messages.len(), before + 1);
+        assert_eq!(state.messages[before].content, "📤 Sent report.pdf (2.0 KB)");
+        assert_eq!(state.messages[before].level, SystemLevel::Success);
+        assert!(state.transfers.is_empty());
+
+        state.transfer_progress(8, "a.png", 0, 10, TransferDirection::Receiving);
+        state.transfer_failed(8, "a.png", TransferDirection::Receiving, "disk full");
+        assert_eq!(state.messages.len(), before + 2);
+        assert_eq!(state.messages[before + 1].content, "📥 Receiving a.png failed: disk full");
+        assert_eq!(state.messages[before + 1].level, SystemLevel::Warning);
+    }
+
+    #[test]
+    fn test_room_history_drops_oldest_past_limit() {
+        let mut state = test_state();
+        state.rooms = vec![("room-aa".into(), "lobby".into()), ("room-bb".into(), "den".into())];
+        state.room_history_limit = 3;
+        for i in 0..5 {
+            state.add_room_message("room-aa", "[room-aa] bob", &format!("msg {}", i), None, 1_000 + i);
+        }
+        let origin = RoomOrigin { author: "eve".into(), id: "cafe0001".into() };
+        state.add_room_message("room-bb", "[room-bb] eve", "elsewhere", Some(origin.clone()), 900);
+
+        let kept: Vec<&str> = state.room_history_tail("room-aa", 10).iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(kept, ["msg 2", "msg 3", "msg 4"]);
+        assert_eq!(state.room_history_tail("room-aa", 1)[0].content, "msg 4");
+        assert_eq!(state.room_history_tail("room-bb", 10)[0].origin, Some(origin));
+
+        // Viewing a room shows its messages and system messages only
+        state.active_room = Some("room-bb".into());
+        assert!(state.visible_messages().iter().all(|m| m.is_system || m.content == "elsewhere"));
+
+        let before = state.messages.len();
+        state.show_room_history("room-aa 2");
+        let shown: Vec<&str> = state.messages[before..].iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(shown.len(), 3);
+        assert!(shown[1].ends_with("msg 3") && shown[2].ends_with("msg 4"));
+
+        state.remove_room("room-aa");
+        assert!(state.room_history_tail("room-aa", 10).is_empty());
+    }
+
+    #[test]
+    fn test_room_messages_are_ordered_by_timestamp() {
+        let mut state = test_state();
+        state.add_room_message("room-aa", "[room-aa] bob", "first", None, 1_000);
+        state.add_room_message("room-aa", "[room-aa] bob", "third", None, 1_010);
+        state.info("between");
+        state.add_room_message("room-bb", "[room-bb] eve", "other room", None, 990);
+        // Delivered late: lands before "third" but leaves other messages alone
+        let id = state.add_room_message("room-aa", "[room-aa] eve", "second", None, 1_005);
+
+        let shown: Vec<&str> = state.messages.iter().rev().take(5).rev().map(|m| m.content.as_str()).collect();
+        assert_eq!(shown, ["first", "second", "third", "between", "other room"]);
+        assert_eq!(state.messages[state.messages.len() - 4].id, id);
+        let kept: Vec<&str> = state.room_history_tail("room-aa", 10).iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(kept, ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn test_edit_and_delete_own_room_message() {
+        let mut state = test_state();
+        state.rooms.push(("room-a".into(), "A".into()));
+        let commands = state.announce("helo");
+        let id = state.messages.last().unwrap().id.clone();
+        assert!(matches!(
+            &commands[..],
+            [NetworkCommand::SendRoomMessage { id: Some(sent), .. }] if *sent == id
+        ));
+
+        match state.change_own_message(&id, Some("hello")).unwrap() {
+            NetworkCommand::SendRoomMessage { room_id, data, .. } => {
+                assert_eq!(room_id, "room-a");
+                assert_eq!(
+                    RoomChange::from_bytes(&data),
+                    Some(RoomChange::Edit { id: id.clone(), text: "hello".into() })
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(state.messages.last().unwrap().content, "hello (edited)");
+
+        // Someone else's message can't be changed from here
+        let origin = RoomOrigin { author: "bob-peer".into(), id: "b0b1".into() };
+        let bob = state.add_room_message("room-a", "[room-a] bob", "hi", Some(origin), crate::network::unix_now());
+        assert!(state.change_own_message(&bob, None).is_err());
+        // ...nor by another member reusing its id or naming ours
+        let forged = RoomChange::Delete { id: "b0b1".into() };
+        assert!(!state.apply_room_change("room-a", Some("eve-peer"), &forged));
+        assert!(!state.apply_room_change("room-a", Some("eve-peer"), &RoomChange::Delete { id: id.clone() }));
+        assert!(!state.apply_room_change("room-a", Some("eve-peer"), &RoomChange::Delete { id: bob.clone() }));
+        assert_eq!(state.messages.last().unwrap().content, "hi");
+        assert!(state.messages.iter().any(|m| m.content == "hello (edited)"));
+        // ...but the author's delete is shown
+        assert!(state.apply_room_change("room-a", Some("bob-peer"), &forged));
+        assert_eq!(state.messages.last().unwrap().content, "(message deleted)");
+        assert_eq!(state.room_history_tail("room-a", 1)[0].content, "(message deleted)");
+
+        state.change_own_message(&id, None).unwrap();
+        assert!(state.change_own_message(&id, Some("again")).is_err());
+    }
+
+    #[test]
+    fn test_pin_and_unpin() {
+        let mut state = test_state();
+        state.add_chat_message("alice", "meet at 6");
+        state.add_chat_message("bob", "ok");
+        let id = state.messages[state.messages.len() - 2].id.clone();
+        assert_eq!(id.len(), 8);
+
+        assert_eq!(state.pin(&id[..6]).unwrap(), id);
+        assert!(state.pin(&id).is_err(), "already pinned");
+        assert!(state.pin("zz").is_err());
+        let lines = state.pin_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("alice: meet at 6"), "{}", lines[0]);
+
+        assert_eq!(state.unpin(&id[..4]).unwrap(), id);
+        assert!(state.pin_lines().is_empty());
+        assert!(state.unpin(&id).is_err());
+    }
+
+    #[test]
+    fn test_focus_cycles_both_ways() {
+        let mut state = test_state();
+        assert_eq!(state.focus, Focus::Input);
+        let mut seen = vec![state.focus];
+        for _ in 0..4 {
+            state.focus_next();
+            seen.push(state.focus);
+        }
+        assert_eq!(
+            seen,
+            vec![Focus::Input, Focus::Messages, Focus::Peers, Focus::Rooms, Focus::Input]
+        );
+        state.focus_prev();
+        assert_eq!(state.focus, Focus::Rooms);
+        state.focus_prev();
+        assert_eq!(state.focus, Focus::Peers);
+    }
+
+    #[test]
+    fn test_selection_clamps_and_acts() {
+        let mut state = test_state();
+        state.peers = vec!["12D3KooWAlphaPeerLongId".into(), "12D3KooWBetaPeerLongId".into()];
+        state.rooms = vec![("room-aa".into(), "lobby".into())];
+        state.unread.insert("room-aa".into(), 3);
+
+        // Selection only moves in list panels and stops at the ends
+        state.move_selection(1);
+        assert_eq!(state.peer_selected, 0);
+        state.focus = Focus::Peers;
+        state.move_selection(-1);
+        assert_eq!(state.peer_selected, 0);
+        state.move_selection(5);
+        assert_eq!(state.selected_peer().unwrap(), "12D3KooWBetaPeerLongId");
+
+        state.focus = Focus::Rooms;
+        state.activate_selection();
+        assert!(!state.unread.contains_key("room-aa"));
+        assert_eq!(state.focus, Focus::Rooms);
+
+        state.focus = Focus::Peers;
+        state.activate_selection();
+        assert_eq!(state.input, "/room invite 12D3KooWBeta room-aa");
+        assert_eq!(state.focus, Focus::Input);
+
+        // A shrunken list clamps the stale index
+        state.peers.truncate(1);
+        assert_eq!(state.selected_peer().unwrap(), "12D3KooWAlphaPeerLongId");
+        state.peers.clear();
+        assert!(state.selected_peer().is_none());
+    }
+
+    #[test]
+    fn test_shared_nicks_get_distinct_suffixes() {
+        let mut state = test_state();
+        state.note_nick("12D3KooWAliceOne3f", "Alice");
+        state.note_nick("12D3KooWBob", "Bob");
+        assert_eq!(state.display_nick("12D3KooWAliceOne3f").as_deref(), Some("Alice"));
+
+        let before = state.messages.len();
+        state.note_nick("12D3KooWAliceTwo9c", "Alice");
+        state.note_nick("12D3KooWAliceTwo9c", "Alice");
+        assert_eq!(state.display_nick("12D3KooWAliceOne3f").as_deref(), Some("Alice#3f"));
+        assert_eq!(state.display_nick("12D3KooWAliceTwo9c").as_deref(), Some("Alice#9c"));
+        assert_eq!(state.display_nick("12D3KooWBob").as_deref(), Some("Bob"));
+        // Warned once, naming both
+        let warnings: Vec<&str> = state.messages[before..]
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Alice#3f") && warnings[0].contains("Alice#9c"));
+
+        // Suffixes grow until they differ
+        state.note_nick("12D3KooWAliceSix39c", "Alice");
+        assert_eq!(state.display_nick("12D3KooWAliceTwo9c").as_deref(), Some("Alice#o9c"));
+        assert_eq!(state.display_nick("12D3KooWAliceSix39c").as_deref(), Some("Alice#39c"));
+        assert_eq!(state.messages.len(), before + 1);
+
+        // Once the others leave or rename, the nick is unique again
+        state.forget_nick("12D3KooWAliceSix39c");
+        state.note_nick("12D3KooWAliceTwo9c", "Alicia");
+        assert_eq!(state.display_nick("12D3KooWAliceOne3f").as_deref(), Some("Alice"));
+        assert_eq!(state.display_nick("12D3KooWAliceTwo9c").as_deref(), Some("Alicia"));
+
+        // Our own nick counts too
+        state.note_nick("12D3KooWImpostor", "tester");
+        assert!(state.display_nick("12D3KooWImpostor").unwrap().starts_with("tester#"));
+    }
+
+    #[test]
+    fn test_room_roster_reflects_members_and_owner() {
+        let mut state = test_state();
+        state.rooms = vec![("room-aa".into(), "lobby".into())];
+        state.owned_rooms.insert("room-aa".into());
+        state.room_members.insert(
+            "room-aa".into(),
+            ["12D3KooWZuluPeer".to_string(), "12D3KooWAlphaPeer".to_string()].into(),
+        );
+        state.peer_nicks.insert("12D3KooWZuluPeer".into(), "zed".into());
+        state.peers = vec!["12D3KooWZuluPeer".into()];
+
+        let roster = state.room_roster("room-aa");
+        let rows: Vec<(&str, bool, bool)> = roster
+            .iter()
+            .map(|m| (m.name.as_str(), m.online, m.owner))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("tester (you)", true, true),
+                ("12D3KooWAlph", false, false),
+                ("zed", true, false),
+            ]
+        );
+
+        // A joined room has no crown; leaving forgets the roster
+        state.owned_rooms.clear();
+        assert!(!state.room_roster("room-aa")[0].owner);
+        state.active_room = Some("room-aa".into());
+        state.remove_room("room-aa");
+        assert!(state.active_room.is_none());
+        assert_eq!(state.room_roster("room-aa").len(), 1);
+    }
+
+    #[test]
+    fn test_scroll_clamped_on_resize() {
+        let mut state = test_state();
+        state.messages.clear();
+        for i in 0..30 {
+            state.add_system_message(&format!("line {}", i));
+        }
+        state.auto_scroll = false;
+        state.scroll_offset = 25;
+
+        // 10-row pane: at most 20 lines above the newest screenful
+        state.clamp_scroll(UiApp::message_pane_height(15));
+        assert_eq!(state.scroll_offset, 20);
+        assert!(!state.auto_scroll);
+
+        // Tall enough for everything: back to following new messages
+        state.clamp_scroll(UiApp::message_pane_height(40));
+        assert_eq!(state.scroll_offset, 0);
+        assert!(state.auto_scroll);
+    }
+
+    #[test]
+    fn test_only_the_shown_tail_is_wrapped() {
+        let mut state = test_state();
+        state.messages.clear();
+        for i in 0..100 {
+            state.add_system_message(&format!("line {} {}", i, "word ".repeat(10)));
+        }
+        let visible = state.visible_messages();
+        // Each message takes several rows at this width
+        let (rows, unwrapped) = UiApp::wrap_tail(&visible, 10, &[], 8, 30);
+        assert!(rows.len() >= 10 && unwrapped > 90);
+        let last = rows.iter().rev().map(Line::to_string).find(|l| l.contains("line")).unwrap();
+        assert!(last.contains("line 99"));
+
+        // Scrolled far enough, everything gets wrapped
+        let (rows, unwrapped) = UiApp::wrap_tail(&visible, usize::MAX, &[], 8, 30);
+        assert_eq!(unwrapped, 0);
+        assert!(rows.len() > 100);
+    }
+
+    #[test]
+    fn test_direct_message_ack_marks_delivery() {
+        let mut state = test_state();
+        let acked = state.add_outgoing_dm("me->bob", "hi");
+        let lost = state.add_outgoing_dm("me->bob", "anyone?");
+        assert_ne!(acked, lost);
+        let delivery = |state: &UiState, i: usize| {
+            let n = state.messages.len();
+            state.messages[n - 2 + i].delivery.map(|(_, d)| d)
+        };
+        assert_eq!(delivery(&state, 0), Some(Delivery::Pending));
+
+        state.set_dm_delivery(&acked, Delivery::Sent);
+        state.set_dm_delivery(&lost, Delivery::Sent);
+        assert_eq!(delivery(&state, 0), Some(Delivery::Sent));
+        state.set_dm_delivery(&acked, Delivery::Acked);
+        state.set_dm_delivery(&lost, Delivery::Failed);
+        assert_eq!(delivery(&state, 0), Some(Delivery::Acked));
+        assert_eq!(delivery(&state, 1), Some(Delivery::Failed));
+
+        // Settled messages stay as they are
+        state.set_dm_delivery(&acked, Delivery::Failed);
+        assert_eq!(delivery(&state, 0), Some(Delivery::Acked));
+        assert!(state.pending_dms.is_empty());
+        assert_eq!(Delivery::Acked.marker().content, " ✓✓");
+    }
+
+    #[test]
+    fn test_delivery_state_follows_events() {
+        let mut state = test_state();
+        let first = state.add_outgoing_message("one");
+        let second = state.add_outgoing_message("two");
+        assert_ne!(first, second);
+        let delivery = |state: &UiState, i: usize| {
+            let n = state.messages.len();
+            state.messages[n - 2 + i].delivery.map(|(_, d)| d)
+        };
+        assert_eq!(delivery(&state, 0), Some(Delivery::Pending));
+        assert_eq!(delivery(&state, 1), Some(Delivery::Pending));
+
+        // Results may arrive in any order and only touch their own message
+        state.set_delivery(second, Delivery::Failed);
+        assert_eq!(delivery(&state, 0), Some(Delivery::Pending));
+        assert_eq!(delivery(&state, 1), Some(Delivery::Failed));
+        state.set_delivery(first, Delivery::Sent);
+        assert_eq!(delivery(&state, 0), Some(Delivery::Sent));
+
+        // Unknown ids and received messages are left alone
+        state.set_delivery(999, Delivery::Failed);
+        state.add_chat_message("peer", "hello");
+        assert_eq!(state.messages.last().unwrap().delivery, None);
+    }
+
+    #[test]
+    fn test_tab_completes_only_partial_commands() {
+        assert!(UiApp::is_completable("/ro"));
+        assert!(!UiApp::is_completable("/room list"));
+        assert!(!UiApp::is_completable("hello"));
+    }
+
+    #[test]
+    fn test_split_caption() {
+        assert_eq!(UiApp::split_caption(" notes.txt "), ("notes.txt", None));
+        assert_eq!(
+            UiApp::split_caption("notes.txt :: meeting notes"),
+            ("notes.txt", Some("meeting notes"))
+        );
+        assert_eq!(UiApp::split_caption("a.png::hi"), ("a.png", Some("hi")));
+        assert_eq!(UiApp::split_caption("a.png ::  "), ("a.png", None));
+        assert_eq!(
+            UiApp::split_caption("a.png :: see: the :: thing"),
+            ("a.png", Some("see: the :: thing"))
+        );
+    }
+
+    #[test]
+    fn test_system_levels_map_to_styles() {
+        assert_eq!(SystemLevel::Info.style(), Style::default().fg(Color::Yellow));
+        assert_eq!(SystemLevel::Success.style(), Style::default().fg(Color::Green));
+        assert_eq!(SystemLevel::Warning.style(), Style::default().fg(Color::LightYellow));
+        assert_eq!(SystemLevel::Error.style(), Style::default().fg(Color::Red));
+
+        let mut state = test_state();
+        state.error("boom");
+        state.success("done");
+        let error = &state.messages[state.messages.len() - 2];
+        assert!(error.is_system);
+        assert_eq!(error.level, SystemLevel::Error);
+        assert_eq!(error.sender, "✖");
+        let success = state.messages.last().unwrap();
+        assert_eq!(success.level, SystemLevel::Success);
+        assert_eq!(success.sender, "✔");
+    }
+
+    #[test]
+    fn test_untrusted_dial_requires_confirmation() {
+        let mut state = test_state();
+        assert_eq!(state.request_dial("/ip4/203.0.113.7/tcp/4001"), None);
+        assert_eq!(state.pending_dial.as_deref(), Some("/ip4/203.0.113.7/tcp/4001"));
+
+        assert_eq!(
+            state.resolve_pending_dial(true).as_deref(),
+            Some("/ip4/203.0.113.7/tcp/4001")
+        );
+        assert!(state.pending_dial.is_none());
+    }
+
+    #[test]
+    fn test_declined_dial_is_dropped() {
+        let mut state = test_state();
+        state.request_dial("/ip4/203.0.113.7/tcp/4001");
+        assert_eq!(state.resolve_pending_dial(false), None);
+        assert!(state.pending_dial.is_none());
+    }
+
+    #[test]
+    fn test_announce_sends_to_every_room() {
+        let mut state = test_state();
+        state.rooms = vec![
+            ("room-aa".into(), "one".into()),
+            ("room-bb".into(), "two".into()),
+            ("room-cc".into(), "three".into()),
+        ];
+        state.muted.insert("room-bb".into());
+        let before = state.messages.len();
+
+        let commands = state.announce("server going down");
+        let targets: Vec<_> = commands
+            .iter()
+            .map(|cmd| match cmd {
+                NetworkCommand::SendRoomMessage { room_id, data, .. } => {
+                    assert_eq!(data, b"server going down");
+                    room_id.as_str()
+                }
+                other => panic!("expected SendRoomMessage, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(targets, vec!["room-aa", "room-bb", "room-cc"]);
+
+        // The muted room still receives it but isn't echoed
+        assert_eq!(state.messages.len(), before + 2);
+        assert!(state.messages.last().unwrap().content.ends_with("(announced to 3 rooms)"));
+    }
+
+    #[test]
+    fn test_dm_unread_counts_until_opened() {
+        let mut state = test_state();
+        state.record_dm("12D3KooWAlpha");
+        state.record_dm("12D3KooWAlpha");
+        state.record_dm("12D3KooWBeta");
+        assert_eq!(state.dm_unread.get("12D3KooWAlpha"), Some(&2));
+        assert!(state.notify_pending);
+
+        state.open_dm("12D3KooWAlpha");
+        assert!(!state.dm_unread.contains_key("12D3KooWAlpha"));
+        assert_eq!(state.dm_unread.get("12D3KooWBeta"), Some(&1));
+
+        // Messages from the peer being read don't count
+        state.record_dm("12D3KooWAlpha");
+        assert!(!state.dm_unread.contains_key("12D3KooWAlpha"));
+        state.record_dm("12D3KooWBeta");
+        assert_eq!(state.dm_unread.get("12D3KooWBeta"), Some(&2));
+    }
+
+    fn editing(input: &str, cursor: usize) -> UiState {
+        let mut state = test_state();
+        state.input = input.to_string();
+        state.cursor_pos = cursor;
+        state
+    }
+
+    #[test]
+    fn test_insert_and_delete_multibyte() {
+        let mut state = editing("", 0);
+        for c in "héllo".chars() {
+            state.insert_char(c);
+        }
+        assert_eq!(state.cursor_pos, "héllo".len());
+        state.cursor_left();
+        state.cursor_left();
+        state.cursor_left();
+        state.cursor_left();
+        assert_eq!(state.cursor_pos, 1);
+        state.delete_forward();
+        assert_eq!(state.input, "hllo");
+        state.cursor_right();
+        state.insert_char('日');
+        state.delete_back();
+        state.delete_back();
+        assert_eq!(state.input, "hlo");
+        assert_eq!(state.cursor_pos, 1);
+
+        // No-ops at the edges
+        let mut state = editing("ü", 0);
+        state.delete_back();
+        state.cursor_left();
+        state.cursor_end();
+        state.delete_forward();
+        state.cursor_right();
+        assert_eq!(state.input, "ü");
+        assert_eq!(state.cursor_pos, 2);
+    }
+
+    #[test]
+    fn test_home_end_and_kills() {
+        let mut state = editing("grüße welt", 7);
+        state.cursor_home();
+        assert_eq!(state.cursor_pos, 0);
+        state.cursor_end();
+        assert_eq!(state.cursor_pos, "grüße welt".len());
+
+        // Ctrl+U keeps what's after the cursor
+        let mut state = editing("grüße welt", "grüße ".len());
+        state.kill_to_start();
+        assert_eq!((state.input.as_str(), state.cursor_pos), ("welt", 0));
+
+        // Ctrl+K keeps what's before it
+        let mut state = editing("grüße welt", "grüße".len());
+        state.kill_to_end();
+        assert_eq!((state.input.as_str(), state.cursor_pos), ("grüße", "grüße".len()));
+    }
+
+    #[test]
+    fn test_delete_word_back() {
+        let mut state = editing("/room create 日本  ", "/room create 日本  ".len());
+        state.delete_word_back();
+        assert_eq!(state.input, "/room create ");
+        state.delete_word_back();
+        assert_eq!(state.input, "/room ");
+        state.delete_word_back();
+        state.delete_word_back();
+        assert_eq!((state.input.as_str(), state.cursor_pos), ("", 0));
+
+        // Only the part before the cursor goes
+        let mut state = editing("héllo wörld", "héllo wö".len());
+        state.delete_word_back();
+        assert_eq!(state.input, "héllo rld");
+        assert_eq!(state.cursor_pos, "héllo ".len());
+    }
+
+    #[test]
+    fn test_word_jumps() {
+        let text = "ünï  cödé 🚀x";
+        let mut state = editing(text, text.len());
+        state.word_left();
+        assert_eq!(state.cursor_pos, text.find('🚀').unwrap());
+        state.word_left();
+        assert_eq!(state.cursor_pos, text.find('c').unwrap());
+        state.word_left();
+        state.word_left();
+        assert_eq!(state.cursor_pos, 0);
+
+        state.word_right();
+        assert_eq!(state.cursor_pos, "ünï".len());
+        state.word_right();
+        assert_eq!(state.cursor_pos, "ünï  cödé".len());
+        state.word_right();
+        state.word_right();
+        assert_eq!(state.cursor_pos, text.len());
+    }
+
+    #[test]
+    fn test_muted_room_does_not_count_unread() {
+        let mut state = test_state();
+        state.rooms.push(("room-noisy".into(), "noisy".into()));
+        state.rooms.push(("room-quiet".into(), "quiet".into()));
+        state.muted.insert("room-noisy".into());
+
+        state.record_room_message("room-noisy");
+        assert!(!state.unread.contains_key("room-noisy"));
+        assert!(!state.notify_pending);
+
+        state.record_room_message("room-quiet");
+        assert_eq!(state.unread.get("room-quiet"), Some(&1));
+        assert!(state.notify_pending);
+    }
+
+    #[test]
+    fn test_dnd_suppresses_notifications_but_counts_unread() {
+        let mut state = test_state();
+        state.dnd = true;
+        state.record_room_message("room-a");
+        assert_eq!(state.unread.get("room-a"), Some(&1));
+        assert!(!state.notify_pending);
+    }
+
+    #[test]
+    fn test_find_room_id_by_prefix() {
+        let mut state = test_state();
+        state.rooms.push(("room-abcdef".into(), "x".into()));
+        assert_eq!(state.find_room_id("room-abc").as_deref(), Some("room-abcdef"));
+        assert_eq!(state.find_room_id("room-zzz"), None);
+    }
+
+    #[test]
+    fn test_lan_and_allowlisted_dial_immediately() {
+        let mut state = test_state();
+        assert!(state.request_dial("/ip4/192.168.1.2/tcp/4001").is_some());
+
+        state.dial_policy = DialPolicy::new(false, vec!["/ip4/203.0.113.*".into()]);
+        assert!(state.request_dial("/ip4/203.0.113.7/tcp/4001").is_some());
+        assert!(state.pending_dial.is_none());
+    }
+}
@@ -3,15 +3,20 @@
 //! Provides an optional HTTP interface using Axum.
 //! Serves status and peer info via REST API, and a WebSocket bridge
 //! at `/ws` that speaks the same JSON protocol as the openwire-relay server.
+//! Room endpoints under `/api/rooms` change state and require the bearer
+//! token printed in the TUI at startup. JSON and binary broadcasts, which
+//! the TUI doesn't show, reach web clients through its payload hook.
 
 use anyhow::Result;
 use axum::{
     Json, Router,
     extract::{
-        State, WebSocketUpgrade,
+        Path, Query, State, WebSocketUpgrade,
         ws::{Message, WebSocket},
     },
-    routing::get,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, net::SocketAddr, sync::Arc};
@@ -19,28 +24,55 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::{RwLock, broadcast, mpsc};
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::network::{NetworkCommand, NetworkEvent};
+use crate::klipy::KlipyClient;
+use crate::network::envelope::ContentType;
+use crate::network::metrics::Metrics;
+use crate::network::topic::TopicKind;
+use crate::network::{DisconnectReason, GifResult, NetworkCommand, NetworkEvent};
+use crate::room::RoomPolicy;
 
 /// Monotonically-increasing counter so each WS connection gets a unique ID.
 static CONNECTION_COUNTER: AtomicU64 = AtomicU64::new(1);
 
+/// Number of GIFs returned by `/api/gif`
+const GIF_SEARCH_LIMIT: u32 = 8;
+
+/// Longest room name accepted by `POST /api/rooms`
+const MAX_ROOM_NAME_LEN: usize = 64;
+
+/// How long `GET /api/rooms` waits for the network to answer
+const ROOM_LIST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
 // ── Shared app state ────────────────────────────────────────────────────────
 
+/// A JSON or binary broadcast for web clients, from the TUI's payload hook
+#[derive(Clone, Debug)]
+pub struct Payload {
+    pub from: String,
+    pub content_type: ContentType,
+    pub data: Vec<u8>,
+}
+
 /// State shared by all Axum handlers.
 #[derive(Clone)]
 pub struct WebState {
-    /// Local peer ID string (set at startup, read-only afterwards)
-    local_peer_id: Arc<String>,
     /// Channel for sending commands to the libp2p network loop
     network_tx: mpsc::Sender<NetworkCommand>,
-    /// Broadcast sender — each WS handler subscribes a new receiver
+    /// Shared event bus — each WS handler subscribes a new receiver, and
+    /// synthetic events sent here reach the TUI too
     event_broadcast: broadcast::Sender<NetworkEvent>,
-    /// Direct sender into the TUI event queue (used to inject synthetic events)
-    event_tx: mpsc::Sender<NetworkEvent>,
+    /// JSON and binary broadcasts, forwarded to every WS client
+    payloads: broadcast::Sender<Payload>,
     /// peer_id → nick for every known web client
     connected_peers: Arc<RwLock<HashMap<String, String>>>,
     /// room_id → room_name
     rooms: Arc<RwLock<HashMap<String, String>>>,
+    /// Klipy client for `/api/gif` (None when KLIPY_KEY isn't set)
+    klipy: Option<Arc<KlipyClient>>,
+    /// Bearer token required by the room endpoints
+    auth_token: Arc<String>,
+    /// Counters served at `/metrics` (None unless `--metrics`)
+    metrics: Option<Arc<Metrics>>,
 }
 
 // ── REST response types ──────────────────────────────────────────────────────
@@ -58,6 +90,43 @@ struct StatusResponse {
     description: &'static str,
 }
 
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct GifSearchResponse {
+    query: String,
+    gifs: Vec<GifResult>,
+}
+
+#[derive(Deserialize)]
+struct GifQuery {
+    q: String,
+}
+
+#[derive(Deserialize)]
+struct CreateRoomRequest {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct AcceptedResponse {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct RoomInfo {
+    room_id: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct RoomListResponse {
+    rooms: Vec<RoomInfo>,
+}
+
 // ── WebSocket message protocol ───────────────────────────────────────────────
 
 /// Messages arriving **from** the web client.
@@ -125,10 +194,11 @@ enum ServerMsg<'a> {
         data: String,
         peer_id: String,
     },
-    /// Game state/action forwarded from CLI to web
-    GameAction {
-        data: String,
+    /// A JSON or binary broadcast; binary data is hex-encoded
+    Payload {
         peer_id: String,
+        content_type: ContentType,
+        data: String,
     },
     Pong,
 }
@@ -138,18 +208,21 @@ enum ServerMsg<'a> {
 /// Start the Axum web server with WebSocket bridge support.
 pub async fn start_web_server(
     port: u16,
-    local_peer_id: String,
     network_tx: mpsc::Sender<NetworkCommand>,
     event_broadcast: broadcast::Sender<NetworkEvent>,
-    event_tx: mpsc::Sender<NetworkEvent>,
+    payloads: broadcast::Sender<Payload>,
+    auth_token: String,
+    metrics: Option<Arc<Metrics>>,
 ) -> Result<()> {
     let state = WebState {
-        local_peer_id: Arc::new(local_peer_id),
         network_tx,
         event_broadcast,
-        event_tx,
+        payloads,
         connected_peers: Arc::new(RwLock::new(HashMap::new())),
         rooms: Arc::new(RwLock::new(HashMap::new())),
+        klipy: KlipyClient::from_env().map(Arc::new),
+        auth_token: Arc::new(auth_token),
+        metrics,
     };
 
     let cors = CorsLayer::new()
@@ -161,7 +234,12 @@ pub async fn start_web_server(
         .route("/", get(index_handler))
         .route("/api/health", get(health_handler))
         .route("/api/status", get(status_handler))
+        .route("/api/gif", get(gif_handler))
+        .route("/api/rooms", get(list_rooms_handler).post(create_room_handler))
+        .route("/api/rooms/{id}/join", post(join_room_handler))
+        .route("/api/rooms/{id}/leave", post(leave_room_handler))
         .route("/ws", get(ws_handler))
+        .route("/metrics", get(metrics_handler))
         .layer(cors)
         .with_state(state);
 
@@ -231,6 +309,169 @@ async fn status_handler() -> Json<StatusResponse> {
     })
 }
 
+/// `GET /metrics` — Prometheus text format, when started with `--metrics`
+async fn metrics_handler(State(state): State<WebState>) -> Response {
+    match &state.metrics {
+        Some(metrics) => (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+            metrics.to_prometheus(),
+        )
+            .into_response(),
+        None => error_response(StatusCode::NOT_FOUND, "Metrics are disabled (start with --metrics)"),
+    }
+}
+
+/// `GET /api/gif?q=<query>` — search Klipy and return the results as JSON.
+async fn gif_handler(State(state): State<WebState>, Query(params): Query<GifQuery>) -> Response {
+    let Some(client) = state.klipy.as_ref() else {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "GIF search unavailable: KLIPY_KEY not configured",
+        );
+    };
+
+    let query = params.q.trim();
+    if query.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Missing search query");
+    }
+
+    match client.search(query, GIF_SEARCH_LIMIT).await {
+        Ok(gifs) => Json(GifSearchResponse {
+            query: query.to_string(),
+            gifs: gifs.into_iter().filter_map(GifResult::from_gif).collect(),
+        })
+        .into_response(),
+        Err(e) => error_response(StatusCode::BAD_GATEWAY, &format!("GIF search failed: {}", e)),
+    }
+}
+
+/// `POST /api/rooms` with `{"name": "..."}` — create a room.
+async fn create_room_handler(
+    State(state): State<WebState>,
+    headers: HeaderMap,
+    Json(body): Json<CreateRoomRequest>,
+) -> Response {
+    if !is_authorized(&state, &headers) {
+        return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid API token");
+    }
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_ROOM_NAME_LEN {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("Room name must be 1-{} characters", MAX_ROOM_NAME_LEN),
+        );
+    }
+    if name.chars().any(char::is_control) {
+        return error_response(StatusCode::BAD_REQUEST, "Room name contains control characters");
+    }
+    enqueue(&state, NetworkCommand::CreateRoom { name: name.to_string(), policy: RoomPolicy::default() }).await
+}
+
+/// `POST /api/rooms/{id}/join` — accept a pending invite to a room.
+async fn join_room_handler(
+    State(state): State<WebState>,
+    headers: HeaderMap,
+    Path(room_id): Path<String>,
+) -> Response {
+    if !is_authorized(&state, &headers) {
+        return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid API token");
+    }
+    if !crate::network::topic::is_valid_room_id(&room_id) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid room ID");
+    }
+    enqueue(&state, NetworkCommand::AcceptInvite { room_id }).await
+}
+
+/// `POST /api/rooms/{id}/leave` — leave a room.
+async fn leave_room_handler(
+    State(state): State<WebState>,
+    headers: HeaderMap,
+    Path(room_id): Path<String>,
+) -> Response {
+    if !is_authorized(&state, &headers) {
+        return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid API token");
+    }
+    if !crate::network::topic::is_valid_room_id(&room_id) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid room ID");
+    }
+    enqueue(&state, NetworkCommand::LeaveRoom { room_id }).await
+}
+
+/// `GET /api/rooms` — the rooms the node has joined.
+async fn list_rooms_handler(State(state): State<WebState>, headers: HeaderMap) -> Response {
+    if !is_authorized(&state, &headers) {
+        return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid API token");
+    }
+    // Subscribe before asking so the answer can't be missed
+    let mut events = state.event_broadcast.subscribe();
+    if state.network_tx.send(NetworkCommand::QueryRooms).await.is_err() {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "Network is not running");
+    }
+    let answer = tokio::time::timeout(ROOM_LIST_TIMEOUT, async {
+        loop {
+            match events.recv().await {
+                Ok(NetworkEvent::Rooms { rooms }) => return Some(rooms),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .await;
+    match answer {
+        Ok(Some(rooms)) => Json(RoomListResponse {
+            rooms: rooms
+                .into_iter()
+                .map(|(room_id, name)| RoomInfo { room_id, name })
+                .collect(),
+        })
+        .into_response(),
+        _ => error_response(StatusCode::GATEWAY_TIMEOUT, "Network did not answer in time"),
+    }
+}
+
+/// Whether the request carries `Authorization: Bearer <token>`
+fn is_authorized(state: &WebState, headers: &HeaderMap) -> bool {
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    constant_time_eq(presented.as_bytes(), state.auth_token.as_bytes())
+}
+
+/// Compare without short-circuiting so the token can't be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Hand a command to the network loop and answer `202 Accepted`
+async fn enqueue(state: &WebState, cmd: NetworkCommand) -> Response {
+    match state.network_tx.send(cmd).await {
+        Ok(()) => (StatusCode::ACCEPTED, Json(AcceptedResponse { status: "queued" })).into_response(),
+        Err(_) => error_response(StatusCode::SERVICE_UNAVAILABLE, "Network is not running"),
+    }
+}
+
+/// Random per-run token for the web API, used when `--web-token` isn't given
+pub fn generate_token() -> String {
+    use rand::TryRng;
+    let mut bytes = [0u8; 16];
+    rand::rng()
+        .try_fill_bytes(&mut bytes)
+        .expect("Failed to generate web API token");
+    hex::encode(bytes)
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+        .into_response()
+}
+
 // ── WebSocket upgrade handler ────────────────────────────────────────────────
 
 async fn ws_handler(
@@ -260,6 +501,7 @@ async fn handle_ws_connection(socket: WebSocket, state: WebState) {
 
     // Subscribe to broadcast events before anything else so we don't miss any
     let mut event_rx = state.event_broadcast.subscribe();
+    let mut payload_rx = state.payloads.subscribe();
 
     // Split the socket into send/receive halves via an internal channel
     let (ws_tx, mut ws_rx_inner) = mpsc::channel::<String>(64);
@@ -278,19 +520,29 @@ async fn handle_ws_connection(socket: WebSocket, state: WebState) {
         }
 
         loop {
-            match event_rx.recv().await {
-                Ok(event) => {
-                    if let Some(msg) = network_event_to_json(event, &state_a).await
-                        && ws_tx_a.send(msg).await.is_err()
-                    {
-                        break; // client disconnected
+            let msg = tokio::select! {
+                event = event_rx.recv() => match event {
+                    Ok(event) => network_event_to_json(event, &state_a).await,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!("Web bridge dropped {} events (lagged)", n);
+                        // Continue — just skip the lost events
+                        None
                     }
-                }
-                Err(broadcast::error::RecvError::Lagged(n)) => {
-                    tracing::warn!("Web bridge dropped {} events (lagged)", n);
-                    // Continue — just skip the lost events
-                }
-                Err(broadcast::error::RecvError::Closed) => break,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                payload = payload_rx.recv() => match payload {
+                    Ok(payload) => Some(payload_to_json(&payload)),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!("Web bridge dropped {} payloads (lagged)", n);
+                        None
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            if let Some(msg) = msg
+                && ws_tx_a.send(msg).await.is_err()
+            {
+                break; // client disconnected
             }
         }
 
@@ -355,9 +607,11 @@ async fn handle_ws_connection(socket: WebSocket, state: WebState) {
     send_task.abort();
 
     // Announce disconnect to all remaining web clients and TUI
-    let disc_event = NetworkEvent::PeerDisconnected(peer_libp2p_c);
-    let _ = state.event_broadcast.send(disc_event.clone());
-    let _ = state.event_tx.send(disc_event).await;
+    let disc_event = NetworkEvent::PeerDisconnected {
+        peer_id: peer_libp2p_c,
+        reason: DisconnectReason::Clean,
+    };
+    let _ = state.event_broadcast.send(disc_event);
 
     state.connected_peers.write().await.remove(&peer_id);
     tracing::debug!("WebSocket client disconnected: {}", peer_id);
@@ -386,11 +640,29 @@ async fn build_welcome(peer_id: &str, state: &WebState) -> String {
     .unwrap_or_default()
 }
 
+/// The JSON string sent to web clients for a JSON or binary broadcast
+fn payload_to_json(payload: &Payload) -> String {
+    let data = match payload.content_type {
+        ContentType::Binary => hex::encode(&payload.data),
+        ContentType::Text | ContentType::Json => String::from_utf8_lossy(&payload.data).into_owned(),
+    };
+    serde_json::to_string(&ServerMsg::Payload {
+        peer_id: payload.from.clone(),
+        content_type: payload.content_type,
+        data,
+    })
+    .unwrap_or_default()
+}
+
 /// Convert a `NetworkEvent` to the JSON string to send to the client,
 /// or `None` if this event type doesn't need forwarding.
 async fn network_event_to_json(event: NetworkEvent, state: &WebState) -> Option<String> {
     match event {
-        NetworkEvent::MessageReceived { from, data, .. } => {
+        NetworkEvent::MessageReceived { from, topic, data } => {
+            // Web clients show general chat only; the lobby isn't theirs
+            if TopicKind::parse(&topic) != Some(TopicKind::General) {
+                return None;
+            }
             let text = String::from_utf8_lossy(&data).into_owned();
             // Don't forward internal CLI protocol messages to web clients
             if text.starts_with("TYPING:") || text.starts_with("TICKER:") {
@@ -427,7 +699,7 @@ async fn network_event_to_json(event: NetworkEvent, state: &WebState) -> Option<
             )
         }
 
-        NetworkEvent::PeerDisconnected(peer_id) => Some(
+        NetworkEvent::PeerDisconnected { peer_id, .. } => Some(
             serde_json::to_string(&ServerMsg::PeerLeft {
                 peer_id: peer_id.to_string(),
             })
@@ -455,6 +727,7 @@ async fn network_event_to_json(event: NetworkEvent, state: &WebState) -> Option<
             room_id,
             sender_nick,
             content,
+            ..
         } => {
             let data = String::from_utf8_lossy(&content).into_owned();
             Some(
@@ -499,8 +772,7 @@ async fn handle_client_message(
             tracing::debug!("Web client joined as '{}'", nick);
             // Announce to all other web clients (→ ServerMsg::PeerJoined) and TUI
             let event = NetworkEvent::PeerDiscovered(peer_libp2p);
-            let _ = state.event_broadcast.send(event.clone());
-            let _ = state.event_tx.send(event).await;
+            let _ = state.event_broadcast.send(event);
         }
 
         ClientMsg::Message { data } => {
@@ -527,8 +799,7 @@ async fn handle_client_message(
                 topic: "openwire-general".to_string(),
                 data: display.into_bytes(),
             };
-            let _ = state.event_broadcast.send(event.clone());
-            let _ = state.event_tx.send(event).await;
+            let _ = state.event_broadcast.send(event);
         }
 
         ClientMsg::RoomCreate { name } => {
@@ -536,7 +807,7 @@ async fn handle_client_message(
             // to all web clients and TUI via network_event_to_json
             let _ = state
                 .network_tx
-                .send(NetworkCommand::CreateRoom { name })
+                .send(NetworkCommand::CreateRoom { name, policy: RoomPolicy::default() })
                 .await;
         }
 
@@ -565,10 +836,7 @@ async fn handle_client_message(
             // Forward to gossipsub for P2P peers
             let _ = state
                 .network_tx
-                .send(NetworkCommand::SendRoomMessage {
-                    room_id: room_id.clone(),
-                    data: data.clone().into_bytes(),
-                })
+                .send(NetworkCommand::room_message(room_id.clone(), data.clone()))
                 .await;
             // Loopback to TUI + other web clients so the message is visible locally
             let event = NetworkEvent::RoomMessageReceived {
@@ -576,9 +844,10 @@ async fn handle_client_message(
                 room_id,
                 sender_nick: nick,
                 content: data.into_bytes(),
+                message_id: None,
+                timestamp: crate::network::unix_now(),
             };
-            let _ = state.event_broadcast.send(event.clone());
-            let _ = state.event_tx.send(event).await;
+            let _ = state.event_broadcast.send(event);
         }
 
         ClientMsg::RoomInvite {
@@ -617,8 +886,7 @@ async fn handle_client_message(
                 topic: "openwire-general".to_string(),
                 data: display.into_bytes(),
             };
-            let _ = state.event_broadcast.send(event.clone());
-            let _ = state.event_tx.send(event).await;
+            let _ = state.event_broadcast.send(event);
         }
 
         ClientMsg::Ping => {
@@ -813,6 +1081,247 @@ mod tests {
         assert!(!b.to_string().is_empty());
     }
 
+    // ── /api/gif ────────────────────────────────────────────────────────
+
+    fn test_state(klipy: Option<KlipyClient>) -> WebState {
+        test_state_with_commands(klipy).0
+    }
+
+    fn test_state_with_commands(
+        klipy: Option<KlipyClient>,
+    ) -> (WebState, mpsc::Receiver<NetworkCommand>) {
+        let (network_tx, network_rx) = mpsc::channel(8);
+        let (event_broadcast, _) = broadcast::channel(8);
+        let (payloads, _) = broadcast::channel(8);
+        let state = WebState {
+            network_tx,
+            event_broadcast,
+            payloads,
+            connected_peers: Arc::new(RwLock::new(HashMap::new())),
+            rooms: Arc::new(RwLock::new(HashMap::new())),
+            klipy: klipy.map(Arc::new),
+            auth_token: Arc::new("secret".into()),
+            metrics: None,
+        };
+        (state, network_rx)
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_prometheus_text() {
+        let mut state = test_state(None);
+        assert_eq!(metrics_handler(State(state.clone())).await.status(), StatusCode::NOT_FOUND);
+
+        let metrics = Arc::new(Metrics::default());
+        Metrics::add(&metrics.bytes_received, 42);
+        state.metrics = Some(metrics);
+        let resp = metrics_handler(State(state)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        for name in [
+            "openwire_connected_peers",
+            "openwire_messages_sent_total",
+            "openwire_messages_received_total",
+            "openwire_bytes_sent_total",
+            "openwire_rooms",
+            "openwire_rejected_messages_total",
+            "openwire_publish_errors_total",
+        ] {
+            assert!(text.contains(&format!("# TYPE {} ", name)), "missing {}", name);
+        }
+        assert!(text.contains("openwire_bytes_received_total 42\n"));
+    }
+
+    fn auth_headers(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn body_json(resp: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gif_endpoint_returns_results() {
+        let client = KlipyClient::new("test-key".into());
+        client.seed_cache(
+            "cats",
+            vec![crate::klipy::Gif {
+                id: "g1".into(),
+                title: Some("Cat".into()),
+                url: Some("https://example.com/cat.gif".into()),
+                preview_url: Some("https://example.com/cat-small.gif".into()),
+                media_formats: None,
+            }],
+        );
+        let resp = gif_handler(
+            State(test_state(Some(client))),
+            Query(GifQuery { q: "cats".into() }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["query"], "cats");
+        assert_eq!(json["gifs"][0]["id"], "g1");
+        assert_eq!(json["gifs"][0]["url"], "https://example.com/cat.gif");
+        assert_eq!(json["gifs"][0]["title"], "Cat");
+    }
+
+    #[tokio::test]
+    async fn test_gif_endpoint_unavailable_without_klipy() {
+        let resp = gif_handler(State(test_state(None)), Query(GifQuery { q: "cats".into() })).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let json = body_json(resp).await;
+        assert!(json["error"].as_str().unwrap().contains("KLIPY_KEY"));
+    }
+
+    #[tokio::test]
+    async fn test_only_general_chat_reaches_web_clients() {
+        let state = test_state(None);
+        let message = |topic: String| NetworkEvent::MessageReceived {
+            from: libp2p::PeerId::random(),
+            topic,
+            data: b"hi".to_vec(),
+        };
+        let general = network_event_to_json(message("openwire-general".into()), &state).await;
+        assert!(general.is_some_and(|json| json.contains("\"hi\"")));
+        let lobby = crate::network::topic::lobby_topic("default");
+        assert!(network_event_to_json(message(lobby), &state).await.is_none());
+        assert!(network_event_to_json(message("openwire-room-abc".into()), &state).await.is_none());
+    }
+
+    #[test]
+    fn test_payloads_are_forwarded_with_their_type() {
+        let payload = |content_type, data: &[u8]| Payload { from: "peer".into(), content_type, data: data.to_vec() };
+        let json: serde_json::Value =
+            serde_json::from_str(&payload_to_json(&payload(ContentType::Json, br#"{"k":1}"#))).unwrap();
+        assert_eq!(json["type"], "payload");
+        assert_eq!(json["content_type"], "json");
+        assert_eq!(json["data"], r#"{"k":1}"#);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&payload_to_json(&payload(ContentType::Binary, &[0, 159, 255]))).unwrap();
+        assert_eq!(json["content_type"], "binary");
+        assert_eq!(json["data"], "009fff");
+        assert_eq!(json["peer_id"], "peer");
+    }
+
+    // ── /api/rooms ──────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_create_room_enqueues_command() {
+        let (state, mut rx) = test_state_with_commands(None);
+        let resp = create_room_handler(
+            State(state),
+            auth_headers("secret"),
+            Json(CreateRoomRequest { name: " Team ".into() }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        match rx.try_recv() {
+            Ok(NetworkCommand::CreateRoom { name, .. }) => assert_eq!(name, "Team"),
+            other => panic!("expected CreateRoom, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_join_room_accepts_the_invite() {
+        let (state, mut rx) = test_state_with_commands(None);
+        let resp = join_room_handler(State(state.clone()), HeaderMap::new(), Path("room-0123456789abcdef".into())).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let resp = join_room_handler(
+            State(state.clone()),
+            auth_headers("secret"),
+            Path("room-0123456789abcdef".into()),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        match rx.try_recv() {
+            Ok(NetworkCommand::AcceptInvite { room_id }) => {
+                assert_eq!(room_id, "room-0123456789abcdef")
+            }
+            other => panic!("expected AcceptInvite, got {:?}", other),
+        }
+
+        let resp = join_room_handler(State(state), auth_headers("secret"), Path("a/b".into())).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_leave_room_enqueues_command() {
+        let (state, mut rx) = test_state_with_commands(None);
+        let resp = leave_room_handler(
+            State(state.clone()),
+            auth_headers("secret"),
+            Path("room-0123456789abcdef".into()),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        match rx.try_recv() {
+            Ok(NetworkCommand::LeaveRoom { room_id }) => {
+                assert_eq!(room_id, "room-0123456789abcdef")
+            }
+            other => panic!("expected LeaveRoom, got {:?}", other),
+        }
+
+        let resp = leave_room_handler(State(state), auth_headers("secret"), Path("a/b".into())).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_rooms_enqueues_command_and_returns_rooms() {
+        let (state, mut rx) = test_state_with_commands(None);
+        let events = state.event_broadcast.clone();
+        let network = tokio::spawn(async move {
+            match rx.recv().await {
+                Some(NetworkCommand::QueryRooms) => {}
+                other => panic!("expected QueryRooms, got {:?}", other),
+            }
+            let _ = events.send(NetworkEvent::Rooms {
+                rooms: vec![("room-1".into(), "General".into())],
+            });
+        });
+        let resp = list_rooms_handler(State(state), auth_headers("secret")).await;
+        network.await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["rooms"][0]["room_id"], "room-1");
+        assert_eq!(json["rooms"][0]["name"], "General");
+    }
+
+    #[tokio::test]
+    async fn test_room_endpoints_require_token() {
+        let (state, mut rx) = test_state_with_commands(None);
+        let resp = create_room_handler(
+            State(state.clone()),
+            auth_headers("wrong"),
+            Json(CreateRoomRequest { name: "Team".into() }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = list_rooms_handler(State(state.clone()), HeaderMap::new()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = create_room_handler(
+            State(state),
+            auth_headers("secret"),
+            Json(CreateRoomRequest { name: "   ".into() }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(rx.try_recv().is_err());
+    }
+
     // ── CONNECTION_COUNTER ──────────────────────────────────────────────
 
     #[test]